@@ -0,0 +1,358 @@
+/*
+ *  This file is part of yamba.
+ *
+ *  yamba is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  yamba is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with yamba.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use chrono::offset::Utc;
+use failure::Fallible;
+use futures::sync::mpsc::{channel, Receiver, Sender};
+use futures::{stream, Future, Stream};
+use http_r::header;
+use hyper::service::service_fn_ok;
+use hyper::{Body, Chunk, Method, Request, Response, Server, StatusCode};
+use tokio::runtime;
+use tokio::timer::Interval;
+use yamba_types::models::callback::{
+    Event, EventData, EventStreamReq, Sequence, StreamInfo, PATH_EVENTS,
+};
+
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::APIErr;
+use SETTINGS;
+
+/// Daemon event stream
+/// Server sent events with sequence numbers & replay from the last acknowledged sequence
+
+/// Interval of keepalive comments, lets consumers detect dead connections
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+const KEEPALIVE: &'static str = ": keepalive\n\n";
+/// Buffered frames per subscriber, slower subscribers are dropped & resync via `since`
+const SUBSCRIBER_BUFFER: usize = 256;
+
+lazy_static! {
+    static ref JOURNAL: Journal = Journal::new(
+        Utc::now().timestamp(),
+        SETTINGS.main.events_replay_max as usize
+    );
+}
+
+/// Frame of an event, formatted for SSE
+type Frame = Arc<String>;
+
+/// Buffer of recent events & subscribers
+struct Journal {
+    epoch: i64,
+    capacity: usize,
+    inner: Mutex<JournalInner>,
+}
+
+struct JournalInner {
+    next_seq: Sequence,
+    events: VecDeque<(Sequence, Frame)>,
+    subscribers: Vec<Sender<Frame>>,
+}
+
+impl Journal {
+    fn new(epoch: i64, capacity: usize) -> Journal {
+        Journal {
+            epoch,
+            capacity,
+            inner: Mutex::new(JournalInner {
+                next_seq: 1,
+                events: VecDeque::with_capacity(capacity),
+                subscribers: Vec::new(),
+            }),
+        }
+    }
+
+    /// Store event & send it to all subscribers
+    fn publish(&self, data: EventData) -> Fallible<Sequence> {
+        let mut inner = self.inner.lock().expect("Can't lock journal!");
+        let seq = inner.next_seq;
+        let frame = Arc::new(format_frame(&Event { seq, data })?);
+        inner.next_seq += 1;
+        inner.events.push_back((seq, frame.clone()));
+        while inner.events.len() > self.capacity {
+            inner.events.pop_front();
+        }
+        // drops subscribers with closed connections or full buffers
+        let subscribers = inner
+            .subscribers
+            .drain(..)
+            .filter_map(|mut s| match s.try_send(frame.clone()) {
+                Ok(_) => Some(s),
+                Err(ref e) if e.is_full() => {
+                    debug!("Dropping slow event stream subscriber");
+                    None
+                }
+                Err(_) => None,
+            })
+            .collect();
+        inner.subscribers = subscribers;
+        Ok(seq)
+    }
+
+    /// Subscribe to events
+    /// Returns events to replay & receiver for all following events
+    fn subscribe(&self, req: &EventStreamReq) -> Fallible<(Vec<Frame>, Receiver<Frame>)> {
+        let mut inner = self.inner.lock().expect("Can't lock journal!");
+        let mut initial = vec![Arc::new(format_frame(&Event {
+            seq: 0,
+            data: EventData::Connected(StreamInfo {
+                epoch: self.epoch,
+                next_seq: inner.next_seq,
+            }),
+        })?)];
+        let start = match (req.since, req.epoch) {
+            (Some(since), Some(epoch)) if epoch == self.epoch => since + 1,
+            // sequence of a previous daemon run
+            (Some(_), _) => 1,
+            (None, _) => inner.next_seq,
+        };
+        let first_available = match inner.events.front() {
+            Some((seq, _)) => *seq,
+            None => inner.next_seq,
+        };
+        if start < first_available {
+            initial.push(Arc::new(format_frame(&Event {
+                seq: 0,
+                data: EventData::Missed { first_available },
+            })?));
+        }
+        initial.extend(
+            inner
+                .events
+                .iter()
+                .filter(|(seq, _)| *seq >= start)
+                .map(|(_, frame)| frame.clone()),
+        );
+        let (sender, receiver) = channel(SUBSCRIBER_BUFFER);
+        inner.subscribers.push(sender);
+        Ok((initial, receiver))
+    }
+}
+
+/// Format event as SSE frame
+fn format_frame(event: &Event) -> Fallible<String> {
+    let data = serde_json::to_string(event)?;
+    Ok(match event.seq {
+        0 => format!("data: {}\n\n", data),
+        seq => format!("id: {}\ndata: {}\n\n", seq, data),
+    })
+}
+
+/// Publish event to all subscribers
+pub fn publish(data: EventData) -> Fallible<()> {
+    let seq = JOURNAL.publish(data)?;
+    trace!("Published event {}", seq);
+    Ok(())
+}
+
+/// Address parser for event stream
+/// Used also for runtime checks
+pub fn parse_addr() -> Fallible<SocketAddr> {
+    super::parse_address(
+        &SETTINGS.main.api_events_bind_ip,
+        &SETTINGS.main.api_events_bind_port,
+    )
+}
+
+/// Start event stream server
+pub fn start_server(runtime: &mut runtime::Runtime) -> Fallible<()> {
+    let addr = parse_addr()?;
+    let server = Server::try_bind(&addr)
+        .map_err(|e| APIErr::EventBindError(e))?
+        .serve(|| service_fn_ok(handle_request))
+        .map_err(|e| error!("Event stream server error: {}", e));
+    runtime.spawn(server);
+    Ok(())
+}
+
+/// Handle event stream subscription
+fn handle_request(req: Request<Body>) -> Response<Body> {
+    if req.method() != Method::GET || req.uri().path() != PATH_EVENTS {
+        return status_response(StatusCode::NOT_FOUND);
+    }
+    let authorized = req.headers().get(header::AUTHORIZATION).map_or(false, |v| {
+        is_secret(v.as_bytes(), &SETTINGS.main.api_secret)
+    });
+    if !authorized {
+        debug!("Unauthorized event stream subscription");
+        return status_response(StatusCode::UNAUTHORIZED);
+    }
+    let mut query: EventStreamReq = match req.uri().query() {
+        Some(v) => match serde_urlencoded::from_str(v) {
+            Ok(v) => v,
+            Err(e) => {
+                debug!("Invalid event stream query: {}", e);
+                return status_response(StatusCode::BAD_REQUEST);
+            }
+        },
+        None => EventStreamReq::default(),
+    };
+    // SSE reconnects of browsers
+    if let Some(v) = req.headers().get("Last-Event-ID") {
+        if let Some(v) = v.to_str().ok().and_then(|v| v.parse().ok()) {
+            query.since = Some(v);
+            query.epoch = query.epoch.or(Some(JOURNAL.epoch));
+        }
+    }
+    debug!("Event stream subscription: {:?}", query);
+    let (initial, receiver) = match JOURNAL.subscribe(&query) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Can't subscribe to events: {}", e);
+            return status_response(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let keepalive = Interval::new(Instant::now() + KEEPALIVE_INTERVAL, KEEPALIVE_INTERVAL)
+        .map(|_| Arc::new(KEEPALIVE.to_string()))
+        .map_err(|e| warn!("Timer error: {}", e));
+    let body = stream::iter_ok(initial)
+        .chain(receiver.select(keepalive))
+        .map(|frame: Frame| Chunk::from((*frame).clone()))
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "Event stream closed"));
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(Body::wrap_stream(body))
+        .unwrap()
+}
+
+/// Compare against the API secret in constant time
+fn is_secret(value: &[u8], secret: &str) -> bool {
+    let secret = secret.as_bytes();
+    value.len() == secret.len()
+        && value
+            .iter()
+            .zip(secret)
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+fn status_response(code: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(code)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use yamba_types::models::callback::{Playstate, PlaystateResponse};
+
+    fn playback(id: i32) -> EventData {
+        EventData::Playback(PlaystateResponse {
+            id,
+            state: Playstate::Playing,
+        })
+    }
+
+    fn parse(frame: &Frame) -> Event {
+        let data = frame.lines().find(|l| l.starts_with("data: ")).unwrap();
+        serde_json::from_str(&data["data: ".len()..]).unwrap()
+    }
+
+    #[test]
+    fn test_secret() {
+        assert!(is_secret(b"change me", "change me"));
+        assert!(!is_secret(b"change you", "change me"));
+        assert!(!is_secret(b"change", "change me"));
+        assert!(!is_secret(b"", "change me"));
+    }
+
+    #[test]
+    fn test_replay() {
+        let journal = Journal::new(42, 3);
+        for i in 0..5 {
+            assert_eq!(i as u64 + 1, journal.publish(playback(i)).unwrap());
+        }
+        // seq 3..5 buffered
+        let (initial, _) = journal
+            .subscribe(&EventStreamReq {
+                since: Some(3),
+                epoch: Some(42),
+            })
+            .unwrap();
+        let events: Vec<Event> = initial.iter().map(parse).collect();
+        assert_eq!(3, events.len());
+        match events[0].data {
+            EventData::Connected(ref v) => assert_eq!(6, v.next_seq),
+            ref v => panic!("Unexpected event {:?}", v),
+        }
+        assert_eq!(
+            vec![4, 5],
+            events[1..].iter().map(|e| e.seq).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_missed() {
+        let journal = Journal::new(42, 2);
+        for i in 0..4 {
+            journal.publish(playback(i)).unwrap();
+        }
+        // other daemon run
+        let (initial, _) = journal
+            .subscribe(&EventStreamReq {
+                since: Some(10),
+                epoch: Some(1),
+            })
+            .unwrap();
+        let events: Vec<Event> = initial.iter().map(parse).collect();
+        match events[1].data {
+            EventData::Missed { first_available } => assert_eq!(3, first_available),
+            ref v => panic!("Unexpected event {:?}", v),
+        }
+        assert_eq!(
+            vec![3, 4],
+            events[2..].iter().map(|e| e.seq).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_live() {
+        let journal = Journal::new(42, 8);
+        journal.publish(playback(1)).unwrap();
+        let (initial, receiver) = journal.subscribe(&EventStreamReq::default()).unwrap();
+        assert_eq!(1, initial.len());
+        journal.publish(playback(2)).unwrap();
+        drop(journal);
+        let received: Vec<Event> = receiver.wait().map(|v| parse(&v.unwrap())).collect();
+        assert_eq!(1, received.len());
+        assert_eq!(2, received[0].seq);
+    }
+
+    #[test]
+    fn test_slow_subscriber() {
+        let journal = Journal::new(42, 8);
+        let (_, receiver) = journal.subscribe(&EventStreamReq::default()).unwrap();
+        for i in 0..(SUBSCRIBER_BUFFER + 2) {
+            journal.publish(playback(i as i32)).unwrap();
+        }
+        assert!(journal.inner.lock().unwrap().subscribers.is_empty());
+        // buffered frames are still delivered, then the stream ends
+        let received = receiver.wait().count();
+        assert!(received >= SUBSCRIBER_BUFFER && received < SUBSCRIBER_BUFFER + 2);
+    }
+}
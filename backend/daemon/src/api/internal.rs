@@ -24,7 +24,7 @@ use std::net::SocketAddr;
 
 use super::{accepted, get_instance_by_id, invalid_instance, APIErr, Rsp};
 use daemon::{HeartbeatMap, Instances};
-use yamba_types::models::{HeartbeatReq, InstanceStartedReq};
+use yamba_types::models::{HeartbeatReq, InstanceDisconnectedReq, InstanceStartedReq};
 use SETTINGS;
 
 /// Internal API, used for plugin<->daemon communication
@@ -84,6 +84,19 @@ impl_web! {
 
         }
 
+        #[post("/internal/disconnected")]
+        #[content_type("application/json")]
+        fn disconnected(&self, body: InstanceDisconnectedReq) -> Rsp {
+            debug!("instance disconnected request: {:?}",body);
+            match get_instance_by_id(&self.instances, &body.id) {
+                Some(v) => {
+                    v.set_stop_reason(body.reason);
+                    accepted()
+                },
+                None => invalid_instance(),
+            }
+        }
+
         #[post("/internal/heartbeat")]
         #[content_type("application/json")]
         fn heartbeat(&self, body: HeartbeatReq) -> Rsp {
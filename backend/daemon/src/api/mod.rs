@@ -20,7 +20,7 @@ use hashbrown::HashMap;
 use http_r::{response::Response, status::StatusCode};
 use owning_ref::OwningRef;
 use serde::Serialize;
-use tokio::{executor, runtime};
+use tokio::runtime;
 use yamba_types::models::{DefaultResponse, ErrorCodes, ErrorResponse};
 
 use std::net::SocketAddr;
@@ -32,6 +32,7 @@ use daemon::{
 };
 
 pub mod callback;
+mod events;
 mod internal;
 mod public;
 
@@ -39,8 +40,8 @@ mod public;
 pub enum APIErr {
     #[fail(display = "API bind error {}", _0)]
     BindError(#[cause] std::io::Error),
-    #[fail(display = "Unable to spawn onto executor {}", _0)]
-    ExcecutionFailed(#[cause] executor::SpawnError),
+    #[fail(display = "Event stream bind error {}", _0)]
+    EventBindError(#[cause] hyper::Error),
 }
 
 /// Start api server
@@ -49,6 +50,7 @@ pub fn start_server(
     instances: Instances,
     base: InstanceBase,
 ) -> Fallible<()> {
+    events::start_server(runtime)?;
     internal::start_server(runtime, instances.clone(), base.heartbeat.clone())?;
     public::start_server(runtime, instances, base)?;
     Ok(())
@@ -58,6 +60,7 @@ pub fn start_server(
 pub fn check_runtime() -> Fallible<()> {
     public::parse_addr()?;
     internal::parse_addr()?;
+    events::parse_addr()?;
     Ok(())
 }
 
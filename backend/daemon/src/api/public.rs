@@ -16,20 +16,28 @@
  */
 
 use failure::Fallible;
+use futures::{future, Future};
 use http_r::status::StatusCode;
-use tokio::{net::TcpListener, runtime};
+use tokio::{fs::File, net::TcpListener, runtime};
 use tower_web::*;
 use tower_web::{middleware::log::LogMiddleware, view::Handlebars};
 use yamba_types::models::callback::{InstanceStateResponse, PlaystateResponse, ResolveResponse};
 use yamba_types::models::*;
 
+use std::io;
 use std::net::SocketAddr;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 
 use super::callback::send_resolve;
 use super::*;
-use daemon::{create_instance, instance, InstanceBase, Instances};
-use ytdl_worker::{RSongs, YTRequest};
+use daemon::instance::InstanceErr;
+use daemon::{create_instance, InstanceBase, Instances};
+use recording;
+use soundboard::SoundboardErr;
+use stream;
+use ytdl::{error_code, search_url};
+use ytdl_worker::{resolve_stats, RSongs, YTRequest};
 use SETTINGS;
 
 static CALLBACK_TICKET: AtomicUsize = AtomicUsize::new(0);
@@ -84,6 +92,14 @@ impl ResolveDispatcher {
             url: req.url,
         }
     }
+
+    /// Create dispatcher for search request, resolving the search pseudo-URL
+    pub fn new_search(req: SearchRequest, ticket: usize) -> ResolveDispatcher {
+        ResolveDispatcher {
+            ticket,
+            url: search_url(req.site, req.amount, &req.query),
+        }
+    }
 }
 
 impl YTRequest for ResolveDispatcher {
@@ -97,6 +113,7 @@ impl YTRequest for ResolveDispatcher {
                 source: self.url.clone(),
                 ticket: self.ticket,
                 success: true,
+                error: ErrorCodes::NONE,
                 songs: s,
                 msg: None,
             },
@@ -104,6 +121,7 @@ impl YTRequest for ResolveDispatcher {
                 source: self.url.clone(),
                 ticket: self.ticket,
                 success: false,
+                error: error_code(&e),
                 songs: Vec::new(),
                 msg: Some(format!("{}", e)),
             },
@@ -113,6 +131,21 @@ impl YTRequest for ResolveDispatcher {
     }
 }
 
+impl ApiResource {
+    /// Current resolver metrics
+    fn resolve_stats_int(&self) -> ResolveStatsResponse {
+        let (in_flight, coalesced) = resolve_stats();
+        let (play_lane, bulk_lane) = self.base.lanes.stats();
+        ResolveStatsResponse {
+            playback_pool: self.base.pool.stats(),
+            in_flight,
+            coalesced,
+            play_lane,
+            bulk_lane,
+        }
+    }
+}
+
 impl_web! {
     impl ApiResource {
 
@@ -126,13 +159,11 @@ impl_web! {
                     id: r.get_id(),
                     playing: r.is_playing(),
                     volume: r.get_volume(),
-                    inst_type: match r.get_voip() {
-                        instance::InstanceType::Teamspeak(_) => String::from("Teamspeak"),
-                    },
+                    inst_type: String::from(r.get_voip().name()),
                     playback_info: r.playback_info(),
                 }
             }).collect();
-            Ok(InstanceOverviewResponse{instances})
+            Ok(InstanceOverviewResponse{instances, resolver: self.resolve_stats_int()})
         }
 
         #[get("/resolve/url")]
@@ -142,8 +173,27 @@ impl_web! {
             match get_instance_by_id(&self.instances, &query_string.instance) {
                 Some(v) => {
                     let t = CALLBACK_TICKET.fetch_add(1, Ordering::SeqCst);
+                    let priority = query_string.priority;
                     let dispatcher = ResolveDispatcher::new(query_string, t.clone());
-                    match v.dispatch_resolve(dispatcher.wrap()) {
+                    match v.dispatch_resolve(dispatcher.wrap(), priority) {
+                        Ok(_) => ok_response(ResolveTicketResponse{ticket: t}),
+                        Err(_) => custom_response(StatusCode::TOO_MANY_REQUESTS,ErrorResponse{msg: String::from("Queue overload!"),details: ErrorCodes::RESOLVE_QUEUE_OVERLOAD})
+                    }
+                }
+                None => invalid_instance()
+            }
+        }
+
+        #[get("/resolve/search")]
+        #[content_type("application/json")]
+        fn search(&self, query_string: SearchRequest) -> Rsp {
+            debug!("search request: {:?}",query_string);
+            match get_instance_by_id(&self.instances, &query_string.instance) {
+                Some(v) => {
+                    let t = CALLBACK_TICKET.fetch_add(1, Ordering::SeqCst);
+                    let priority = query_string.priority;
+                    let dispatcher = ResolveDispatcher::new_search(query_string, t.clone());
+                    match v.dispatch_resolve(dispatcher.wrap(), priority) {
                         Ok(_) => ok_response(ResolveTicketResponse{ticket: t}),
                         Err(_) => custom_response(StatusCode::TOO_MANY_REQUESTS,ErrorResponse{msg: String::from("Queue overload!"),details: ErrorCodes::RESOLVE_QUEUE_OVERLOAD})
                     }
@@ -173,7 +223,7 @@ impl_web! {
         fn instance_list(&self) -> Fallible<InstanceListResponse> {
             debug!("instance list request");
             let inst_r = self.instances.read().expect("Can't write instances!");
-            let ids = inst_r.values().map(|val|InstanceListEntry{id: val.get_id(), started: val.get_startup_time()}).collect();
+            let ids = inst_r.values().map(|val|InstanceListEntry{id: val.get_id(), started: val.get_startup_time(), owner: val.get_owner().cloned()}).collect();
             Ok(InstanceListResponse{instances:ids})
         }
 
@@ -199,13 +249,101 @@ impl_web! {
         }
 
 
+        #[post("/instance/effects")]
+        #[content_type("application/json")]
+        fn instance_effects_set(&self, body: EffectsSetReq) -> Rsp {
+            debug!("effects set request: {:?}",body);
+            match get_instance_by_id(&self.instances, &body.id) {
+                Some(v) => match v.set_effects(&body.effects) {
+                    Ok(()) => ok(),
+                    Err(e) => match e.downcast_ref::<InstanceErr>() {
+                        Some(InstanceErr::InvalidEffects) => custom_response(StatusCode::BAD_REQUEST,ErrorResponse{msg: format!("{}", e),details: ErrorCodes::INVALID_EFFECTS}),
+                        _ => Err(e),
+                    },
+                },
+                None => invalid_instance(),
+            }
+        }
+
+        #[get("/instance/effects")]
+        #[content_type("application/json")]
+        fn instance_effects_get(&self, query_string: EffectsGetReq) -> Rsp {
+            trace!("effects get: {:?}",query_string);
+            match get_instance_by_id(&self.instances, &query_string.id) {
+                Some(v) => ok_response(v.get_effects()),
+                None => invalid_instance(),
+            }
+        }
+
         #[post("/playback/url")]
         #[content_type("application/json")]
         fn playback_start(&self, body: PlaybackUrlReq) -> Rsp {
             // if body.song.source TODO: check for non-localhost URL
             debug!("playback request: {:?}",body);
             match get_instance_by_id(&self.instances, &body.id) {
-                Some(v) => {v.play_track(body.song)?; ok() },
+                Some(v) => {v.play_track(body.song, body.trim)?; ok() },
+                None => invalid_instance(),
+            }
+        }
+
+        #[post("/playback/jingle")]
+        #[content_type("application/json")]
+        fn playback_jingle(&self, body: JinglePlayReq) -> Rsp {
+            debug!("jingle request: {:?}",body);
+            match get_instance_by_id(&self.instances, &body.id) {
+                Some(v) => match v.play_jingle(body) {
+                    Ok(()) => accepted(),
+                    Err(e) => match e.downcast_ref::<SoundboardErr>() {
+                        Some(SoundboardErr::InvalidName(_)) => custom_response(StatusCode::BAD_REQUEST,ErrorResponse{msg: format!("{}", e),details: ErrorCodes::INVALID_CLIP}),
+                        _ => Err(e),
+                    },
+                },
+                None => invalid_instance(),
+            }
+        }
+
+        #[post("/playback/seek")]
+        #[content_type("application/json")]
+        fn playback_seek(&self, body: PlaybackSeekReq) -> Rsp {
+            debug!("playback seek request: {:?}",body);
+            match get_instance_by_id(&self.instances, &body.id) {
+                Some(v) => {v.seek(&body.song, body.position_ms); ok() },
+                None => invalid_instance(),
+            }
+        }
+
+        #[post("/playback/rate")]
+        #[content_type("application/json")]
+        fn playback_rate_set(&self, body: PlaybackRateReq) -> Rsp {
+            debug!("playback rate request: {:?}",body);
+            match get_instance_by_id(&self.instances, &body.id) {
+                Some(v) => match v.set_rate(body.rate, body.preserve_pitch) {
+                    Ok(()) => ok(),
+                    Err(e) => match e.downcast_ref::<InstanceErr>() {
+                        Some(InstanceErr::InvalidRate(_)) => custom_response(StatusCode::BAD_REQUEST,ErrorResponse{msg: format!("{}", e),details: ErrorCodes::INVALID_RATE}),
+                        _ => Err(e),
+                    },
+                },
+                None => invalid_instance(),
+            }
+        }
+
+        #[get("/playback/rate")]
+        #[content_type("application/json")]
+        fn playback_rate_get(&self, query_string: RateGetReq) -> Rsp {
+            trace!("playback rate get: {:?}",query_string);
+            match get_instance_by_id(&self.instances, &query_string.id) {
+                Some(v) => ok_response(v.get_rate()),
+                None => invalid_instance(),
+            }
+        }
+
+        #[post("/playback/trim")]
+        #[content_type("application/json")]
+        fn playback_trim(&self, body: PlaybackTrimReq) -> Rsp {
+            debug!("playback trim request: {:?}",body);
+            match get_instance_by_id(&self.instances, &body.id) {
+                Some(v) => {v.set_trim(&body.song, body.trim); ok() },
                 None => invalid_instance(),
             }
         }
@@ -260,5 +398,156 @@ impl_web! {
             let volume = get_instance_by_id(&self.instances, &query_string.id).map(|inst| inst.get_volume());
             Ok(VolumeResponse{volume,msg: None})
         }
+
+        #[get("/resolve/stats")]
+        #[content_type("application/json")]
+        fn resolve_stats(&self) -> Fallible<ResolveStatsResponse> {
+            Ok(self.resolve_stats_int())
+        }
+
+        #[post("/output/attach")]
+        #[content_type("application/json")]
+        fn output_attach(&self, body: OutputAttachReq) -> Rsp {
+            debug!("output attach request: {:?}",body);
+            match get_instance_by_id(&self.instances, &body.id) {
+                Some(v) => ok_response(v.attach_output(body.output)?),
+                None => invalid_instance(),
+            }
+        }
+
+        #[post("/output/detach")]
+        #[content_type("application/json")]
+        fn output_detach(&self, body: OutputDetachReq) -> Rsp {
+            debug!("output detach request: {:?}",body);
+            match get_instance_by_id(&self.instances, &body.id) {
+                Some(v) => match v.detach_output(body.output) {
+                    true => ok(),
+                    false => custom_response(StatusCode::NOT_FOUND,ErrorResponse{msg: String::from("Invalid output"),details: ErrorCodes::INVALID_OUTPUT}),
+                },
+                None => invalid_instance(),
+            }
+        }
+
+        #[get("/output/list")]
+        #[content_type("application/json")]
+        fn output_list(&self, query_string: OutputListReq) -> Rsp {
+            debug!("output list request: {:?}",query_string);
+            match get_instance_by_id(&self.instances, &query_string.id) {
+                Some(v) => ok_response(OutputListResponse{outputs: v.get_outputs()}),
+                None => invalid_instance(),
+            }
+        }
+
+        #[post("/recording/start")]
+        #[content_type("application/json")]
+        fn recording_start(&self, body: RecordingStartReq) -> Rsp {
+            debug!("recording start request: {:?}",body);
+            match get_instance_by_id(&self.instances, &body.id) {
+                Some(v) => match v.start_recording(body.format) {
+                    Ok(data) => ok_response(data),
+                    Err(e) => match e.downcast_ref::<recording::RecordingErr>() {
+                        Some(_) => custom_response(StatusCode::CONFLICT,ErrorResponse{msg: format!("{}", e),details: ErrorCodes::RECORDING_ACTIVE}),
+                        None => Err(e),
+                    },
+                },
+                None => invalid_instance(),
+            }
+        }
+
+        #[post("/recording/stop")]
+        #[content_type("application/json")]
+        fn recording_stop(&self, body: RecordingStopReq) -> Rsp {
+            debug!("recording stop request: {:?}",body);
+            match get_instance_by_id(&self.instances, &body.id) {
+                Some(v) => match v.stop_recording()? {
+                    Some(data) => ok_response(data),
+                    None => custom_response(StatusCode::NOT_FOUND,ErrorResponse{msg: String::from("Not recording"),details: ErrorCodes::NO_RECORDING}),
+                },
+                None => invalid_instance(),
+            }
+        }
+
+        #[get("/recording/list")]
+        #[content_type("application/json")]
+        fn recording_list(&self, query_string: RecordingListReq) -> Fallible<RecordingListResponse> {
+            debug!("recording list request: {:?}",query_string);
+            Ok(RecordingListResponse{recordings: recording::list(query_string.id)?})
+        }
+
+        #[get("/recording/file")]
+        #[content_type("application/octet-stream")]
+        fn recording_file(&self, query_string: RecordingFileReq) -> impl Future<Item = File, Error = io::Error> + Send {
+            debug!("recording download request: {:?}",query_string);
+            future::result(recording::file_path(&query_string.name)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Invalid recording")))
+                .and_then(File::open)
+        }
+
+        #[post("/soundboard/play")]
+        #[content_type("application/json")]
+        fn soundboard_play(&self, body: ClipPlayReq) -> Rsp {
+            debug!("soundboard play request: {:?}",body);
+            match get_instance_by_id(&self.instances, &body.id) {
+                Some(v) => match v.play_clip(body) {
+                    Ok(()) => accepted(),
+                    Err(e) => match e.downcast_ref::<SoundboardErr>() {
+                        Some(SoundboardErr::InvalidName(_)) => custom_response(StatusCode::BAD_REQUEST,ErrorResponse{msg: format!("{}", e),details: ErrorCodes::INVALID_CLIP}),
+                        Some(SoundboardErr::NotPlaying) => custom_response(StatusCode::CONFLICT,ErrorResponse{msg: format!("{}", e),details: ErrorCodes::SOUNDBOARD_NOT_PLAYING}),
+                        Some(SoundboardErr::TooManyClips(_)) => custom_response(StatusCode::TOO_MANY_REQUESTS,ErrorResponse{msg: format!("{}", e),details: ErrorCodes::SOUNDBOARD_BUSY}),
+                        None => Err(e),
+                    },
+                },
+                None => invalid_instance(),
+            }
+        }
+
+        #[post("/soundboard/stop")]
+        #[content_type("application/json")]
+        fn soundboard_stop(&self, body: ClipStopReq) -> Rsp {
+            debug!("soundboard stop request: {:?}",body);
+            match get_instance_by_id(&self.instances, &body.id) {
+                Some(v) => {
+                    v.stop_clips();
+                    ok()
+                },
+                None => invalid_instance(),
+            }
+        }
+
+        #[get("/stream/status")]
+        #[content_type("application/json")]
+        fn stream_status(&self) -> Fallible<StreamStatusResponse> {
+            Ok(StreamStatusResponse{streams: stream::status()})
+        }
+
+        #[get("/ytdl/versions")]
+        #[content_type("application/json")]
+        fn ytdl_versions(&self) -> Fallible<YtdlVersionsResponse> {
+            debug!("ytdl versions request");
+            Ok(self.base.ytdl.versions())
+        }
+
+        #[post("/ytdl/update")]
+        #[content_type("application/json")]
+        fn ytdl_update(&self) -> Rsp {
+            debug!("ytdl update request");
+            let ytdl = self.base.ytdl.clone();
+            thread::spawn(move || {
+                if let Err(e) = ytdl.update_downloader() {
+                    warn!("Error when updating ytdl: {}", e);
+                }
+            });
+            accepted()
+        }
+
+        #[post("/ytdl/rollback")]
+        #[content_type("application/json")]
+        fn ytdl_rollback(&self) -> Rsp {
+            debug!("ytdl rollback request");
+            match self.base.ytdl.rollback() {
+                Ok(v) => ok_response(DefaultResponse{msg: Some(v)}),
+                Err(e) => custom_response(StatusCode::CONFLICT,ErrorResponse{msg: format!("{}", e),details: ErrorCodes::YTDL_ROLLBACK_FAILED})
+            }
+        }
     }
 }
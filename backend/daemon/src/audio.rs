@@ -324,6 +324,11 @@ impl NullSink {
     pub fn get_sink_name(&self) -> &str {
         self.name.as_str()
     }
+
+    /// Returns name of the monitor source, capturing everything played into the sink
+    pub fn get_monitor_name(&self) -> String {
+        format!("{}.monitor", self.name)
+    }
 }
 
 impl Drop for NullSink {
@@ -23,6 +23,7 @@ use std::io::{self, Write};
 use config_rs::{Config, ConfigError as ConfigRSError, Environment, File as CFile};
 use failure::Fallible;
 
+use ytdl_profile::ResolverProfile;
 use {CONF_DIR, DEFAULT_CONFIG_NAME};
 
 /// Config handler
@@ -46,6 +47,146 @@ pub struct ConfigRoot {
     pub main: ConfigMain,
     pub ytdl: ConfigYtDL,
     pub ts: ConfigTS,
+    #[serde(default)]
+    pub mumble: ConfigMumble,
+    #[serde(default)]
+    pub stream: ConfigStream,
+    #[serde(default)]
+    pub soundboard: ConfigSoundboard,
+    #[serde(default)]
+    pub playback: ConfigPlayback,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConfigMumble {
+    /// Accept any server certificate, allows MITM attacks
+    #[serde(default)]
+    pub accept_invalid_certs: bool,
+    /// SHA-256 fingerprints of accepted server certificates  
+    /// For self-signed certificates, default of murmur
+    #[serde(default)]
+    pub pinned_certs: Vec<String>,
+    /// Opus bitrate of sent voice
+    #[serde(default = "default_mumble_bitrate")]
+    pub bitrate: u32,
+}
+
+impl Default for ConfigMumble {
+    fn default() -> ConfigMumble {
+        ConfigMumble {
+            accept_invalid_certs: false,
+            pinned_certs: Vec::new(),
+            bitrate: default_mumble_bitrate(),
+        }
+    }
+}
+
+fn default_mumble_bitrate() -> u32 {
+    96000
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConfigStream {
+    /// Bind of the HTTP audio stream server
+    #[serde(default = "default_stream_bind_ip")]
+    pub bind_ip: String,
+    #[serde(default = "default_stream_bind_port")]
+    pub bind_port: u16,
+    /// Default bitrate in kbit/s
+    #[serde(default = "default_stream_bitrate")]
+    pub bitrate: u32,
+    /// Max listeners per stream
+    #[serde(default = "default_stream_max_listeners")]
+    pub max_listeners: u32,
+}
+
+impl Default for ConfigStream {
+    fn default() -> ConfigStream {
+        ConfigStream {
+            bind_ip: default_stream_bind_ip(),
+            bind_port: default_stream_bind_port(),
+            bitrate: default_stream_bitrate(),
+            max_listeners: default_stream_max_listeners(),
+        }
+    }
+}
+
+fn default_stream_bind_ip() -> String {
+    String::from("127.0.0.1")
+}
+
+fn default_stream_bind_port() -> u16 {
+    1339
+}
+
+fn default_stream_bitrate() -> u32 {
+    128
+}
+
+fn default_stream_max_listeners() -> u32 {
+    100
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConfigSoundboard {
+    /// Cache folder of clips fetched from the manager
+    #[serde(default = "default_soundboard_dir")]
+    pub dir: String,
+    /// Music volume while clips are playing, 0 to 1
+    #[serde(default = "default_soundboard_duck_volume")]
+    pub duck_volume: f64,
+    /// Max concurrent clips per instance
+    #[serde(default = "default_soundboard_max_clips")]
+    pub max_clips: usize,
+}
+
+impl Default for ConfigSoundboard {
+    fn default() -> ConfigSoundboard {
+        ConfigSoundboard {
+            dir: default_soundboard_dir(),
+            duck_volume: default_soundboard_duck_volume(),
+            max_clips: default_soundboard_max_clips(),
+        }
+    }
+}
+
+fn default_soundboard_dir() -> String {
+    String::from("clips")
+}
+
+fn default_soundboard_duck_volume() -> f64 {
+    0.3
+}
+
+fn default_soundboard_max_clips() -> usize {
+    4
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ConfigPlayback {
+    /// Fade in & out on play, pause, stop & track changes in ms, 0 to disable
+    #[serde(default = "default_playback_fade_ms")]
+    pub fade_ms: u64,
+    /// Ramp of volume changes in ms, 0 to disable
+    #[serde(default = "default_playback_volume_ramp_ms")]
+    pub volume_ramp_ms: u64,
+}
+
+impl Default for ConfigPlayback {
+    fn default() -> ConfigPlayback {
+        ConfigPlayback {
+            fade_ms: default_playback_fade_ms(),
+            volume_ramp_ms: default_playback_volume_ramp_ms(),
+        }
+    }
+}
+
+fn default_playback_fade_ms() -> u64 {
+    500
+}
+
+fn default_playback_volume_ramp_ms() -> u64 {
+    300
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -66,11 +207,42 @@ pub struct ConfigMain {
     pub api_jsonrpc_ip: String,
     pub api_bind_port: u16,
     pub api_bind_ip: String,
-    pub api_callback_port: u16,
-    pub api_callback_ip: String,
+    /// Bind of the event stream
+    #[serde(default = "default_events_bind_port")]
+    pub api_events_bind_port: u16,
+    #[serde(default = "default_events_bind_ip")]
+    pub api_events_bind_ip: String,
+    /// Amount of events buffered for replay
+    #[serde(default = "default_events_replay_max")]
+    pub events_replay_max: u32,
     pub cache_lifetime_secs: u64,
-    pub api_callback_secret: String,
     pub api_secret: String,
+    /// Interval of batched position events, 0 to disable
+    #[serde(default = "default_position_heartbeat_secs")]
+    pub position_heartbeat_secs: u16,
+    /// Folder of recorded files
+    #[serde(default = "default_recordings_dir")]
+    pub recordings_dir: String,
+}
+
+fn default_recordings_dir() -> String {
+    String::from("recordings")
+}
+
+fn default_position_heartbeat_secs() -> u16 {
+    30
+}
+
+fn default_events_bind_port() -> u16 {
+    1336
+}
+
+fn default_events_bind_ip() -> String {
+    String::from("127.0.0.1")
+}
+
+fn default_events_replay_max() -> u32 {
+    4096
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -84,6 +256,45 @@ pub struct ConfigYtDL {
     pub download_source: String,
     pub timeout_version: u8,
     pub min_audio_bitrate: i64,
+    #[serde(default)]
+    pub pin_version: Option<String>,
+    /// Resolved after updates, URL or local path  
+    /// Empty disables the self-test, only the version is checked then
+    #[serde(default)]
+    pub self_test_source: String,
+    #[serde(default = "default_true")]
+    pub direct_resolve: bool,
+    #[serde(default)]
+    pub direct_hosts: Vec<String>,
+    /// Per-site cookies, proxy & arguments
+    #[serde(default)]
+    pub profiles: Vec<ResolverProfile>,
+    /// Workers resolving tracks for playback
+    #[serde(default = "default_playback_workers")]
+    pub playback_workers: u8,
+    /// Max amount of queued playback resolves
+    #[serde(default = "default_playback_backlog_max")]
+    pub playback_backlog_max: u16,
+    /// Workers of the "play now" resolver lane, `workers` are used for bulk resolves
+    #[serde(default = "default_play_workers")]
+    pub play_workers: u8,
+}
+
+fn default_play_workers() -> u8 {
+    2
+}
+
+fn default_playback_workers() -> u8 {
+    4
+}
+
+fn default_playback_backlog_max() -> u16 {
+    64
+}
+
+/// Serde default for enabled-by-default settings
+fn default_true() -> bool {
+    true
 }
 
 /// Init settings
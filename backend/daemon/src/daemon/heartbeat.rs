@@ -44,13 +44,16 @@ impl HeartbeatMap {
             Interval::new_interval(CHECK_INTERVAL)
                 .for_each(move |_| {
                     let mut inst_rw = instances.write().expect("Can't lock instances!");
-                    hbm_c
-                        .get_entries_older_than(CHECK_INTERVAL)
-                        .iter()
-                        .for_each(move |id| {
-                            warn!("Killing instance {}, timeout for heartbeat.", id);
-                            inst_rw.remove(id);
-                        });
+                    for id in hbm_c.get_entries_older_than(CHECK_INTERVAL) {
+                        hbm_c.storage.remove(&id);
+                        if let Some(mut inst) = inst_rw.remove(&id) {
+                            let reason = inst.classify_timeout();
+                            warn!(
+                                "Killing instance {}, timeout for heartbeat, reason: {:?}",
+                                id, reason
+                            );
+                        }
+                    }
                     Ok(())
                 })
                 .map_err(|e| {
@@ -67,11 +70,11 @@ impl HeartbeatMap {
         self.storage.insert(id, Instant::now());
     }
     /// Get entries older than specified duration
-    fn get_entries_older_than<'a>(&'a self, limit: Duration) -> Vec<&'a ID> {
+    fn get_entries_older_than(&self, limit: Duration) -> Vec<ID> {
         self.storage
             .iter()
             .filter_map(|(key, val)| match val.elapsed() >= limit {
-                true => Some(key),
+                true => Some(*key),
                 false => None,
             })
             .collect()
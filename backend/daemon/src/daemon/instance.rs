@@ -18,31 +18,47 @@
 use chrono::offset::Utc;
 use failure::Fallible;
 use futures::sync::mpsc::Receiver;
-use futures::Stream;
+use futures::{Future, Stream};
 use gst::ResourceError;
 use gst_player::PlayerError;
 use tokio::runtime;
+use tokio::timer::Interval;
 
+use std::cmp::min;
 use std::sync::{
-    atomic::{AtomicUsize, Ordering},
-    Arc, RwLock,
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Mutex, RwLock,
 };
-use std::thread;
+use std::time::Duration;
 
 use api::callback;
-use audio::NullSink;
 use cache::Cache;
 use daemon::{HeartbeatMap, Instances, WInstances};
+use output::Outputs;
 use playback::{PlaybackState, Player, PlayerEvent, PlayerEventType};
-use ts::TSInstance;
-use yamba_types::models::{callback::*, CacheSong, InstanceStartedReq, Song, SongID, TimeStarted};
+use pool::WorkerPool;
+use recording::{ActiveRecording, RecordingErr};
+use soundboard::{self, SoundboardErr};
+use trim::{self, TrimAction};
+use voip::VoipBackend;
+use yamba_types::models::{
+    callback::*, AudioEffects, CacheSong, ClipPlayReq, InstanceStartedReq, JinglePlayReq, OutputID,
+    OutputInfo, OutputType, Rate, RateResponse, RecordFormat, Recording, ResolvePriority, Song,
+    SongID, SongTrim, StopReason, TimeMS, TimeStarted,
+};
 use ytdl::YtDL;
-use ytdl_worker::{Controller, YTReqWrapped, YTSender};
+use ytdl_worker::{self, LaneSenders, Lanes, YTReqWrapped};
 use SETTINGS;
 
 /// module containing a single instance
 
 const RETRY_MAX: usize = 3;
+/// Max backoff between reconnects of live sources
+const LIVE_BACKOFF_MAX_SECS: u64 = 60;
+/// Max reconnects of a live source before it's reported as ended
+const LIVE_RETRY_MAX: usize = 10;
+/// Interval of trim point checks
+const TRIM_CHECK_INTERVAL: Duration = Duration::from_millis(250);
 
 #[derive(Fail, Debug, PartialEq)]
 pub enum InstanceErr {
@@ -54,14 +70,19 @@ pub enum InstanceErr {
     NoCurrentSong,
     #[fail(display = "Max amount of error retries reached for song")]
     MaxRetries,
+    #[fail(display = "Playback rate {} not supported", _0)]
+    InvalidRate(Rate),
+    #[fail(display = "Audio effects out of range")]
+    InvalidEffects,
 }
 
 /// Data provider for creation of instances
 pub trait InstanceDataProvider {
-    fn get_controller(&self) -> &Controller;
+    fn get_lanes(&self) -> &Lanes;
     fn get_ytdl(&self) -> &Arc<YtDL>;
     fn get_cache(&self) -> &SongCache;
     fn get_weak_instances(&self) -> &WInstances;
+    fn get_pool(&self) -> &WorkerPool;
 }
 
 pub type ID = i32;
@@ -76,27 +97,52 @@ pub type CurrentSong = Song;
 /// Base for each instance
 pub struct Instance {
     id: ID,
-    voip: InstanceType,
+    voip: Box<dyn VoipBackend>,
     player: Player,
+    outputs: Mutex<Outputs>,
+    recording: Mutex<Option<ActiveRecording>>,
     ytdl: Arc<YtDL>,
     current_song: CURRENT_SONG,
     error_retries: AtomicUsize,
     cache: SongCache,
     instances: WInstances,
-    url_resolve: YTSender,
+    url_resolve: LaneSenders,
+    pool: WorkerPool,
     startup_time: TimeStarted,
+    owner: Option<String>,
     state: RwLock<InstanceState>,
+    stop_reason: RwLock<Option<StopReason>>,
+    stream_title: RwLock<Option<String>>,
+    /// Trim points of the current song
+    trim: RwLock<Option<SongTrim>>,
+    /// Pitch preservation on rate changes
+    preserve_pitch: AtomicBool,
 }
 
 impl Drop for Instance {
     fn drop(&mut self) {
         // don't store on clone drop
         println!("Dropping instance {}", self.id);
-        self.player.stop();
+        if let Err(e) = self.stop_recording() {
+            warn!("Can't finish recording: {}", e);
+        }
+        self.outputs
+            .lock()
+            .expect("Can't lock outputs!")
+            .detach_all(&self.player);
+        self.player.stop_clips();
+        self.player.stop_now();
+        self.voip.teardown();
 
+        let reason = self
+            .stop_reason
+            .read()
+            .expect("Can't read stop reason!")
+            .unwrap_or(StopReason::Requested);
         let _ = callback::send_instance_state(&InstanceStateResponse {
             id: self.get_id(),
             state: InstanceState::Stopped,
+            reason: Some(reason),
         })
         .map_err(|e| warn!("Can't send instance stopped: {}", e));
     }
@@ -105,23 +151,32 @@ impl Drop for Instance {
 impl Instance {
     pub fn new(
         id: ID,
-        voip: InstanceType,
+        voip: Box<dyn VoipBackend>,
         base: &InstanceDataProvider,
         player: Player,
         heartbeats: HeartbeatMap,
+        owner: Option<String>,
     ) -> Instance {
         let instance = Instance {
             voip: voip,
-            url_resolve: base.get_controller().channel(id.clone(), 64),
+            url_resolve: base.get_lanes().channel(id.clone()),
             player,
+            outputs: Mutex::new(Outputs::new(id)),
+            recording: Mutex::new(None),
             id: id,
             ytdl: base.get_ytdl().clone(),
             cache: base.get_cache().clone(),
             current_song: Arc::new(RwLock::new(None)),
             instances: base.get_weak_instances().clone(),
+            pool: base.get_pool().clone(),
             error_retries: AtomicUsize::new(0),
             startup_time: Utc::now().timestamp(),
+            owner,
             state: RwLock::new(InstanceState::Started),
+            stop_reason: RwLock::new(None),
+            stream_title: RwLock::new(None),
+            trim: RwLock::new(None),
+            preserve_pitch: AtomicBool::new(false),
         };
 
         heartbeats.update(instance.get_id());
@@ -144,16 +199,22 @@ impl Instance {
         self.error_retries.load(Ordering::Relaxed)
     }
 
-    /// Resolve URL under this instances queue
-    pub fn dispatch_resolve(&self, request: YTReqWrapped) -> Fallible<()> {
-        Ok(self.url_resolve.try_send(request)?)
+    /// Resolve URL under this instances queue of the given priority
+    pub fn dispatch_resolve(
+        &self,
+        request: YTReqWrapped,
+        priority: ResolvePriority,
+    ) -> Fallible<()> {
+        self.url_resolve.dispatch(request, priority)
     }
 
     /// Stop playback
     pub fn stop_playback(&self) {
         let mut lock = self.current_song.write().expect("Can't lock current song!");
         *lock = None;
+        *self.trim.write().expect("Can't lock trim!") = None;
         // don't store to history, still in queue, no end-of-stream triggered
+        self.player.stop_clips();
         self.player.stop();
     }
 
@@ -174,19 +235,170 @@ impl Instance {
                 let cache = self.cache.clone();
                 let id = self.id.clone();
                 let ytdl = self.ytdl.clone();
-                thread::spawn(move || {
+                self.pool.execute(move || {
                     if let Err(e) =
                         Instance::play_track_inner(instances, cache, id, ytdl, source, songid, true)
                     {
                         warn!("Error while retrying track! {}", e);
                     }
-                });
-                Ok(())
+                })
             }
             None => Err(InstanceErr::NoCurrentSong.into()),
         }
     }
 
+    /// Reconnect to live source after backoff  
+    /// Re-resolves the source if the last reconnect failed already  
+    /// Ends the song after LIVE_RETRY_MAX failed reconnects
+    fn reconnect_live(&self) -> Fallible<()> {
+        let song_r = self.current_song.read().expect("Can't lock current song!");
+        let song = match *song_r {
+            Some(ref v) => v,
+            None => return Err(InstanceErr::NoCurrentSong.into()),
+        };
+        let retries = self.get_error_retries();
+        if retries >= LIVE_RETRY_MAX {
+            drop(song_r);
+            warn!(
+                "Live source of instance {} failed {} reconnects, ending it",
+                self.id, retries
+            );
+            self.end_current_song();
+            return Err(InstanceErr::MaxRetries.into());
+        }
+        self.increate_error_retries();
+        if retries > 0 {
+            self.cache.delete(&song.id);
+        }
+        let backoff = live_backoff(retries);
+        info!(
+            "Reconnecting live source for instance {} in {}s",
+            self.id,
+            backoff.as_secs()
+        );
+        let source = song.source.clone();
+        let songid = song.id.clone();
+        let instances = self.instances.clone();
+        let cache = self.cache.clone();
+        let id = self.id.clone();
+        let ytdl = self.ytdl.clone();
+        self.pool.execute_after(backoff, move || {
+            let result = Instance::play_track_inner(
+                instances.clone(),
+                cache,
+                id,
+                ytdl,
+                source,
+                songid.clone(),
+                true,
+            );
+            if let Err(e) = result {
+                warn!("Error while reconnecting live source! {}", e);
+                if let Some(instances) = instances.upgrade() {
+                    let lock = instances.read().expect("Can't read instances!");
+                    if let Some(inst) = lock.get(&id) {
+                        if inst.is_current_song(&songid) {
+                            if let Err(e) = inst.reconnect_live() {
+                                warn!("Can't reconnect live source: {}", e);
+                            }
+                        }
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Returns true if the current song is a live source
+    fn is_live(&self) -> bool {
+        self.current_song
+            .read()
+            .expect("Can't lock current song!")
+            .as_ref()
+            .map_or(false, |v| v.live)
+    }
+
+    /// Returns true if song_id is the current song
+    fn is_current_song(&self, song_id: &SongID) -> bool {
+        self.current_song
+            .read()
+            .expect("Can't lock current song!")
+            .as_ref()
+            .map_or(false, |v| &v.id == song_id)
+    }
+
+    /// Returns true if the current song is a jingle
+    fn is_jingle(&self) -> bool {
+        self.current_song
+            .read()
+            .expect("Can't lock current song!")
+            .as_ref()
+            .map_or(false, |v| soundboard::is_jingle(&v.id))
+    }
+
+    /// Handle playback errors, retry depending on the source
+    fn playback_error(&self, retry: bool) {
+        let result = if self.is_live() {
+            self.reconnect_live()
+        } else if retry && !self.is_jingle() {
+            self.force_song_retry()
+        } else {
+            return;
+        };
+        if let Err(e) = result {
+            warn!("Couldn't restart playback: {}", e);
+        }
+    }
+
+    /// Handle playback start
+    fn playback_started(&self) {
+        // live source reconnected successfully
+        if self.is_live() {
+            self.reset_error_retries();
+        }
+    }
+
+    /// Handle player state change for the running recording
+    fn recording_state(&self, playing: bool) {
+        if let Some(active) = self
+            .recording
+            .lock()
+            .expect("Can't lock recording!")
+            .as_mut()
+        {
+            active.set_playing(playing);
+        }
+    }
+
+    /// Handle media info change, sends stream title updates of live sources
+    fn media_info_updated(&self, title: Option<String>) {
+        if !self.is_live() {
+            return;
+        }
+        let mut title_w = self.stream_title.write().expect("Can't lock stream title!");
+        if *title_w == title {
+            return;
+        }
+        *title_w = title.clone();
+        drop(title_w);
+        if let Some(ref title) = title {
+            let source = self
+                .current_song
+                .read()
+                .expect("Can't lock current song!")
+                .as_ref()
+                .map(|v| v.source.clone())
+                .unwrap_or_default();
+            self.mark_recording(title.clone(), None, source);
+        }
+        if let Err(e) = callback::send_song_info(&SongInfoUpdate {
+            id: self.get_id(),
+            stream_title: title,
+        }) {
+            warn!("Can't send song info update: {}", e);
+        }
+    }
+
     /// Handle end of stream event
     fn end_of_stream(&self) {
         // !stop-flag && no current song (avoid feedback loop)
@@ -196,10 +408,15 @@ impl Instance {
             .expect("Can't lock current song!")
             .is_some();
 
-        if has_current_song {
-            self.send_playstate_change(Playstate::EndOfMedia);
-        } else {
+        if !has_current_song {
             trace!("Ignoring end of stream");
+        } else if self.is_live() {
+            // live sources don't end, connection got lost
+            if let Err(e) = self.reconnect_live() {
+                warn!("Couldn't reconnect live source: {}", e);
+            }
+        } else {
+            self.send_playstate_change(Playstate::EndOfMedia);
         }
     }
 
@@ -207,8 +424,8 @@ impl Instance {
         self.id
     }
 
-    pub fn get_voip(&self) -> &InstanceType {
-        &self.voip
+    pub fn get_voip(&self) -> &dyn VoipBackend {
+        &*self.voip
     }
 
     pub fn is_playing(&self) -> bool {
@@ -233,12 +450,108 @@ impl Instance {
         self.player.pause();
     }
 
+    /// Apply audio effects to the music
+    pub fn set_effects(&self, effects: &AudioEffects) -> Fallible<()> {
+        if !effects.is_valid() {
+            return Err(InstanceErr::InvalidEffects.into());
+        }
+        self.player.set_effects(effects)
+    }
+
+    /// Returns current audio effects
+    pub fn get_effects(&self) -> AudioEffects {
+        self.player.get_effects()
+    }
+
+    /// Set playback rate, pitch preservation applies from the next track on
+    pub fn set_rate(&self, rate: Rate, preserve_pitch: Option<bool>) -> Fallible<()> {
+        if !yamba_types::is_valid_rate(rate) {
+            return Err(InstanceErr::InvalidRate(rate).into());
+        }
+        if let Some(preserve) = preserve_pitch {
+            if self.preserve_pitch.swap(preserve, Ordering::Relaxed) != preserve {
+                self.player.set_preserve_pitch(preserve)?;
+            }
+        }
+        self.player.set_rate(rate);
+        self.send_position_update();
+        Ok(())
+    }
+
+    /// Returns playback rate & pitch preservation
+    pub fn get_rate(&self) -> RateResponse {
+        RateResponse {
+            rate: self.player.get_rate(),
+            preserve_pitch: self.preserve_pitch.load(Ordering::Relaxed),
+        }
+    }
+
     pub fn get_playback_state(&self) -> Playstate {
         playback_to_public_state(self.player.get_state())
     }
 
+    /// Play loaded media, starts at the trimmed start
     pub fn play(&self) {
         self.player.play();
+        let start = self
+            .trim
+            .read()
+            .expect("Can't lock trim!")
+            .as_ref()
+            .map_or(0, |v| v.start_ms);
+        if start > 0 {
+            self.player.seek(start);
+        }
+    }
+
+    /// Update trim points, ignored if the song isn't playing anymore
+    pub fn set_trim(&self, song: &SongID, trim: Option<SongTrim>) {
+        if self.is_current_song(song) {
+            *self.trim.write().expect("Can't lock trim!") = trim;
+            self.check_trim();
+        }
+    }
+
+    /// Seek to position, ignored if the song isn't playing anymore or live
+    pub fn seek(&self, song: &SongID, position_ms: TimeMS) {
+        if self.is_current_song(song) && !self.is_live() {
+            self.player.seek(position_ms);
+        }
+    }
+
+    /// Apply trim points at the current position
+    fn check_trim(&self) {
+        if !self.player.is_playing() {
+            return;
+        }
+        let position = match self.player.get_position_ms() {
+            Some(v) => v,
+            None => return,
+        };
+        let action = match *self.trim.read().expect("Can't lock trim!") {
+            Some(ref v) => trim::action(v, position),
+            None => return,
+        };
+        match action {
+            Some(TrimAction::Seek(target)) => {
+                debug!("Instance {} skipping to {}ms", self.id, target);
+                self.player.seek(target);
+            }
+            Some(TrimAction::End) => {
+                debug!("Instance {} reached trimmed end", self.id);
+                self.end_current_song();
+            }
+            None => (),
+        }
+    }
+
+    /// Stop the current song & signal end of media
+    fn end_current_song(&self) {
+        // stop before signaling, the manager starts the next track on end of media
+        self.player.stop_now();
+        *self.current_song.write().expect("Can't lock current song!") = None;
+        *self.trim.write().expect("Can't lock trim!") = None;
+        self.send_playstate_change(Playstate::EndOfMedia);
     }
 
     /// Returns startup time as UNIX timestamp
@@ -246,6 +559,11 @@ impl Instance {
         self.startup_time
     }
 
+    /// Returns ownership token of the manager which started this instance
+    pub fn get_owner(&self) -> Option<&String> {
+        self.owner.as_ref()
+    }
+
     /// Resume playback
     pub fn resume_playback(&self) -> Fallible<()> {
         if self
@@ -259,17 +577,141 @@ impl Instance {
         Ok(())
     }
 
+    /// Attach additional output, fed by the player of this instance
+    pub fn attach_output(&self, kind: OutputType) -> Fallible<OutputInfo> {
+        self.outputs
+            .lock()
+            .expect("Can't lock outputs!")
+            .attach(&self.player, kind)
+    }
+
+    /// Detach output, returns false for unknown outputs
+    pub fn detach_output(&self, id: OutputID) -> bool {
+        self.outputs
+            .lock()
+            .expect("Can't lock outputs!")
+            .detach(&self.player, id)
+    }
+
+    /// Returns attached outputs
+    pub fn get_outputs(&self) -> Vec<OutputInfo> {
+        self.outputs.lock().expect("Can't lock outputs!").list()
+    }
+
+    /// Play soundboard clip over the music  
+    /// Clips are fetched in the background, fails without running playback
+    pub fn play_clip(&self, request: ClipPlayReq) -> Fallible<()> {
+        if !soundboard::is_valid_name(&request.clip) {
+            return Err(SoundboardErr::InvalidName(request.clip).into());
+        }
+        if !self.player.is_playing() {
+            return Err(SoundboardErr::NotPlaying.into());
+        }
+        let max = SETTINGS.soundboard.max_clips;
+        if self.player.clip_count() >= max {
+            return Err(SoundboardErr::TooManyClips(max).into());
+        }
+        let instances = self.instances.clone();
+        self.pool.execute(move || {
+            if let Err(e) = Instance::play_clip_inner(instances, request) {
+                warn!("Can't play clip: {}", e);
+            }
+        })
+    }
+
+    /// Stop all clips
+    pub fn stop_clips(&self) {
+        self.player.stop_clips();
+    }
+
+    /// Start recording the instance output to file
+    pub fn start_recording(&self, format: RecordFormat) -> Fallible<Recording> {
+        let mut recording = self.recording.lock().expect("Can't lock recording!");
+        if recording.is_some() {
+            return Err(RecordingErr::AlreadyRecording.into());
+        }
+        let output = self.attach_output(OutputType::File { format })?;
+        let mut active = ActiveRecording::new(
+            output.output,
+            output.location,
+            self.id,
+            format,
+            self.player.is_playing(),
+        );
+        if let Some(song) = self
+            .current_song
+            .read()
+            .expect("Can't lock current song!")
+            .as_ref()
+        {
+            active.mark(song.name.clone(), song.artist.clone(), song.source.clone());
+        } else {
+            active.save();
+        }
+        let data = active.get_data();
+        info!("Instance {} recording to {}", self.id, data.file);
+        *recording = Some(active);
+        Ok(data)
+    }
+
+    /// Stop recording, returns None if not recording
+    pub fn stop_recording(&self) -> Fallible<Option<Recording>> {
+        let active = match self.recording.lock().expect("Can't lock recording!").take() {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        self.detach_output(active.get_output());
+        Ok(Some(active.finish()?))
+    }
+
+    /// Add track marker to running recording
+    fn mark_recording(&self, title: String, artist: Option<String>, source: String) {
+        if let Some(active) = self
+            .recording
+            .lock()
+            .expect("Can't lock recording!")
+            .as_mut()
+        {
+            active.mark(title, artist, source);
+        }
+    }
+
     /// Returns current instance state  
     /// Must never be stopped.
     pub fn get_state(&self) -> InstanceState {
         self.state.read().expect("Can't read state").clone()
     }
 
+    /// Set reason for the upcoming stop, the first reported reason is kept
+    pub fn set_stop_reason(&self, reason: StopReason) {
+        let mut stop_reason = self.stop_reason.write().expect("Can't lock stop reason!");
+        if stop_reason.is_none() {
+            debug!("Instance {} stopping, reason: {:?}", self.id, reason);
+            *stop_reason = Some(reason);
+        }
+    }
+
+    /// Classify missing heartbeats  
+    /// Uses the reason reported by the plugin or checks whether the VoIP client crashed
+    pub fn classify_timeout(&mut self) -> StopReason {
+        if let Some(reason) = *self.stop_reason.read().expect("Can't read stop reason!") {
+            return reason;
+        }
+        let reason = match self.voip.is_running() {
+            Ok(true) => StopReason::HeartbeatTimeout,
+            Ok(false) => StopReason::Crashed,
+            Err(e) => {
+                warn!("Can't check VoIP client of instance {}: {}", self.id, e);
+                StopReason::HeartbeatTimeout
+            }
+        };
+        self.set_stop_reason(reason);
+        reason
+    }
+
     /// Called when voip is connected & able to send audio
     pub(crate) fn connected(&self, param: InstanceStartedReq) -> Fallible<()> {
-        match self.voip {
-            InstanceType::Teamspeak(ref ts) => ts.on_connected(param.pid)?,
-        }
+        self.voip.on_connected(&param)?;
         let mut state = self.state.write().expect("Can't lock state!");
         *state = InstanceState::Running;
         drop(state);
@@ -277,6 +719,7 @@ impl Instance {
         let _ = callback::send_instance_state(&InstanceStateResponse {
             id: self.get_id(),
             state: InstanceState::Running,
+            reason: None,
         })
         .map_err(|e| warn!("Can't send instance running state {}", e));
 
@@ -290,15 +733,24 @@ impl Instance {
         match song_guard.as_ref() {
             Some(cur_song) => {
                 let position = self.player.get_position();
-                let length = format_time(cur_song.length);
+                let length = match cur_song.live {
+                    true => String::from("LIVE"),
+                    false => format_time(cur_song.length),
+                };
                 let artist = match cur_song.artist.as_ref() {
                     Some(v) => format!(" - {}", v),
                     None => String::new(),
                 };
+                let stream_title =
+                    match *self.stream_title.read().expect("Can't lock stream title!") {
+                        Some(ref v) => format!(" | {}", v),
+                        None => String::new(),
+                    };
                 format!(
-                    "{}{} {:02}:{:02} / {} {}",
+                    "{}{}{} {:02}:{:02} / {} {}",
                     cur_song.name.as_str(),
                     artist,
+                    stream_title,
                     position.minutes + (position.hours * 60),
                     position.seconds,
                     length,
@@ -313,25 +765,51 @@ impl Instance {
     }
 
     /// Play song
-    pub fn play_track(&self, song: Song) -> Fallible<()> {
+    pub fn play_track(&self, song: Song, trim: Option<SongTrim>) -> Fallible<()> {
+        self.mark_recording(song.name.clone(), song.artist.clone(), song.source.clone());
         let mut c_song_w = self.current_song.write().expect("Can't lock current song!");
+        *self.trim.write().expect("Can't lock trim!") = trim;
 
         let source = song.source.clone();
         let songid = song.id.clone();
         *c_song_w = Some(song);
+        *self.stream_title.write().expect("Can't lock stream title!") = None;
         let instances = self.instances.clone();
         let cache = self.cache.clone();
         let id = self.id.clone();
         let ytdl = self.ytdl.clone();
-        thread::spawn(move || {
+        self.pool.execute(move || {
             if let Err(e) =
                 Instance::play_track_inner(instances, cache, id, ytdl, source, songid, false)
             {
                 warn!("Error while resolving next track! {}", e);
             }
-        });
+        })
+    }
 
-        Ok(())
+    /// Play jingle from the soundboard clips as track  
+    /// Not marked in recordings, fetched in the background
+    pub fn play_jingle(&self, request: JinglePlayReq) -> Fallible<()> {
+        if !soundboard::is_valid_name(&request.clip) {
+            return Err(SoundboardErr::InvalidName(request.clip).into());
+        }
+        *self.current_song.write().expect("Can't lock current song!") = Some(Song {
+            id: soundboard::jingle_song_id(&request.clip, request.revision),
+            name: request.clip.clone(),
+            source: request.clip.clone(),
+            artist: None,
+            length: None,
+            live: false,
+            chapters: Vec::new(),
+        });
+        *self.stream_title.write().expect("Can't lock stream title!") = None;
+        *self.trim.write().expect("Can't lock trim!") = None;
+        let instances = self.instances.clone();
+        self.pool.execute(move || {
+            if let Err(e) = Instance::play_jingle_inner(instances, request) {
+                warn!("Can't play jingle: {}", e);
+            }
+        })
     }
 
     /// Send playstate change
@@ -344,12 +822,26 @@ impl Instance {
         }
     }
 
-    /// Send position change
-    fn send_position_update(id: ID, position_ms: u32) {
-        if let Err(e) =
-            callback::send_track_position_update(&TrackPositionUpdate { id, position_ms })
-        {
-            error!("Can't send playback state change: {}", e);
+    /// Returns current playback timing, None without media
+    fn position_update(&self) -> Option<TrackPositionUpdate> {
+        let position_ms = self.player.get_position_ms()?;
+        Some(TrackPositionUpdate {
+            id: self.id,
+            position_ms,
+            timestamp_ms: Utc::now().timestamp_millis(),
+            rate: self.player.get_rate(),
+            paused: !self.player.is_playing(),
+        })
+    }
+
+    /// Send playback timing, on start, pause & seek
+    fn send_position_update(&self) {
+        let update = match self.position_update() {
+            Some(v) => v,
+            None => return,
+        };
+        if let Err(e) = callback::send_track_position_update(&update) {
+            error!("Can't send position update: {}", e);
         }
     }
 
@@ -368,18 +860,16 @@ impl Instance {
             v
         } else {
             debug!("No cache entry for {}", song_id);
-            let track = ytdl.get_url_info(source.as_str())?;
-            let track = match track.get(0) {
-                Some(t) => t,
+            // shares running resolves of the same source
+            let songs = ytdl_worker::resolve(cache.clone(), &ytdl, source.as_str())?;
+            let first = match songs.get(0) {
+                Some(v) => v.id.clone(),
                 None => return Err(InstanceErr::InvalidSource(source).into()),
             };
-
-            let url_temp = match track.best_audio_format(SETTINGS.ytdl.min_audio_bitrate) {
-                Some(v) => v.url.clone(),
+            match cache.get(&song_id).or_else(|| cache.get(&first)) {
+                Some(v) => v,
                 None => return Err(InstanceErr::NoAudioTrack(source).into()),
-            };
-            cache.upsert(song_id, url_temp.clone());
-            url_temp
+            }
         };
 
         let instances = match instances.upgrade() {
@@ -391,6 +881,8 @@ impl Instance {
             if !retry {
                 inst.reset_error_retries();
             }
+            inst.player.stop_clips();
+            inst.player.fade_out();
             inst.player.set_uri(audio_url.as_str());
         } else {
             warn!("Instance gone, ignoring playback resolver..");
@@ -398,6 +890,47 @@ impl Instance {
 
         Ok(())
     }
+
+    /// Inner function, blocking
+    /// Fetches the clip & mixes it in
+    fn play_jingle_inner(instances: WInstances, request: JinglePlayReq) -> Fallible<()> {
+        let path = soundboard::fetch(&request.clip, request.revision)?.canonicalize()?;
+        let instances = match instances.upgrade() {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+        let lock = instances.read().expect("Can't read instances!");
+        let song_id = soundboard::jingle_song_id(&request.clip, request.revision);
+        match lock.get(&request.id) {
+            Some(inst) if inst.is_current_song(&song_id) => {
+                inst.reset_error_retries();
+                inst.player.stop_clips();
+                inst.player.fade_out();
+                inst.player.set_file(&path)?;
+            }
+            Some(_) => debug!("Track changed, dropping jingle {}", request.clip),
+            None => warn!("Instance gone, ignoring jingle.."),
+        }
+        Ok(())
+    }
+
+    fn play_clip_inner(instances: WInstances, request: ClipPlayReq) -> Fallible<()> {
+        let path = soundboard::fetch(&request.clip, request.revision)?;
+        let instances = match instances.upgrade() {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+        let lock = instances.read().expect("Can't read instances!");
+        match lock.get(&request.id) {
+            Some(inst) if inst.player.is_playing() => {
+                inst.player.play_clip(&path, request.volume)?;
+                debug!("Instance {} playing clip {}", request.id, request.clip);
+            }
+            Some(_) => debug!("Playback stopped, dropping clip {}", request.clip),
+            None => warn!("Instance gone, ignoring clip.."),
+        }
+        Ok(())
+    }
 }
 
 /// Format time by
@@ -409,28 +942,11 @@ fn format_time(length: Option<u32>) -> String {
     }
 }
 
-/// Instance type for different VoIP systems
-pub enum InstanceType {
-    Teamspeak(Teamspeak),
-}
-
-/// Teamspeak specific VoIP instance
-pub struct Teamspeak {
-    pub ts: TSInstance,
-    pub sink: NullSink,
-    pub mute_sink: Arc<NullSink>,
-}
-
-impl Teamspeak {
-    /// Setup call on successfull connection
-    /// process_id is the real ts id, as the xvfb wrapper doesn't count
-    pub fn on_connected(&self, process_id: u32) -> Fallible<()> {
-        trace!("Setting monitor for ts");
-        self.sink.set_monitor_for_process(process_id)?;
-        trace!("Setting sink for ts");
-        self.mute_sink.set_sink_for_process(process_id)?;
-        Ok(())
-    }
+/// Backoff for live source reconnects, doubles per retry
+#[inline(always)]
+fn live_backoff(retries: usize) -> Duration {
+    let secs = 1u64 << min(retries, 6);
+    Duration::from_secs(min(secs, LIVE_BACKOFF_MAX_SECS))
 }
 
 /// Convert PlaybackState from Player to PlayerState from Types
@@ -443,6 +959,46 @@ fn playback_to_public_state(state: PlaybackState) -> Playstate {
     }
 }
 
+/// Send positions of all playing instances in one event, as resync for consumers  
+/// Disabled by setting the interval to 0
+pub fn create_position_heartbeat(runtime: &mut runtime::Runtime, instances: Instances) {
+    let interval = SETTINGS.main.position_heartbeat_secs;
+    if interval == 0 {
+        return;
+    }
+    let heartbeat = Interval::new_interval(Duration::from_secs(u64::from(interval)))
+        .for_each(move |_| {
+            let positions: Vec<_> = instances
+                .read()
+                .expect("Can't read instances!")
+                .values()
+                .filter(|v| v.is_playing())
+                .filter_map(|v| v.position_update())
+                .collect();
+            if !positions.is_empty() {
+                if let Err(e) = callback::send_position_batch(&PositionBatch { positions }) {
+                    warn!("Can't send position heartbeat: {}", e);
+                }
+            }
+            Ok(())
+        })
+        .map_err(|e| warn!("Position heartbeat timer error: {}", e));
+    runtime.spawn(heartbeat);
+}
+
+/// Apply trim points of playing instances
+pub fn create_trim_watcher(runtime: &mut runtime::Runtime, instances: Instances) {
+    let watcher = Interval::new_interval(TRIM_CHECK_INTERVAL)
+        .for_each(move |_| {
+            for inst in instances.read().expect("Can't read instances!").values() {
+                inst.check_trim();
+            }
+            Ok(())
+        })
+        .map_err(|e| warn!("Trim watcher timer error: {}", e));
+    runtime.spawn(watcher);
+}
+
 /// Register event handler for playback in daemon
 pub fn create_playback_event_handler(
     runtime: &mut runtime::Runtime,
@@ -480,15 +1036,33 @@ pub fn create_playback_event_handler(
                     .expect("Can't read instance!")
                     .get(&event.id)
                 {
+                    if state == PlaybackState::Playing {
+                        v.playback_started();
+                    }
+                    v.recording_state(state == PlaybackState::Playing);
                     v.send_playstate_change(playback_to_public_state(state));
+                    v.send_position_update();
                 }
             }
-            PlayerEventType::PositionUpdated(time) => {
-                if let Some(time) = time.mseconds() {
-                    Instance::send_position_update(event.id, time as u32);
+            PlayerEventType::SeekDone(time) => {
+                trace!("Seek done for {} at {}", event.id, time);
+                if let Some(v) = instances
+                    .read()
+                    .expect("Can't read instance!")
+                    .get(&event.id)
+                {
+                    v.send_position_update();
+                }
+            }
+            PlayerEventType::MediaInfoUpdated(title) => {
+                if let Some(v) = instances
+                    .read()
+                    .expect("Can't read instance!")
+                    .get(&event.id)
+                {
+                    v.media_info_updated(title);
                 }
             }
-            PlayerEventType::MediaInfoUpdated => (), // silence
             PlayerEventType::Error(e) => {
                 let mut retry = false;
                 if let Some(err) = e.kind::<ResourceError>() {
@@ -515,16 +1089,12 @@ pub fn create_playback_event_handler(
                         }
                     }
                 }
-                if retry {
-                    if let Some(v) = instances
-                        .read()
-                        .expect("Can't read instance!")
-                        .get(&event.id)
-                    {
-                        if let Err(e) = v.force_song_retry() {
-                            warn!("Couldn't restart playback: {}", e);
-                        }
-                    }
+                if let Some(v) = instances
+                    .read()
+                    .expect("Can't read instance!")
+                    .get(&event.id)
+                {
+                    v.playback_error(retry);
                 }
             }
         }
@@ -38,9 +38,13 @@ use self::instance::*;
 use api;
 use audio::{self, CContext, CMainloop, NullSink};
 use cache::Cache;
+use mumble::Mumble;
 use playback::{PlaybackSender, Player, PlayerEvent};
-use ts::TSInstance;
-use yamba_types::models::{self, SongID, TSSettings};
+use pool::WorkerPool;
+use stream::{self, HttpStream};
+use ts::Teamspeak;
+use voip::VoipSpawn;
+use yamba_types::models::{self, SongID};
 use ytdl::YtDL;
 use ytdl_worker;
 use SETTINGS;
@@ -68,14 +72,15 @@ pub struct InstanceBase {
     pub default_sink: Arc<NullSink>,
     pub ytdl: Arc<YtDL>,
     pub cache: SongCache,
-    pub controller: ytdl_worker::Controller,
+    pub lanes: ytdl_worker::Lanes,
     pub w_instances: WInstances,
     pub heartbeat: HeartbeatMap,
+    pub pool: WorkerPool,
 }
 
 impl InstanceDataProvider for InstanceBase {
-    fn get_controller(&self) -> &ytdl_worker::Controller {
-        &self.controller
+    fn get_lanes(&self) -> &ytdl_worker::Lanes {
+        &self.lanes
     }
     fn get_ytdl(&self) -> &Arc<YtDL> {
         &self.ytdl
@@ -86,6 +91,9 @@ impl InstanceDataProvider for InstanceBase {
     fn get_weak_instances(&self) -> &WInstances {
         &self.w_instances
     }
+    fn get_pool(&self) -> &WorkerPool {
+        &self.pool
+    }
 }
 
 unsafe impl Send for InstanceBase {}
@@ -135,7 +143,7 @@ pub fn start_runtime() -> Fallible<()> {
 
         let cache = Cache::<SongID, String>::new(&mut rt);
 
-        let controller = ytdl_worker::crate_ytdl_scheduler(
+        let lanes = ytdl_worker::crate_ytdl_scheduler(
             &mut rt,
             ytdl.clone(),
             cache.clone(),
@@ -145,6 +153,15 @@ pub fn start_runtime() -> Fallible<()> {
         ytdl_worker::crate_yt_updater(&mut rt, ytdl.clone());
 
         create_playback_event_handler(&mut rt, player_rx, instances.clone())?;
+        create_position_heartbeat(&mut rt, instances.clone());
+        create_trim_watcher(&mut rt, instances.clone());
+
+        let pool = WorkerPool::new(
+            "playback-resolve",
+            SETTINGS.ytdl.playback_workers as usize,
+            SETTINGS.ytdl.playback_backlog_max as usize,
+            rt.executor(),
+        )?;
 
         let base = InstanceBase {
             player_send: player_tx,
@@ -153,12 +170,14 @@ pub fn start_runtime() -> Fallible<()> {
             default_sink: default_sink,
             ytdl: ytdl,
             cache: cache,
-            controller: controller,
+            lanes,
             w_instances: Arc::downgrade(&instances),
             heartbeat: heartbeat::HeartbeatMap::new(instances.clone(), &mut rt),
+            pool,
         };
 
         api::start_server(&mut rt, instances.clone(), base)?;
+        stream::server::start_server(&mut rt)?;
 
         info!("Daemon initialized");
 
@@ -218,53 +237,54 @@ fn restart() {
 
 /// Create instance
 pub fn create_instance(base: &InstanceBase, inst: models::InstanceLoadReq) -> Fallible<Instance> {
+    let effects = inst.effects;
+    let (rate, preserve_pitch) = (inst.rate, inst.preserve_pitch);
     let inst = match inst.data {
         models::InstanceType::TS(settings) => {
-            create_ts_instance(base, settings, inst.id, inst.volume)?
+            create_voip_instance::<Teamspeak>(base, settings, inst.id, inst.volume, inst.owner)?
+        }
+        models::InstanceType::Mumble(settings) => {
+            create_voip_instance::<Mumble>(base, settings, inst.id, inst.volume, inst.owner)?
+        }
+        models::InstanceType::Stream(settings) => {
+            create_voip_instance::<HttpStream>(base, settings, inst.id, inst.volume, inst.owner)?
         }
     };
+    if let Err(e) = inst.set_effects(&effects) {
+        warn!("Can't apply effects of instance {}: {}", inst.get_id(), e);
+    }
+    if let Err(e) = inst.set_rate(rate, Some(preserve_pitch)) {
+        warn!("Can't apply rate of instance {}: {}", inst.get_id(), e);
+    }
 
     let _ = api::callback::send_instance_state(&models::callback::InstanceStateResponse {
         id: inst.get_id(),
         state: models::callback::InstanceState::Started,
+        reason: None,
     })
     .map_err(|e| warn!("Can't send instance started {}", e));
 
     Ok(inst)
 }
 
-/// Crate instance of type TS
-fn create_ts_instance(
+/// Crate instance with VoIP backend V
+fn create_voip_instance<V: VoipSpawn + 'static>(
     base: &InstanceBase,
-    data: TSSettings,
+    settings: V::Settings,
     id: ID,
     volume: f64,
+    owner: Option<String>,
 ) -> Fallible<Instance> {
     let player = Player::new(base.player_send.clone(), id.clone(), volume)?;
-    let sink = NullSink::new(
-        base.mainloop.clone(),
-        base.context.clone(),
-        format!("yambasink{}", &id),
-    )?;
-    player.set_pulse_device(sink.get_sink_name())?;
-    let voip = InstanceType::Teamspeak(Teamspeak {
-        ts: TSInstance::spawn(
-            &data,
-            &id,
-            &SETTINGS.main.api_internal_bind_ip,
-            &SETTINGS.main.api_internal_bind_port,
-            &SETTINGS.main.api_jsonrpc_ip,
-            &SETTINGS.main.api_jsonrpc_port,
-        )?,
-        sink,
-        mute_sink: base.default_sink.clone(),
-    });
+    let voip = V::spawn(base, settings, id)?;
+    voip.route_audio(&player)?;
 
     Ok(Instance::new(
         id,
-        voip,
+        Box::new(voip),
         base,
         player,
         base.heartbeat.clone(),
+        owner,
     ))
 }
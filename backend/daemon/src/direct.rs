@@ -0,0 +1,566 @@
+/*
+ *  This file is part of yamba.
+ *
+ *  yamba is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  yamba is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with yamba.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use failure::Fallible;
+use metrohash::MetroHash128;
+use reqwest::Url;
+
+use std::hash::Hasher;
+
+use http;
+use yamba_types::models::Song;
+use SETTINGS;
+
+/// Direct resolver for plain audio file URLs, bypassing ytdl
+
+/// Bytes to read for tag & format detection
+const PROBE_BYTES: u64 = 64 * 1024;
+/// File extensions considered as direct audio links
+const AUDIO_EXTENSIONS: [&'static str; 9] = [
+    "mp3", "ogg", "oga", "opus", "flac", "m4a", "aac", "wav", "webm",
+];
+
+/// Tags read from the audio file
+#[derive(Debug, Default, PartialEq)]
+struct Tags {
+    title: Option<String>,
+    artist: Option<String>,
+}
+
+impl Tags {
+    fn is_complete(&self) -> bool {
+        self.title.is_some() && self.artist.is_some()
+    }
+}
+
+/// Returns whether url should be resolved directly
+/// True for URLs with an audio file extension or configured direct hosts
+pub fn is_candidate(url: &str) -> bool {
+    let url = match Url::parse(url) {
+        Ok(v) => v,
+        Err(_) => return false,
+    };
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return false;
+    }
+    if let Some(host) = url.host_str() {
+        if SETTINGS
+            .ytdl
+            .direct_hosts
+            .iter()
+            .any(|h| h.eq_ignore_ascii_case(host))
+        {
+            return true;
+        }
+    }
+    match url.path().rsplit('.').next() {
+        Some(ext) => AUDIO_EXTENSIONS.iter().any(|v| v.eq_ignore_ascii_case(ext)),
+        None => false,
+    }
+}
+
+/// Resolve url directly
+/// Returns the song and the audio URL to play or None if this isn't a direct audio link
+pub fn resolve(url: &str) -> Fallible<Option<(Song, String)>> {
+    let probe = http::probe(url, PROBE_BYTES)?;
+    if !probe.status.is_success() {
+        debug!("Direct probe of {} returned {}", url, probe.status);
+        return Ok(None);
+    }
+    let is_audio = match probe.content_type {
+        Some(ref v) => is_audio_mime(v) || (is_generic_mime(v) && has_audio_magic(&probe.head)),
+        None => has_audio_magic(&probe.head),
+    };
+    if !is_audio {
+        trace!("No direct audio for {}: {:?}", url, probe.content_type);
+        return Ok(None);
+    }
+
+    let tags = read_tags(&probe.head);
+    let name = tags
+        .title
+        .or_else(|| probe.icy_name.clone())
+        .unwrap_or_else(|| name_from_url(url));
+    let song = Song {
+        id: song_id(url),
+        name,
+        source: url.to_string(),
+        artist: tags.artist,
+        length: None,
+        live: probe.icy,
+        chapters: Vec::new(),
+    };
+    debug!("Resolved {} directly: {:?}", url, song);
+    Ok(Some((song, probe.url)))
+}
+
+/// Calculate song ID for direct URLs
+fn song_id(url: &str) -> String {
+    let mut hasher = MetroHash128::default();
+    hasher.write(url.as_bytes());
+    let (h1, h2) = hasher.finish128();
+    format!("{:x}{:x}", h1, h2)
+}
+
+/// Check for audio media types
+fn is_audio_mime(mime: &str) -> bool {
+    mime.starts_with("audio/") || mime == "application/ogg"
+}
+
+/// Check for generic media types, used by misconfigured file servers
+fn is_generic_mime(mime: &str) -> bool {
+    mime == "application/octet-stream" || mime == "binary/octet-stream"
+}
+
+/// Check for magic bytes of common audio containers
+fn has_audio_magic(head: &[u8]) -> bool {
+    head.starts_with(b"ID3")
+        || head.starts_with(b"OggS")
+        || head.starts_with(b"fLaC")
+        || (head.len() >= 12 && &head[0..4] == b"RIFF" && &head[8..12] == b"WAVE")
+        || (head.len() >= 2 && head[0] == 0xFF && head[1] & 0xE0 == 0xE0)
+}
+
+/// Song name from last path segment without extension
+fn name_from_url(url: &str) -> String {
+    let segment = Url::parse(url).ok().and_then(|u| {
+        u.path_segments()
+            .and_then(|s| s.filter(|v| !v.is_empty()).last().map(|v| v.to_string()))
+    });
+    match segment {
+        Some(v) => {
+            let decoded = percent_decode(&v);
+            match decoded.rfind('.') {
+                Some(i) if i > 0 => decoded[..i].to_string(),
+                _ => decoded,
+            }
+        }
+        None => url.to_string(),
+    }
+}
+
+/// Percent-decode URL path segment, invalid sequences are kept
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = ::std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+            if let Ok(v) = u8::from_str_radix(hex, 16) {
+                out.push(v);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Read tags from start of file, supports ID3v2, Ogg Vorbis/Opus & FLAC
+fn read_tags(head: &[u8]) -> Tags {
+    if head.starts_with(b"ID3") {
+        read_id3v2(head)
+    } else if head.starts_with(b"fLaC") {
+        read_flac(head)
+    } else if head.starts_with(b"OggS") {
+        read_ogg(head)
+    } else {
+        Tags::default()
+    }
+}
+
+/// Decode 28 bit syncsafe integer
+fn syncsafe(b: &[u8]) -> usize {
+    ((b[0] as usize & 0x7F) << 21)
+        | ((b[1] as usize & 0x7F) << 14)
+        | ((b[2] as usize & 0x7F) << 7)
+        | (b[3] as usize & 0x7F)
+}
+
+/// Read ID3v2.2 - ID3v2.4 title & artist frames
+fn read_id3v2(data: &[u8]) -> Tags {
+    let mut tags = Tags::default();
+    if data.len() < 10 {
+        return tags;
+    }
+    let version = data[3];
+    let flags = data[5];
+    // unsynchronisation & extended headers are not supported
+    if flags & 0xC0 != 0 {
+        return tags;
+    }
+    let end = (10 + syncsafe(&data[6..10])).min(data.len());
+    let (id_len, header_len) = match version {
+        2 => (3, 6),
+        3 | 4 => (4, 10),
+        _ => return tags,
+    };
+    let mut pos = 10;
+    while pos + header_len <= end && !tags.is_complete() {
+        let frame = &data[pos..pos + header_len];
+        if frame[0] == 0 {
+            // padding
+            break;
+        }
+        let size = match version {
+            2 => (frame[3] as usize) << 16 | (frame[4] as usize) << 8 | frame[5] as usize,
+            3 => {
+                (frame[4] as usize) << 24
+                    | (frame[5] as usize) << 16
+                    | (frame[6] as usize) << 8
+                    | frame[7] as usize
+            }
+            _ => syncsafe(&frame[4..8]),
+        };
+        let start = pos + header_len;
+        if start + size > end {
+            break;
+        }
+        let content = &data[start..start + size];
+        match &frame[..id_len] {
+            b"TIT2" | b"TT2" => tags.title = decode_id3_text(content),
+            b"TPE1" | b"TP1" => tags.artist = decode_id3_text(content),
+            _ => (),
+        }
+        pos = start + size;
+    }
+    tags
+}
+
+/// Decode ID3 text frame content
+fn decode_id3_text(content: &[u8]) -> Option<String> {
+    if content.is_empty() {
+        return None;
+    }
+    let text = &content[1..];
+    let value = match content[0] {
+        0 => text.iter().map(|&c| c as char).collect(),
+        1 | 2 => {
+            let mut big_endian = content[0] == 2;
+            let mut text = text;
+            if text.len() >= 2 && (text[0..2] == [0xFE, 0xFF] || text[0..2] == [0xFF, 0xFE]) {
+                big_endian = text[0] == 0xFE;
+                text = &text[2..];
+            }
+            let units: Vec<u16> = text
+                .chunks(2)
+                .filter(|c| c.len() == 2)
+                .map(|c| match big_endian {
+                    true => (c[0] as u16) << 8 | c[1] as u16,
+                    false => (c[1] as u16) << 8 | c[0] as u16,
+                })
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        3 => String::from_utf8_lossy(text).into_owned(),
+        _ => return None,
+    };
+    clean_tag(&value)
+}
+
+/// Trim null terminators & whitespace, returns None for empty values
+fn clean_tag(value: &str) -> Option<String> {
+    // multiple values are null separated, use the first one
+    let value = value.split('\0').find(|v| !v.trim().is_empty())?.trim();
+    match value.is_empty() {
+        true => None,
+        false => Some(value.to_string()),
+    }
+}
+
+/// Read little endian u32
+fn read_u32_le(data: &[u8], pos: usize) -> Option<usize> {
+    if pos + 4 > data.len() {
+        return None;
+    }
+    Some(
+        data[pos] as usize
+            | (data[pos + 1] as usize) << 8
+            | (data[pos + 2] as usize) << 16
+            | (data[pos + 3] as usize) << 24,
+    )
+}
+
+/// Read vorbis comment block, used by Ogg Vorbis, Opus & FLAC
+fn read_vorbis_comment(data: &[u8]) -> Tags {
+    let mut tags = Tags::default();
+    let vendor_len = match read_u32_le(data, 0) {
+        Some(v) => v,
+        None => return tags,
+    };
+    let mut pos = 4 + vendor_len;
+    let amount = match read_u32_le(data, pos) {
+        Some(v) => v,
+        None => return tags,
+    };
+    pos += 4;
+    for _ in 0..amount {
+        let len = match read_u32_le(data, pos) {
+            Some(v) => v,
+            None => break,
+        };
+        pos += 4;
+        if pos + len > data.len() {
+            break;
+        }
+        let comment = String::from_utf8_lossy(&data[pos..pos + len]);
+        pos += len;
+        let mut split = comment.splitn(2, '=');
+        let (key, value) = match (split.next(), split.next()) {
+            (Some(k), Some(v)) => (k, v),
+            _ => continue,
+        };
+        if key.eq_ignore_ascii_case("TITLE") && tags.title.is_none() {
+            tags.title = clean_tag(value);
+        } else if key.eq_ignore_ascii_case("ARTIST") && tags.artist.is_none() {
+            tags.artist = clean_tag(value);
+        }
+        if tags.is_complete() {
+            break;
+        }
+    }
+    tags
+}
+
+/// Read FLAC vorbis comment metadata block
+fn read_flac(data: &[u8]) -> Tags {
+    let mut pos = 4;
+    while pos + 4 <= data.len() {
+        let last = data[pos] & 0x80 != 0;
+        let block_type = data[pos] & 0x7F;
+        let len =
+            (data[pos + 1] as usize) << 16 | (data[pos + 2] as usize) << 8 | data[pos + 3] as usize;
+        pos += 4;
+        if block_type == 4 {
+            let end = (pos + len).min(data.len());
+            return read_vorbis_comment(&data[pos..end]);
+        }
+        if last {
+            break;
+        }
+        pos += len;
+    }
+    Tags::default()
+}
+
+/// Read comment header of Ogg Vorbis & Opus
+/// Assumes the comment packet to start in the probed data
+fn read_ogg(data: &[u8]) -> Tags {
+    let markers: [&[u8]; 2] = [b"\x03vorbis", b"OpusTags"];
+    for marker in markers.iter() {
+        if let Some(pos) = find(data, marker) {
+            return read_vorbis_comment(&data[pos + marker.len()..]);
+        }
+    }
+    Tags::default()
+}
+
+/// Find position of needle in data
+fn find(data: &[u8], needle: &[u8]) -> Option<usize> {
+    data.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serve a single http response on a local port, returns the base url
+    fn serve(header: &'static str, body: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 4096];
+            let _ = stream.read(&mut buf).unwrap();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\n{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                header,
+                body.len()
+            );
+            // client may close early, not reading the whole body
+            let _ = stream.write_all(response.as_bytes());
+            let _ = stream.write_all(&body);
+        });
+        format!("http://{}", addr)
+    }
+
+    /// Build ID3v2.3 tag with title & artist
+    fn id3_tag(title: &str, artist: &str) -> Vec<u8> {
+        let mut frames = Vec::new();
+        for (id, value) in [(b"TIT2", title), (b"TPE1", artist)].iter() {
+            frames.extend_from_slice(&id[..]);
+            let size = value.len() as u32 + 1;
+            frames.extend_from_slice(&[
+                (size >> 24) as u8,
+                (size >> 16) as u8,
+                (size >> 8) as u8,
+                size as u8,
+                0,
+                0,
+                3,
+            ]);
+            frames.extend_from_slice(value.as_bytes());
+        }
+        let size = frames.len();
+        let mut tag = vec![
+            b'I',
+            b'D',
+            b'3',
+            3,
+            0,
+            0,
+            (size >> 21 & 0x7F) as u8,
+            (size >> 14 & 0x7F) as u8,
+            (size >> 7 & 0x7F) as u8,
+            (size & 0x7F) as u8,
+        ];
+        tag.extend(frames);
+        tag.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+        tag
+    }
+
+    /// Build vorbis comment block
+    fn vorbis_comment(comments: &[&str]) -> Vec<u8> {
+        let mut data = Vec::new();
+        let push_u32 = |data: &mut Vec<u8>, v: usize| {
+            data.extend_from_slice(&[v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8])
+        };
+        push_u32(&mut data, 4);
+        data.extend_from_slice(b"test");
+        push_u32(&mut data, comments.len());
+        for c in comments {
+            push_u32(&mut data, c.len());
+            data.extend_from_slice(c.as_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_is_candidate() {
+        assert!(is_candidate("https://example.com/music/a.mp3"));
+        assert!(is_candidate("http://example.com/b.OPUS"));
+        assert!(!is_candidate("https://www.youtube.com/watch?v=abc"));
+        assert!(!is_candidate("ftp://example.com/a.mp3"));
+        assert!(!is_candidate("no url"));
+    }
+
+    #[test]
+    fn test_name_from_url() {
+        assert_eq!(
+            "My Song",
+            name_from_url("http://example.com/dir/My%20Song.mp3")
+        );
+        assert_eq!("track", name_from_url("http://example.com/track/"));
+        assert_eq!("100%", name_from_url("http://example.com/100%"));
+    }
+
+    #[test]
+    fn test_audio_magic() {
+        assert!(has_audio_magic(b"ID3\x03\x00"));
+        assert!(has_audio_magic(b"OggS\x00"));
+        assert!(has_audio_magic(&[0xFF, 0xFB, 0x90]));
+        assert!(!has_audio_magic(b"<html>"));
+    }
+
+    #[test]
+    fn test_id3v2() {
+        let tags = read_tags(&id3_tag("Title", "Artist"));
+        assert_eq!(Some("Title".to_string()), tags.title);
+        assert_eq!(Some("Artist".to_string()), tags.artist);
+    }
+
+    #[test]
+    fn test_id3_utf16() {
+        let content = [1, 0xFF, 0xFE, b'A', 0, b'b', 0, 0, 0];
+        assert_eq!(Some("Ab".to_string()), decode_id3_text(&content));
+    }
+
+    #[test]
+    fn test_ogg_opus() {
+        let mut data = b"OggS\x00\x02".to_vec();
+        data.extend_from_slice(b"OpusTags");
+        data.extend(vorbis_comment(&["title=Opus Title", "ARTIST=Someone"]));
+        let tags = read_tags(&data);
+        assert_eq!(Some("Opus Title".to_string()), tags.title);
+        assert_eq!(Some("Someone".to_string()), tags.artist);
+    }
+
+    #[test]
+    fn test_flac() {
+        let comment = vorbis_comment(&["TITLE=Flac"]);
+        let mut data = b"fLaC".to_vec();
+        // streaminfo
+        data.extend_from_slice(&[0, 0, 0, 2, 0, 0]);
+        data.extend_from_slice(&[
+            0x84,
+            (comment.len() >> 16) as u8,
+            (comment.len() >> 8) as u8,
+            comment.len() as u8,
+        ]);
+        data.extend(comment);
+        let tags = read_tags(&data);
+        assert_eq!(Some("Flac".to_string()), tags.title);
+        assert_eq!(None, tags.artist);
+    }
+
+    #[test]
+    fn test_resolve_mp3() {
+        let base = serve("Content-Type: audio/mpeg", id3_tag("Local", "Server"));
+        let url = format!("{}/file.mp3", base);
+        let (song, audio_url) = resolve(&url).unwrap().unwrap();
+        assert_eq!("Local", song.name);
+        assert_eq!(Some("Server".to_string()), song.artist);
+        assert_eq!(url, song.source);
+        assert_eq!(url, audio_url);
+        assert!(!song.live);
+    }
+
+    #[test]
+    fn test_resolve_octet_stream() {
+        let base = serve(
+            "Content-Type: application/octet-stream",
+            vec![0xFF, 0xFB, 0x90, 0x00],
+        );
+        let url = format!("{}/files/Some%20File.mp3", base);
+        let (song, _) = resolve(&url).unwrap().unwrap();
+        assert_eq!("Some File", song.name);
+        assert_eq!(None, song.artist);
+    }
+
+    #[test]
+    fn test_resolve_no_audio() {
+        let base = serve("Content-Type: text/html", b"<html></html>".to_vec());
+        assert!(resolve(&format!("{}/page.mp3", base)).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_resolve_icy() {
+        let base = serve(
+            "Content-Type: audio/mpeg\r\nicy-name: Radio Test",
+            vec![0xFF, 0xFB, 0x90, 0x00],
+        );
+        let (song, _) = resolve(&format!("{}/stream.mp3", base)).unwrap().unwrap();
+        assert!(song.live);
+        assert_eq!("Radio Test", song.name);
+    }
+}
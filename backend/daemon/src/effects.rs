@@ -0,0 +1,178 @@
+/*
+ *  This file is part of yamba.
+ *
+ *  yamba is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  yamba is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with yamba.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use failure::Fallible;
+use gst;
+use gst::prelude::*;
+use yamba_types::models::{AudioEffects, Equalizer, EqualizerPreset, EQUALIZER_BANDS};
+
+use std::sync::RwLock;
+
+use playback::{make_element, PlaybackErr};
+
+/// Effects chain of the music: equalizer, compressor/limiter & mono downmix  
+/// Elements stay linked with neutral settings when disabled, so effects can change during playback
+
+/// Caps forcing a mono downmix
+const MONO_CAPS: &'static str = "audio/x-raw,channels=1";
+/// Caps keeping the channels
+const ANY_CAPS: &'static str = "audio/x-raw";
+
+type Gains = [f64; EQUALIZER_BANDS];
+
+pub struct EffectsChain {
+    equalizer: gst::Element,
+    dynamic: gst::Element,
+    mono: gst::Element,
+    /// All elements in link order
+    elements: Vec<gst::Element>,
+    current: RwLock<AudioEffects>,
+}
+
+impl EffectsChain {
+    /// Create chain with all effects disabled
+    pub fn new() -> Fallible<EffectsChain> {
+        let equalizer = make_element("equalizer-10bands")?;
+        let dynamic = make_element("audiodynamic")?;
+        let downmix = make_element("audioconvert")?;
+        let mono = make_element("capsfilter")?;
+        // back to the channels of the mixer
+        let upmix = make_element("audioconvert")?;
+        let chain = EffectsChain {
+            elements: vec![
+                equalizer.clone(),
+                dynamic.clone(),
+                downmix,
+                mono.clone(),
+                upmix,
+            ],
+            equalizer,
+            dynamic,
+            mono,
+            current: RwLock::new(AudioEffects::default()),
+        };
+        chain.apply(&AudioEffects::default())?;
+        Ok(chain)
+    }
+
+    /// Returns elements in link order
+    pub fn elements(&self) -> &[gst::Element] {
+        &self.elements
+    }
+
+    /// Returns current effects
+    pub fn get(&self) -> AudioEffects {
+        self.current.read().expect("Can't read effects!").clone()
+    }
+
+    /// Apply effects, values have to be validated
+    pub fn apply(&self, effects: &AudioEffects) -> Fallible<()> {
+        for (i, gain) in band_gains(effects.equalizer.as_ref()).iter().enumerate() {
+            self.equalizer
+                .set_property(&format!("band{}", i), gain)
+                .map_err(|_| PlaybackErr::GST("Couldn't set equalizer band!"))?;
+        }
+        // ratio 1 is a passthrough
+        let (threshold, ratio) = match effects.compressor {
+            Some(ref v) => (v.threshold as f32, v.ratio as f32),
+            None => (1.0f32, 1.0f32),
+        };
+        self.dynamic
+            .set_property("threshold", &threshold)
+            .map_err(|_| PlaybackErr::GST("Couldn't set compressor threshold!"))?;
+        self.dynamic
+            .set_property("ratio", &ratio)
+            .map_err(|_| PlaybackErr::GST("Couldn't set compressor ratio!"))?;
+        let caps = gst::Caps::from_string(match effects.mono {
+            true => MONO_CAPS,
+            false => ANY_CAPS,
+        })
+        .ok_or(PlaybackErr::GST("Invalid mono caps"))?;
+        self.mono
+            .set_property("caps", &caps)
+            .map_err(|_| PlaybackErr::GST("Couldn't set mono caps!"))?;
+        *self.current.write().expect("Can't write effects!") = effects.clone();
+        Ok(())
+    }
+}
+
+/// Returns equalizer gains in dB per band, flat if disabled
+pub fn band_gains(equalizer: Option<&Equalizer>) -> Gains {
+    match equalizer {
+        None => [0.0; EQUALIZER_BANDS],
+        Some(Equalizer::Preset(preset)) => preset_gains(*preset),
+        Some(Equalizer::Bands(bands)) => {
+            let mut gains = [0.0; EQUALIZER_BANDS];
+            for (gain, band) in gains.iter_mut().zip(bands.iter()) {
+                *gain = *band;
+            }
+            gains
+        }
+    }
+}
+
+/// Gains of presets, bands from 29Hz to 15kHz
+fn preset_gains(preset: EqualizerPreset) -> Gains {
+    match preset {
+        EqualizerPreset::Flat => [0.0; EQUALIZER_BANDS],
+        EqualizerPreset::BassBoost => [6.0, 5.0, 4.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+        EqualizerPreset::Voice => [-6.0, -4.0, -2.0, 0.0, 2.0, 3.0, 3.0, 2.0, 0.0, -2.0],
+        EqualizerPreset::Treble => [0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 2.0, 4.0, 5.0, 6.0],
+        EqualizerPreset::Loudness => [5.0, 3.0, 0.0, -1.0, -2.0, -1.0, 0.0, 2.0, 4.0, 5.0],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use yamba_types::models::Compressor;
+
+    #[test]
+    fn test_band_gains() {
+        assert_eq!([0.0; EQUALIZER_BANDS], band_gains(None));
+        assert_eq!(
+            preset_gains(EqualizerPreset::Voice),
+            band_gains(Some(&Equalizer::Preset(EqualizerPreset::Voice)))
+        );
+        let bands = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0];
+        assert_eq!(
+            [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0],
+            band_gains(Some(&Equalizer::Bands(bands)))
+        );
+    }
+
+    #[test]
+    fn test_validation() {
+        let mut effects = AudioEffects::default();
+        assert!(effects.is_valid());
+        effects.equalizer = Some(Equalizer::Bands(vec![0.0; 3]));
+        assert!(!effects.is_valid());
+        effects.equalizer = Some(Equalizer::Bands(vec![13.0; EQUALIZER_BANDS]));
+        assert!(!effects.is_valid());
+        effects.equalizer = Some(Equalizer::Preset(EqualizerPreset::BassBoost));
+        effects.compressor = Some(Compressor {
+            threshold: 0.5,
+            ratio: 2.0,
+        });
+        assert!(!effects.is_valid());
+        effects.compressor = Some(Compressor {
+            threshold: 0.5,
+            ratio: 0.0,
+        });
+        assert!(effects.is_valid());
+    }
+}
@@ -0,0 +1,225 @@
+/*
+ *  This file is part of yamba.
+ *
+ *  yamba is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  yamba is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with yamba.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use failure::Fallible;
+use gst;
+use gst::prelude::*;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use playback::make_element;
+
+/// Volume ramps of the music, for smooth volume changes & fades  
+/// Ramps are stepped property updates, a newer ramp cancels the running one  
+/// Background ramps run on one ramp thread per fader
+
+/// Time between ramp steps
+const STEP: Duration = Duration::from_millis(20);
+
+/// Callback after a finished background ramp
+type Done = Box<dyn FnOnce() + Send + 'static>;
+
+/// Background ramp request
+struct Ramp {
+    level: f64,
+    duration: Duration,
+    generation: usize,
+    done: Option<Done>,
+}
+
+#[derive(Clone)]
+pub struct Fader {
+    state: Arc<FadeState>,
+    /// Background ramps, the ramp thread stops once all faders are dropped
+    ramps: Arc<Mutex<Sender<Ramp>>>,
+}
+
+struct FadeState {
+    element: gst::Element,
+    /// Current level of the element
+    level: RwLock<f64>,
+    /// Level after the running ramp
+    target: RwLock<f64>,
+    /// Incremented per ramp, running ramps stop on change
+    generation: AtomicUsize,
+}
+
+impl Fader {
+    /// Create fader at level
+    pub fn new(level: f64) -> Fallible<Fader> {
+        let state = Arc::new(FadeState {
+            element: make_element("volume")?,
+            level: RwLock::new(level),
+            target: RwLock::new(level),
+            generation: AtomicUsize::new(0),
+        });
+        state.set_level(level);
+        let (sender, receiver) = channel();
+        let ramp_state = state.clone();
+        thread::Builder::new()
+            .name(String::from("fader"))
+            .spawn(move || ramp_worker(ramp_state, receiver))?;
+        Ok(Fader {
+            state,
+            ramps: Arc::new(Mutex::new(sender)),
+        })
+    }
+
+    /// Returns the volume element
+    pub fn element(&self) -> &gst::Element {
+        &self.state.element
+    }
+
+    /// Returns level after the running ramp
+    pub fn get(&self) -> f64 {
+        *self.state.target.read().expect("Can't read fader target!")
+    }
+
+    /// Set level instantly, cancels running ramps
+    pub fn set(&self, level: f64) {
+        self.state.start(level);
+        self.state.set_level(level);
+    }
+
+    /// Ramp to level over duration, blocking  
+    /// Returns false if cancelled by another ramp
+    pub fn ramp(&self, level: f64, duration: Duration) -> bool {
+        let generation = self.state.start(level);
+        self.state.run(level, duration, generation)
+    }
+
+    /// Ramp to level over duration in the background
+    pub fn ramp_background(&self, level: f64, duration: Duration) {
+        self.queue(level, duration, None);
+    }
+
+    /// Ramp to level over duration in the background  
+    /// Runs done after the ramp, unless it got cancelled
+    pub fn ramp_then<F>(&self, level: f64, duration: Duration, done: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.queue(level, duration, Some(Box::new(done)));
+    }
+
+    /// Hand ramp to the ramp thread, cancels the running one
+    fn queue(&self, level: f64, duration: Duration, done: Option<Done>) {
+        if ramp_steps(duration) == 1 {
+            self.set(level);
+            if let Some(done) = done {
+                done();
+            }
+            return;
+        }
+        let generation = self.state.start(level);
+        let result = self
+            .ramps
+            .lock()
+            .expect("Can't lock fader ramps!")
+            .send(Ramp {
+                level,
+                duration,
+                generation,
+                done,
+            });
+        if result.is_err() {
+            warn!("Fader ramp thread stopped, setting level");
+            self.state.set_level(level);
+        }
+    }
+}
+
+impl FadeState {
+    /// Start new ramp to level, cancels running ramps  
+    /// Returns generation of the new ramp
+    fn start(&self, level: f64) -> usize {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        *self.target.write().expect("Can't write fader target!") = level;
+        generation
+    }
+
+    /// Step to level, returns false if cancelled
+    fn run(&self, level: f64, duration: Duration, generation: usize) -> bool {
+        let from = *self.level.read().expect("Can't read fader level!");
+        let steps = ramp_steps(duration);
+        for step in 1..=steps {
+            if self.generation.load(Ordering::SeqCst) != generation {
+                return false;
+            }
+            self.set_level(interpolate(from, level, step, steps));
+            if step < steps {
+                thread::sleep(STEP);
+            }
+        }
+        true
+    }
+
+    fn set_level(&self, level: f64) {
+        *self.level.write().expect("Can't write fader level!") = level;
+        if self.element.set_property("volume", &level).is_err() {
+            warn!("Can't set fader volume");
+        }
+    }
+}
+
+/// Run background ramps, skips ramps cancelled while queued
+fn ramp_worker(state: Arc<FadeState>, receiver: Receiver<Ramp>) {
+    for ramp in receiver.iter() {
+        if state.run(ramp.level, ramp.duration, ramp.generation) {
+            if let Some(done) = ramp.done {
+                done();
+            }
+        }
+    }
+}
+
+/// Amount of steps for a ramp, at least one
+fn ramp_steps(duration: Duration) -> u32 {
+    let millis = duration.as_secs() * 1000 + u64::from(duration.subsec_millis());
+    let step = STEP.as_secs() * 1000 + u64::from(STEP.subsec_millis());
+    (millis / step).max(1) as u32
+}
+
+/// Level at step of a linear ramp
+fn interpolate(from: f64, to: f64, step: u32, steps: u32) -> f64 {
+    from + (to - from) * f64::from(step) / f64::from(steps)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ramp_steps() {
+        assert_eq!(1, ramp_steps(Duration::from_millis(0)));
+        assert_eq!(1, ramp_steps(Duration::from_millis(15)));
+        assert_eq!(25, ramp_steps(Duration::from_millis(500)));
+        assert_eq!(50, ramp_steps(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_interpolate() {
+        assert_eq!(0.5, interpolate(0.0, 1.0, 1, 2));
+        assert_eq!(1.0, interpolate(0.0, 1.0, 2, 2));
+        assert_eq!(0.25, interpolate(0.5, 0.0, 1, 2));
+        assert_eq!(0.3, interpolate(0.3, 0.3, 1, 4));
+    }
+}
@@ -17,10 +17,12 @@
 use failure::Fallible;
 use reqwest::header::HeaderMap;
 use reqwest::header::{
-    ACCEPT, ACCEPT_ENCODING, CONNECTION, CONTENT_ENCODING, LOCATION, USER_AGENT,
+    ACCEPT, ACCEPT_ENCODING, CONNECTION, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE,
+    CONTENT_TYPE, LOCATION, RANGE, USER_AGENT,
 };
-use reqwest::{Client, Response};
+use reqwest::{Client, Response, StatusCode};
 use std::fs::File;
+use std::io::Read;
 use std::path::Path;
 use std::time::Duration;
 
@@ -28,6 +30,31 @@ use SETTINGS;
 
 /// Http request abstraction for blocking http requests
 
+/// Header to request ICY stream metadata
+const ICY_METADATA: &'static str = "icy-metadata";
+/// Prefix of ICY response headers (icy-name, icy-br..)
+const ICY_HEADER_PREFIX: &'static str = "icy-";
+/// ICY stream name header
+const ICY_NAME: &'static str = "icy-name";
+
+/// Result of a probe request
+#[derive(Debug)]
+pub struct Probe {
+    /// Final URL after redirects
+    pub url: String,
+    pub status: StatusCode,
+    /// Media type without parameters, lowercase
+    pub content_type: Option<String>,
+    /// Total length of the resource in bytes
+    pub length: Option<u64>,
+    /// Whether this is an Icecast/Shoutcast stream
+    pub icy: bool,
+    /// ICY stream name
+    pub icy_name: Option<String>,
+    /// Start of the body, empty for ICY streams
+    pub head: Vec<u8>,
+}
+
 /// Header type for get requests
 pub enum HeaderType {
     /// Html browser request
@@ -49,6 +76,84 @@ pub fn get_file(url: &str, path: &Path) -> Fallible<u64> {
     Ok(response.copy_to(&mut file)?)
 }
 
+/// Check whether url is an Icecast/Shoutcast stream  
+/// Requests ICY metadata and checks for icy headers, doesn't read the body
+pub fn is_icy_stream(url: &str) -> Fallible<bool> {
+    let client = Client::builder().timeout(Duration::from_secs(10)).build()?;
+    let mut headers = header(HeaderType::Html);
+    headers.insert(ICY_METADATA, "1".parse().unwrap());
+    let res = client.get(url).headers(headers).send()?;
+    let is_icy = res
+        .headers()
+        .keys()
+        .any(|k| k.as_str().starts_with(ICY_HEADER_PREFIX));
+    trace!("ICY stream check for {}: {}", url, is_icy);
+    Ok(is_icy)
+}
+
+/// Probe url via range request, reading at most max_bytes of the body  
+/// Doesn't read the body of ICY streams, as these never end
+pub fn probe(url: &str, max_bytes: u64) -> Fallible<Probe> {
+    let client = Client::builder().timeout(Duration::from_secs(10)).build()?;
+    let mut headers = header(HeaderType::Html);
+    headers.insert(ACCEPT_ENCODING, "identity".parse().unwrap());
+    headers.insert(ICY_METADATA, "1".parse().unwrap());
+    headers.insert(
+        RANGE,
+        format!("bytes=0-{}", max_bytes.saturating_sub(1))
+            .parse()
+            .unwrap(),
+    );
+    let mut res = client.get(url).headers(headers).send()?;
+    debug!("Probe response header: {:?}", res.headers());
+
+    let status = res.status();
+    let content_type = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().to_lowercase());
+    // partial content returns the total length in the range header
+    let length = match status {
+        StatusCode::PARTIAL_CONTENT => res
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit('/').next())
+            .and_then(|v| v.parse::<u64>().ok()),
+        _ => res
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok()),
+    };
+    let icy = res
+        .headers()
+        .keys()
+        .any(|k| k.as_str().starts_with(ICY_HEADER_PREFIX));
+    let icy_name = res
+        .headers()
+        .get(ICY_NAME)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty());
+
+    let mut head = Vec::new();
+    if status.is_success() && !icy {
+        res.by_ref().take(max_bytes).read_to_end(&mut head)?;
+    }
+    Ok(Probe {
+        url: res.url().as_str().to_string(),
+        status,
+        content_type,
+        length,
+        icy,
+        icy_name,
+        head,
+    })
+}
+
 /// Does a raw get request under the provided url & header
 fn get_raw(url: &str, htype: HeaderType) -> Fallible<Response> {
     trace!("Starting request {}", url);
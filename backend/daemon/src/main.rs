@@ -33,13 +33,16 @@ extern crate erased_serde;
 extern crate futures;
 extern crate glib;
 extern crate gstreamer as gst;
+extern crate gstreamer_app as gst_app;
 extern crate gstreamer_player as gst_player;
 extern crate hashbrown;
+extern crate hyper;
 extern crate libpulse_binding as pulse;
 extern crate libpulse_glib_binding as pglib;
 extern crate libpulse_sys as pulse_sys;
 extern crate metrohash;
 extern crate mpmc_scheduler;
+extern crate native_tls;
 extern crate owning_ref;
 extern crate reqwest;
 extern crate rusqlite;
@@ -71,10 +74,23 @@ mod audio;
 mod cache;
 mod config;
 mod daemon;
+mod direct;
+mod effects;
+mod fade;
 mod http;
+mod mumble;
+mod output;
 mod playback;
+mod pool;
+mod recording;
+mod single_flight;
+mod soundboard;
+mod stream;
+mod trim;
 mod ts;
+mod voip;
 mod ytdl;
+mod ytdl_profile;
 mod ytdl_worker;
 
 use clap::{App, Arg, SubCommand};
@@ -101,7 +117,6 @@ lazy_static! {
         info!("Loading config..");
         config::init_settings().unwrap()
     };
-    static ref USERAGENT: String = format!("YAMBA v{}", VERSION);
 }
 
 fn main() -> Fallible<()> {
@@ -254,6 +269,7 @@ fn main() -> Fallible<()> {
 /// Check runtime relevant config values
 fn check_runtime() -> Fallible<()> {
     api::check_runtime()?;
+    stream::server::parse_addr()?;
     Ok(())
 }
 
@@ -0,0 +1,494 @@
+/*
+ *  This file is part of yamba.
+ *
+ *  yamba is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  yamba is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with yamba.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use failure::Fallible;
+use reqwest;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
+
+use super::{MumbleErr, Outgoing, Target};
+use daemon::instance::ID;
+use yamba_types::commands::{parse, Command, HELP, UNKNOWN_REPLY};
+use yamba_types::rpc::*;
+use SETTINGS;
+
+/// Chat commands of the shared command table, mapped onto the manager JSON-RPC methods
+
+/// Chat command of a user  
+/// Mumble doesn't expose ACL groups to clients, requests carry no invoker groups.
+/// Mumble users thus never pass group checks like volume lock, jingle skip or trim.
+pub struct CommandReq {
+    pub invoker: String,
+    /// Certificate hash, empty for users without certificate
+    pub invoker_uid: String,
+    /// Message as received, HTML
+    pub message: String,
+    pub target: Target,
+}
+
+/// Render help of the shared command table
+fn help() -> String {
+    let mut help = String::from("<b>YAMBA HELP</b>");
+    for (description, usage) in HELP {
+        help.push_str(&format!(
+            "<br>{}: <i>{}</i>",
+            escape(description),
+            escape(usage)
+        ));
+    }
+    help
+}
+
+/// Convert Mumble HTML message to plain text
+pub fn plain_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    let mut tag = String::new();
+    for c in html.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                tag.clear();
+            }
+            '>' if in_tag => {
+                in_tag = false;
+                let name = tag.trim_start_matches('/').to_lowercase();
+                if name.starts_with("br") || name == "p" {
+                    text.push('\n');
+                }
+            }
+            c if in_tag => tag.push(c),
+            c => text.push(c),
+        }
+    }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// Escape text for Mumble HTML messages
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\n', "<br>")
+}
+
+/// Start worker handling chat commands of an instance
+/// Calls are blocking, running them on the connection would stall voice
+pub fn start_worker(id: ID, outgoing: Sender<Outgoing>) -> Fallible<Sender<CommandReq>> {
+    let (sender, receiver) = channel::<CommandReq>();
+    let rpc = RpcClient::new(id);
+    thread::Builder::new()
+        .name(format!("mumble-cmd-{}", id))
+        .spawn(move || {
+            for req in receiver.iter() {
+                let cmd = match parse(&plain_text(&req.message)) {
+                    Some(v) => v,
+                    None => continue,
+                };
+                info!("\"{}\" from \"{}\"", req.message, req.invoker);
+                let reply = match rpc.execute(&cmd, &req) {
+                    Ok(Some(v)) => v,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        warn!("Error on JSONRPC: {}", e);
+                        format!("RPC call failed<br>Reason: {}", escape(&e.to_string()))
+                    }
+                };
+                let target = req.target.clone();
+                if outgoing
+                    .send(Outgoing::Text {
+                        target,
+                        message: reply,
+                    })
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            debug!("Command worker of instance {} stopped", id);
+        })?;
+    Ok(sender)
+}
+
+#[derive(Serialize)]
+struct RpcRequest<'a, P> {
+    jsonrpc: &'static str,
+    method: &'a str,
+    params: P,
+    id: u32,
+}
+
+#[derive(Deserialize)]
+struct RpcResponse<R> {
+    result: Option<R>,
+    error: Option<RpcError>,
+}
+
+#[derive(Deserialize)]
+struct RpcError {
+    message: String,
+}
+
+/// Blocking JSON-RPC client for the manager
+struct RpcClient {
+    client: reqwest::Client,
+    url: String,
+    id: ID,
+}
+
+impl RpcClient {
+    fn new(id: ID) -> RpcClient {
+        RpcClient {
+            client: reqwest::Client::new(),
+            url: format!(
+                "http://{}:{}",
+                SETTINGS.main.api_jsonrpc_ip, SETTINGS.main.api_jsonrpc_port
+            ),
+            id,
+        }
+    }
+
+    /// Call method
+    fn call<P: Serialize, R: DeserializeOwned>(&self, method: &str, params: P) -> Fallible<R> {
+        let response: RpcResponse<R> = self
+            .client
+            .post(&self.url)
+            .json(&RpcRequest {
+                jsonrpc: "2.0",
+                method,
+                params,
+                id: 1,
+            })
+            .send()?
+            .error_for_status()?
+            .json()?;
+        match (response.result, response.error) {
+            (Some(v), _) => Ok(v),
+            (None, Some(e)) => Err(MumbleErr::Rpc(e.message).into()),
+            (None, None) => Err(MumbleErr::Rpc(String::from("Empty response")).into()),
+        }
+    }
+
+    fn default_params(&self, req: &CommandReq) -> ParamDefault {
+        ParamDefault {
+            id: self.id,
+            invoker_name: req.invoker.clone(),
+            invoker_uid: req.invoker_uid.clone(),
+            invoker_groups: String::new(),
+        }
+    }
+
+    /// Execute command, returns reply
+    fn execute(&self, cmd: &Command, req: &CommandReq) -> Fallible<Option<String>> {
+        let ok = Some(String::from("Ok"));
+        Ok(match *cmd {
+            Command::Help => Some(help()),
+            Command::VolumeGet => {
+                let res: VolumeResponse = self.call("volume_get", self.default_params(req))?;
+                Some(format!("{}", (res.volume * 100.0) as i32))
+            }
+            Command::VolumeSet(v) => {
+                let res: DefaultResponse = self.call(
+                    "volume_set",
+                    ParamVolume {
+                        id: self.id,
+                        invoker_name: req.invoker.clone(),
+                        invoker_uid: req.invoker_uid.clone(),
+                        invoker_groups: String::new(),
+                        volume: f64::from(v) / 100.0,
+                    },
+                )?;
+                // message set if refused
+                match res.message.is_empty() {
+                    true => ok,
+                    false => Some(escape(&res.message)),
+                }
+            }
+            Command::VolumeLock(lock) => {
+                let res: DefaultResponse = self.call(
+                    "volume_lock",
+                    ParamVolumeLock {
+                        id: self.id,
+                        invoker_name: req.invoker.clone(),
+                        invoker_uid: req.invoker_uid.clone(),
+                        invoker_groups: String::new(),
+                        lock,
+                    },
+                )?;
+                match res.message.is_empty() {
+                    true => ok,
+                    false => Some(escape(&res.message)),
+                }
+            }
+            Command::Playing => {
+                let res: TitleResponse = self.call("track_get", self.default_params(req))?;
+                Some(escape(&res.title))
+            }
+            Command::Next => {
+                let res: DefaultResponse = self.call("track_next", self.default_params(req))?;
+                // message set if refused
+                match res.message.is_empty() {
+                    true => ok,
+                    false => Some(escape(&res.message)),
+                }
+            }
+            Command::Random => {
+                let _: serde_json::Value =
+                    self.call("playback_random", self.default_params(req))?;
+                ok
+            }
+            Command::Queue(ref url) => {
+                let res: DefaultResponse = self.call(
+                    "queue",
+                    ParamQueue {
+                        id: self.id,
+                        invoker_name: req.invoker.clone(),
+                        invoker_uid: req.invoker_uid.clone(),
+                        invoker_groups: String::new(),
+                        url: url.clone(),
+                    },
+                )?;
+                // empty on success, otherwise why the url can't be played
+                match res.message.is_empty() {
+                    true => ok,
+                    false => Some(escape(&res.message)),
+                }
+            }
+            Command::Tracks(n) => {
+                let res: TitleListResponse = self.call(
+                    "queue_tracks",
+                    ParamQueueTracks {
+                        id: self.id,
+                        invoker_name: req.invoker.clone(),
+                        invoker_uid: req.invoker_uid.clone(),
+                        invoker_groups: String::new(),
+                        n,
+                    },
+                )?;
+                Some(format_list("Upcoming tracks:", &res.tracklist))
+            }
+            Command::Search(ref query, ref site) => {
+                let res: TitleListResponse = self.call(
+                    "search",
+                    ParamSearch {
+                        id: self.id,
+                        invoker_name: req.invoker.clone(),
+                        invoker_uid: req.invoker_uid.clone(),
+                        invoker_groups: String::new(),
+                        query: query.clone(),
+                        site: site.clone(),
+                    },
+                )?;
+                if res.tracklist.is_empty() {
+                    Some(String::from("No results found."))
+                } else {
+                    Some(format_list("Results, !pick &lt;n&gt;:", &res.tracklist))
+                }
+            }
+            Command::Pick(n) => {
+                let res: DefaultResponse = self.call(
+                    "pick",
+                    ParamPick {
+                        id: self.id,
+                        invoker_name: req.invoker.clone(),
+                        invoker_uid: req.invoker_uid.clone(),
+                        invoker_groups: String::new(),
+                        n,
+                    },
+                )?;
+                Some(escape(&res.message))
+            }
+            Command::Soundboard(ref clip) => {
+                let res: DefaultResponse = self.call(
+                    "soundboard",
+                    ParamSoundboard {
+                        id: self.id,
+                        invoker_name: req.invoker.clone(),
+                        invoker_uid: req.invoker_uid.clone(),
+                        invoker_groups: String::new(),
+                        clip: clip.clone(),
+                    },
+                )?;
+                // silent on success
+                match res.message.is_empty() {
+                    true => None,
+                    false => Some(escape(&res.message)),
+                }
+            }
+            Command::Speed(ref command) => {
+                let res: DefaultResponse = self.call(
+                    "speed",
+                    ParamSpeed {
+                        id: self.id,
+                        invoker_name: req.invoker.clone(),
+                        invoker_uid: req.invoker_uid.clone(),
+                        invoker_groups: String::new(),
+                        command: command.clone(),
+                    },
+                )?;
+                Some(escape(&res.message))
+            }
+            Command::Chapters => {
+                let res: TitleListResponse = self.call("chapters", self.default_params(req))?;
+                if res.tracklist.is_empty() {
+                    Some(String::from("No chapters available."))
+                } else {
+                    Some(format_list("Chapters, !chapter &lt;n&gt;:", &res.tracklist))
+                }
+            }
+            Command::Chapter(ref command) => {
+                let res: DefaultResponse = self.call(
+                    "chapter",
+                    ParamChapter {
+                        id: self.id,
+                        invoker_name: req.invoker.clone(),
+                        invoker_uid: req.invoker_uid.clone(),
+                        invoker_groups: String::new(),
+                        command: command.clone(),
+                    },
+                )?;
+                Some(escape(&res.message))
+            }
+            Command::Trim(ref command) => {
+                let res: DefaultResponse = self.call(
+                    "trim",
+                    ParamTrim {
+                        id: self.id,
+                        invoker_name: req.invoker.clone(),
+                        invoker_uid: req.invoker_uid.clone(),
+                        invoker_groups: String::new(),
+                        command: command.clone(),
+                    },
+                )?;
+                Some(escape(&res.message))
+            }
+            Command::SoundboardList => {
+                let res: TitleListResponse =
+                    self.call("soundboard_list", self.default_params(req))?;
+                if res.tracklist.is_empty() {
+                    Some(String::from("No clips available."))
+                } else {
+                    Some(format_list("Clips, !sb &lt;name&gt;:", &res.tracklist))
+                }
+            }
+            Command::Previous => {
+                let _: serde_json::Value = self.call("track_previous", self.default_params(req))?;
+                ok
+            }
+            Command::Resume => {
+                let _: serde_json::Value = self.call("track_resume", self.default_params(req))?;
+                ok
+            }
+            Command::Pause => {
+                let _: serde_json::Value = self.call("track_pause", self.default_params(req))?;
+                ok
+            }
+            Command::Stop => {
+                let _: serde_json::Value = self.call("track_stop", self.default_params(req))?;
+                ok
+            }
+            Command::QueueClear => {
+                let _: serde_json::Value = self.call("queue_clear", self.default_params(req))?;
+                ok
+            }
+            Command::QueueLock(lock) => {
+                let _: serde_json::Value = self.call(
+                    "queue_lock",
+                    ParamQueueLock {
+                        id: self.id,
+                        invoker_name: req.invoker.clone(),
+                        invoker_uid: req.invoker_uid.clone(),
+                        invoker_groups: String::new(),
+                        lock,
+                    },
+                )?;
+                ok
+            }
+            Command::PlaylistGet => {
+                let res: PlaylistResponse = self.call("playlist_get", self.default_params(req))?;
+                match res.allowed {
+                    true => Some(escape(&res.name)),
+                    false => Some(format!(
+                        "Action not allowed!<br>Reason: {}",
+                        escape(&res.message)
+                    )),
+                }
+            }
+            Command::PlaylistLoad(ref playlist_name) => {
+                let _: serde_json::Value = self.call(
+                    "playlist_load",
+                    ParamPlaylistLoad {
+                        id: self.id,
+                        invoker_name: req.invoker.clone(),
+                        invoker_uid: req.invoker_uid.clone(),
+                        invoker_groups: String::new(),
+                        playlist_name: playlist_name.clone(),
+                    },
+                )?;
+                ok
+            }
+            Command::InvalidNumber => Some(String::from("n not parseable")),
+            Command::Halt | Command::Unknown => Some(String::from(UNKNOWN_REPLY)),
+        })
+    }
+}
+
+/// Format list with header
+fn format_list(header: &str, entries: &[String]) -> String {
+    let mut message = String::from(header);
+    for entry in entries {
+        message.push_str("<br>");
+        message.push_str(&escape(entry));
+    }
+    message
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_help() {
+        let help = help();
+        assert!(help.starts_with("<b>YAMBA HELP</b><br>Help: <i>!help</i>"));
+        assert!(help.contains("Pick search result &lt;n&gt;: <i>!pick &lt;n&gt;</i>"));
+        assert_eq!(HELP.len(), help.matches("<br>").count());
+    }
+
+    #[test]
+    fn test_plain_text() {
+        let message = "!queue <a href=\"https://example.com/?a=1&amp;b=2\">https://example.com/?a=1&amp;b=2</a>";
+        assert_eq!(
+            Some(Command::Queue(String::from("https://example.com/?a=1&b=2"))),
+            parse(&plain_text(message))
+        );
+        assert_eq!(
+            "a\nb &lt;c&gt;",
+            plain_text("<p>a<br/>b &amp;lt;c&amp;gt;</p>").trim()
+        );
+        assert_eq!("a &amp; b<br>&lt;c&gt;", escape("a & b\n<c>"));
+    }
+}
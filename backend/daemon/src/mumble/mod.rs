@@ -0,0 +1,748 @@
+/*
+ *  This file is part of yamba.
+ *
+ *  yamba is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  yamba is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with yamba.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod commands;
+mod proto;
+mod voice;
+
+use failure::Fallible;
+use hashbrown::HashMap;
+use native_tls::{TlsConnector, TlsStream};
+use sha2::{Digest, Sha256};
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use self::commands::CommandReq;
+use self::proto::{FrameBuffer, Message, PROTOCOL_VERSION};
+use self::voice::VoiceCapture;
+use audio::NullSink;
+use daemon::instance::ID;
+use daemon::{HeartbeatMap, InstanceBase, WInstances};
+use playback::Player;
+use voip::{self, VoipBackend, VoipSpawn};
+use yamba_types::models::{InstanceStartedReq, MumbleSettings, StopReason};
+use SETTINGS;
+
+/// Native Mumble client, no external process required
+
+const DEFAULT_PORT: u16 = 64738;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+/// Max blocking time of reads, outgoing voice is sent in between
+const READ_TIMEOUT: Duration = Duration::from_millis(10);
+/// Server disconnects clients without ping after 30s
+const PING_INTERVAL: Duration = Duration::from_secs(15);
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+/// Root channel of each server
+const ROOT_CHANNEL: u32 = 0;
+
+#[derive(Fail, Debug)]
+pub enum MumbleErr {
+    #[fail(display = "Unable to resolve {}", _0)]
+    Resolve(String),
+    #[fail(display = "TLS error {}", _0)]
+    Tls(String),
+    #[fail(display = "Server certificate {} isn't pinned", _0)]
+    UnpinnedCert(String),
+    #[fail(display = "RPC call failed: {}", _0)]
+    Rpc(String),
+    #[fail(display = "Voice pipeline error: {}", _0)]
+    Pipeline(String),
+}
+
+/// Receiver of a text message
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    /// Current channel
+    Channel,
+    /// User by session
+    User(u32),
+}
+
+/// Data sent by the connection
+pub enum Outgoing {
+    Voice(Vec<u8>),
+    Text { target: Target, message: String },
+}
+
+/// Mumble VoIP backend
+/// Audio is captured from a pulse null sink, encoded to Opus & tunneled through the control channel
+pub struct Mumble {
+    sink: NullSink,
+    outgoing: Mutex<Sender<Outgoing>>,
+    running: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+    voice: Mutex<Option<VoiceCapture>>,
+}
+
+impl VoipSpawn for Mumble {
+    type Settings = MumbleSettings;
+
+    fn spawn(base: &InstanceBase, settings: MumbleSettings, id: ID) -> Fallible<Mumble> {
+        let sink = NullSink::new(
+            base.mainloop.clone(),
+            base.context.clone(),
+            format!("yambamumble{}", &id),
+        )?;
+        let (sender, receiver) = channel();
+        let running = Arc::new(AtomicBool::new(true));
+        let stop = Arc::new(AtomicBool::new(false));
+        let connection = Connection {
+            id,
+            settings,
+            session: Session::default(),
+            commands: commands::start_worker(id, sender.clone())?,
+            instances: base.w_instances.clone(),
+            heartbeats: base.heartbeat.clone(),
+            running: running.clone(),
+            stop: stop.clone(),
+            reported_connected: false,
+        };
+        thread::Builder::new()
+            .name(format!("mumble-{}", id))
+            .spawn(move || connection.run(receiver))?;
+        Ok(Mumble {
+            sink,
+            outgoing: Mutex::new(sender),
+            running,
+            stop,
+            voice: Mutex::new(None),
+        })
+    }
+}
+
+impl VoipBackend for Mumble {
+    fn name(&self) -> &'static str {
+        "Mumble"
+    }
+
+    fn route_audio(&self, player: &Player) -> Fallible<()> {
+        player.set_pulse_device(self.sink.get_sink_name())
+    }
+
+    /// Starts voice capture
+    fn on_connected(&self, _param: &InstanceStartedReq) -> Fallible<()> {
+        let mut voice = self.voice.lock().expect("Can't lock voice capture!");
+        if voice.is_none() {
+            let outgoing = self.outgoing.lock().expect("Can't lock outgoing!").clone();
+            *voice = Some(VoiceCapture::start(
+                &self.sink.get_monitor_name(),
+                SETTINGS.mumble.bitrate,
+                outgoing,
+            )?);
+        }
+        Ok(())
+    }
+
+    fn is_running(&mut self) -> Fallible<bool> {
+        Ok(self.running.load(Ordering::Relaxed))
+    }
+
+    fn send_message(&self, message: &str) -> Fallible<()> {
+        let _ = self
+            .outgoing
+            .lock()
+            .expect("Can't lock outgoing!")
+            .send(Outgoing::Text {
+                target: Target::Channel,
+                message: commands::escape(message),
+            });
+        Ok(())
+    }
+
+    /// Doesn't wait for the connection, it may be blocked on the instances we're removed from
+    fn teardown(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.voice.lock().expect("Can't lock voice capture!").take();
+    }
+}
+
+/// Event from handling a received message
+#[derive(Debug, PartialEq)]
+enum Event {
+    Send(Message),
+    /// Server sync done, able to send audio
+    Synced,
+    Stop(StopReason),
+    Command {
+        invoker: String,
+        invoker_uid: String,
+        message: String,
+        target: Target,
+    },
+}
+
+#[derive(Debug, Default)]
+struct User {
+    name: String,
+    /// Certificate hash, empty without certificate
+    hash: String,
+    channel: u32,
+}
+
+/// Server state as seen by us
+#[derive(Default)]
+struct Session {
+    /// Own session, known after sync
+    own: Option<u32>,
+    /// Channel to join after sync
+    wanted_channel: Option<String>,
+    channels: HashMap<u32, String>,
+    users: HashMap<u32, User>,
+}
+
+impl Session {
+    fn new(wanted_channel: Option<String>) -> Session {
+        Session {
+            wanted_channel,
+            ..Session::default()
+        }
+    }
+
+    fn is_synced(&self) -> bool {
+        self.own.is_some()
+    }
+
+    /// Channel we're in
+    fn channel(&self) -> u32 {
+        self.own
+            .and_then(|v| self.users.get(&v))
+            .map_or(ROOT_CHANNEL, |v| v.channel)
+    }
+
+    /// Handle received message
+    fn handle(&mut self, msg: Message) -> Vec<Event> {
+        match msg {
+            Message::Reject { reason } => {
+                warn!("Connection rejected: {:?}", reason);
+                vec![Event::Stop(StopReason::ConnectFailed)]
+            }
+            Message::ServerSync {
+                session,
+                welcome_text,
+            } => {
+                debug!("Server sync, welcome text: {:?}", welcome_text);
+                self.own = session;
+                let mut events = vec![Event::Synced];
+                let join = self.wanted_channel.as_ref().and_then(|name| {
+                    let found = self
+                        .channels
+                        .iter()
+                        .find(|(_, v)| *v == name)
+                        .map(|(k, _)| *k);
+                    if found.is_none() {
+                        warn!("Channel {} not found, staying in current channel", name);
+                    }
+                    found
+                });
+                if let Some(channel_id) = join {
+                    events.push(Event::Send(Message::UserState {
+                        session: self.own,
+                        name: None,
+                        channel_id: Some(channel_id),
+                        hash: None,
+                    }));
+                }
+                events
+            }
+            Message::ChannelState {
+                channel_id: Some(id),
+                name: Some(name),
+                ..
+            } => {
+                self.channels.insert(id, name);
+                Vec::new()
+            }
+            Message::ChannelRemove { channel_id } => {
+                self.channels.remove(&channel_id);
+                Vec::new()
+            }
+            Message::UserState {
+                session: Some(session),
+                name,
+                channel_id,
+                hash,
+            } => {
+                let user = self.users.entry(session).or_insert_with(User::default);
+                if let Some(name) = name {
+                    user.name = name;
+                }
+                if let Some(hash) = hash {
+                    user.hash = hash;
+                }
+                if let Some(channel) = channel_id {
+                    user.channel = channel;
+                }
+                Vec::new()
+            }
+            Message::UserRemove {
+                session,
+                actor,
+                reason,
+                ban,
+            } => {
+                if Some(session) != self.own {
+                    self.users.remove(&session);
+                    return Vec::new();
+                }
+                info!(
+                    "Removed from server by {:?}, ban: {} reason: {:?}",
+                    actor, ban, reason
+                );
+                vec![Event::Stop(if ban {
+                    StopReason::Banned
+                } else {
+                    StopReason::Kicked
+                })]
+            }
+            Message::TextMessage {
+                actor: Some(actor),
+                session,
+                channel_id,
+                message,
+            } => {
+                if Some(actor) == self.own {
+                    return Vec::new();
+                }
+                let target = if channel_id.is_empty() && !session.is_empty() {
+                    Target::User(actor)
+                } else {
+                    Target::Channel
+                };
+                let (invoker, invoker_uid) = match self.users.get(&actor) {
+                    Some(v) => (v.name.clone(), v.hash.clone()),
+                    None => (actor.to_string(), String::new()),
+                };
+                vec![Event::Command {
+                    invoker,
+                    invoker_uid,
+                    message,
+                    target,
+                }]
+            }
+            Message::CodecVersion { opus: false } => {
+                warn!("Server doesn't announce Opus support, voice may not be heard");
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Text message for target, None before sync
+    fn text_message(&self, target: Target, message: String) -> Option<Message> {
+        let own = self.own?;
+        Some(match target {
+            Target::Channel => Message::TextMessage {
+                actor: Some(own),
+                session: Vec::new(),
+                channel_id: vec![self.channel()],
+                message,
+            },
+            Target::User(session) => Message::TextMessage {
+                actor: Some(own),
+                session: vec![session],
+                channel_id: Vec::new(),
+                message,
+            },
+        })
+    }
+}
+
+/// Connect & authenticate
+fn connect(settings: &MumbleSettings) -> Fallible<TlsStream<TcpStream>> {
+    let port = settings.port.unwrap_or(DEFAULT_PORT);
+    let addr = (settings.host.as_str(), port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| MumbleErr::Resolve(settings.host.clone()))?;
+    let stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+    // murmur creates self-signed certificates by default, these have to be pinned
+    let pinned = &SETTINGS.mumble.pinned_certs;
+    let connector = TlsConnector::builder()
+        .danger_accept_invalid_certs(SETTINGS.mumble.accept_invalid_certs || !pinned.is_empty())
+        .build()?;
+    let mut tls = connector
+        .connect(&settings.host, stream)
+        .map_err(|e| MumbleErr::Tls(e.to_string()))?;
+    if !pinned.is_empty() && !SETTINGS.mumble.accept_invalid_certs {
+        check_pinned(&tls, pinned)?;
+    }
+    tls.get_ref().set_read_timeout(Some(READ_TIMEOUT))?;
+
+    tls.write_all(
+        &Message::Version {
+            version: PROTOCOL_VERSION,
+            release: format!("yamba {}", env!("CARGO_PKG_VERSION")),
+            os: String::from("Linux"),
+            os_version: String::new(),
+        }
+        .encode(),
+    )?;
+    tls.write_all(
+        &Message::Authenticate {
+            username: settings.name.clone(),
+            password: settings.password.clone(),
+            opus: true,
+        }
+        .encode(),
+    )?;
+    Ok(tls)
+}
+
+/// Verify server certificate against the pinned fingerprints
+fn check_pinned(tls: &TlsStream<TcpStream>, pinned: &[String]) -> Fallible<()> {
+    let cert = tls
+        .peer_certificate()
+        .map_err(|e| MumbleErr::Tls(e.to_string()))?
+        .ok_or_else(|| MumbleErr::Tls(String::from("No server certificate")))?;
+    let der = cert.to_der().map_err(|e| MumbleErr::Tls(e.to_string()))?;
+    let fingerprint = fingerprint(&der);
+    match is_pinned(&fingerprint, pinned) {
+        true => Ok(()),
+        false => Err(MumbleErr::UnpinnedCert(fingerprint).into()),
+    }
+}
+
+/// SHA-256 fingerprint of DER certificate, lowercase hex
+fn fingerprint(der: &[u8]) -> String {
+    let mut sha2 = Sha256::default();
+    sha2.input(der);
+    format!("{:x}", sha2.result())
+}
+
+/// Whether fingerprint is pinned, colons & case of pins are ignored
+fn is_pinned(fingerprint: &str, pinned: &[String]) -> bool {
+    pinned
+        .iter()
+        .any(|v| v.replace(':', "").to_lowercase() == fingerprint)
+}
+
+/// Control connection of an instance, runs on its own thread
+struct Connection {
+    id: ID,
+    settings: MumbleSettings,
+    session: Session,
+    commands: Sender<CommandReq>,
+    instances: WInstances,
+    heartbeats: HeartbeatMap,
+    running: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+    reported_connected: bool,
+}
+
+impl Connection {
+    /// Run until disconnected, reports the stop reason to the instance
+    fn run(mut self, outgoing: Receiver<Outgoing>) {
+        self.session = Session::new(self.settings.channel.clone());
+        let reason = match self.serve(&outgoing) {
+            Ok(v) => v,
+            Err(e) => {
+                warn!("Mumble connection of instance {} failed: {}", self.id, e);
+                if self.session.is_synced() {
+                    StopReason::ServerDown
+                } else {
+                    StopReason::ConnectFailed
+                }
+            }
+        };
+        self.running.store(false, Ordering::Relaxed);
+        info!(
+            "Mumble connection of instance {} closed: {:?}",
+            self.id, reason
+        );
+        // instance is stopping already otherwise
+        if reason != StopReason::Requested {
+            if let Some(instances) = self.instances.upgrade() {
+                let instances = instances.read().expect("Can't read instances!");
+                if let Some(inst) = instances.get(&self.id) {
+                    inst.set_stop_reason(reason);
+                }
+            }
+        }
+    }
+
+    /// Handle connection, returns reason of the disconnect
+    fn serve(&mut self, outgoing: &Receiver<Outgoing>) -> Fallible<StopReason> {
+        let mut tls = connect(&self.settings)?;
+        let mut frames = FrameBuffer::default();
+        let mut buffer = [0u8; 4096];
+        let mut last_ping = Instant::now();
+        let mut last_heartbeat = Instant::now();
+        self.heartbeats.update(self.id);
+        loop {
+            if self.stop.load(Ordering::Relaxed) {
+                return Ok(StopReason::Requested);
+            }
+            match tls.read(&mut buffer) {
+                Ok(0) => return Ok(StopReason::ServerDown),
+                Ok(n) => frames.push(&buffer[..n]),
+                Err(ref e)
+                    if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {}
+                Err(e) => return Err(e.into()),
+            }
+            while let Some(msg) = frames.next_message()? {
+                for event in self.session.handle(msg) {
+                    match event {
+                        Event::Send(msg) => tls.write_all(&msg.encode())?,
+                        Event::Synced => info!("Instance {} connected to mumble", self.id),
+                        Event::Stop(reason) => return Ok(reason),
+                        Event::Command {
+                            invoker,
+                            invoker_uid,
+                            message,
+                            target,
+                        } => {
+                            let _ = self.commands.send(CommandReq {
+                                invoker,
+                                invoker_uid,
+                                message,
+                                target,
+                            });
+                        }
+                    }
+                }
+            }
+            if self.session.is_synced() && !self.reported_connected {
+                // retried until the instance is stored
+                self.reported_connected = voip::report_connected(&self.instances, self.id);
+            }
+            while let Ok(out) = outgoing.try_recv() {
+                let msg = match out {
+                    Outgoing::Voice(_) if !self.session.is_synced() => continue,
+                    Outgoing::Voice(packet) => Message::UdpTunnel(packet),
+                    Outgoing::Text { target, message } => {
+                        match self.session.text_message(target, message) {
+                            Some(v) => v,
+                            None => continue,
+                        }
+                    }
+                };
+                tls.write_all(&msg.encode())?;
+            }
+            if last_ping.elapsed() >= PING_INTERVAL {
+                let timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|v| v.as_secs())
+                    .unwrap_or(0);
+                tls.write_all(&Message::Ping { timestamp }.encode())?;
+                last_ping = Instant::now();
+            }
+            if last_heartbeat.elapsed() >= HEARTBEAT_INTERVAL {
+                self.heartbeats.update(self.id);
+                last_heartbeat = Instant::now();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn synced(channel: Option<&str>) -> (Session, Vec<Event>) {
+        let mut session = Session::new(channel.map(String::from));
+        for (id, name) in vec![(0, "Root"), (4, "Music")] {
+            session.handle(Message::ChannelState {
+                channel_id: Some(id),
+                parent: None,
+                name: Some(String::from(name)),
+            });
+        }
+        session.handle(Message::UserState {
+            session: Some(7),
+            name: Some(String::from("yamba")),
+            channel_id: Some(0),
+            hash: None,
+        });
+        session.handle(Message::UserState {
+            session: Some(2),
+            name: Some(String::from("alice")),
+            channel_id: Some(4),
+            hash: Some(String::from("a4f1c0ffee")),
+        });
+        let events = session.handle(Message::ServerSync {
+            session: Some(7),
+            welcome_text: None,
+        });
+        (session, events)
+    }
+
+    #[test]
+    fn test_pinned() {
+        let fingerprint = fingerprint(b"cert");
+        assert_eq!(64, fingerprint.len());
+        let upper = fingerprint
+            .as_bytes()
+            .chunks(2)
+            .map(|v| String::from_utf8_lossy(v).to_uppercase())
+            .collect::<Vec<_>>()
+            .join(":");
+        assert!(is_pinned(&fingerprint, &[String::from("ab"), upper]));
+        assert!(!is_pinned(&fingerprint, &[String::from("ab")]));
+        assert!(!is_pinned(&fingerprint, &[]));
+    }
+
+    #[test]
+    fn test_sync() {
+        let (_, events) = synced(None);
+        assert_eq!(vec![Event::Synced], events);
+
+        let (mut session, events) = synced(Some("Music"));
+        assert_eq!(
+            vec![
+                Event::Synced,
+                Event::Send(Message::UserState {
+                    session: Some(7),
+                    name: None,
+                    channel_id: Some(4),
+                    hash: None,
+                })
+            ],
+            events
+        );
+        assert_eq!(ROOT_CHANNEL, session.channel());
+        session.handle(Message::UserState {
+            session: Some(7),
+            name: None,
+            channel_id: Some(4),
+            hash: None,
+        });
+        assert_eq!(4, session.channel());
+        match session.text_message(Target::Channel, String::from("hi")) {
+            Some(Message::TextMessage { channel_id, .. }) => assert_eq!(vec![4], channel_id),
+            v => panic!("Unexpected message {:?}", v),
+        }
+    }
+
+    #[test]
+    fn test_text_message() {
+        let (mut session, _) = synced(None);
+        let events = session.handle(Message::TextMessage {
+            actor: Some(2),
+            session: vec![7],
+            channel_id: vec![],
+            message: String::from("!playing"),
+        });
+        assert_eq!(
+            vec![Event::Command {
+                invoker: String::from("alice"),
+                invoker_uid: String::from("a4f1c0ffee"),
+                message: String::from("!playing"),
+                target: Target::User(2),
+            }],
+            events
+        );
+        // own messages
+        let events = session.handle(Message::TextMessage {
+            actor: Some(7),
+            session: vec![],
+            channel_id: vec![0],
+            message: String::from("Ok"),
+        });
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn test_removed() {
+        let (mut session, _) = synced(None);
+        let remove = |session: u32, ban: bool| Message::UserRemove {
+            session,
+            actor: Some(2),
+            reason: None,
+            ban,
+        };
+        assert!(session.handle(remove(2, false)).is_empty());
+        assert_eq!(
+            vec![Event::Stop(StopReason::Kicked)],
+            session.handle(remove(7, false))
+        );
+        assert_eq!(
+            vec![Event::Stop(StopReason::Banned)],
+            session.handle(remove(7, true))
+        );
+    }
+
+    /// Requires a local murmur server, for example `murmurd -fg`
+    /// Host & port can be set via MUMBLE_TEST_HOST & MUMBLE_TEST_PORT
+    #[test]
+    #[ignore]
+    fn test_murmur() {
+        let settings = MumbleSettings {
+            host: ::std::env::var("MUMBLE_TEST_HOST").unwrap_or(String::from("127.0.0.1")),
+            port: ::std::env::var("MUMBLE_TEST_PORT")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            name: String::from("yamba-test"),
+            password: None,
+            channel: None,
+        };
+        let mut tls = connect(&settings).unwrap();
+        let mut session = Session::new(None);
+        let mut frames = FrameBuffer::default();
+        let mut buffer = [0u8; 4096];
+        let start = Instant::now();
+        while !session.is_synced() {
+            assert!(start.elapsed() < Duration::from_secs(10), "No server sync");
+            match tls.read(&mut buffer) {
+                Ok(0) => panic!("Connection closed"),
+                Ok(n) => frames.push(&buffer[..n]),
+                Err(_) => continue,
+            }
+            while let Some(msg) = frames.next_message().unwrap() {
+                for event in session.handle(msg) {
+                    if let Event::Stop(reason) = event {
+                        panic!("Stopped: {:?}", reason);
+                    }
+                }
+            }
+        }
+        let own = session.own.unwrap();
+        assert_eq!("yamba-test", session.users[&own].name);
+        assert!(session.channels.contains_key(&ROOT_CHANNEL));
+        let msg = session
+            .text_message(Target::Channel, String::from("yamba test"))
+            .unwrap();
+        tls.write_all(&msg.encode()).unwrap();
+        let mut encoder = voice::VoiceEncoder::default();
+        let packet = encoder.packet(&[0xFC, 0xFF, 0xFE]).unwrap();
+        tls.write_all(&Message::UdpTunnel(packet).encode()).unwrap();
+        // server has to accept both without dropping us
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(1) {
+            match tls.read(&mut buffer) {
+                Ok(0) => panic!("Connection closed"),
+                Ok(n) => frames.push(&buffer[..n]),
+                Err(_) => continue,
+            }
+            while let Some(msg) = frames.next_message().unwrap() {
+                for event in session.handle(msg) {
+                    if let Event::Stop(reason) = event {
+                        panic!("Stopped: {:?}", reason);
+                    }
+                }
+            }
+        }
+        assert_eq!(Some(own), session.own);
+    }
+}
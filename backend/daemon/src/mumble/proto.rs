@@ -0,0 +1,610 @@
+/*
+ *  This file is part of yamba.
+ *
+ *  yamba is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  yamba is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with yamba.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use failure::Fallible;
+
+/// Mumble control channel messages
+/// Minimal protobuf wire format for the messages we use, see Mumble.proto
+
+/// Max accepted control message size
+const FRAME_MAX_LEN: usize = 8 * 1024 * 1024;
+/// Type & length prefix of each frame
+const FRAME_HEADER_LEN: usize = 6;
+
+/// Protocol version 1.3.0
+pub const PROTOCOL_VERSION: u32 = (1 << 16) | (3 << 8);
+
+const TYPE_VERSION: u16 = 0;
+const TYPE_UDP_TUNNEL: u16 = 1;
+const TYPE_AUTHENTICATE: u16 = 2;
+const TYPE_PING: u16 = 3;
+const TYPE_REJECT: u16 = 4;
+const TYPE_SERVER_SYNC: u16 = 5;
+const TYPE_CHANNEL_REMOVE: u16 = 6;
+const TYPE_CHANNEL_STATE: u16 = 7;
+const TYPE_USER_REMOVE: u16 = 8;
+const TYPE_USER_STATE: u16 = 9;
+const TYPE_TEXT_MESSAGE: u16 = 11;
+const TYPE_CODEC_VERSION: u16 = 21;
+
+const WIRE_VARINT: u8 = 0;
+const WIRE_FIXED64: u8 = 1;
+const WIRE_BYTES: u8 = 2;
+const WIRE_FIXED32: u8 = 5;
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum ProtoErr {
+    #[fail(display = "Truncated message")]
+    Truncated,
+    #[fail(display = "Invalid varint")]
+    InvalidVarint,
+    #[fail(display = "Unsupported wire type {}", _0)]
+    WireType(u8),
+    #[fail(display = "Frame of {} bytes exceeds limit", _0)]
+    FrameTooLarge(usize),
+    #[fail(display = "Invalid UTF8 in field {}", _0)]
+    InvalidString(u32),
+}
+
+/// Control message
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Version {
+        version: u32,
+        release: String,
+        os: String,
+        os_version: String,
+    },
+    /// Voice packet tunneled through TCP
+    UdpTunnel(Vec<u8>),
+    Authenticate {
+        username: String,
+        password: Option<String>,
+        opus: bool,
+    },
+    Ping {
+        timestamp: u64,
+    },
+    Reject {
+        reason: Option<String>,
+    },
+    ServerSync {
+        session: Option<u32>,
+        welcome_text: Option<String>,
+    },
+    ChannelRemove {
+        channel_id: u32,
+    },
+    ChannelState {
+        channel_id: Option<u32>,
+        parent: Option<u32>,
+        name: Option<String>,
+    },
+    UserRemove {
+        session: u32,
+        actor: Option<u32>,
+        reason: Option<String>,
+        ban: bool,
+    },
+    UserState {
+        session: Option<u32>,
+        name: Option<String>,
+        channel_id: Option<u32>,
+        /// Certificate hash
+        hash: Option<String>,
+    },
+    TextMessage {
+        actor: Option<u32>,
+        session: Vec<u32>,
+        channel_id: Vec<u32>,
+        message: String,
+    },
+    CodecVersion {
+        opus: bool,
+    },
+    /// Message we don't handle
+    Unknown(u16),
+}
+
+impl Message {
+    /// Encode message as frame
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        let kind = match *self {
+            Message::Version {
+                version,
+                ref release,
+                ref os,
+                ref os_version,
+            } => {
+                w.varint(1, u64::from(version));
+                w.string(2, release);
+                w.string(3, os);
+                w.string(4, os_version);
+                TYPE_VERSION
+            }
+            Message::UdpTunnel(ref data) => {
+                w.data.extend_from_slice(data);
+                TYPE_UDP_TUNNEL
+            }
+            Message::Authenticate {
+                ref username,
+                ref password,
+                opus,
+            } => {
+                w.string(1, username);
+                if let Some(ref v) = *password {
+                    w.string(2, v);
+                }
+                w.bool(5, opus);
+                TYPE_AUTHENTICATE
+            }
+            Message::Ping { timestamp } => {
+                w.varint(1, timestamp);
+                TYPE_PING
+            }
+            Message::Reject { ref reason } => {
+                if let Some(ref v) = *reason {
+                    w.string(2, v);
+                }
+                TYPE_REJECT
+            }
+            Message::ServerSync {
+                session,
+                ref welcome_text,
+            } => {
+                w.opt_varint(1, session);
+                if let Some(ref v) = *welcome_text {
+                    w.string(3, v);
+                }
+                TYPE_SERVER_SYNC
+            }
+            Message::ChannelRemove { channel_id } => {
+                w.varint(1, u64::from(channel_id));
+                TYPE_CHANNEL_REMOVE
+            }
+            Message::ChannelState {
+                channel_id,
+                parent,
+                ref name,
+            } => {
+                w.opt_varint(1, channel_id);
+                w.opt_varint(2, parent);
+                if let Some(ref v) = *name {
+                    w.string(3, v);
+                }
+                TYPE_CHANNEL_STATE
+            }
+            Message::UserRemove {
+                session,
+                actor,
+                ref reason,
+                ban,
+            } => {
+                w.varint(1, u64::from(session));
+                w.opt_varint(2, actor);
+                if let Some(ref v) = *reason {
+                    w.string(3, v);
+                }
+                w.bool(4, ban);
+                TYPE_USER_REMOVE
+            }
+            Message::UserState {
+                session,
+                ref name,
+                channel_id,
+                ref hash,
+            } => {
+                w.opt_varint(1, session);
+                if let Some(ref v) = *name {
+                    w.string(3, v);
+                }
+                w.opt_varint(5, channel_id);
+                if let Some(ref v) = *hash {
+                    w.string(17, v);
+                }
+                TYPE_USER_STATE
+            }
+            Message::TextMessage {
+                actor,
+                ref session,
+                ref channel_id,
+                ref message,
+            } => {
+                w.opt_varint(1, actor);
+                for v in session {
+                    w.varint(2, u64::from(*v));
+                }
+                for v in channel_id {
+                    w.varint(3, u64::from(*v));
+                }
+                w.string(5, message);
+                TYPE_TEXT_MESSAGE
+            }
+            Message::CodecVersion { opus } => {
+                // alpha & beta are required fields
+                w.varint(1, 0);
+                w.varint(2, 0);
+                w.bool(3, true);
+                w.bool(4, opus);
+                TYPE_CODEC_VERSION
+            }
+            Message::Unknown(kind) => kind,
+        };
+        let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + w.data.len());
+        frame.extend_from_slice(&kind.to_be_bytes());
+        frame.extend_from_slice(&(w.data.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&w.data);
+        frame
+    }
+
+    /// Decode message payload of type
+    pub fn decode(kind: u16, payload: &[u8]) -> Fallible<Message> {
+        if kind == TYPE_UDP_TUNNEL {
+            return Ok(Message::UdpTunnel(payload.to_vec()));
+        }
+        let mut msg = match kind {
+            TYPE_VERSION => Message::Version {
+                version: 0,
+                release: String::new(),
+                os: String::new(),
+                os_version: String::new(),
+            },
+            TYPE_AUTHENTICATE => Message::Authenticate {
+                username: String::new(),
+                password: None,
+                opus: false,
+            },
+            TYPE_PING => Message::Ping { timestamp: 0 },
+            TYPE_REJECT => Message::Reject { reason: None },
+            TYPE_SERVER_SYNC => Message::ServerSync {
+                session: None,
+                welcome_text: None,
+            },
+            TYPE_CHANNEL_REMOVE => Message::ChannelRemove { channel_id: 0 },
+            TYPE_CHANNEL_STATE => Message::ChannelState {
+                channel_id: None,
+                parent: None,
+                name: None,
+            },
+            TYPE_USER_REMOVE => Message::UserRemove {
+                session: 0,
+                actor: None,
+                reason: None,
+                ban: false,
+            },
+            TYPE_USER_STATE => Message::UserState {
+                session: None,
+                name: None,
+                channel_id: None,
+                hash: None,
+            },
+            TYPE_TEXT_MESSAGE => Message::TextMessage {
+                actor: None,
+                session: Vec::new(),
+                channel_id: Vec::new(),
+                message: String::new(),
+            },
+            TYPE_CODEC_VERSION => Message::CodecVersion { opus: false },
+            v => return Ok(Message::Unknown(v)),
+        };
+        let mut reader = Reader::new(payload);
+        while let Some((field, value)) = reader.next_field()? {
+            msg.set_field(field, value)?;
+        }
+        Ok(msg)
+    }
+
+    /// Apply decoded field, unknown fields are skipped
+    fn set_field(&mut self, field: u32, value: Value) -> Fallible<()> {
+        match (self, field) {
+            (Message::Version { version, .. }, 1) => *version = value.uint()? as u32,
+            (Message::Version { release, .. }, 2) => *release = value.string(field)?,
+            (Message::Version { os, .. }, 3) => *os = value.string(field)?,
+            (Message::Version { os_version, .. }, 4) => *os_version = value.string(field)?,
+            (Message::Authenticate { username, .. }, 1) => *username = value.string(field)?,
+            (Message::Authenticate { password, .. }, 2) => *password = Some(value.string(field)?),
+            (Message::Authenticate { opus, .. }, 5) => *opus = value.uint()? != 0,
+            (Message::Ping { timestamp }, 1) => *timestamp = value.uint()?,
+            (Message::Reject { reason }, 2) => *reason = Some(value.string(field)?),
+            (Message::ServerSync { session, .. }, 1) => *session = Some(value.uint()? as u32),
+            (Message::ServerSync { welcome_text, .. }, 3) => {
+                *welcome_text = Some(value.string(field)?)
+            }
+            (Message::ChannelRemove { channel_id }, 1) => *channel_id = value.uint()? as u32,
+            (Message::ChannelState { channel_id, .. }, 1) => {
+                *channel_id = Some(value.uint()? as u32)
+            }
+            (Message::ChannelState { parent, .. }, 2) => *parent = Some(value.uint()? as u32),
+            (Message::ChannelState { name, .. }, 3) => *name = Some(value.string(field)?),
+            (Message::UserRemove { session, .. }, 1) => *session = value.uint()? as u32,
+            (Message::UserRemove { actor, .. }, 2) => *actor = Some(value.uint()? as u32),
+            (Message::UserRemove { reason, .. }, 3) => *reason = Some(value.string(field)?),
+            (Message::UserRemove { ban, .. }, 4) => *ban = value.uint()? != 0,
+            (Message::UserState { session, .. }, 1) => *session = Some(value.uint()? as u32),
+            (Message::UserState { name, .. }, 3) => *name = Some(value.string(field)?),
+            (Message::UserState { channel_id, .. }, 5) => *channel_id = Some(value.uint()? as u32),
+            (Message::UserState { hash, .. }, 17) => *hash = Some(value.string(field)?),
+            (Message::TextMessage { actor, .. }, 1) => *actor = Some(value.uint()? as u32),
+            (Message::TextMessage { session, .. }, 2) => session.extend(value.repeated()?),
+            (Message::TextMessage { channel_id, .. }, 3) => channel_id.extend(value.repeated()?),
+            (Message::TextMessage { message, .. }, 5) => *message = value.string(field)?,
+            (Message::CodecVersion { opus }, 4) => *opus = value.uint()? != 0,
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+/// Buffer for received data, splits it into frames
+#[derive(Default)]
+pub struct FrameBuffer {
+    buffer: Vec<u8>,
+}
+
+impl FrameBuffer {
+    /// Add received data
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Returns next complete message
+    pub fn next_message(&mut self) -> Fallible<Option<Message>> {
+        if self.buffer.len() < FRAME_HEADER_LEN {
+            return Ok(None);
+        }
+        let kind = u16::from(self.buffer[0]) << 8 | u16::from(self.buffer[1]);
+        let len = self.buffer[2..FRAME_HEADER_LEN]
+            .iter()
+            .fold(0usize, |acc, v| acc << 8 | *v as usize);
+        if len > FRAME_MAX_LEN {
+            return Err(ProtoErr::FrameTooLarge(len).into());
+        }
+        if self.buffer.len() < FRAME_HEADER_LEN + len {
+            return Ok(None);
+        }
+        let frame: Vec<u8> = self.buffer.drain(..FRAME_HEADER_LEN + len).collect();
+        Message::decode(kind, &frame[FRAME_HEADER_LEN..]).map(Some)
+    }
+}
+
+/// Decoded field value
+enum Value<'a> {
+    Varint(u64),
+    Fixed(u64),
+    Bytes(&'a [u8]),
+}
+
+impl<'a> Value<'a> {
+    fn uint(&self) -> Fallible<u64> {
+        match *self {
+            Value::Varint(v) | Value::Fixed(v) => Ok(v),
+            Value::Bytes(_) => Err(ProtoErr::WireType(WIRE_BYTES).into()),
+        }
+    }
+
+    fn string(&self, field: u32) -> Fallible<String> {
+        match *self {
+            Value::Bytes(v) => {
+                Ok(String::from_utf8(v.to_vec()).map_err(|_| ProtoErr::InvalidString(field))?)
+            }
+            _ => Err(ProtoErr::WireType(WIRE_VARINT).into()),
+        }
+    }
+
+    /// Repeated uint32, packed or not
+    fn repeated(&self) -> Fallible<Vec<u32>> {
+        match *self {
+            Value::Bytes(v) => {
+                let mut reader = Reader::new(v);
+                let mut values = Vec::new();
+                while !reader.is_empty() {
+                    values.push(reader.varint()? as u32);
+                }
+                Ok(values)
+            }
+            _ => Ok(vec![self.uint()? as u32]),
+        }
+    }
+}
+
+/// Protobuf reader
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn varint(&mut self) -> Fallible<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.data.get(self.pos).ok_or(ProtoErr::Truncated)?;
+            self.pos += 1;
+            value |= u64::from(byte & 0x7F) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(ProtoErr::InvalidVarint.into())
+    }
+
+    fn take(&mut self, len: usize) -> Fallible<&'a [u8]> {
+        if self.data.len() - self.pos < len {
+            return Err(ProtoErr::Truncated.into());
+        }
+        let data = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(data)
+    }
+
+    fn next_field(&mut self) -> Fallible<Option<(u32, Value<'a>)>> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let field = (key >> 3) as u32;
+        let value = match (key & 0x7) as u8 {
+            WIRE_VARINT => Value::Varint(self.varint()?),
+            WIRE_FIXED64 => Value::Fixed(
+                self.take(8)?
+                    .iter()
+                    .rev()
+                    .fold(0u64, |acc, v| acc << 8 | u64::from(*v)),
+            ),
+            WIRE_BYTES => {
+                let len = self.varint()? as usize;
+                Value::Bytes(self.take(len)?)
+            }
+            WIRE_FIXED32 => Value::Fixed(
+                self.take(4)?
+                    .iter()
+                    .rev()
+                    .fold(0u64, |acc, v| acc << 8 | u64::from(*v)),
+            ),
+            v => return Err(ProtoErr::WireType(v).into()),
+        };
+        Ok(Some((field, value)))
+    }
+}
+
+/// Protobuf writer
+#[derive(Default)]
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn raw_varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.data.push((value as u8 & 0x7F) | 0x80);
+            value >>= 7;
+        }
+        self.data.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire: u8) {
+        self.raw_varint(u64::from(field) << 3 | u64::from(wire));
+    }
+
+    fn varint(&mut self, field: u32, value: u64) {
+        self.key(field, WIRE_VARINT);
+        self.raw_varint(value);
+    }
+
+    fn opt_varint(&mut self, field: u32, value: Option<u32>) {
+        if let Some(v) = value {
+            self.varint(field, u64::from(v));
+        }
+    }
+
+    fn bool(&mut self, field: u32, value: bool) {
+        self.varint(field, value as u64);
+    }
+
+    fn string(&mut self, field: u32, value: &str) {
+        self.key(field, WIRE_BYTES);
+        self.raw_varint(value.len() as u64);
+        self.data.extend_from_slice(value.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn roundtrip(msg: Message) {
+        let mut buffer = FrameBuffer::default();
+        let frame = msg.encode();
+        let (a, b) = frame.split_at(frame.len() / 2);
+        buffer.push(a);
+        assert_eq!(None, buffer.next_message().unwrap());
+        buffer.push(b);
+        assert_eq!(Some(msg), buffer.next_message().unwrap());
+        assert_eq!(None, buffer.next_message().unwrap());
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        roundtrip(Message::Version {
+            version: PROTOCOL_VERSION,
+            release: String::from("yamba"),
+            os: String::from("Linux"),
+            os_version: String::new(),
+        });
+        roundtrip(Message::Authenticate {
+            username: String::from("yamba"),
+            password: Some(String::from("secret")),
+            opus: true,
+        });
+        roundtrip(Message::Ping { timestamp: 1 << 40 });
+        roundtrip(Message::UserState {
+            session: Some(2),
+            name: Some(String::from("alice")),
+            channel_id: None,
+            hash: Some(String::from("a4f1c0ffee")),
+        });
+        roundtrip(Message::UserRemove {
+            session: 3,
+            actor: Some(1),
+            reason: Some(String::from("bye")),
+            ban: true,
+        });
+        roundtrip(Message::TextMessage {
+            actor: Some(2),
+            session: vec![],
+            channel_id: vec![0, 300],
+            message: String::from("!queue <a href=\"x\">x</a>"),
+        });
+        roundtrip(Message::UdpTunnel(vec![0x80, 1, 2, 3]));
+    }
+
+    #[test]
+    fn test_decode() {
+        // ServerSync session 7, max_bandwidth 72000, welcome "hi"
+        let payload = [0x08, 0x07, 0x10, 0xC0, 0xB2, 0x04, 0x1A, 0x02, b'h', b'i'];
+        assert_eq!(
+            Message::ServerSync {
+                session: Some(7),
+                welcome_text: Some(String::from("hi")),
+            },
+            Message::decode(TYPE_SERVER_SYNC, &payload).unwrap()
+        );
+        // packed sessions 1 & 2
+        let payload = [0x12, 0x02, 0x01, 0x02, 0x2A, 0x01, b'a'];
+        match Message::decode(TYPE_TEXT_MESSAGE, &payload).unwrap() {
+            Message::TextMessage { session, .. } => assert_eq!(vec![1, 2], session),
+            v => panic!("Unexpected message {:?}", v),
+        }
+        assert_eq!(Message::Unknown(24), Message::decode(24, &[0x08]).unwrap());
+        assert!(Message::decode(TYPE_PING, &[0x08]).is_err());
+    }
+
+    #[test]
+    fn test_frame_limit() {
+        let mut buffer = FrameBuffer::default();
+        buffer.push(&[0, 1, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert!(buffer.next_message().is_err());
+    }
+}
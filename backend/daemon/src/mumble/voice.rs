@@ -0,0 +1,228 @@
+/*
+ *  This file is part of yamba.
+ *
+ *  yamba is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  yamba is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with yamba.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use failure::Fallible;
+use gst;
+use gst::prelude::*;
+use gst_app;
+
+use std::sync::mpsc::Sender;
+use std::sync::Mutex;
+
+use super::{MumbleErr, Outgoing};
+
+/// Voice capture & packetizing, Opus frames are sent tunneled through the control channel
+
+/// Voice packet type of Opus
+const VOICE_TYPE_OPUS: u8 = 4;
+/// Sequence numbers count 10ms units, frames are 20ms
+const FRAME_SEQ_STEP: u64 = 2;
+/// Marks the last frame of a transmission
+const OPUS_TERMINATOR: u64 = 0x2000;
+/// Max size of an Opus frame in a voice packet
+const OPUS_MAX_LEN: usize = 0x1FFF;
+/// Frames up to this size are DTX silence
+const SILENCE_MAX_LEN: usize = 2;
+/// Silent frames after which the transmission is ended
+const SILENCE_FRAMES: u32 = 10;
+
+/// Creates voice packets from Opus frames
+/// Stops transmitting during silence, so the bot isn't shown as talking while paused
+#[derive(Default)]
+pub struct VoiceEncoder {
+    seq: u64,
+    silent: u32,
+    talking: bool,
+}
+
+impl VoiceEncoder {
+    /// Returns voice packet for frame, None while silent
+    pub fn packet(&mut self, frame: &[u8]) -> Option<Vec<u8>> {
+        if frame.len() > OPUS_MAX_LEN {
+            warn!("Dropping oversized opus frame of {} bytes", frame.len());
+            return None;
+        }
+        if frame.len() <= SILENCE_MAX_LEN {
+            self.silent = self.silent.saturating_add(1);
+        } else {
+            self.silent = 0;
+        }
+        let last = self.silent >= SILENCE_FRAMES;
+        if last && !self.talking {
+            return None;
+        }
+        self.talking = !last;
+
+        let mut packet = Vec::with_capacity(frame.len() + 8);
+        packet.push(VOICE_TYPE_OPUS << 5);
+        write_varint(&mut packet, self.seq);
+        let mut header = frame.len() as u64;
+        if last {
+            header |= OPUS_TERMINATOR;
+        }
+        write_varint(&mut packet, header);
+        packet.extend_from_slice(frame);
+        self.seq += FRAME_SEQ_STEP;
+        Some(packet)
+    }
+}
+
+/// Mumble varint, different from protobuf
+fn write_varint(buffer: &mut Vec<u8>, value: u64) {
+    if value < 0x80 {
+        buffer.push(value as u8);
+    } else if value < 0x4000 {
+        buffer.extend_from_slice(&[(value >> 8) as u8 | 0x80, value as u8]);
+    } else if value < 0x20_0000 {
+        buffer.extend_from_slice(&[(value >> 16) as u8 | 0xC0, (value >> 8) as u8, value as u8]);
+    } else if value < 0x1000_0000 {
+        buffer.extend_from_slice(&[
+            (value >> 24) as u8 | 0xE0,
+            (value >> 16) as u8,
+            (value >> 8) as u8,
+            value as u8,
+        ]);
+    } else if value <= u64::from(u32::max_value()) {
+        buffer.push(0xF0);
+        buffer.extend_from_slice(&(value as u32).to_be_bytes());
+    } else {
+        buffer.push(0xF4);
+        buffer.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+/// Encodes audio of a pulse source to Opus & sends it as voice packets
+/// Stops on drop
+pub struct VoiceCapture {
+    pipeline: gst::Element,
+}
+
+impl VoiceCapture {
+    /// Start capture of pulse source
+    pub fn start(device: &str, bitrate: u32, outgoing: Sender<Outgoing>) -> Fallible<VoiceCapture> {
+        let pipeline = gst::parse_launch(&format!(
+            "pulsesrc device=\"{}\" ! audioconvert ! audioresample \
+             ! audio/x-raw,rate=48000,channels=1 \
+             ! opusenc bitrate={} frame-size=20 dtx=true \
+             ! appsink name=voice sync=false",
+            device, bitrate
+        ))
+        .map_err(|e| MumbleErr::Pipeline(e.to_string()))?;
+        let sink = pipeline
+            .clone()
+            .dynamic_cast::<gst::Bin>()
+            .map_err(|_| MumbleErr::Pipeline(String::from("Not a bin")))?
+            .get_by_name("voice")
+            .ok_or_else(|| MumbleErr::Pipeline(String::from("Missing appsink")))?
+            .dynamic_cast::<gst_app::AppSink>()
+            .map_err(|_| MumbleErr::Pipeline(String::from("Not an appsink")))?;
+
+        let encoder = Mutex::new(VoiceEncoder::default());
+        let outgoing = Mutex::new(outgoing);
+        sink.set_callbacks(
+            gst_app::AppSinkCallbacks::new()
+                .new_sample(move |sink| {
+                    let sample = match sink.pull_sample() {
+                        Some(v) => v,
+                        None => return gst::FlowReturn::Eos,
+                    };
+                    let buffer = match sample.get_buffer() {
+                        Some(v) => v,
+                        None => return gst::FlowReturn::Error,
+                    };
+                    let map = match buffer.map_readable() {
+                        Some(v) => v,
+                        None => return gst::FlowReturn::Error,
+                    };
+                    let packet = encoder
+                        .lock()
+                        .expect("Can't lock voice encoder!")
+                        .packet(map.as_slice());
+                    if let Some(packet) = packet {
+                        let outgoing = outgoing.lock().expect("Can't lock voice sender!");
+                        if outgoing.send(Outgoing::Voice(packet)).is_err() {
+                            // connection closed
+                            return gst::FlowReturn::Eos;
+                        }
+                    }
+                    gst::FlowReturn::Ok
+                })
+                .build(),
+        );
+
+        if pipeline.set_state(gst::State::Playing) == gst::StateChangeReturn::Failure {
+            return Err(MumbleErr::Pipeline(String::from("Can't start capture")).into());
+        }
+        Ok(VoiceCapture { pipeline })
+    }
+}
+
+impl Drop for VoiceCapture {
+    fn drop(&mut self) {
+        if self.pipeline.set_state(gst::State::Null) == gst::StateChangeReturn::Failure {
+            warn!("Can't stop voice capture");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn varint(value: u64) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        write_varint(&mut buffer, value);
+        buffer
+    }
+
+    #[test]
+    fn test_varint() {
+        assert_eq!(vec![0x7F], varint(0x7F));
+        assert_eq!(vec![0x80, 0x80], varint(0x80));
+        assert_eq!(vec![0xBF, 0xFF], varint(0x3FFF));
+        assert_eq!(vec![0xC0, 0x40, 0x00], varint(0x4000));
+        assert_eq!(vec![0xE1, 0x00, 0x00, 0x00], varint(0x100_0000));
+        assert_eq!(vec![0xF0, 0x10, 0x00, 0x00, 0x00], varint(0x1000_0000));
+        assert_eq!(9, varint(1 << 40).len());
+    }
+
+    #[test]
+    fn test_packets() {
+        let mut encoder = VoiceEncoder::default();
+        let frame = [1u8; 100];
+        assert_eq!(
+            Some([&[0x80, 0x00, 100][..], &frame[..]].concat()),
+            encoder.packet(&frame)
+        );
+        assert_eq!(Some(2), encoder.packet(&frame).map(|v| v[1]));
+
+        // silence is sent until the transmission ends with a terminator
+        for _ in 1..SILENCE_FRAMES {
+            assert!(encoder.packet(&[0xF8]).is_some());
+        }
+        let last = encoder.packet(&[0xF8]).unwrap();
+        assert_eq!(&[0x20 | 0x80, 0x01, 0xF8][..], &last[2..]);
+        assert_eq!(None, encoder.packet(&[0xF8]));
+
+        let next = encoder.packet(&frame).unwrap();
+        assert_eq!(0x80, next[0]);
+        assert_eq!(
+            u64::from(SILENCE_FRAMES + 2) * FRAME_SEQ_STEP,
+            u64::from(next[1])
+        );
+    }
+}
@@ -0,0 +1,198 @@
+/*
+ *  This file is part of yamba.
+ *
+ *  yamba is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  yamba is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with yamba.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use chrono::{offset::Utc, DateTime};
+use failure::Fallible;
+use gst;
+use hashbrown::HashMap;
+use yamba_types::models::{OutputID, OutputInfo, OutputType, RecordFormat};
+
+use std::fs::DirBuilder;
+use std::path::PathBuf;
+
+use daemon::instance::ID;
+use playback::{Player, OUTPUT_SINK_NAME};
+use stream;
+use SETTINGS;
+
+/// Additional outputs of an instance, simulcasting its player
+
+#[derive(Fail, Debug)]
+pub enum OutputErr {
+    #[fail(display = "Output pipeline error: {}", _0)]
+    Pipeline(String),
+    #[fail(display = "Invalid recording path {:?}", _0)]
+    InvalidPath(PathBuf),
+}
+
+/// Attached output
+struct Attached {
+    info: OutputInfo,
+    /// Stream mount, unmounted on detach
+    mount: Option<String>,
+}
+
+/// Outputs of one instance
+pub struct Outputs {
+    instance: ID,
+    next_id: OutputID,
+    attached: HashMap<OutputID, Attached>,
+}
+
+impl Outputs {
+    pub fn new(instance: ID) -> Outputs {
+        Outputs {
+            instance,
+            next_id: 1,
+            attached: HashMap::new(),
+        }
+    }
+
+    /// Attach output to player
+    pub fn attach(&mut self, player: &Player, kind: OutputType) -> Fallible<OutputInfo> {
+        let id = self.next_id;
+        let (bin, location, mount) = match kind {
+            OutputType::Stream { format, bitrate } => {
+                let mount = format!("{}/{}", self.instance, id);
+                let bin = stream::output_bin(mount.clone(), self.instance, format, bitrate)?;
+                (bin, mount.clone(), Some(mount))
+            }
+            OutputType::File { format } => {
+                let path = recording_path(self.instance, id, format, Utc::now());
+                let bin = file_bin(&path, format)?;
+                let name = path
+                    .file_name()
+                    .map(|v| v.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                (bin, name, None)
+            }
+        };
+        if let Err(e) = player.add_output(id, bin) {
+            if let Some(mount) = mount {
+                stream::unmount(&mount);
+            }
+            return Err(e);
+        }
+        self.next_id += 1;
+        let info = OutputInfo {
+            output: id,
+            kind,
+            location,
+        };
+        debug!("Attached output {:?} to instance {}", info, self.instance);
+        self.attached.insert(
+            id,
+            Attached {
+                info: info.clone(),
+                mount,
+            },
+        );
+        Ok(info)
+    }
+
+    /// Detach output, returns false for unknown outputs
+    pub fn detach(&mut self, player: &Player, id: OutputID) -> bool {
+        match self.attached.remove(&id) {
+            Some(attached) => {
+                if let Some(mount) = attached.mount {
+                    stream::unmount(&mount);
+                }
+                player.remove_output(id);
+                debug!("Detached output {} of instance {}", id, self.instance);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Detach all outputs
+    pub fn detach_all(&mut self, player: &Player) {
+        let ids: Vec<OutputID> = self.attached.keys().cloned().collect();
+        for id in ids {
+            self.detach(player, id);
+        }
+    }
+
+    pub fn list(&self) -> Vec<OutputInfo> {
+        let mut outputs: Vec<OutputInfo> = self.attached.values().map(|v| v.info.clone()).collect();
+        outputs.sort_by_key(|v| v.output);
+        outputs
+    }
+}
+
+/// File extension of format
+fn extension(format: RecordFormat) -> &'static str {
+    match format {
+        RecordFormat::OggVorbis => "ogg",
+        RecordFormat::Flac => "flac",
+    }
+}
+
+/// Path of new recording
+fn recording_path(
+    instance: ID,
+    output: OutputID,
+    format: RecordFormat,
+    time: DateTime<Utc>,
+) -> PathBuf {
+    PathBuf::from(&SETTINGS.main.recordings_dir).join(format!(
+        "{}_{}_{}.{}",
+        instance,
+        time.format("%Y%m%d-%H%M%S"),
+        output,
+        extension(format)
+    ))
+}
+
+/// Create output bin recording to file
+fn file_bin(path: &PathBuf, format: RecordFormat) -> Fallible<gst::Bin> {
+    if let Some(parent) = path.parent() {
+        DirBuilder::new().recursive(true).create(parent)?;
+    }
+    let location = path
+        .to_str()
+        .ok_or_else(|| OutputErr::InvalidPath(path.clone()))?;
+    let encoder = match format {
+        RecordFormat::OggVorbis => "vorbisenc ! oggmux",
+        RecordFormat::Flac => "flacenc",
+    };
+    Ok(gst::parse_bin_from_description(
+        &format!(
+            "queue ! audioconvert ! audioresample ! {} ! filesink name={} location=\"{}\" async=false",
+            encoder, OUTPUT_SINK_NAME, location
+        ),
+        true,
+    )
+    .map_err(|e| OutputErr::Pipeline(e.to_string()))?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_recording_path() {
+        let time = Utc.ymd(2019, 3, 4).and_hms(5, 6, 7);
+        let path = recording_path(2, 3, RecordFormat::Flac, time);
+        assert_eq!(
+            Some("2_20190304-050607_3.flac"),
+            path.file_name().and_then(|v| v.to_str())
+        );
+        assert!(path.starts_with(&SETTINGS.main.recordings_dir));
+    }
+}
@@ -21,11 +21,19 @@ use glib::FlagsClass;
 use gst;
 use gst::prelude::*;
 use gst_player::{self, Cast};
+use hashbrown::HashMap;
 
 use std::path::Path;
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
 
 use daemon::instance::ID;
+use effects::EffectsChain;
+use fade::Fader;
+use yamba_types::models::{AudioEffects, OutputID};
+use SETTINGS;
 
 /// Playback abstraction
 
@@ -47,8 +55,10 @@ pub enum PlaybackState {
 #[derive(Debug)]
 pub enum PlayerEventType {
     UriLoaded,
-    MediaInfoUpdated,
-    PositionUpdated(gst::ClockTime),
+    /// Media info changed, contains current title (ICY stream title for radios)
+    MediaInfoUpdated(Option<String>),
+    /// Seek finished at position
+    SeekDone(gst::ClockTime),
     EndOfStream,
     StateChanged(PlaybackState),
     VolumeChanged(f64),
@@ -70,13 +80,58 @@ pub enum PlaybackErr {
     InvalidFilePath(String),
     #[fail(display = "Error during GST call: {}", _0)]
     GST(&'static str),
+    #[fail(display = "Couldn't create {} element", _0)]
+    MissingElement(&'static str),
+}
+
+/// Name of the final sink in output bins, EOS is awaited there on detach
+pub const OUTPUT_SINK_NAME: &'static str = "out";
+
+/// Format all audio is converted to before mixing
+const MIX_CAPS: &'static str = "audio/x-raw,format=S16LE,layout=interleaved,rate=48000,channels=2";
+
+/// Additional output branch of the tee
+struct Output {
+    bin: gst::Bin,
+    tee_pad: gst::Pad,
+}
+
+type ClipID = u32;
+
+/// Clip mixed over the music
+struct Clip {
+    bin: gst::Bin,
+    mixer_pad: gst::Pad,
+}
+
+#[derive(Default)]
+struct Clips {
+    next_id: ClipID,
+    playing: HashMap<ClipID, Clip>,
+}
+
+/// Mixes clips over the music, ducking the music while clips are playing
+#[derive(Clone)]
+struct Mixer {
+    audio_bin: gst::Bin,
+    mixer: gst::Element,
+    duck: gst::Element,
+    clips: Arc<Mutex<Clips>>,
 }
 
 /// Player struct holding the player for one instance
+/// Music & clips are mixed, then split by a tee into the pulse sink & additional outputs  
+/// Volume changes are ramped, the music fades in & out on playback changes
 pub struct Player {
     player: gst_player::Player,
     pulsesink: gst::Element,
-    volume: RwLock<f64>,
+    audio_bin: gst::Bin,
+    tee: gst::Element,
+    mixer: Mixer,
+    effects: EffectsChain,
+    outputs: Mutex<HashMap<OutputID, Output>>,
+    volume: Fader,
+    fade: Fader,
     state: Arc<RwLock<PlaybackState>>,
 }
 
@@ -103,8 +158,8 @@ impl Player {
 
         config.set_name(&name);
 
-        // Get position updates every 1000ms.
-        config.set_position_update_interval(1000);
+        // no periodic position updates, timing is sent on changes
+        config.set_position_update_interval(0);
         player.set_config(config).unwrap();
 
         let playbin = player.get_pipeline();
@@ -123,8 +178,14 @@ impl Player {
 
         let pulsesink = gst::ElementFactory::make("pulsesink", name.as_str())
             .ok_or(PlaybackErr::GST("Couldn't create pulsesink"))?;
+        let effects = EffectsChain::new()?;
+        let volume = Fader::new(volume)?;
+        // silent until played
+        let fade = Fader::new(0.0)?;
+        let (audio_bin, tee, mixer, duck) =
+            Player::create_audio_bin(&name, &pulsesink, &effects, &volume, &fade)?;
         playbin
-            .set_property("audio-sink", &pulsesink)
+            .set_property("audio-sink", &audio_bin)
             .map_err(|_| PlaybackErr::GST("Couldn't set audio sink to playbin!"))?;
 
         let events_clone = events.clone();
@@ -155,26 +216,27 @@ impl Player {
 
         let events_clone = events.clone();
         let id_clone = id.clone();
-        player.connect_media_info_updated(move |_, _| {
+        player.connect_media_info_updated(move |_, info| {
             let mut events = events_clone.clone();
             let id = id_clone.clone();
+            let title = info.get_title().map(|v| v.to_string());
             events
                 .try_send(PlayerEvent {
                     id,
-                    event_type: PlayerEventType::MediaInfoUpdated,
+                    event_type: PlayerEventType::MediaInfoUpdated(title),
                 })
                 .unwrap();
         });
 
         let events_clone = events.clone();
         let id_clone = id.clone();
-        player.connect_position_updated(move |_, time| {
+        player.connect_seek_done(move |_, time| {
             let mut events = events_clone.clone();
             let id = id_clone.clone();
             events
                 .try_send(PlayerEvent {
                     id,
-                    event_type: PlayerEventType::PositionUpdated(time),
+                    event_type: PlayerEventType::SeekDone(time),
                 })
                 .unwrap();
         });
@@ -235,25 +297,166 @@ impl Player {
         Ok(Player {
             player,
             pulsesink,
-            volume: RwLock::new(volume),
+            mixer: Mixer {
+                audio_bin: audio_bin.clone(),
+                mixer,
+                duck,
+                clips: Arc::new(Mutex::new(Clips::default())),
+            },
+            audio_bin,
+            tee,
+            effects,
+            outputs: Mutex::new(HashMap::new()),
+            volume,
+            fade,
             state: state_store,
         })
     }
 
+    /// Create audio sink bin mixing in clips, split into the pulse sink & outputs  
+    /// Music passes volume, effects chain, ducking & fade before mixing  
+    /// Returns the bin, its tee, mixer & duck volume
+    fn create_audio_bin(
+        name: &str,
+        pulsesink: &gst::Element,
+        effects: &EffectsChain,
+        volume: &Fader,
+        fade: &Fader,
+    ) -> Fallible<(gst::Bin, gst::Element, gst::Element, gst::Element)> {
+        let bin = gst::Bin::new(format!("{}_audio", name).as_str());
+        let convert = make_element("audioconvert")?;
+        let resample = make_element("audioresample")?;
+        let capsfilter = make_element("capsfilter")?;
+        let caps = gst::Caps::from_string(MIX_CAPS).ok_or(PlaybackErr::GST("Invalid mix caps"))?;
+        capsfilter.set_property("caps", &caps)?;
+        let duck = make_element("volume")?;
+        let mixer = make_element("audiomixer")?;
+        let tee = make_element("tee")?;
+        // outputs are linked & unlinked at runtime
+        tee.set_property("allow-not-linked", &true)?;
+        let queue = make_element("queue")?;
+        bin.add_many(&[
+            &convert,
+            volume.element(),
+            &resample,
+            &capsfilter,
+            &duck,
+            fade.element(),
+            &mixer,
+            &tee,
+            &queue,
+            pulsesink,
+        ])
+        .map_err(|_| PlaybackErr::GST("Couldn't add elements to audio bin"))?;
+        let effect_elements: Vec<&gst::Element> = effects.elements().iter().collect();
+        bin.add_many(&effect_elements)
+            .map_err(|_| PlaybackErr::GST("Couldn't add effects to audio bin"))?;
+        let mut music = vec![&convert, volume.element()];
+        music.extend(effect_elements);
+        music.extend(&[&resample, &capsfilter, &duck, fade.element(), &mixer]);
+        gst::Element::link_many(&music)
+            .map_err(|_| PlaybackErr::GST("Couldn't link music into mixer"))?;
+        gst::Element::link_many(&[&mixer, &tee, &queue, pulsesink])
+            .map_err(|_| PlaybackErr::GST("Couldn't link audio bin"))?;
+        let pad = convert
+            .get_static_pad("sink")
+            .ok_or(PlaybackErr::GST("Converter without sink pad"))?;
+        let ghost = gst::GhostPad::new("sink", &pad)
+            .ok_or(PlaybackErr::GST("Couldn't create ghost pad"))?;
+        bin.add_pad(&ghost)
+            .map_err(|_| PlaybackErr::GST("Couldn't add ghost pad"))?;
+        Ok((bin, tee, mixer, duck))
+    }
+
+    /// Attach output bin, fed with the same audio as the pulse sink  
+    /// The bin requires a sink pad & its final sink named `OUTPUT_SINK_NAME`
+    pub fn add_output(&self, id: OutputID, bin: gst::Bin) -> Fallible<()> {
+        let sinkpad = bin
+            .get_static_pad("sink")
+            .ok_or(PlaybackErr::GST("Output without sink pad"))?;
+        self.audio_bin
+            .add(&bin)
+            .map_err(|_| PlaybackErr::GST("Couldn't add output"))?;
+        if bin.sync_state_with_parent().is_err() {
+            let _ = self.audio_bin.remove(&bin);
+            return Err(PlaybackErr::GST("Couldn't start output").into());
+        }
+        let tee_pad = match self.tee.get_request_pad("src_%u") {
+            Some(v) => v,
+            None => {
+                remove_child_bin(&self.audio_bin, &bin);
+                return Err(PlaybackErr::GST("Couldn't request tee pad").into());
+            }
+        };
+        if tee_pad.link(&sinkpad) != gst::PadLinkReturn::Ok {
+            self.tee.release_request_pad(&tee_pad);
+            remove_child_bin(&self.audio_bin, &bin);
+            return Err(PlaybackErr::GST("Couldn't link output").into());
+        }
+        self.outputs
+            .lock()
+            .expect("Can't lock outputs!")
+            .insert(id, Output { bin, tee_pad });
+        Ok(())
+    }
+
+    /// Detach output, returns false for unknown outputs  
+    /// Outputs receive an EOS, so muxers can finalize their files
+    pub fn remove_output(&self, id: OutputID) -> bool {
+        let output = match self
+            .outputs
+            .lock()
+            .expect("Can't lock outputs!")
+            .remove(&id)
+        {
+            Some(v) => v,
+            None => return false,
+        };
+        let tee = self.tee.clone();
+        let audio_bin = self.audio_bin.clone();
+        let bin = output.bin;
+        output
+            .tee_pad
+            .add_probe(gst::PadProbeType::IDLE, move |pad, _| {
+                if let Some(sinkpad) = bin.get_static_pad("sink") {
+                    pad.unlink(&sinkpad);
+                    finish_output(&audio_bin, &bin, &sinkpad);
+                }
+                tee.release_request_pad(pad);
+                gst::PadProbeReturn::Remove
+            });
+        true
+    }
+
+    /// Mix clip file over the music, ducking the music while it plays  
+    /// The clip starts at the current mixer position, requires running playback
+    pub fn play_clip(&self, file: &Path, volume: f64) -> Fallible<()> {
+        self.mixer.play(file, volume)
+    }
+
+    /// Amount of currently playing clips
+    pub fn clip_count(&self) -> usize {
+        self.mixer.count()
+    }
+
+    /// Stop all clips, they would restart on a new pipeline start
+    pub fn stop_clips(&self) {
+        self.mixer.stop_all();
+    }
+
     /// Get player name by id, used to identify on sound systems
     pub fn get_name_by_id(id: &i32) -> String {
         format!("YAMBA_Player{}", id)
     }
 
-    /// Set volume as value between 0 and 100
+    /// Set volume as value between 0 and 1, ramped in the background
     pub fn set_volume(&self, volume: f64) {
-        *self.volume.write().expect("Can't write volume") = volume;
-        self.player.set_volume(volume);
+        self.volume.ramp_background(volume, volume_ramp_duration());
     }
 
-    /// Get volume as value between 0 and 100
+    /// Get volume as value between 0 and 1
     pub fn get_volume(&self) -> f64 {
-        *self.volume.read().expect("Can't read volume")
+        self.volume.get()
     }
 
     /// Set uri as media
@@ -262,7 +465,6 @@ impl Player {
     }
 
     /// Set file as media
-    #[allow(dead_code)]
     pub fn set_file(&self, file: &Path) -> Fallible<()> {
         self.set_uri(&format!(
             "file://{}",
@@ -290,16 +492,79 @@ impl Player {
         }
     }
 
-    /// Play current media
+    /// Returns the current position in ms, None without media
+    pub fn get_position_ms(&self) -> Option<u32> {
+        self.player.get_position().mseconds().map(|v| v as u32)
+    }
+
+    /// Seek to position in ms
+    pub fn seek(&self, position_ms: u32) {
+        self.player
+            .seek(gst::ClockTime::from_mseconds(u64::from(position_ms)));
+    }
+
+    /// Returns the current playback rate
+    pub fn get_rate(&self) -> f64 {
+        self.player.get_rate()
+    }
+
+    /// Apply audio effects to the music
+    pub fn set_effects(&self, effects: &AudioEffects) -> Fallible<()> {
+        self.effects.apply(effects)
+    }
+
+    /// Returns current audio effects
+    pub fn get_effects(&self) -> AudioEffects {
+        self.effects.get()
+    }
+
+    /// Set playback rate, kept across tracks
+    pub fn set_rate(&self, rate: f64) {
+        self.player.set_rate(rate);
+    }
+
+    /// Keep the pitch on rate changes via scaletempo  
+    /// Playbin applies audio filters on reconfiguration, so this takes effect with the next track
+    pub fn set_preserve_pitch(&self, preserve: bool) -> Fallible<()> {
+        let filter = match preserve {
+            true => Some(make_element("scaletempo")?),
+            false => None,
+        };
+        self.player
+            .get_pipeline()
+            .set_property("audio-filter", &filter)
+            .map_err(|_| PlaybackErr::GST("Couldn't set audio filter!").into())
+    }
+
+    /// Play current media, fades in
     pub fn play(&self) {
+        if !self.is_playing() {
+            self.fade.set(0.0);
+        }
         self.player.play();
-        self.player
-            .set_volume(*self.volume.read().expect("Can't read volume!"));
+        self.fade.ramp_background(1.0, fade_duration());
+    }
+
+    /// Fade out if playing, blocking  
+    /// Used before changing the media, returns false if cancelled by another fade
+    pub fn fade_out(&self) -> bool {
+        if self.is_playing() {
+            self.fade.ramp(0.0, fade_duration())
+        } else {
+            true
+        }
     }
 
-    /// Pause playback
+    /// Pause playback, fades out in the background  
+    /// Not paused if cancelled, for example by resuming
     pub fn pause(&self) {
-        self.player.pause();
+        if self.is_playing() {
+            let player = self.player.clone();
+            self.fade
+                .ramp_then(0.0, fade_duration(), move || player.pause());
+        } else {
+            self.player.pause();
+        }
     }
 
     /// Whether player is currently paused
@@ -324,12 +589,231 @@ impl Player {
             .map_err(|_| PlaybackErr::GST("Can't set pulse device!").into())
     }
 
-    /// Stop current media
+    /// Stop current media, fades out in the background  
+    /// Not stopped if cancelled, for example by playing new media
     pub fn stop(&self) {
+        if self.is_playing() {
+            let player = self.player.clone();
+            self.fade
+                .ramp_then(0.0, fade_duration(), move || player.stop());
+        } else {
+            self.player.stop();
+        }
+    }
+
+    /// Stop current media instantly, cancels running fades
+    pub fn stop_now(&self) {
+        self.fade.set(0.0);
         self.player.stop();
     }
 }
 
+/// Duration of fades on playback changes
+fn fade_duration() -> Duration {
+    Duration::from_millis(SETTINGS.playback.fade_ms)
+}
+
+/// Duration of volume change ramps
+fn volume_ramp_duration() -> Duration {
+    Duration::from_millis(SETTINGS.playback.volume_ramp_ms)
+}
+
+/// Send EOS through a detached output, remove it after the EOS arrived at its sink
+fn finish_output(parent: &gst::Bin, bin: &gst::Bin, sinkpad: &gst::Pad) {
+    let removed = Arc::new(AtomicBool::new(false));
+    let remove = {
+        let removed = removed.clone();
+        let parent = parent.clone();
+        let bin = bin.clone();
+        move || {
+            if !removed.swap(true, Ordering::SeqCst) {
+                let parent = parent.clone();
+                let bin = bin.clone();
+                // state changes aren't allowed from streaming threads
+                thread::spawn(move || remove_child_bin(&parent, &bin));
+            }
+        }
+    };
+    let eos_pad = bin
+        .get_by_name(OUTPUT_SINK_NAME)
+        .and_then(|v| v.get_static_pad("sink"));
+    if let Some(eos_pad) = eos_pad {
+        let remove = remove.clone();
+        eos_pad.add_probe(gst::PadProbeType::EVENT_DOWNSTREAM, move |_, info| {
+            let eos = match info.data {
+                Some(gst::PadProbeData::Event(ref event)) => {
+                    event.get_type() == gst::EventType::Eos
+                }
+                _ => false,
+            };
+            if !eos {
+                return gst::PadProbeReturn::Ok;
+            }
+            remove();
+            // the bin would wait for an EOS of all sinks otherwise
+            gst::PadProbeReturn::Drop
+        });
+        if sinkpad.send_event(gst::Event::new_eos().build()) {
+            return;
+        }
+    }
+    // not flowing, for example while stopped
+    remove();
+}
+
+impl Mixer {
+    fn play(&self, file: &Path, volume: f64) -> Fallible<()> {
+        let location = file.to_str().ok_or(PlaybackErr::InvalidFilePath(
+            file.to_string_lossy().into_owned(),
+        ))?;
+        let position = self
+            .running_time()
+            .ok_or(PlaybackErr::Player("Mixer not running"))?;
+        let bin = gst::parse_bin_from_description(
+            &format!(
+                "filesrc location=\"{}\" ! decodebin ! audioconvert ! audioresample \
+                 ! volume volume={} ! capsfilter caps=\"{}\"",
+                location, volume, MIX_CAPS
+            ),
+            true,
+        )
+        .map_err(|_| PlaybackErr::GST("Couldn't create clip"))?;
+        let srcpad = bin
+            .get_static_pad("src")
+            .ok_or(PlaybackErr::GST("Clip without src pad"))?;
+        // clip timestamps start at zero, align them to the mixer
+        srcpad.set_offset(position);
+        self.audio_bin
+            .add(&bin)
+            .map_err(|_| PlaybackErr::GST("Couldn't add clip"))?;
+        let mixer_pad = match self.mixer.get_request_pad("sink_%u") {
+            Some(v) => v,
+            None => {
+                remove_child_bin(&self.audio_bin, &bin);
+                return Err(PlaybackErr::GST("Couldn't request mixer pad").into());
+            }
+        };
+        if srcpad.link(&mixer_pad) != gst::PadLinkReturn::Ok {
+            self.mixer.release_request_pad(&mixer_pad);
+            remove_child_bin(&self.audio_bin, &bin);
+            return Err(PlaybackErr::GST("Couldn't link clip").into());
+        }
+        let id = {
+            let mut clips = self.clips.lock().expect("Can't lock clips!");
+            let id = clips.next_id;
+            clips.next_id = clips.next_id.wrapping_add(1);
+            clips.playing.insert(
+                id,
+                Clip {
+                    bin: bin.clone(),
+                    mixer_pad,
+                },
+            );
+            id
+        };
+        let mixer = self.clone();
+        srcpad.add_probe(gst::PadProbeType::EVENT_DOWNSTREAM, move |_, info| {
+            let eos = match info.data {
+                Some(gst::PadProbeData::Event(ref event)) => {
+                    event.get_type() == gst::EventType::Eos
+                }
+                _ => false,
+            };
+            if eos {
+                let mixer = mixer.clone();
+                // state changes aren't allowed from streaming threads
+                thread::spawn(move || mixer.remove(id));
+            }
+            gst::PadProbeReturn::Ok
+        });
+        self.update_ducking();
+        if bin.sync_state_with_parent().is_err() {
+            self.remove(id);
+            return Err(PlaybackErr::GST("Couldn't start clip").into());
+        }
+        Ok(())
+    }
+
+    fn count(&self) -> usize {
+        self.clips.lock().expect("Can't lock clips!").playing.len()
+    }
+
+    /// Running time of the mixer output, new clips are mixed in from there
+    fn running_time(&self) -> Option<i64> {
+        let pad = self.mixer.get_static_pad("src")?;
+        let position = pad.query_position::<gst::ClockTime>()?;
+        let event = pad.get_sticky_event(gst::EventType::Segment, 0)?;
+        let running_time = match event.view() {
+            gst::EventView::Segment(e) => e
+                .get_segment()
+                .downcast_ref::<gst::ClockTime>()?
+                .to_running_time(position),
+            _ => return None,
+        };
+        running_time.nanoseconds().map(|v| v as i64)
+    }
+
+    /// Unlink & remove clip
+    fn remove(&self, id: ClipID) {
+        let clip = match self
+            .clips
+            .lock()
+            .expect("Can't lock clips!")
+            .playing
+            .remove(&id)
+        {
+            Some(v) => v,
+            None => return,
+        };
+        if let Some(srcpad) = clip.bin.get_static_pad("src") {
+            srcpad.unlink(&clip.mixer_pad);
+        }
+        self.mixer.release_request_pad(&clip.mixer_pad);
+        remove_child_bin(&self.audio_bin, &clip.bin);
+        self.update_ducking();
+    }
+
+    fn stop_all(&self) {
+        let ids: Vec<ClipID> = self
+            .clips
+            .lock()
+            .expect("Can't lock clips!")
+            .playing
+            .keys()
+            .cloned()
+            .collect();
+        for id in ids {
+            self.remove(id);
+        }
+    }
+
+    /// Duck the music while clips are playing
+    fn update_ducking(&self) {
+        let volume = match self.count() {
+            0 => 1.0,
+            _ => SETTINGS.soundboard.duck_volume,
+        };
+        if self.duck.set_property("volume", &volume).is_err() {
+            warn!("Can't set duck volume");
+        }
+    }
+}
+
+/// Create element of factory
+pub fn make_element(factory: &'static str) -> Fallible<gst::Element> {
+    Ok(gst::ElementFactory::make(factory, None).ok_or(PlaybackErr::MissingElement(factory))?)
+}
+
+/// Stop & remove bin of the audio bin
+fn remove_child_bin(parent: &gst::Bin, bin: &gst::Bin) {
+    if bin.set_state(gst::State::Null) == gst::StateChangeReturn::Failure {
+        warn!("Can't stop player bin");
+    }
+    if parent.remove(bin).is_err() {
+        warn!("Can't remove player bin");
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -375,7 +859,7 @@ mod tests {
         sender
             .try_send(PlayerEvent {
                 id: TEST_ID.clone(),
-                event_type: PlayerEventType::PositionUpdated(gst::ClockTime::from_mseconds(1000)),
+                event_type: PlayerEventType::SeekDone(gst::ClockTime::from_mseconds(1000)),
             })
             .unwrap();
         loop {
@@ -389,9 +873,7 @@ mod tests {
             sender
                 .try_send(PlayerEvent {
                     id: TEST_ID.clone(),
-                    event_type: PlayerEventType::PositionUpdated(gst::ClockTime::from_mseconds(
-                        1000,
-                    )),
+                    event_type: PlayerEventType::SeekDone(gst::ClockTime::from_mseconds(1000)),
                 })
                 .unwrap();
         }
@@ -0,0 +1,214 @@
+/*
+ *  This file is part of yamba.
+ *
+ *  yamba is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  yamba is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with yamba.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use failure::Fallible;
+use futures::Future;
+use tokio::runtime::TaskExecutor;
+use tokio::timer::Delay;
+use yamba_types::models::WorkerPoolStats;
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Bounded worker pool for blocking jobs
+
+pub type Job = Box<dyn FnOnce() + Send + 'static>;
+
+#[derive(Fail, Debug)]
+pub enum PoolErr {
+    #[fail(display = "Worker pool {} queue is full", _0)]
+    QueueFull(&'static str),
+    #[fail(display = "Worker pool {} is shut down", _0)]
+    Disconnected(&'static str),
+}
+
+#[derive(Default)]
+struct Metrics {
+    queued: AtomicUsize,
+    active: AtomicUsize,
+    completed: AtomicUsize,
+    rejected: AtomicUsize,
+}
+
+#[derive(Clone)]
+pub struct WorkerPool {
+    name: &'static str,
+    workers: usize,
+    sender: Arc<Mutex<SyncSender<Job>>>,
+    metrics: Arc<Metrics>,
+    executor: TaskExecutor,
+}
+
+impl WorkerPool {
+    /// Create pool with amount of workers & max amount of queued jobs
+    /// Executor is used for delayed jobs
+    pub fn new(
+        name: &'static str,
+        workers: usize,
+        backlog: usize,
+        executor: TaskExecutor,
+    ) -> Fallible<WorkerPool> {
+        let (sender, receiver) = sync_channel::<Job>(backlog);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let metrics = Arc::new(Metrics::default());
+        for i in 0..workers.max(1) {
+            let receiver = receiver.clone();
+            let metrics = metrics.clone();
+            thread::Builder::new()
+                .name(format!("{}-{}", name, i))
+                .spawn(move || worker(name, receiver, metrics))?;
+        }
+        Ok(WorkerPool {
+            name,
+            workers: workers.max(1),
+            sender: Arc::new(Mutex::new(sender)),
+            metrics,
+            executor,
+        })
+    }
+
+    /// Queue job, fails if the backlog is full
+    pub fn execute<F>(&self, job: F) -> Fallible<()>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.metrics.queued.fetch_add(1, Ordering::SeqCst);
+        let result = self
+            .sender
+            .lock()
+            .expect("Can't lock pool sender!")
+            .try_send(Box::new(job));
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                self.metrics.queued.fetch_sub(1, Ordering::SeqCst);
+                self.metrics.rejected.fetch_add(1, Ordering::Relaxed);
+                match e {
+                    TrySendError::Full(_) => Err(PoolErr::QueueFull(self.name).into()),
+                    TrySendError::Disconnected(_) => Err(PoolErr::Disconnected(self.name).into()),
+                }
+            }
+        }
+    }
+
+    /// Queue job after delay, without blocking a worker
+    pub fn execute_after<F>(&self, delay: Duration, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let pool = self.clone();
+        self.executor.spawn(
+            Delay::new(Instant::now() + delay)
+                .map_err(|e| warn!("Timer error: {}", e))
+                .map(move |_| {
+                    if let Err(e) = pool.execute(job) {
+                        warn!("Can't execute delayed job: {}", e);
+                    }
+                }),
+        );
+    }
+
+    /// Returns current metrics
+    pub fn stats(&self) -> WorkerPoolStats {
+        WorkerPoolStats {
+            workers: self.workers,
+            queued: self.metrics.queued.load(Ordering::Relaxed),
+            active: self.metrics.active.load(Ordering::Relaxed),
+            completed: self.metrics.completed.load(Ordering::Relaxed),
+            rejected: self.metrics.rejected.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Worker loop, exits when all pool handles are dropped
+fn worker(name: &'static str, receiver: Arc<Mutex<Receiver<Job>>>, metrics: Arc<Metrics>) {
+    loop {
+        let job = {
+            let lock = receiver.lock().expect("Can't lock pool receiver!");
+            match lock.recv() {
+                Ok(v) => v,
+                Err(_) => break,
+            }
+        };
+        metrics.queued.fetch_sub(1, Ordering::SeqCst);
+        metrics.active.fetch_add(1, Ordering::SeqCst);
+        if catch_unwind(AssertUnwindSafe(job)).is_err() {
+            error!("Job panicked in worker pool {}", name);
+        }
+        metrics.active.fetch_sub(1, Ordering::SeqCst);
+        metrics.completed.fetch_add(1, Ordering::Relaxed);
+    }
+    trace!("Worker of pool {} stopped", name);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Barrier;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn test_bounded() {
+        let rt = Runtime::new().unwrap();
+        let pool = WorkerPool::new("test", 1, 1, rt.executor()).unwrap();
+        let barrier = Arc::new(Barrier::new(2));
+        let barrier_c = barrier.clone();
+        // blocks the only worker
+        pool.execute(move || {
+            barrier_c.wait();
+        })
+        .unwrap();
+        while pool.stats().active == 0 {
+            thread::sleep(Duration::from_millis(10));
+        }
+        pool.execute(|| ()).unwrap();
+        assert!(pool.execute(|| ()).is_err());
+        let stats = pool.stats();
+        assert_eq!(1, stats.queued);
+        assert_eq!(1, stats.rejected);
+        barrier.wait();
+        while pool.stats().completed < 2 {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(0, pool.stats().queued);
+    }
+
+    #[test]
+    fn test_panic() {
+        let rt = Runtime::new().unwrap();
+        let pool = WorkerPool::new("test", 1, 4, rt.executor()).unwrap();
+        pool.execute(|| panic!("job panic")).unwrap();
+        let (send, recv) = ::std::sync::mpsc::channel();
+        pool.execute(move || send.send(1).unwrap()).unwrap();
+        assert_eq!(Ok(1), recv.recv_timeout(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_delayed() {
+        let rt = Runtime::new().unwrap();
+        let pool = WorkerPool::new("test", 1, 4, rt.executor()).unwrap();
+        let (send, recv) = ::std::sync::mpsc::channel();
+        let start = Instant::now();
+        pool.execute_after(Duration::from_millis(100), move || send.send(1).unwrap());
+        assert_eq!(Ok(1), recv.recv_timeout(Duration::from_secs(2)));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,246 @@
+/*
+ *  This file is part of yamba.
+ *
+ *  yamba is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  yamba is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with yamba.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use chrono::offset::Utc;
+use failure::Fallible;
+use yamba_types::models::{OutputID, RecordFormat, Recording, TrackMarker};
+
+use std::ffi::OsStr;
+use std::fs::{read_dir, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use daemon::instance::ID;
+use SETTINGS;
+
+/// Recordings of instances with track markers
+
+const SIDECAR_EXTENSION: &'static str = "json";
+
+#[derive(Fail, Debug)]
+pub enum RecordingErr {
+    #[fail(display = "Instance is already recording")]
+    AlreadyRecording,
+}
+
+/// Measures recorded time, only running while the player is playing
+#[derive(Debug, Default)]
+struct Stopwatch {
+    elapsed: Duration,
+    running_since: Option<Instant>,
+}
+
+impl Stopwatch {
+    fn set_running(&mut self, running: bool, now: Instant) {
+        match (running, self.running_since) {
+            (true, None) => self.running_since = Some(now),
+            (false, Some(since)) => {
+                self.elapsed += now.duration_since(since);
+                self.running_since = None;
+            }
+            _ => (),
+        }
+    }
+
+    fn elapsed(&self, now: Instant) -> Duration {
+        match self.running_since {
+            Some(since) => self.elapsed + now.duration_since(since),
+            None => self.elapsed,
+        }
+    }
+}
+
+fn as_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+}
+
+/// Running recording of an instance
+pub struct ActiveRecording {
+    output: OutputID,
+    data: Recording,
+    clock: Stopwatch,
+}
+
+impl ActiveRecording {
+    /// Create recording of the file output `output`
+    pub fn new(
+        output: OutputID,
+        file: String,
+        instance: ID,
+        format: RecordFormat,
+        playing: bool,
+    ) -> ActiveRecording {
+        let mut clock = Stopwatch::default();
+        clock.set_running(playing, Instant::now());
+        ActiveRecording {
+            output,
+            data: Recording {
+                file,
+                instance,
+                format,
+                started: Utc::now().timestamp(),
+                duration_ms: 0,
+                active: true,
+                markers: Vec::new(),
+            },
+            clock,
+        }
+    }
+
+    pub fn get_output(&self) -> OutputID {
+        self.output
+    }
+
+    /// Returns current state of the recording
+    pub fn get_data(&self) -> Recording {
+        let mut data = self.data.clone();
+        data.duration_ms = as_millis(self.clock.elapsed(Instant::now()));
+        data
+    }
+
+    /// Update player state, recorded time doesn't advance while paused
+    pub fn set_playing(&mut self, playing: bool) {
+        self.clock.set_running(playing, Instant::now());
+    }
+
+    /// Add track marker at the current position & update the sidecar
+    pub fn mark(&mut self, title: String, artist: Option<String>, source: String) {
+        let offset_ms = as_millis(self.clock.elapsed(Instant::now()));
+        self.data.markers.push(TrackMarker {
+            offset_ms,
+            title,
+            artist,
+            source,
+        });
+        self.save();
+    }
+
+    /// Update the sidecar, listing the recording while it's running
+    pub fn save(&self) {
+        if let Err(e) = write_sidecar(&self.get_data()) {
+            warn!("Can't write recording sidecar: {}", e);
+        }
+    }
+
+    /// Finish recording, writes the final sidecar
+    pub fn finish(self) -> Fallible<Recording> {
+        let mut data = self.get_data();
+        data.active = false;
+        write_sidecar(&data)?;
+        Ok(data)
+    }
+}
+
+/// Sidecar of recording file
+fn sidecar_path(file: &str) -> PathBuf {
+    Path::new(&SETTINGS.main.recordings_dir).join(format!("{}.{}", file, SIDECAR_EXTENSION))
+}
+
+fn write_sidecar(data: &Recording) -> Fallible<()> {
+    let file = File::create(sidecar_path(&data.file))?;
+    serde_json::to_writer_pretty(BufWriter::new(file), data)?;
+    Ok(())
+}
+
+fn read_sidecar(path: &Path) -> Fallible<Recording> {
+    let file = File::open(path)?;
+    Ok(serde_json::from_reader(BufReader::new(file))?)
+}
+
+/// List recordings of instance, sorted by start
+pub fn list(instance: ID) -> Fallible<Vec<Recording>> {
+    let dir = Path::new(&SETTINGS.main.recordings_dir);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut recordings = Vec::new();
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension() != Some(OsStr::new(SIDECAR_EXTENSION)) {
+            continue;
+        }
+        let recording = match read_sidecar(&path) {
+            Ok(v) => v,
+            Err(e) => {
+                warn!("Invalid recording sidecar {:?}: {}", path, e);
+                continue;
+            }
+        };
+        if recording.instance == instance {
+            recordings.push(recording);
+        }
+    }
+    recordings.sort_by_key(|v| v.started);
+    Ok(recordings)
+}
+
+/// Path of recording or sidecar for download, None for invalid names
+pub fn file_path(name: &str) -> Option<PathBuf> {
+    if !is_valid_name(name) {
+        return None;
+    }
+    let path = Path::new(&SETTINGS.main.recordings_dir).join(name);
+    match path.is_file() {
+        true => Some(path),
+        false => None,
+    }
+}
+
+/// Only plain file names, no traversal
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains(|c| c == '/' || c == '\\')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stopwatch() {
+        let start = Instant::now();
+        let mut clock = Stopwatch::default();
+        assert_eq!(Duration::from_secs(0), clock.elapsed(start));
+        clock.set_running(true, start);
+        clock.set_running(true, start + Duration::from_secs(1));
+        assert_eq!(
+            Duration::from_secs(2),
+            clock.elapsed(start + Duration::from_secs(2))
+        );
+        clock.set_running(false, start + Duration::from_secs(3));
+        // paused
+        assert_eq!(
+            Duration::from_secs(3),
+            clock.elapsed(start + Duration::from_secs(10))
+        );
+        clock.set_running(true, start + Duration::from_secs(10));
+        assert_eq!(
+            Duration::from_secs(5),
+            clock.elapsed(start + Duration::from_secs(12))
+        );
+    }
+
+    #[test]
+    fn test_valid_name() {
+        assert!(is_valid_name("1_20190304-050607_1.ogg"));
+        assert!(is_valid_name("1_20190304-050607_1.ogg.json"));
+        assert!(!is_valid_name("../config.toml"));
+        assert!(!is_valid_name("a/b.ogg"));
+        assert!(!is_valid_name(".."));
+        assert!(!is_valid_name(""));
+    }
+}
@@ -0,0 +1,198 @@
+/*
+ *  This file is part of yamba.
+ *
+ *  yamba is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  yamba is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with yamba.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use hashbrown::HashMap;
+
+use std::hash::Hash;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Single-flight execution
+/// Concurrent calls for the same key wait for & share the result of the first one
+
+enum State<V, E> {
+    Pending,
+    Done(Result<V, E>),
+    /// Leader panicked, waiters have to retry
+    Abandoned,
+}
+
+struct Flight<V, E> {
+    state: Mutex<State<V, E>>,
+    cvar: Condvar,
+}
+
+pub struct SingleFlight<K, V, E> {
+    flights: Mutex<HashMap<K, Arc<Flight<V, E>>>>,
+    coalesced: AtomicUsize,
+}
+
+/// Removes the flight on completion or panic of the leader
+struct FlightGuard<'a, K: 'a + Hash + Eq, V: 'a, E: 'a> {
+    parent: &'a SingleFlight<K, V, E>,
+    key: K,
+    flight: Arc<Flight<V, E>>,
+}
+
+impl<'a, K: 'a + Hash + Eq, V: 'a, E: 'a> Drop for FlightGuard<'a, K, V, E> {
+    fn drop(&mut self) {
+        // remove first, so waiters of an abandoned flight start a new one
+        self.parent
+            .flights
+            .lock()
+            .expect("Can't lock flights!")
+            .remove(&self.key);
+        {
+            let mut state = self.flight.state.lock().expect("Can't lock flight!");
+            if let State::Pending = *state {
+                *state = State::Abandoned;
+            }
+        }
+        self.flight.cvar.notify_all();
+    }
+}
+
+impl<K, V, E> SingleFlight<K, V, E>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+    E: Clone,
+{
+    pub fn new() -> SingleFlight<K, V, E> {
+        SingleFlight {
+            flights: Mutex::new(HashMap::new()),
+            coalesced: AtomicUsize::new(0),
+        }
+    }
+
+    /// Run f for key, or wait for the result of the call already in flight for key
+    pub fn run<F>(&self, key: K, f: F) -> Result<V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+    {
+        loop {
+            let (flight, leader) = {
+                let mut flights = self.flights.lock().expect("Can't lock flights!");
+                match flights.get(&key) {
+                    Some(v) => (v.clone(), false),
+                    None => {
+                        let flight = Arc::new(Flight {
+                            state: Mutex::new(State::Pending),
+                            cvar: Condvar::new(),
+                        });
+                        flights.insert(key.clone(), flight.clone());
+                        (flight, true)
+                    }
+                }
+            };
+
+            if leader {
+                let guard = FlightGuard {
+                    parent: self,
+                    key,
+                    flight,
+                };
+                let result = f();
+                *guard.flight.state.lock().expect("Can't lock flight!") =
+                    State::Done(result.clone());
+                return result;
+            }
+
+            self.coalesced.fetch_add(1, Ordering::Relaxed);
+            let mut state = flight.state.lock().expect("Can't lock flight!");
+            loop {
+                match *state {
+                    State::Pending => (),
+                    State::Done(ref v) => return v.clone(),
+                    State::Abandoned => break,
+                }
+                state = flight.cvar.wait(state).expect("Can't lock flight!");
+            }
+        }
+    }
+
+    /// Amount of calls which waited for another call
+    pub fn coalesced(&self) -> usize {
+        self.coalesced.load(Ordering::Relaxed)
+    }
+
+    /// Amount of calls currently in flight
+    pub fn in_flight(&self) -> usize {
+        self.flights.lock().expect("Can't lock flights!").len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Barrier;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_coalescing() {
+        let flights: Arc<SingleFlight<String, usize, ()>> = Arc::new(SingleFlight::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(4));
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let flights = flights.clone();
+                let calls = calls.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    flights.run("url".to_string(), || {
+                        thread::sleep(Duration::from_millis(200));
+                        Ok(calls.fetch_add(1, Ordering::SeqCst))
+                    })
+                })
+            })
+            .collect();
+        for h in handles {
+            assert_eq!(Ok(0), h.join().unwrap());
+        }
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+        assert_eq!(3, flights.coalesced());
+        assert_eq!(0, flights.in_flight());
+    }
+
+    #[test]
+    fn test_sequential() {
+        let flights: SingleFlight<&str, usize, String> = SingleFlight::new();
+        assert_eq!(Ok(1), flights.run("a", || Ok(1)));
+        assert_eq!(
+            Err("b".to_string()),
+            flights.run("a", || Err("b".to_string()))
+        );
+        assert_eq!(0, flights.coalesced());
+    }
+
+    #[test]
+    fn test_abandoned() {
+        let flights: Arc<SingleFlight<&str, usize, ()>> = Arc::new(SingleFlight::new());
+        let flights_c = flights.clone();
+        let leader = thread::spawn(move || {
+            flights_c.run("a", || {
+                thread::sleep(Duration::from_millis(200));
+                panic!("leader panic");
+            })
+        });
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(Ok(2), flights.run("a", || Ok(2)));
+        assert!(leader.join().is_err());
+    }
+}
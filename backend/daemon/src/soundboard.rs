@@ -0,0 +1,179 @@
+/*
+ *  This file is part of yamba.
+ *
+ *  yamba is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  yamba is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with yamba.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use failure::Fallible;
+use reqwest;
+use yamba_types::models::SongID;
+
+use std::fs::{read_dir, remove_file, rename, DirBuilder, File};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use SETTINGS;
+
+/// Soundboard clips, fetched from the manager & cached by revision
+
+/// Max length of clip names
+const NAME_MAX_LENGTH: usize = 64;
+/// Song ID prefix of jingles, not valid in clip names
+const JINGLE_PREFIX: &'static str = "jingle:";
+
+lazy_static! {
+    /// Unique part files for concurrent downloads
+    static ref DOWNLOADS: AtomicUsize = AtomicUsize::new(0);
+}
+
+#[derive(Fail, Debug)]
+pub enum SoundboardErr {
+    #[fail(display = "Invalid clip name {}", _0)]
+    InvalidName(String),
+    #[fail(display = "Clips require running playback")]
+    NotPlaying,
+    #[fail(display = "Max of {} concurrent clips reached", _0)]
+    TooManyClips(usize),
+}
+
+/// Clip names are used in URLs & file names
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= NAME_MAX_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Song ID of jingle playing a clip revision
+pub fn jingle_song_id(name: &str, revision: i64) -> SongID {
+    format!("{}{}", JINGLE_PREFIX, cache_name(name, revision))
+}
+
+pub fn is_jingle(song: &SongID) -> bool {
+    song.starts_with(JINGLE_PREFIX)
+}
+
+/// Clip download URL of the manager
+fn clip_url(name: &str) -> String {
+    format!(
+        "http://{}:{}/clip/{}",
+        SETTINGS.main.api_jsonrpc_ip, SETTINGS.main.api_jsonrpc_port, name
+    )
+}
+
+/// Cache file name of clip revision
+fn cache_name(name: &str, revision: i64) -> String {
+    format!("{}_{}", name, revision)
+}
+
+/// Whether the cache file name is another revision of the clip
+fn is_other_revision(file: &str, name: &str, revision: i64) -> bool {
+    if !file.starts_with(name) || file == cache_name(name, revision) {
+        return false;
+    }
+    match file[name.len()..].chars().next() {
+        Some('_') => file[name.len() + 1..].parse::<i64>().is_ok(),
+        _ => false,
+    }
+}
+
+/// Returns path of the clip, downloads it from the manager if not cached  
+/// Blocking
+pub fn fetch(name: &str, revision: i64) -> Fallible<PathBuf> {
+    if !is_valid_name(name) {
+        return Err(SoundboardErr::InvalidName(name.to_string()).into());
+    }
+    let dir = Path::new(&SETTINGS.soundboard.dir);
+    let path = dir.join(cache_name(name, revision));
+    if path.is_file() {
+        return Ok(path);
+    }
+    DirBuilder::new().recursive(true).create(dir)?;
+    let part = dir.join(format!(
+        "{}.part{}",
+        cache_name(name, revision),
+        DOWNLOADS.fetch_add(1, Ordering::Relaxed)
+    ));
+    if let Err(e) = download(&clip_url(name), &part, &path) {
+        let _ = remove_file(&part);
+        return Err(e);
+    }
+    debug!("Cached clip {} revision {}", name, revision);
+    remove_revisions(dir, name, revision);
+    Ok(path)
+}
+
+/// Download into part file, moved to path when complete
+fn download(url: &str, part: &Path, path: &Path) -> Fallible<()> {
+    let mut response = reqwest::get(url)?.error_for_status()?;
+    let mut file = File::create(part)?;
+    response.copy_to(&mut file)?;
+    rename(part, path)?;
+    Ok(())
+}
+
+/// Remove outdated revisions of clip
+fn remove_revisions(dir: &Path, name: &str, revision: i64) {
+    let entries = match read_dir(dir) {
+        Ok(v) => v,
+        Err(e) => {
+            warn!("Can't read clip cache: {}", e);
+            return;
+        }
+    };
+    for entry in entries.filter_map(|v| v.ok()) {
+        let outdated = entry
+            .file_name()
+            .to_str()
+            .map(|v| is_other_revision(v, name, revision))
+            .unwrap_or(false);
+        if outdated {
+            if let Err(e) = remove_file(entry.path()) {
+                warn!("Can't remove outdated clip {:?}: {}", entry.path(), e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_valid_name() {
+        assert!(is_valid_name("air_horn-2"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("../config"));
+        assert!(!is_valid_name("air horn"));
+        assert!(!is_valid_name(&"a".repeat(NAME_MAX_LENGTH + 1)));
+    }
+
+    #[test]
+    fn test_jingle_song_id() {
+        assert!(is_jingle(&jingle_song_id("horn", 2)));
+        assert!(!is_jingle(&String::from("dQw4w9WgXcQ")));
+    }
+
+    #[test]
+    fn test_other_revision() {
+        assert!(is_other_revision("horn_1", "horn", 2));
+        assert!(!is_other_revision("horn_2", "horn", 2));
+        // other clips sharing the prefix
+        assert!(!is_other_revision("horn_big_1", "horn", 2));
+        assert!(!is_other_revision("horns_1", "horn", 2));
+        // running downloads
+        assert!(!is_other_revision("horn_1.part0", "horn", 2));
+    }
+}
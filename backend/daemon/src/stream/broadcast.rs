@@ -0,0 +1,197 @@
+/*
+ *  This file is part of yamba.
+ *
+ *  yamba is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  yamba is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with yamba.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use futures::sync::mpsc::{channel, Receiver, Sender};
+use yamba_types::models::{StreamFormat, StreamStatus};
+
+use std::sync::{Arc, Mutex};
+
+use super::StreamErr;
+use daemon::instance::ID;
+
+/// Distribution of encoded audio to HTTP listeners
+
+/// Buffered chunks per listener, slower listeners are dropped
+const LISTENER_BUFFER: usize = 256;
+
+/// Chunk of encoded audio, shared between listeners
+pub type Frame = Arc<Vec<u8>>;
+
+/// Encoded stream with its listeners
+pub struct Broadcast {
+    instance: ID,
+    format: StreamFormat,
+    name: Option<String>,
+    max_listeners: u32,
+    inner: Mutex<BroadcastInner>,
+}
+
+struct BroadcastInner {
+    /// Stream headers, sent first to each listener
+    headers: Vec<Frame>,
+    /// Data after the headers was sent
+    headers_done: bool,
+    listeners: Vec<Sender<Frame>>,
+    listeners_peak: u32,
+}
+
+impl Broadcast {
+    pub fn new(
+        instance: ID,
+        format: StreamFormat,
+        name: Option<String>,
+        max_listeners: u32,
+    ) -> Broadcast {
+        Broadcast {
+            instance,
+            format,
+            name,
+            max_listeners,
+            inner: Mutex::new(BroadcastInner {
+                headers: Vec::new(),
+                headers_done: false,
+                listeners: Vec::new(),
+                listeners_peak: 0,
+            }),
+        }
+    }
+
+    pub fn get_format(&self) -> StreamFormat {
+        self.format
+    }
+
+    pub fn get_name(&self) -> Option<&String> {
+        self.name.as_ref()
+    }
+
+    /// Send encoded data to all listeners
+    /// `header` marks stream headers, which new listeners receive first
+    pub fn push(&self, data: &[u8], header: bool) {
+        let mut inner = self.inner.lock().expect("Can't lock broadcast!");
+        let frame = Arc::new(data.to_vec());
+        if header {
+            // new stream sequence, for example after an encoder restart
+            if inner.headers_done {
+                inner.headers.clear();
+                inner.headers_done = false;
+            }
+            inner.headers.push(frame.clone());
+        } else {
+            inner.headers_done = true;
+        }
+        let instance = self.instance;
+        let listeners = inner
+            .listeners
+            .drain(..)
+            .filter_map(|mut l| match l.try_send(frame.clone()) {
+                Ok(_) => Some(l),
+                Err(ref e) if e.is_full() => {
+                    debug!("Dropping slow listener of stream {}", instance);
+                    None
+                }
+                Err(_) => None,
+            })
+            .collect();
+        inner.listeners = listeners;
+    }
+
+    /// Add listener
+    /// Returns headers to send first & receiver of all following data
+    pub fn subscribe(&self) -> Result<(Vec<Frame>, Receiver<Frame>), StreamErr> {
+        let mut inner = self.inner.lock().expect("Can't lock broadcast!");
+        if inner.listeners.len() as u32 >= self.max_listeners {
+            return Err(StreamErr::ListenersExceeded(self.max_listeners));
+        }
+        let (sender, receiver) = channel(LISTENER_BUFFER);
+        inner.listeners.push(sender);
+        let listeners = inner.listeners.len() as u32;
+        if listeners > inner.listeners_peak {
+            inner.listeners_peak = listeners;
+        }
+        Ok((inner.headers.clone(), receiver))
+    }
+
+    /// Status of stream mounted at `mount`
+    pub fn status(&self, mount: &str) -> StreamStatus {
+        let inner = self.inner.lock().expect("Can't lock broadcast!");
+        StreamStatus {
+            mount: mount.to_string(),
+            instance: self.instance,
+            format: self.format,
+            listeners: inner.listeners.len() as u32,
+            listeners_peak: inner.listeners_peak,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::Stream;
+
+    fn receive(receiver: Receiver<Frame>, amount: usize) -> Vec<Vec<u8>> {
+        receiver
+            .wait()
+            .take(amount)
+            .map(|v| (*v.unwrap()).clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_headers() {
+        let broadcast = Broadcast::new(1, StreamFormat::OggOpus, None, 10);
+        broadcast.push(&[1], true);
+        broadcast.push(&[2], true);
+        broadcast.push(&[3], false);
+        let (headers, receiver) = broadcast.subscribe().unwrap();
+        assert_eq!(
+            vec![vec![1], vec![2]],
+            headers.iter().map(|v| (**v).clone()).collect::<Vec<_>>()
+        );
+        broadcast.push(&[4], false);
+        assert_eq!(vec![vec![4]], receive(receiver, 1));
+
+        // restarted stream replaces headers
+        broadcast.push(&[5], true);
+        broadcast.push(&[6], false);
+        let (headers, _receiver) = broadcast.subscribe().unwrap();
+        assert_eq!(1, headers.len());
+        assert_eq!(vec![5], *headers[0]);
+    }
+
+    #[test]
+    fn test_listeners() {
+        let broadcast = Broadcast::new(1, StreamFormat::Mp3, None, 2);
+        let (_, first) = broadcast.subscribe().unwrap();
+        let (_, second) = broadcast.subscribe().unwrap();
+        assert!(broadcast.subscribe().is_err());
+        assert_eq!(2, broadcast.status("1").listeners);
+
+        drop(first);
+        broadcast.push(&[1], false);
+        let status = broadcast.status("1");
+        assert_eq!(1, status.listeners);
+        assert_eq!(2, status.listeners_peak);
+
+        // listener not reading anymore
+        for _ in 0..LISTENER_BUFFER * 2 {
+            broadcast.push(&[1], false);
+        }
+        assert_eq!(0, broadcast.status("1").listeners);
+        drop(second);
+    }
+}
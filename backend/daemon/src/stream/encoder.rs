@@ -0,0 +1,132 @@
+/*
+ *  This file is part of yamba.
+ *
+ *  yamba is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  yamba is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with yamba.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use failure::Fallible;
+use gst;
+use gst::prelude::*;
+use gst_app;
+use yamba_types::models::StreamFormat;
+
+use std::sync::Arc;
+
+use super::broadcast::Broadcast;
+use super::StreamErr;
+
+/// Encoding of captured audio for HTTP streams
+
+/// Content type of format
+pub fn content_type(format: StreamFormat) -> &'static str {
+    match format {
+        StreamFormat::OggOpus => "audio/ogg",
+        StreamFormat::Mp3 => "audio/mpeg",
+    }
+}
+
+/// Elements encoding raw audio into format, bitrate in kbit/s
+pub fn encoder_description(format: StreamFormat, bitrate: u32) -> String {
+    match format {
+        StreamFormat::OggOpus => format!(
+            "audioconvert ! audioresample ! audio/x-raw,rate=48000,channels=2 \
+             ! opusenc bitrate={} ! oggmux",
+            bitrate * 1000
+        ),
+        StreamFormat::Mp3 => format!(
+            "audioconvert ! audioresample ! audio/x-raw,rate=44100,channels=2 \
+             ! lamemp3enc target=bitrate bitrate={} cbr=true",
+            bitrate
+        ),
+    }
+}
+
+/// Install callback sending all samples of the appsink `name` in `bin` to broadcast
+pub fn connect_appsink(bin: &gst::Bin, name: &str, broadcast: Arc<Broadcast>) -> Fallible<()> {
+    let sink = bin
+        .get_by_name(name)
+        .ok_or_else(|| StreamErr::Pipeline(String::from("Missing appsink")))?
+        .dynamic_cast::<gst_app::AppSink>()
+        .map_err(|_| StreamErr::Pipeline(String::from("Not an appsink")))?;
+    sink.set_callbacks(
+        gst_app::AppSinkCallbacks::new()
+            .new_sample(move |sink| {
+                let sample = match sink.pull_sample() {
+                    Some(v) => v,
+                    None => return gst::FlowReturn::Eos,
+                };
+                let buffer = match sample.get_buffer() {
+                    Some(v) => v,
+                    None => return gst::FlowReturn::Error,
+                };
+                let header = buffer.get_flags().contains(gst::BufferFlags::HEADER);
+                let map = match buffer.map_readable() {
+                    Some(v) => v,
+                    None => return gst::FlowReturn::Error,
+                };
+                broadcast.push(map.as_slice(), header);
+                gst::FlowReturn::Ok
+            })
+            .build(),
+    );
+    Ok(())
+}
+
+/// Captures a pulse source & encodes it into a broadcast
+/// Stops on drop
+pub struct Capture {
+    pipeline: gst::Element,
+}
+
+impl Capture {
+    /// Start capture of pulse source, bitrate in kbit/s
+    pub fn start(device: &str, bitrate: u32, broadcast: Arc<Broadcast>) -> Fallible<Capture> {
+        let pipeline = gst::parse_launch(&format!(
+            "pulsesrc device=\"{}\" ! {} ! appsink name=stream sync=false",
+            device,
+            encoder_description(broadcast.get_format(), bitrate)
+        ))
+        .map_err(|e| StreamErr::Pipeline(e.to_string()))?;
+        {
+            let bin = pipeline
+                .clone()
+                .dynamic_cast::<gst::Bin>()
+                .map_err(|_| StreamErr::Pipeline(String::from("Not a bin")))?;
+            connect_appsink(&bin, "stream", broadcast)?;
+        }
+        if pipeline.set_state(gst::State::Playing) == gst::StateChangeReturn::Failure {
+            return Err(StreamErr::Pipeline(String::from("Can't start capture")).into());
+        }
+        Ok(Capture { pipeline })
+    }
+
+    /// Returns the first error of the pipeline since the last check
+    pub fn check_error(&self) -> Option<String> {
+        let bus = self.pipeline.get_bus()?;
+        while let Some(msg) = bus.pop() {
+            if let gst::MessageView::Error(err) = msg.view() {
+                return Some(err.get_error().to_string());
+            }
+        }
+        None
+    }
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        if self.pipeline.set_state(gst::State::Null) == gst::StateChangeReturn::Failure {
+            warn!("Can't stop stream capture");
+        }
+    }
+}
@@ -0,0 +1,248 @@
+/*
+ *  This file is part of yamba.
+ *
+ *  yamba is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  yamba is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with yamba.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod broadcast;
+mod encoder;
+pub mod server;
+
+use failure::Fallible;
+use gst;
+use hashbrown::HashMap;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use self::broadcast::Broadcast;
+use self::encoder::Capture;
+use audio::NullSink;
+use daemon::instance::ID;
+use daemon::{HeartbeatMap, InstanceBase, WInstances};
+use playback::{Player, OUTPUT_SINK_NAME};
+use voip::{self, VoipBackend, VoipErr, VoipSpawn};
+use yamba_types::models::{
+    InstanceStartedReq, StopReason, StreamFormat, StreamSettings, StreamStatus,
+};
+use SETTINGS;
+
+/// HTTP audio streams, instances without VoIP client
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+lazy_static! {
+    /// Streams by mount
+    static ref MOUNTS: RwLock<HashMap<String, Arc<Broadcast>>> = RwLock::new(HashMap::new());
+}
+
+#[derive(Fail, Debug)]
+pub enum StreamErr {
+    #[fail(display = "Stream server bind error {}", _0)]
+    BindError(#[cause] hyper::Error),
+    #[fail(display = "Stream pipeline error: {}", _0)]
+    Pipeline(String),
+    #[fail(display = "Max listeners of {} reached", _0)]
+    ListenersExceeded(u32),
+}
+
+/// Make broadcast available at mount
+fn mount(mount: String, broadcast: Arc<Broadcast>) {
+    MOUNTS
+        .write()
+        .expect("Can't lock mounts!")
+        .insert(mount, broadcast);
+}
+
+pub fn unmount(mount: &str) {
+    MOUNTS.write().expect("Can't lock mounts!").remove(mount);
+}
+
+fn get_mount(mount: &str) -> Option<Arc<Broadcast>> {
+    MOUNTS
+        .read()
+        .expect("Can't lock mounts!")
+        .get(mount)
+        .cloned()
+}
+
+/// Create player output bin for stream & mount it, bitrate in kbit/s
+pub fn output_bin(
+    mount_name: String,
+    instance: ID,
+    format: StreamFormat,
+    bitrate: Option<u32>,
+) -> Fallible<gst::Bin> {
+    let broadcast = Arc::new(Broadcast::new(
+        instance,
+        format,
+        None,
+        SETTINGS.stream.max_listeners,
+    ));
+    let bin = gst::parse_bin_from_description(
+        &format!(
+            "queue ! {} ! appsink name={} sync=true async=false",
+            encoder::encoder_description(format, bitrate.unwrap_or(SETTINGS.stream.bitrate)),
+            OUTPUT_SINK_NAME
+        ),
+        true,
+    )
+    .map_err(|e| StreamErr::Pipeline(e.to_string()))?;
+    encoder::connect_appsink(&bin, OUTPUT_SINK_NAME, broadcast.clone())?;
+    mount(mount_name, broadcast);
+    Ok(bin)
+}
+
+/// Status of all streams
+pub fn status() -> Vec<StreamStatus> {
+    MOUNTS
+        .read()
+        .expect("Can't lock mounts!")
+        .iter()
+        .map(|(k, v)| v.status(k))
+        .collect()
+}
+
+/// HTTP stream backend, serves the instance audio over HTTP instead of a VoIP client
+/// Audio is captured from a pulse null sink
+pub struct HttpStream {
+    sink: NullSink,
+    mount: String,
+    bitrate: u32,
+    broadcast: Arc<Broadcast>,
+    capture: Arc<Mutex<Option<Capture>>>,
+    running: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+}
+
+impl VoipSpawn for HttpStream {
+    type Settings = StreamSettings;
+
+    fn spawn(base: &InstanceBase, settings: StreamSettings, id: ID) -> Fallible<HttpStream> {
+        let sink = NullSink::new(
+            base.mainloop.clone(),
+            base.context.clone(),
+            format!("yambastream{}", &id),
+        )?;
+        let broadcast = Arc::new(Broadcast::new(
+            id,
+            settings.format,
+            settings.name,
+            SETTINGS.stream.max_listeners,
+        ));
+        let stream = HttpStream {
+            sink,
+            mount: id.to_string(),
+            bitrate: settings.bitrate.unwrap_or(SETTINGS.stream.bitrate),
+            broadcast,
+            capture: Arc::new(Mutex::new(None)),
+            running: Arc::new(AtomicBool::new(true)),
+            stop: Arc::new(AtomicBool::new(false)),
+        };
+        let supervisor = Supervisor {
+            id,
+            instances: base.w_instances.clone(),
+            heartbeats: base.heartbeat.clone(),
+            capture: stream.capture.clone(),
+            running: stream.running.clone(),
+            stop: stream.stop.clone(),
+        };
+        thread::Builder::new()
+            .name(format!("stream-{}", id))
+            .spawn(move || supervisor.run())?;
+        Ok(stream)
+    }
+}
+
+impl VoipBackend for HttpStream {
+    fn name(&self) -> &'static str {
+        "Stream"
+    }
+
+    fn route_audio(&self, player: &Player) -> Fallible<()> {
+        player.set_pulse_device(self.sink.get_sink_name())
+    }
+
+    /// Starts encoding & mounts the stream
+    fn on_connected(&self, _param: &InstanceStartedReq) -> Fallible<()> {
+        let mut capture = self.capture.lock().expect("Can't lock capture!");
+        if capture.is_none() {
+            *capture = Some(Capture::start(
+                &self.sink.get_monitor_name(),
+                self.bitrate,
+                self.broadcast.clone(),
+            )?);
+            mount(self.mount.clone(), self.broadcast.clone());
+            info!("Stream mounted at /{}", self.mount);
+        }
+        Ok(())
+    }
+
+    fn is_running(&mut self) -> Fallible<bool> {
+        Ok(self.running.load(Ordering::Relaxed))
+    }
+
+    fn send_message(&self, _message: &str) -> Fallible<()> {
+        Err(VoipErr::Unsupported("Chat messages", self.name()).into())
+    }
+
+    fn teardown(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        unmount(&self.mount);
+        self.capture.lock().expect("Can't lock capture!").take();
+    }
+}
+
+/// Heartbeats for the instance while the capture is healthy
+struct Supervisor {
+    id: ID,
+    instances: WInstances,
+    heartbeats: HeartbeatMap,
+    capture: Arc<Mutex<Option<Capture>>>,
+    running: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Supervisor {
+    fn run(self) {
+        let mut connected = false;
+        self.heartbeats.update(self.id);
+        while !self.stop.load(Ordering::Relaxed) {
+            if !connected {
+                // retried until the instance is stored
+                connected = voip::report_connected(&self.instances, self.id);
+            }
+            let error = match *self.capture.lock().expect("Can't lock capture!") {
+                Some(ref capture) => capture.check_error(),
+                None => None,
+            };
+            if let Some(e) = error {
+                error!("Stream capture of instance {} failed: {}", self.id, e);
+                self.running.store(false, Ordering::Relaxed);
+                if let Some(instances) = self.instances.upgrade() {
+                    let instances = instances.read().expect("Can't read instances!");
+                    if let Some(inst) = instances.get(&self.id) {
+                        inst.set_stop_reason(StopReason::Crashed);
+                    }
+                }
+                // heartbeat timeout removes the instance
+                return;
+            }
+            self.heartbeats.update(self.id);
+            thread::sleep(HEARTBEAT_INTERVAL);
+        }
+    }
+}
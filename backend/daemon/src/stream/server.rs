@@ -0,0 +1,94 @@
+/*
+ *  This file is part of yamba.
+ *
+ *  yamba is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  yamba is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with yamba.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use failure::Fallible;
+use futures::{stream, Future, Stream};
+use http_r::header;
+use hyper::service::service_fn_ok;
+use hyper::{Body, Chunk, Method, Request, Response, Server, StatusCode};
+use tokio::runtime;
+
+use std::io;
+use std::net::SocketAddr;
+
+use super::broadcast::Frame;
+use super::encoder::content_type;
+use super::{get_mount, StreamErr};
+use SETTINGS;
+
+/// HTTP server of audio streams, `GET /<mount>` streams the audio
+
+/// Address parser for stream server
+/// Used also for runtime checks
+pub fn parse_addr() -> Fallible<SocketAddr> {
+    Ok(format!("{}:{}", SETTINGS.stream.bind_ip, SETTINGS.stream.bind_port).parse()?)
+}
+
+/// Start stream server
+pub fn start_server(runtime: &mut runtime::Runtime) -> Fallible<()> {
+    let addr = parse_addr()?;
+    let server = Server::try_bind(&addr)
+        .map_err(|e| StreamErr::BindError(e))?
+        .serve(|| service_fn_ok(handle_request))
+        .map_err(|e| error!("Stream server error: {}", e));
+    runtime.spawn(server);
+    Ok(())
+}
+
+/// Handle listener request
+fn handle_request(req: Request<Body>) -> Response<Body> {
+    if req.method() != Method::GET {
+        return status_response(StatusCode::METHOD_NOT_ALLOWED);
+    }
+    let mount = req.uri().path().trim_matches('/');
+    let broadcast = match get_mount(mount) {
+        Some(v) => v,
+        None => return status_response(StatusCode::NOT_FOUND),
+    };
+    let (headers, receiver) = match broadcast.subscribe() {
+        Ok(v) => v,
+        Err(e) => {
+            debug!("Rejecting listener of {}: {}", mount, e);
+            return status_response(StatusCode::SERVICE_UNAVAILABLE);
+        }
+    };
+    debug!("New listener for stream {}", mount);
+
+    let body = stream::iter_ok(headers)
+        .chain(receiver)
+        .map(|frame: Frame| Chunk::from((*frame).clone()))
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "Stream closed"));
+
+    let mut builder = Response::builder();
+    builder
+        .header(header::CONTENT_TYPE, content_type(broadcast.get_format()))
+        .header(header::CACHE_CONTROL, "no-cache");
+    if let Some(name) = broadcast
+        .get_name()
+        .and_then(|v| header::HeaderValue::from_str(v).ok())
+    {
+        builder.header("icy-name", name);
+    }
+    builder.body(Body::wrap_stream(body)).unwrap()
+}
+
+fn status_response(code: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(code)
+        .body(Body::empty())
+        .unwrap()
+}
@@ -0,0 +1,91 @@
+/*
+ *  This file is part of yamba.
+ *
+ *  yamba is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  yamba is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with yamba.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use yamba_types::models::{SongTrim, TimeMS};
+
+/// Trim points & skip ranges, applied by seeking during playback
+
+#[derive(Debug, PartialEq)]
+pub enum TrimAction {
+    /// Seek to position in ms
+    Seek(TimeMS),
+    /// Trimmed end reached
+    End,
+}
+
+/// Returns action required at the playback position
+pub fn action(trim: &SongTrim, position_ms: TimeMS) -> Option<TrimAction> {
+    let mut target = position_ms.max(trim.start_ms);
+    // follow adjacent & overlapping ranges
+    for _ in 0..=trim.skips.len() {
+        match trim
+            .skips
+            .iter()
+            .find(|v| v.start_ms <= target && target < v.end_ms)
+        {
+            Some(range) => target = range.end_ms,
+            None => break,
+        }
+    }
+    match trim.end_ms {
+        Some(end) if target >= end => Some(TrimAction::End),
+        _ if target != position_ms => Some(TrimAction::Seek(target)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use yamba_types::models::SkipRange;
+
+    fn trim() -> SongTrim {
+        SongTrim {
+            start_ms: 1000,
+            end_ms: Some(10_000),
+            skips: vec![
+                SkipRange {
+                    start_ms: 3000,
+                    end_ms: 4000,
+                },
+                SkipRange {
+                    start_ms: 4000,
+                    end_ms: 5000,
+                },
+                SkipRange {
+                    start_ms: 8000,
+                    end_ms: 12_000,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_action() {
+        let trim = trim();
+        assert_eq!(Some(TrimAction::Seek(1000)), action(&trim, 0));
+        assert_eq!(None, action(&trim, 1000));
+        assert_eq!(None, action(&trim, 2999));
+        // adjacent ranges
+        assert_eq!(Some(TrimAction::Seek(5000)), action(&trim, 3500));
+        assert_eq!(None, action(&trim, 5000));
+        // skip past the end
+        assert_eq!(Some(TrimAction::End), action(&trim, 8000));
+        assert_eq!(Some(TrimAction::End), action(&trim, 10_000));
+        assert_eq!(None, action(&SongTrim::default(), 0));
+    }
+}
@@ -24,6 +24,7 @@ use std::io::{self, ErrorKind};
 use std::os::unix::fs::symlink;
 use std::path::{Path, PathBuf};
 use std::process::{Child, Command};
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 use std::vec::Vec;
@@ -32,8 +33,12 @@ use std::env::current_dir;
 
 use rusqlite::{self, Connection};
 
+use audio::NullSink;
 use daemon::instance::ID;
-use yamba_types::models::TSSettings;
+use daemon::InstanceBase;
+use playback::Player;
+use voip::{VoipBackend, VoipErr, VoipSpawn};
+use yamba_types::models::{InstanceStartedReq, TSSettings};
 use SETTINGS;
 
 /// TS Instance
@@ -60,33 +65,7 @@ pub enum TSInstanceErr {
 
 impl Drop for TSInstance {
     fn drop(&mut self) {
-        //
-        let kill = match self.is_running() {
-            Ok(true) => true,
-            Err(_) => {
-                warn!("Can't check xvfb state!");
-                true
-            }
-            Ok(false) => false,
-        };
-        // ignore error, otherwise run only if alive
-        if kill {
-            info!("xvfb Running, killing");
-
-            // pkill all childs of xvfb-run TODO: killing only ts should be enough (we're also killing xvfb currently)
-            if let Err(e) = TSInstance::kill_by_ppid(&self.process.id()) {
-                warn!("Couldn't kill ts instance by ppid: {}", e);
-            }
-            // we expect xvfb-run to return when xvfb/ts dies
-            if let Err(e_wait) = TSInstance::wait_for_child_timeout(5000, &mut self.process) {
-                // otherwise we just kill xvfb-run also
-                warn!("Xvfb not finished, killing.. {}", e_wait);
-                match self.kill() {
-                    Ok(()) => (),
-                    Err(e) => warn!("Couldn't kill xvfb instance on cleanup {}", e),
-                }
-            }
-        }
+        self.shutdown();
     }
 }
 
@@ -315,4 +294,99 @@ impl TSInstance {
     pub fn is_running(&mut self) -> Fallible<bool> {
         Ok(self.process.try_wait()?.is_none())
     }
+
+    /// Kill ts & xvfb if still running
+    pub fn shutdown(&mut self) {
+        let kill = match self.is_running() {
+            Ok(true) => true,
+            Err(_) => {
+                warn!("Can't check xvfb state!");
+                true
+            }
+            Ok(false) => false,
+        };
+        // ignore error, otherwise run only if alive
+        if kill {
+            info!("xvfb Running, killing");
+
+            // pkill all childs of xvfb-run TODO: killing only ts should be enough (we're also killing xvfb currently)
+            if let Err(e) = TSInstance::kill_by_ppid(&self.process.id()) {
+                warn!("Couldn't kill ts instance by ppid: {}", e);
+            }
+            // we expect xvfb-run to return when xvfb/ts dies
+            if let Err(e_wait) = TSInstance::wait_for_child_timeout(5000, &mut self.process) {
+                // otherwise we just kill xvfb-run also
+                warn!("Xvfb not finished, killing.. {}", e_wait);
+                match self.kill() {
+                    Ok(()) => (),
+                    Err(e) => warn!("Couldn't kill xvfb instance on cleanup {}", e),
+                }
+            }
+        }
+    }
+}
+
+/// Teamspeak VoIP backend  
+/// GUI client under xvfb, audio is routed through a pulse null sink
+pub struct Teamspeak {
+    ts: TSInstance,
+    sink: NullSink,
+    mute_sink: Arc<NullSink>,
+}
+
+impl VoipSpawn for Teamspeak {
+    type Settings = TSSettings;
+
+    fn spawn(base: &InstanceBase, settings: TSSettings, id: ID) -> Fallible<Teamspeak> {
+        let sink = NullSink::new(
+            base.mainloop.clone(),
+            base.context.clone(),
+            format!("yambasink{}", &id),
+        )?;
+        Ok(Teamspeak {
+            ts: TSInstance::spawn(
+                &settings,
+                &id,
+                &SETTINGS.main.api_internal_bind_ip,
+                &SETTINGS.main.api_internal_bind_port,
+                &SETTINGS.main.api_jsonrpc_ip,
+                &SETTINGS.main.api_jsonrpc_port,
+            )?,
+            sink,
+            mute_sink: base.default_sink.clone(),
+        })
+    }
+}
+
+impl VoipBackend for Teamspeak {
+    fn name(&self) -> &'static str {
+        "Teamspeak"
+    }
+
+    fn route_audio(&self, player: &Player) -> Fallible<()> {
+        player.set_pulse_device(self.sink.get_sink_name())
+    }
+
+    /// Setup call on successfull connection
+    /// pid is the real ts id, as the xvfb wrapper doesn't count
+    fn on_connected(&self, param: &InstanceStartedReq) -> Fallible<()> {
+        trace!("Setting monitor for ts");
+        self.sink.set_monitor_for_process(param.pid)?;
+        trace!("Setting sink for ts");
+        self.mute_sink.set_sink_for_process(param.pid)?;
+        Ok(())
+    }
+
+    fn is_running(&mut self) -> Fallible<bool> {
+        self.ts.is_running()
+    }
+
+    /// Chat is handled inside the client by the yamba plugin
+    fn send_message(&self, _message: &str) -> Fallible<()> {
+        Err(VoipErr::Unsupported("Sending messages", self.name()).into())
+    }
+
+    fn teardown(&mut self) {
+        self.ts.shutdown();
+    }
 }
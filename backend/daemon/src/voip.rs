@@ -0,0 +1,87 @@
+/*
+ *  This file is part of yamba.
+ *
+ *  yamba is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  yamba is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with yamba.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use failure::Fallible;
+
+use std::process;
+
+use daemon::instance::ID;
+use daemon::{InstanceBase, WInstances};
+use playback::Player;
+use yamba_types::models::InstanceStartedReq;
+
+/// Generic VoIP backend, one per instance
+
+#[derive(Fail, Debug)]
+pub enum VoipErr {
+    #[fail(display = "{} not supported by {} backend", _0, _1)]
+    Unsupported(&'static str, &'static str),
+}
+
+/// VoIP system an instance plays into
+/// Dropped after `teardown` when the instance stops
+pub trait VoipBackend: Send + Sync {
+    /// Name of the VoIP system
+    fn name(&self) -> &'static str;
+
+    /// Route audio of the player into the VoIP client, called once before playback
+    fn route_audio(&self, player: &Player) -> Fallible<()>;
+
+    /// Called when the client is connected & able to send audio
+    fn on_connected(&self, param: &InstanceStartedReq) -> Fallible<()>;
+
+    /// Returns whether the client is still alive
+    fn is_running(&mut self) -> Fallible<bool>;
+
+    /// Send chat message to the current channel
+    fn send_message(&self, message: &str) -> Fallible<()>;
+
+    /// Disconnect & release all resources of the client
+    fn teardown(&mut self);
+}
+
+/// Creation of a VoIP backend from its instance settings
+pub trait VoipSpawn: VoipBackend + Sized {
+    /// Settings from the instance load request
+    type Settings;
+
+    /// Spawn client for instance
+    fn spawn(base: &InstanceBase, settings: Self::Settings, id: ID) -> Fallible<Self>;
+}
+
+/// Signal connected state for backends without external client process  
+/// Returns false if the instance isn't stored yet, has to be retried
+pub fn report_connected(instances: &WInstances, id: ID) -> bool {
+    let instances = match instances.upgrade() {
+        Some(v) => v,
+        None => return false,
+    };
+    let instances = instances.read().expect("Can't read instances!");
+    match instances.get(&id) {
+        Some(inst) => {
+            let param = InstanceStartedReq {
+                id,
+                pid: process::id(),
+            };
+            if let Err(e) = inst.connected(param) {
+                warn!("Can't start audio of instance {}: {}", id, e);
+            }
+            true
+        }
+        None => false,
+    }
+}
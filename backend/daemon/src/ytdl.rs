@@ -15,22 +15,29 @@
  *  along with yamba.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use chrono::offset::Utc;
+use hashbrown::HashSet;
+
+use std::collections::VecDeque;
 use std::env::current_dir;
-use std::fs::{remove_file, rename, set_permissions, DirBuilder, File};
-use std::io::{BufRead, BufReader, ErrorKind, Read};
+use std::fs::{copy, remove_file, rename, set_permissions, DirBuilder, File};
+use std::io::{self, BufRead, BufReader, BufWriter, ErrorKind, Read};
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::sync::{Arc, RwLock};
 use std::thread;
+use yamba_types::models::{ErrorCodes, SearchSite, YtdlUpdateEntry, YtdlVersionsResponse};
 use yamba_types::track::Track;
 
-use failure::{Fallible, ResultExt};
+use failure::{Error, Fallible, ResultExt};
+use reqwest;
 use serde_json;
 use serde_json::value::Value as JsonValue;
 use sha2::{Digest, Sha256};
 
 use http;
+use ytdl_profile::{self, ResolverProfile};
 use SETTINGS;
 
 /// Ytdl handler
@@ -42,7 +49,15 @@ const VERSIONS_KEY: &'static str = "versions";
 const VERSION_BIN_KEY: &'static str = "bin";
 // key for versions sub group
 const VERSION_SHA_INDEX: usize = 1;
+const VERSION_URL_INDEX: usize = 0;
 const YTDL_NAME: &'static str = "youtube-dl"; // name of the python program file
+const YTDL_PREVIOUS_NAME: &'static str = "youtube-dl.previous"; // kept for rollbacks
+const YTDL_BACKUP_NAME: &'static str = "ytdl_backup"; // temporary during updates
+const YTDL_BLOCKED_NAME: &'static str = "blocked_versions.json"; // versions not to install again
+/// Max amount of update history entries
+const UPDATE_HISTORY_MAX: usize = 20;
+/// Max amount of search results per request
+pub const SEARCH_MAX_RESULTS: u8 = 10;
 
 lazy_static! {
     static ref LOCK: Arc<RwLock<()>> = Arc::new(RwLock::new(()));
@@ -56,22 +71,163 @@ pub enum YtDLErr {
     JsonError(&'static str),
     #[fail(display = "Invalid response {}", _0)]
     ResponseError(String),
+    #[fail(display = "Resolve failed {:?} stderr: {}", _0, _1)]
+    ResolveError(ErrorCodes, String),
     #[fail(display = "Incorrect hash for {}", _0)]
     InvalidHash(String),
     #[fail(display = "Thread panicked at {}", _0)]
     ThreadPanic(String),
+    #[fail(display = "Self-test failed for version {}: {}", _0, _1)]
+    SelfTestFailed(String, String),
+    #[fail(display = "No previous version to roll back to")]
+    NoPreviousVersion,
+}
+
+/// Create youtube-dl search pseudo-URL for query
+/// Amount is clamped to 1..=SEARCH_MAX_RESULTS
+pub fn search_url(site: SearchSite, amount: u8, query: &str) -> String {
+    let prefix = match site {
+        SearchSite::Youtube => "ytsearch",
+        SearchSite::Soundcloud => "scsearch",
+    };
+    let amount = amount.max(1).min(SEARCH_MAX_RESULTS);
+    let query: String = query
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    format!("{}{}:{}", prefix, amount, query.trim())
+}
+
+/// Classify ytdl error output into resolve error codes  
+/// Order matters, messages can match multiple classes
+pub fn classify_stderr(stderr: &str) -> ErrorCodes {
+    const CLASSES: [(ErrorCodes, &[&str]); 8] = [
+        (
+            ErrorCodes::RESOLVE_RATE_LIMITED,
+            &["http error 429", "too many requests"],
+        ),
+        (
+            ErrorCodes::RESOLVE_AGE_RESTRICTED,
+            &[
+                "confirm your age",
+                "age-restricted",
+                "age restricted",
+                "inappropriate for some users",
+            ],
+        ),
+        (
+            ErrorCodes::RESOLVE_PRIVATE,
+            &[
+                "private video",
+                "video is private",
+                "members-only",
+                "members only",
+            ],
+        ),
+        (
+            ErrorCodes::RESOLVE_GEO_BLOCKED,
+            &[
+                "in your country",
+                "geo restriction",
+                "geo-restricted",
+                "geo restricted",
+                "not available from your location",
+            ],
+        ),
+        (ErrorCodes::RESOLVE_COPYRIGHT, &["copyright"]),
+        (
+            ErrorCodes::RESOLVE_UNSUPPORTED_URL,
+            &["unsupported url", "is not a valid url"],
+        ),
+        (
+            ErrorCodes::RESOLVE_UNAVAILABLE,
+            &[
+                "video unavailable",
+                "video is unavailable",
+                "video is no longer available",
+                "has been removed",
+                "does not exist",
+                "http error 404",
+                "has been terminated",
+            ],
+        ),
+        (
+            ErrorCodes::RESOLVE_NETWORK,
+            &[
+                "unable to download",
+                "urlopen error",
+                "timed out",
+                "connection reset",
+                "connection refused",
+                "name or service not known",
+                "temporary failure in name resolution",
+                "network is unreachable",
+            ],
+        ),
+    ];
+    let stderr = stderr.to_lowercase();
+    CLASSES
+        .iter()
+        .find(|(_, patterns)| patterns.iter().any(|p| stderr.contains(p)))
+        .map_or(ErrorCodes::RESOLVE_UNKNOWN, |(code, _)| *code)
+}
+
+/// Returns error code of a failed resolve
+pub fn error_code(error: &Error) -> ErrorCodes {
+    if let Some(YtDLErr::ResolveError(code, _)) = error.downcast_ref::<YtDLErr>() {
+        return *code;
+    }
+    if error.downcast_ref::<reqwest::Error>().is_some() {
+        return ErrorCodes::RESOLVE_NETWORK;
+    }
+    if let Some(e) = error.downcast_ref::<io::Error>() {
+        return io_error_code(e.kind());
+    }
+    ErrorCodes::RESOLVE_UNKNOWN
+}
+
+/// Classify I/O error, local failures like a missing binary aren't retryable
+fn io_error_code(kind: ErrorKind) -> ErrorCodes {
+    match kind {
+        ErrorKind::TimedOut
+        | ErrorKind::ConnectionRefused
+        | ErrorKind::ConnectionReset
+        | ErrorKind::ConnectionAborted
+        | ErrorKind::NotConnected
+        | ErrorKind::AddrNotAvailable => ErrorCodes::RESOLVE_NETWORK,
+        _ => ErrorCodes::RESOLVE_UNKNOWN,
+    }
 }
 
 /// Version struct for retrieval of version & sha on update check
 pub struct Version {
     version: String,
     sha256: String,
+    /// download url
+    url: String,
+}
+
+/// Update history & versions not to install again
+#[derive(Default)]
+struct UpdateState {
+    history: VecDeque<YtdlUpdateEntry>,
+    blocked: HashSet<String>,
+}
+
+impl UpdateState {
+    fn add_entry(&mut self, entry: YtdlUpdateEntry) {
+        if self.history.len() >= UPDATE_HISTORY_MAX {
+            self.history.pop_back();
+        }
+        self.history.push_front(entry);
+    }
 }
 
 #[derive(Clone)]
 pub struct YtDL {
     // base dir from which ytdl is called
     base: Arc<PathBuf>,
+    state: Arc<RwLock<UpdateState>>,
 }
 
 impl YtDL {
@@ -86,8 +242,16 @@ impl YtDL {
             path = path_w.join(&SETTINGS.ytdl.dir);
         }
         DirBuilder::new().recursive(true).create(&path)?;
+        let blocked = load_blocked(&path.join(YTDL_BLOCKED_NAME)).unwrap_or_else(|e| {
+            warn!("Can't load blocked ytdl versions: {}", e);
+            HashSet::new()
+        });
         Ok(YtDL {
             base: Arc::new(path),
+            state: Arc::new(RwLock::new(UpdateState {
+                blocked,
+                ..UpdateState::default()
+            })),
         })
     }
 
@@ -95,11 +259,50 @@ impl YtDL {
     /// If url is no track, then only one track is returned
     pub fn get_url_info(&self, url: &str) -> Fallible<Vec<Track>> {
         let _guard = LOCK.read().unwrap();
+        self.get_url_info_inner(url, false)
+    }
+
+    /// Inner url info method, doesn't perform any lock checks!  
+    /// Applies the resolver profile matching the url host. Otherwise retries
+    /// with the profile matching the extractor of a failed resolve.
+    fn get_url_info_inner(&self, url: &str, file_urls: bool) -> Fallible<Vec<Track>> {
+        let profiles = &SETTINGS.ytdl.profiles;
+        if let Some(profile) = ytdl_profile::find_by_url(profiles, url) {
+            return self.run_url_info(url, file_urls, Some(profile));
+        }
+        match self.run_url_info(url, file_urls, None) {
+            Err(e) => {
+                let msg = format!("{}", e);
+                match ytdl_profile::extractor_from_error(&msg)
+                    .and_then(|v| ytdl_profile::find_by_extractor(profiles, v))
+                {
+                    Some(profile) => {
+                        debug!("Retrying {} with profile {}", url, profile.name);
+                        self.run_url_info(url, file_urls, Some(profile))
+                    }
+                    None => Err(e),
+                }
+            }
+            v => v,
+        }
+    }
+
+    /// Run ytdl for url info, doesn't perform any lock checks!
+    fn run_url_info(
+        &self,
+        url: &str,
+        file_urls: bool,
+        profile: Option<&ResolverProfile>,
+    ) -> Fallible<Vec<Track>> {
+        let args = url_info_args(url, file_urls, profile);
+        debug!(
+            "Resolving with profile {:?} args {:?}",
+            profile.map(|p| &p.name),
+            ytdl_profile::redact_args(&args)
+        );
         let mut child = self
             .cmd_base()
-            .arg("-j")
-            .arg("--no-playlist")
-            .arg(url)
+            .args(&args)
             .stdin(Stdio::null())
             .stderr(Stdio::piped())
             .stdout(Stdio::piped())
@@ -132,7 +335,7 @@ impl YtDL {
                 // don't abort if some tracks fail (playlist..)
                 if stderr.len() > 0 {
                     if tracks.len() == 0 {
-                        return Err(YtDLErr::ResponseError(format!("stderr: {}", stderr)).into());
+                        return Err(YtDLErr::ResolveError(classify_stderr(&stderr), stderr).into());
                     } else {
                         warn!("Stderr from ytdl: {}", stderr);
                     }
@@ -150,11 +353,19 @@ impl YtDL {
         self.base.join(YTDL_NAME)
     }
 
+    /// get path of version kept for rollbacks
+    fn get_previous_path(&self) -> PathBuf {
+        self.base.join(YTDL_PREVIOUS_NAME)
+    }
+
     /// Run a self-test checking for either yt-dl binaries or update failure
     /// depending on the config
     /// Returns true on success
     pub fn startup_test(&self) -> bool {
         info!("Testing yt-dl settings");
+        if SETTINGS.ytdl.self_test_source.trim().is_empty() {
+            warn!("No ytdl self_test_source configured, updates are only checked for a working version");
+        }
         match self.update_downloader() {
             Ok(_) => true,
             Err(e) => {
@@ -164,32 +375,106 @@ impl YtDL {
                     e.as_fail(),
                     e.backtrace()
                 );
-                false
+                if !self.get_exec_path().exists() {
+                    return false;
+                }
+                // still usable if the installed version works
+                match self.self_test() {
+                    Ok(_) => {
+                        warn!("Using installed yt-dl version after failed update");
+                        true
+                    }
+                    Err(e) => {
+                        error!("Installed yt-dl failed self-test: {}", e);
+                        false
+                    }
+                }
             }
         }
     }
 
+    /// Resolve configured self-test source, checks the version otherwise
+    /// Local paths are resolved as file urls
+    pub fn self_test(&self) -> Fallible<()> {
+        let _guard = LOCK.read().unwrap();
+        self.self_test_inner()
+    }
+
+    /// Inner self-test method, doesn't perform any lock checks!
+    fn self_test_inner(&self) -> Fallible<()> {
+        let version = self.current_version_inner()?;
+        let source = SETTINGS.ytdl.self_test_source.trim();
+        if source.is_empty() {
+            return Ok(());
+        }
+        let (url, file_urls) = match source.contains("://") {
+            true => (source.to_string(), false),
+            false => {
+                let path = current_dir()?.join(source).canonicalize()?;
+                (format!("file://{}", path.to_string_lossy()), true)
+            }
+        };
+        debug!("Running yt-dl self-test for {} on {}", version, url);
+        match self.get_url_info_inner(&url, file_urls) {
+            Ok(ref v) if !v.is_empty() => Ok(()),
+            Ok(_) => Err(YtDLErr::SelfTestFailed(version, "No tracks".into()).into()),
+            Err(e) => Err(YtDLErr::SelfTestFailed(version, format!("{}", e)).into()),
+        }
+    }
+
     /// Retrieve latest version
     pub fn latest_version() -> Fallible<Version> {
-        let result = http::get_text(&SETTINGS.ytdl.version_source, http::HeaderType::Ajax)?;
-        let mut parsed: JsonValue = serde_json::from_str(&result)?;
-        let version: String = match parsed[UPDATE_VERSION_KEY].take() {
+        let parsed = YtDL::version_list()?;
+        let version: String = match parsed[UPDATE_VERSION_KEY] {
             JsonValue::Null => return Err(YtDLErr::JsonError("Version key not found!").into()),
-            JsonValue::String(v) => v,
+            JsonValue::String(ref v) => v.clone(),
             _ => return Err(YtDLErr::JsonError("Version key is not of correct type!").into()),
         };
+        let mut latest = YtDL::parse_version(parsed, version)?;
+        latest.url = SETTINGS.ytdl.download_source.clone();
+        Ok(latest)
+    }
 
-        let sha256: String =
-            match parsed[VERSIONS_KEY][&version][VERSION_BIN_KEY][VERSION_SHA_INDEX].take() {
-                JsonValue::Null => return Err(YtDLErr::JsonError("SHA256 key not found!").into()),
-                JsonValue::String(r_sha256) => {
-                    debug!("sha: {:?}", r_sha256);
-                    r_sha256
-                }
-                _ => return Err(YtDLErr::JsonError("Sha256 is not of correct type!").into()),
-            };
+    /// Retrieve specific version, used for pinning
+    pub fn pinned_version(version: &str) -> Fallible<Version> {
+        YtDL::parse_version(YtDL::version_list()?, version.to_string())
+    }
 
-        Ok(Version { version, sha256 })
+    /// Retrieve version list
+    fn version_list() -> Fallible<JsonValue> {
+        let result = http::get_text(&SETTINGS.ytdl.version_source, http::HeaderType::Ajax)?;
+        Ok(serde_json::from_str(&result)?)
+    }
+
+    /// Parse sha & download url for version
+    fn parse_version(mut parsed: JsonValue, version: String) -> Fallible<Version> {
+        let mut bin = parsed[VERSIONS_KEY][&version][VERSION_BIN_KEY].take();
+        let sha256: String = match bin[VERSION_SHA_INDEX].take() {
+            JsonValue::Null => return Err(YtDLErr::JsonError("SHA256 key not found!").into()),
+            JsonValue::String(r_sha256) => {
+                debug!("sha: {:?}", r_sha256);
+                r_sha256
+            }
+            _ => return Err(YtDLErr::JsonError("Sha256 is not of correct type!").into()),
+        };
+        let url: String = match bin[VERSION_URL_INDEX].take() {
+            JsonValue::String(v) => v,
+            _ => return Err(YtDLErr::JsonError("Download URL not found!").into()),
+        };
+
+        Ok(Version {
+            version,
+            sha256,
+            url,
+        })
+    }
+
+    /// Version to install, respecting the version pin
+    fn target_version() -> Fallible<Version> {
+        match SETTINGS.ytdl.pin_version {
+            Some(ref v) if !v.trim().is_empty() => YtDL::pinned_version(v.trim()),
+            _ => YtDL::latest_version(),
+        }
     }
 
     /// create command base
@@ -204,7 +489,29 @@ impl YtDL {
     /// Get current version
     pub fn current_version(&self) -> Fallible<String> {
         let _guard = LOCK.read().unwrap();
-        let result = self.cmd_base().arg("--version").output()?; //.context("Could not run yt-dl")?;
+        self.current_version_inner()
+    }
+
+    /// Get version of the rollback binary
+    pub fn previous_version(&self) -> Fallible<Option<String>> {
+        let _guard = LOCK.read().unwrap();
+        let path = self.get_previous_path();
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(self.version_of(&path)?))
+    }
+
+    /// Inner version method, doesn't perform any lock checks!
+    fn current_version_inner(&self) -> Fallible<String> {
+        self.version_of(&self.get_exec_path())
+    }
+
+    /// Get version of ytdl executable
+    fn version_of(&self, exec: &Path) -> Fallible<String> {
+        let mut cmd = Command::new(exec);
+        cmd.current_dir(self.base.as_path());
+        let result = cmd.arg("--version").output()?; //.context("Could not run yt-dl")?;
         if result.status.success() {
             Ok(String::from_utf8_lossy(&result.stdout).trim().to_string())
         } else {
@@ -218,43 +525,78 @@ impl YtDL {
         }
     }
 
+    /// Returns versions & update history
+    pub fn versions(&self) -> YtdlVersionsResponse {
+        let current = self
+            .current_version()
+            .map_err(|e| warn!("Can't get ytdl version: {}", e))
+            .ok();
+        let previous = self
+            .previous_version()
+            .map_err(|e| warn!("Can't get previous ytdl version: {}", e))
+            .unwrap_or(None);
+        let state = self.state.read().expect("Can't lock update state!");
+        YtdlVersionsResponse {
+            current,
+            previous,
+            pinned: SETTINGS.ytdl.pin_version.clone(),
+            blocked: state.blocked.iter().cloned().collect(),
+            history: state.history.iter().cloned().collect(),
+        }
+    }
+
+    /// Record update attempt
+    fn record_update(&self, entry: YtdlUpdateEntry) {
+        let mut state = self.state.write().expect("Can't lock update state!");
+        if entry.rolled_back {
+            state.blocked.insert(entry.to.clone());
+            self.store_blocked(&state.blocked);
+        }
+        state.add_entry(entry);
+    }
+
+    /// Persist blocked versions, kept over restarts
+    fn store_blocked(&self, blocked: &HashSet<String>) {
+        if let Err(e) = save_blocked(&self.base.join(YTDL_BLOCKED_NAME), blocked) {
+            warn!("Can't store blocked ytdl versions: {}", e);
+        }
+    }
+
     /// Update yt-dl, blocks untill complection.
-    /// Blocks new jobs untill finish & waits till current jobs are completed.
+    /// Blocks new jobs untill finish & waits till current jobs are completed.  
+    /// Keeps the previous version for rollbacks and rolls back automatically
+    /// if the new version fails the self-test.
     pub fn update_downloader(&self) -> Fallible<()> {
-        let latest = YtDL::latest_version()?;
+        let target = YtDL::target_version()?;
         // if the guard is poinsoned, we can't do anything anymore
         let current_file = self.get_exec_path();
 
         let mut force_download = true;
 
         if current_file.exists() {
-            let backup_file = self.base.join("ytdl_backup");
             self.set_permissions()
                 .context("Unable to set permissions")?;
             let current_version = self.current_version()?;
             debug!(
-                "Version current: {} latest: {}",
-                current_version, latest.version
+                "Version current: {} target: {}",
+                current_version, target.version
             );
-            if latest.version != current_version {
-                let _guard = LOCK.write().unwrap();
-                rename(&current_file, &backup_file)?;
-                match self.download_latest(&current_file, &latest.sha256) {
-                    Ok(_) => {
-                        remove_file(backup_file)?;
-                        force_download = false;
-                    }
-                    Err(e) => {
-                        // use backup
-                        rename(&backup_file, &current_file)?;
-                        return Err(e);
-                    }
+            if target.version != current_version {
+                if self
+                    .state
+                    .read()
+                    .expect("Can't lock update state!")
+                    .blocked
+                    .contains(&target.version)
+                {
+                    debug!("Skipping blocked ytdl version {}", target.version);
+                    return Ok(());
                 }
-                drop(_guard);
+                return self.update_to(&target, current_version);
             } else {
                 // correct version, correct hash?
                 debug!("ytdl existing, checking hash");
-                if self.check_sha256(&latest.sha256)? {
+                if self.check_sha256(&target.sha256)? {
                     force_download = false;
                 } else {
                     warn!("Forcing download, current hash mismatch!");
@@ -268,7 +610,7 @@ impl YtDL {
             }
             info!("No ytdl installed, downloading..");
             let _guard = LOCK.write().unwrap();
-            self.download_latest(&current_file, &latest.sha256)?;
+            self.download_version(&current_file, &target)?;
             drop(_guard);
         }
 
@@ -278,6 +620,86 @@ impl YtDL {
         Ok(())
     }
 
+    /// Replace installed version with target, keeping the installed version for rollbacks
+    fn update_to(&self, target: &Version, current_version: String) -> Fallible<()> {
+        let current_file = self.get_exec_path();
+        let backup_file = self.base.join(YTDL_BACKUP_NAME);
+        let mut entry = YtdlUpdateEntry {
+            time: Utc::now().timestamp(),
+            from: Some(current_version),
+            to: target.version.clone(),
+            success: false,
+            rolled_back: false,
+            msg: None,
+        };
+        info!("Updating ytdl to {}", target.version);
+
+        let _guard = LOCK.write().unwrap();
+        // concurrent update (API & interval) finished while waiting
+        if self.current_version_inner()? == target.version {
+            return Ok(());
+        }
+        copy(&current_file, &backup_file)?;
+        let result = self
+            .download_version(&current_file, target)
+            .and_then(|_| self.set_permissions())
+            .and_then(|_| self.self_test_inner());
+        match result {
+            Ok(_) => {
+                rename(&backup_file, self.get_previous_path())?;
+                entry.success = true;
+                self.record_update(entry);
+                Ok(())
+            }
+            Err(e) => {
+                // use backup
+                error!("Update to {} failed, rolling back: {}", target.version, e);
+                rename(&backup_file, &current_file)?;
+                entry.rolled_back = true;
+                entry.msg = Some(format!("{}", e));
+                self.record_update(entry);
+                Err(e)
+            }
+        }
+    }
+
+    /// Roll back to the previous version, swapping it with the installed one  
+    /// Blocks the current version for automatic updates
+    /// Returns the now installed version
+    pub fn rollback(&self) -> Fallible<String> {
+        let current_file = self.get_exec_path();
+        let previous_file = self.get_previous_path();
+        let backup_file = self.base.join(YTDL_BACKUP_NAME);
+
+        let _guard = LOCK.write().unwrap();
+        if !previous_file.exists() {
+            return Err(YtDLErr::NoPreviousVersion.into());
+        }
+        let from = self.current_version_inner().ok();
+        rename(&current_file, &backup_file)?;
+        rename(&previous_file, &current_file)?;
+        rename(&backup_file, &previous_file)?;
+        let version = self.current_version_inner()?;
+        info!("Rolled back ytdl from {:?} to {}", from, version);
+
+        let mut state = self.state.write().expect("Can't lock update state!");
+        if let Some(ref v) = from {
+            state.blocked.insert(v.clone());
+        }
+        // allow manual restore of blocked versions via rollback
+        state.blocked.remove(&version);
+        self.store_blocked(&state.blocked);
+        state.add_entry(YtdlUpdateEntry {
+            time: Utc::now().timestamp(),
+            from,
+            to: version.clone(),
+            success: true,
+            rolled_back: true,
+            msg: Some(String::from("Manual rollback")),
+        });
+        Ok(version)
+    }
+
     /// Set permissions for executable
     fn set_permissions(&self) -> Fallible<()> {
         debug!("permission application: {:?}", self.get_exec_path());
@@ -312,11 +734,11 @@ impl YtDL {
         Ok(is_matching)
     }
 
-    /// Inner update method, downloads latest version to target
+    /// Inner update method, downloads version to target
     /// Doesn't perform any lock checks!
-    fn download_latest(&self, target: &Path, hash: &str) -> Fallible<()> {
-        http::get_file(&SETTINGS.ytdl.download_source, &target)?;
-        if self.check_sha256(hash)? {
+    fn download_version(&self, target: &Path, version: &Version) -> Fallible<()> {
+        http::get_file(&version.url, &target)?;
+        if self.check_sha256(&version.sha256)? {
             Ok(())
         } else {
             remove_file(&target)?;
@@ -325,6 +747,38 @@ impl YtDL {
     }
 }
 
+/// Arguments of an url info run, url last
+fn url_info_args(url: &str, file_urls: bool, profile: Option<&ResolverProfile>) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(p) = profile {
+        args.extend(p.args());
+    }
+    if file_urls {
+        args.push("--enable-file-urls".to_string());
+    }
+    args.push("-j".to_string());
+    args.push("--no-playlist".to_string());
+    args.push(url.to_string());
+    args
+}
+
+/// Load blocked versions, empty if none are stored
+fn load_blocked(path: &Path) -> Fallible<HashSet<String>> {
+    if !path.exists() {
+        return Ok(HashSet::new());
+    }
+    let blocked: Vec<String> = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+    Ok(blocked.into_iter().collect())
+}
+
+/// Store blocked versions
+fn save_blocked(path: &Path, blocked: &HashSet<String>) -> Fallible<()> {
+    let mut blocked: Vec<&String> = blocked.iter().collect();
+    blocked.sort();
+    serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), &blocked)?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -338,6 +792,121 @@ mod test {
         };
     }
 
+    #[test]
+    fn test_url_info_args() {
+        let url = "https://www.youtube.com/watch?v=abc";
+        assert_eq!(
+            vec!["-j", "--no-playlist", url],
+            url_info_args(url, false, None)
+        );
+        let profile = ResolverProfile {
+            name: "yt".into(),
+            cookies: Some("cookies/yt.txt".into()),
+            proxy: Some("socks5://127.0.0.1:1080".into()),
+            rate_limit: Some("1M".into()),
+            args: vec!["--geo-bypass".into()],
+            ..Default::default()
+        };
+        assert_eq!(
+            vec![
+                "--cookies",
+                "cookies/yt.txt",
+                "--proxy",
+                "socks5://127.0.0.1:1080",
+                "--limit-rate",
+                "1M",
+                "--geo-bypass",
+                "-j",
+                "--no-playlist",
+                url
+            ],
+            url_info_args(url, false, Some(&profile))
+        );
+        assert_eq!(
+            vec![
+                "--enable-file-urls",
+                "-j",
+                "--no-playlist",
+                "file:///tmp/example.ogg"
+            ],
+            url_info_args("file:///tmp/example.ogg", true, None)
+        );
+    }
+
+    #[test]
+    fn test_blocked() {
+        let path =
+            ::std::env::temp_dir().join(format!("yamba_blocked_{}.json", ::std::process::id()));
+        assert!(load_blocked(&path).unwrap().is_empty());
+        let mut blocked = HashSet::new();
+        blocked.insert(String::from("2019.04.07"));
+        blocked.insert(String::from("2019.04.01"));
+        save_blocked(&path, &blocked).unwrap();
+        assert_eq!(blocked, load_blocked(&path).unwrap());
+        remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_classify_stderr() {
+        let classify = |v| classify_stderr(&format!("ERROR: [youtube] abc: {}", v));
+        assert_eq!(
+            ErrorCodes::RESOLVE_UNAVAILABLE,
+            classify("Video unavailable")
+        );
+        assert_eq!(
+            ErrorCodes::RESOLVE_PRIVATE,
+            classify("This video is private.")
+        );
+        assert_eq!(
+            ErrorCodes::RESOLVE_AGE_RESTRICTED,
+            classify("Sign in to confirm your age")
+        );
+        assert_eq!(
+            ErrorCodes::RESOLVE_GEO_BLOCKED,
+            classify("The uploader has not made this video available in your country.")
+        );
+        assert_eq!(
+            ErrorCodes::RESOLVE_COPYRIGHT,
+            classify("Video unavailable. This video contains content from X, who has blocked it on copyright grounds.")
+        );
+        assert_eq!(
+            ErrorCodes::RESOLVE_UNSUPPORTED_URL,
+            classify_stderr("ERROR: Unsupported URL: https://example.com")
+        );
+        assert_eq!(
+            ErrorCodes::RESOLVE_RATE_LIMITED,
+            classify("Unable to download webpage: HTTP Error 429: Too Many Requests")
+        );
+        assert_eq!(
+            ErrorCodes::RESOLVE_NETWORK,
+            classify("Unable to download webpage: <urlopen error timed out>")
+        );
+        assert_eq!(ErrorCodes::RESOLVE_UNKNOWN, classify("something else"));
+    }
+
+    #[test]
+    fn test_error_code() {
+        let error: Error = io::Error::new(ErrorKind::TimedOut, "timeout").into();
+        assert_eq!(ErrorCodes::RESOLVE_NETWORK, error_code(&error));
+        let error: Error = io::Error::new(ErrorKind::NotFound, "no youtube-dl").into();
+        assert_eq!(ErrorCodes::RESOLVE_UNKNOWN, error_code(&error));
+        let error: Error = YtDLErr::ResolveError(ErrorCodes::RESOLVE_PRIVATE, String::new()).into();
+        assert_eq!(ErrorCodes::RESOLVE_PRIVATE, error_code(&error));
+    }
+
+    #[test]
+    fn test_search_url() {
+        assert_eq!(
+            "ytsearch5:foo bar",
+            search_url(SearchSite::Youtube, 5, " foo\nbar ")
+        );
+        assert_eq!(
+            format!("scsearch{}:x", SEARCH_MAX_RESULTS),
+            search_url(SearchSite::Soundcloud, 200, "x")
+        );
+        assert_eq!("ytsearch1:x", search_url(SearchSite::Youtube, 0, "x"));
+    }
+
     /// Test header creation
     #[test]
     fn test_yt_info() {
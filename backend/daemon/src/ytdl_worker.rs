@@ -15,7 +15,7 @@
  *  along with yamba.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use failure::Fallible;
+use failure::{Error, Fallible};
 use futures::{Future, Stream};
 use mpmc_scheduler as scheduler;
 use tokio::runtime::Runtime;
@@ -23,23 +23,44 @@ use tokio::timer::Interval;
 use tokio_threadpool::blocking;
 
 use std::boxed::Box;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use ytdl::YtDL;
+use direct;
+use http;
+use reqwest::Url;
+use single_flight::SingleFlight;
+use ytdl::{error_code, YtDL, YtDLErr};
 
 use daemon::instance::{SongCache, ID};
 use daemon::Instances;
-use yamba_types::models::Song;
+use yamba_types::models::{ErrorCodes, ResolveLaneStats, ResolvePriority, Song};
 use SETTINGS;
 
 /// Worker for ytdl tasks
 
-pub type R = (YTReqWrapped, RSongs);
+/// Extractor used by ytdl for direct links
+const GENERIC_EXTRACTOR: &'static str = "generic";
+
+pub type R = (Queued, RSongs);
 pub type YTReqWrapped = Box<dyn YTRequest + 'static + Send + Sync>;
 pub type RSongs = Fallible<Vec<Song>>;
-pub type Controller = scheduler::Controller<ID, YTReqWrapped, R>;
-pub type YTSender = scheduler::Sender<YTReqWrapped>;
+type Controller = scheduler::Controller<ID, Queued, R>;
+type YTSender = scheduler::Sender<Queued>;
+/// Shareable resolve error, code & message
+type SharedErr = (ErrorCodes, String);
+
+lazy_static! {
+    /// Running resolves by canonical url
+    static ref IN_FLIGHT: SingleFlight<String, Vec<Song>, SharedErr> = SingleFlight::new();
+}
+
+#[derive(Fail, Debug)]
+pub enum LaneErr {
+    #[fail(display = "Resolver backlog of instance is full")]
+    BacklogFull,
+}
 
 pub trait YTRequest {
     /// Url to resolve
@@ -73,22 +94,54 @@ pub fn crate_yt_updater(runtime: &mut Runtime, ytdl: Arc<YtDL>) {
     runtime.spawn(updater);
 }
 
+/// Create resolver lanes, one scheduler per priority
 pub fn crate_ytdl_scheduler(
     runtime: &mut Runtime,
     ytdl: Arc<YtDL>,
     cache: SongCache,
     instances: Instances,
-) -> Controller {
+) -> Lanes {
+    Lanes {
+        play: create_lane(
+            runtime,
+            SETTINGS.ytdl.play_workers as usize,
+            ytdl.clone(),
+            cache.clone(),
+            instances.clone(),
+        ),
+        bulk: create_lane(
+            runtime,
+            SETTINGS.ytdl.workers as usize,
+            ytdl,
+            cache,
+            instances,
+        ),
+    }
+}
+
+/// Create scheduler for one lane, round-robin per instance
+fn create_lane(
+    runtime: &mut Runtime,
+    workers: usize,
+    ytdl: Arc<YtDL>,
+    cache: SongCache,
+    instances: Instances,
+) -> Lane {
+    let metrics = Arc::new(LaneMetrics {
+        workers,
+        ..Default::default()
+    });
+    let metrics_c = metrics.clone();
     let (controller, scheduler) = scheduler::Scheduler::new(
-        SETTINGS.ytdl.workers as usize,
-        move |req: YTReqWrapped| {
-            let ytdl_c = ytdl.clone();
+        workers,
+        move |req: Queued| {
+            metrics_c.started(req.since.elapsed());
             let start = Instant::now();
-            let result = scheduler_retrieve(cache.clone(), &ytdl_c, req.url());
+            let result = resolve(cache.clone(), &ytdl, req.req.url());
             let end = start.elapsed();
             debug!(
                 "Request {} took {}{:03}ms to process",
-                req.url(),
+                req.req.url(),
                 end.as_secs(),
                 end.subsec_millis()
             );
@@ -96,24 +149,201 @@ pub fn crate_ytdl_scheduler(
         },
         Some(move |(mut req, tracks): R| {
             let instances_c = instances.clone();
-            req.callback(tracks, instances_c);
+            req.req.callback(tracks, instances_c);
         }),
         false,
     );
 
     runtime.spawn(scheduler);
-    controller
+    Lane {
+        controller,
+        metrics,
+    }
+}
+
+/// Resolver lanes by priority
+pub struct Lanes {
+    play: Lane,
+    bulk: Lane,
+}
+
+impl Lanes {
+    /// Create senders for instance, bounded by the instance backlog
+    pub fn channel(&self, id: ID) -> LaneSenders {
+        let backlog = SETTINGS.ytdl.instance_backlog_max as usize;
+        LaneSenders {
+            play: self.play.channel(id, backlog),
+            bulk: self.bulk.channel(id, backlog),
+        }
+    }
+
+    /// Returns (play, bulk) lane metrics
+    pub fn stats(&self) -> (ResolveLaneStats, ResolveLaneStats) {
+        (self.play.metrics.stats(), self.bulk.metrics.stats())
+    }
+}
+
+struct Lane {
+    controller: Controller,
+    metrics: Arc<LaneMetrics>,
+}
+
+impl Lane {
+    fn channel(&self, id: ID, backlog: usize) -> LaneSender {
+        LaneSender {
+            sender: self.controller.channel(id, backlog),
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+/// Request waiting in a lane
+pub struct Queued {
+    req: YTReqWrapped,
+    since: Instant,
+}
+
+#[derive(Default)]
+struct LaneMetrics {
+    workers: usize,
+    queued: AtomicUsize,
+    processed: AtomicUsize,
+    rejected: AtomicUsize,
+    wait_ms_total: AtomicUsize,
+    wait_ms_max: AtomicUsize,
+}
+
+impl LaneMetrics {
+    /// Record start of request after waiting in queue
+    fn started(&self, waited: Duration) {
+        let waited = (waited.as_secs() * 1000 + u64::from(waited.subsec_millis())) as usize;
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+        self.processed.fetch_add(1, Ordering::Relaxed);
+        self.wait_ms_total.fetch_add(waited, Ordering::Relaxed);
+        let mut max = self.wait_ms_max.load(Ordering::Relaxed);
+        while waited > max {
+            match self.wait_ms_max.compare_exchange_weak(
+                max,
+                waited,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(v) => max = v,
+            }
+        }
+    }
+
+    fn stats(&self) -> ResolveLaneStats {
+        let processed = self.processed.load(Ordering::Relaxed);
+        ResolveLaneStats {
+            workers: self.workers,
+            queued: self.queued.load(Ordering::Relaxed),
+            processed,
+            rejected: self.rejected.load(Ordering::Relaxed),
+            avg_wait_ms: match processed {
+                0 => 0,
+                v => (self.wait_ms_total.load(Ordering::Relaxed) / v) as u64,
+            },
+            max_wait_ms: self.wait_ms_max.load(Ordering::Relaxed) as u64,
+        }
+    }
+}
+
+struct LaneSender {
+    sender: YTSender,
+    metrics: Arc<LaneMetrics>,
+}
+
+impl LaneSender {
+    fn try_send(&self, req: YTReqWrapped) -> Fallible<()> {
+        self.metrics.queued.fetch_add(1, Ordering::SeqCst);
+        let result = self.sender.try_send(Queued {
+            req,
+            since: Instant::now(),
+        });
+        if result.is_err() {
+            self.metrics.queued.fetch_sub(1, Ordering::SeqCst);
+            self.metrics.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(LaneErr::BacklogFull.into());
+        }
+        Ok(())
+    }
+}
+
+/// Per instance senders of all lanes
+pub struct LaneSenders {
+    play: LaneSender,
+    bulk: LaneSender,
+}
+
+impl LaneSenders {
+    /// Queue request in lane of priority
+    pub fn dispatch(&self, req: YTReqWrapped, priority: ResolvePriority) -> Fallible<()> {
+        match priority {
+            ResolvePriority::Play => self.play.try_send(req),
+            ResolvePriority::Bulk => self.bulk.try_send(req),
+        }
+    }
+}
+
+/// Resolve url, update cache  
+/// Concurrent resolves of the same url wait for & share one resolve
+pub fn resolve(cache: SongCache, ytdl: &YtDL, url: &str) -> RSongs {
+    IN_FLIGHT
+        .run(canonical_url(url), || {
+            resolve_inner(cache, ytdl, url).map_err(share_error)
+        })
+        .map_err(|(code, msg)| YtDLErr::ResolveError(code, msg).into())
+}
+
+/// Returns (in_flight, coalesced) resolve metrics
+pub fn resolve_stats() -> (usize, usize) {
+    (IN_FLIGHT.in_flight(), IN_FLIGHT.coalesced())
+}
+
+/// Convert resolve error to shareable error
+fn share_error(e: Error) -> SharedErr {
+    match e.downcast::<YtDLErr>() {
+        Ok(YtDLErr::ResolveError(code, msg)) => (code, msg),
+        Ok(e) => (ErrorCodes::RESOLVE_UNKNOWN, format!("{}", e)),
+        Err(e) => (error_code(&e), format!("{}", e)),
+    }
+}
+
+/// Canonical form of url, used as key for running resolves
+fn canonical_url(url: &str) -> String {
+    let url = url.trim();
+    match Url::parse(url) {
+        Ok(mut v) => {
+            v.set_fragment(None);
+            v.into_string()
+        }
+        // search pseudo urls
+        Err(_) => url.to_string(),
+    }
 }
 
 /// Retrieve function for scheduler
 /// query ytdl, update cache
 /// returns all song IDs
-fn scheduler_retrieve(cache: SongCache, ytdl: &YtDL, url: &str) -> RSongs {
+fn resolve_inner(cache: SongCache, ytdl: &YtDL, url: &str) -> RSongs {
     // check DB & cache
     // also works with playlists as playlists are not expected to
     // be a source URL entry in the database
     // TODO: handle caching via song ID
 
+    if SETTINGS.ytdl.direct_resolve && direct::is_candidate(url) {
+        match direct::resolve(url) {
+            Ok(Some((song, audio_url))) => {
+                cache.upsert(song.id.clone(), audio_url);
+                return Ok(vec![song]);
+            }
+            Ok(None) => debug!("No direct audio for {}, using ytdl", url),
+            Err(e) => debug!("Direct resolve failed for {}, using ytdl: {}", url, e),
+        }
+    }
+
     let tracks = ytdl.get_url_info(url)?;
     Ok(tracks
         .into_iter()
@@ -126,7 +356,19 @@ fn scheduler_retrieve(cache: SongCache, ytdl: &YtDL, url: &str) -> RSongs {
                 }
             };
 
-            let song: Song = t.into();
+            // direct stream links have no duration and no live flag from ytdl
+            let check_icy =
+                !t.is_live() && t.duration.is_none() && t.extractor == GENERIC_EXTRACTOR;
+            let mut song: Song = t.into();
+            if check_icy {
+                song.live = match http::is_icy_stream(&min_song) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        debug!("Can't check for ICY stream {}: {}", song.source, e);
+                        false
+                    }
+                };
+            }
             cache.upsert(song.id.clone(), min_song);
             Some(song)
         })
@@ -25,7 +25,6 @@ extern crate lazy_static;
 extern crate jsonrpc_client_core;
 extern crate failure;
 extern crate jsonrpc_client_http;
-extern crate regex;
 extern crate reqwest;
 #[macro_use]
 extern crate failure_derive;
@@ -37,10 +36,11 @@ use models::*;
 
 use failure::Fallible;
 use jsonrpc_client_http::HttpTransport;
-use regex::*;
 use ts3plugin::TsApi;
 use ts3plugin::*;
+use yamba_types::commands::{self, Command, HELP, UNKNOWN_REPLY};
 use yamba_types::rpc::*;
+use yamba_types::StopReason;
 
 use std::env;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
@@ -64,45 +64,65 @@ jsonrpc_client!(
     pub struct BackendRPCClient {
 
     // Return: allowed, message, Volume [0 - 100]
-    pub fn volume_get(&mut self, id : i32, invoker_name : String, invoker_groups : String) -> RpcRequest<VolumeResponse>;
+    pub fn volume_get(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String) -> RpcRequest<VolumeResponse>;
     // Return: allowed, message, success
-    pub fn volume_set(&mut self, id : i32, invoker_name : String, invoker_groups : String, volume : f64) -> RpcRequest<DefaultResponse>;
+    pub fn volume_set(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String, volume : f64) -> RpcRequest<DefaultResponse>;
     // Return: allowed, message, success
-    pub fn volume_lock(&mut self, id : i32, invoker_name : String, invoker_groups : String, lock : bool) -> RpcRequest<DefaultResponse>;
+    pub fn volume_lock(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String, lock : bool) -> RpcRequest<DefaultResponse>;
 
     // Return: allowed, message, title
-    pub fn track_get(&mut self, id : i32, invoker_name : String, invoker_groups : String) -> RpcRequest<TitleResponse>;
+    pub fn track_get(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String) -> RpcRequest<TitleResponse>;
     // Return: allowed, message, success
-    pub fn track_next(&mut self, id : i32, invoker_name : String, invoker_groups : String) -> RpcRequest<DefaultResponse>;
+    pub fn track_next(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String) -> RpcRequest<DefaultResponse>;
     // Return: allowed, message, success
-    pub fn track_previous(&mut self, id : i32, invoker_name : String, invoker_groups : String) -> RpcRequest<DefaultResponse>;
+    pub fn track_previous(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String) -> RpcRequest<DefaultResponse>;
     // Return: allowed, message, success
-    pub fn track_resume(&mut self, id : i32, invoker_name : String, invoker_groups : String) -> RpcRequest<DefaultResponse>;
+    pub fn track_resume(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String) -> RpcRequest<DefaultResponse>;
     // Return: allowed, message, success
-    pub fn track_pause(&mut self, id : i32, invoker_name : String, invoker_groups : String) -> RpcRequest<DefaultResponse>;
+    pub fn track_pause(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String) -> RpcRequest<DefaultResponse>;
     // Return: allowed, message, success
-    pub fn track_stop(&mut self, id : i32, invoker_name : String, invoker_groups : String) -> RpcRequest<DefaultResponse>;
+    pub fn track_stop(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String) -> RpcRequest<DefaultResponse>;
     // Return allowed, message
-    pub fn playback_random(&mut self, id : i32, invoker_name : String, invoker_groups : String) -> RpcRequest<DefaultResponse>;
+    pub fn playback_random(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String) -> RpcRequest<DefaultResponse>;
 
 
     // Return: allowed, message, name
-    pub fn playlist_get(&mut self, id : i32, invoker_name : String, invoker_groups : String) -> RpcRequest<PlaylistResponse>;
+    pub fn playlist_get(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String) -> RpcRequest<PlaylistResponse>;
     // n > 0: return the next n tracks
-    // n <= 0 isn't allowed
     // Return: allowed, message, tracklist
-    pub fn queue_tracks(&mut self, id : i32, invoker_name : String, invoker_groups : String, n : i32) -> RpcRequest<TitleListResponse>;
+    pub fn queue_tracks(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String, n : usize) -> RpcRequest<TitleListResponse>;
     // Return: allowed, message, success
-    pub fn queue_clear(&mut self, id : i32, invoker_name : String, invoker_groups : String) -> RpcRequest<DefaultResponse>;
+    pub fn queue_clear(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String) -> RpcRequest<DefaultResponse>;
     // Return: allowed, message, success
-    pub fn queue_lock(&mut self, id : i32, invoker_name : String, invoker_groups : String, lock : bool) -> RpcRequest<DefaultResponse>;
+    pub fn queue_lock(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String, lock : bool) -> RpcRequest<DefaultResponse>;
     // Return: allowed, message, success
-    pub fn queue(&mut self, id : i32, invoker_name : String, invoker_groups : String, url : String) -> RpcRequest<DefaultResponse>;
+    pub fn queue(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String, url : String) -> RpcRequest<DefaultResponse>;
+    // Return: numbered result list
+    pub fn search(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String, query : String, site : SearchSite) -> RpcRequest<TitleListResponse>;
+    // n > 0: queue result n of the last search
+    // Return: message
+    pub fn pick(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String, n : usize) -> RpcRequest<DefaultResponse>;
+    // play soundboard clip over the music
+    // Return: message, empty on success
+    pub fn soundboard(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String, clip : String) -> RpcRequest<DefaultResponse>;
+    // edit trim points of the current track, command is the input after !trim
+    // Return: message
+    pub fn trim(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String, command : String) -> RpcRequest<DefaultResponse>;
+    // show or set playback speed, command is the input after !speed
+    // Return: message
+    pub fn speed(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String, command : String) -> RpcRequest<DefaultResponse>;
+    // Return: chapters of the current track
+    pub fn chapters(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String) -> RpcRequest<TitleListResponse>;
+    // jump to chapter of the current track, command is next or the chapter number
+    // Return: message
+    pub fn chapter(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String, command : String) -> RpcRequest<DefaultResponse>;
+    // Return: clips playable by the invoker
+    pub fn soundboard_list(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String) -> RpcRequest<TitleListResponse>;
     // Return: allowed, message, success
-    pub fn playlist_load(&mut self, id : i32, invoker_name : String, invoker_groups : String, playlist_name : String) -> RpcRequest<DefaultResponse>;
+    pub fn playlist_load(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String, playlist_name : String) -> RpcRequest<DefaultResponse>;
 
     // debug, halt bot
-    pub fn halt(&mut self, id : i32, invoker_name : String, invoker_groups : String) -> RpcRequest<DefaultResponse>;
+    pub fn halt(&mut self, id : i32, invoker_name : String, invoker_uid : String, invoker_groups : String) -> RpcRequest<DefaultResponse>;
 });
 
 lazy_static! {
@@ -110,7 +130,10 @@ lazy_static! {
     static ref ADDRESS: SocketAddr = env::var("CALLBACK_YAMBA")
         .unwrap_or("127.0.0.1:1337".to_string())
         .parse::<SocketAddr>()
-        .unwrap_or(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 1337));
+        .unwrap_or(SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            1337
+        ));
     static ref CALLBACK_INTERNAL: SocketAddr = env::var("CALLBACK_YAMBA_INTERNAL")
         .unwrap_or("127.0.0.1:1330".to_string())
         .parse::<SocketAddr>()
@@ -120,30 +143,6 @@ lazy_static! {
         .parse::<i32>()
         .map(|v| Some(v))
         .unwrap_or(None);
-    pub static ref R_IGNORE: Regex =
-        Regex::new(r"^((Sorry, I didn't get that... Have you tried !help yet)|(RPC call failed)|(n not parseable))")
-            .unwrap();
-    pub static ref R_HELP: Regex = Regex::new(r"^((\?)|(!help))").unwrap();
-    pub static ref R_VOL_LOCK: Regex = Regex::new(r"^(!l(o?ck)?( )?v(ol(ume)?)?)").unwrap();
-    pub static ref R_VOL_UNLOCK: Regex = Regex::new(r"^(!un?l(o?ck)?( )?v(ol(ume)?)?)").unwrap();
-    pub static ref R_VOL_SET: Regex = Regex::new(r"^(!v(ol(ume)?)? (\d*))").unwrap();
-    pub static ref R_VOL_GET: Regex = Regex::new(r"^!v(ol(ume)?)?").unwrap();
-    pub static ref R_TRACK_GET: Regex = Regex::new(r"^!playing").unwrap();
-    pub static ref R_TRACK_NEXT: Regex = Regex::new(r"^((!n(e?xt)?)|(>>))").unwrap();
-    pub static ref R_TRACK_PREVIOUS: Regex = Regex::new(r"^((!(prv)|(previous))|<<)").unwrap();
-    pub static ref R_TRACK_RESUME: Regex = Regex::new(r"^((!r(es(ume)?)?)|>)$").unwrap();
-    pub static ref R_RANDOM: Regex = Regex::new(r"^!random").unwrap();
-    pub static ref R_TRACK_PAUSE: Regex = Regex::new(r"^((!pause)|(\|\|))").unwrap();
-    pub static ref R_TRACK_STOP: Regex = Regex::new(r"^!s(to?p)?").unwrap();
-    pub static ref R_PLAYLIST_GET: Regex = Regex::new(r"^!((playlist)|(plst))").unwrap();
-    pub static ref R_PLAYLIST_TRACKS_5: Regex = Regex::new(r"^!t((rx)|(racks))?").unwrap();
-    pub static ref R_PLAYLIST_TRACKS_N: Regex = Regex::new(r"^!t((rx)|(racks))? (\d*)").unwrap();
-    pub static ref R_QUEUE_CLEAR: Regex = Regex::new("^!c(lear)?").unwrap();
-    pub static ref R_PLAYLIST_LOCK: Regex = Regex::new(r"^!l(o?ck)?( )?p((laylist)|(lst))").unwrap();
-    pub static ref R_PLAYLIST_UNLOCK: Regex = Regex::new(r"^!un?l(o?ck)?( )?p((laylist)|(lst))").unwrap();
-    pub static ref R_ENQUEUE: Regex = Regex::new(r"^!q(ueue)? ([^ ]+)").unwrap();
-    pub static ref R_PLAYLIST_LOAD: Regex = Regex::new(r"^!pl(oa)?d (.+)").unwrap();
-    pub static ref R_HALT: Regex = Regex::new(r"^!halt").unwrap();
 }
 
 #[derive(Debug)]
@@ -153,55 +152,27 @@ struct MyTsPlugin {
 }
 
 const PLUGIN_NAME_I: &'static str = env!("CARGO_PKG_NAME");
-const HELP: &str = r#"
-[b]YAMBA HELP[/b]
-
-[b]Help[/b]: !help
-
-[i]Get[/i] [b]volume[/b]: [i]!volume[/i]
-[i]Set[/i] volume <vol>: [i]!volume[/i] <vol>
-
-[i]Randomize[/i] queue: [i]!random[/i]
-
-Get [b]current track[/b]: [I]!playing[/I]
-[b]Enqueue[/b] <url> : [I]!queue[/I] <url>
-"#;
-
-/*
-const HELP: &str = r#"
-[b]YAMBA HELP[/b]
-
-[b]Help[/b]: !help
-
-[i]Get[/i] [b]volume[/b]: [i]!volume[/i]
-[i]Set[/i] volume <vol>: [i]!volume[/i] <vol>
-[i]Lock[/i] volume: [i]!lock volume[/i]
-[i]Unlock[/i] volume: [i]!unlock volume[/i]
-
-Get [b]current track[/b]: [I]!playing[/I]
-[b]Enqueue[/b] <url> : [I]!queue[/I] <url>
-Get [b]next X tracks[/b]: [I]!tracks[/I] <amount>
-Defaults to 5 if amount not provided
-Adds track to playback queue.
-[b]Load playlist[/b] <playlist>: [I]!lpload [/I]<playlist>
-Load playlist with specified name into queue
-[b]Enqueue playlist of tracks[/b] <url>: [I]!pqueue[/I] <playlist>
-Add a playlist (yt..) to playback queue.
-[b]Next[/b] track: [I]!next[/I]
-[b]Previous[/b] track: [I]!previous[/I]
-[b]Resume[/b] playback: [I]!resume[/I]
-[b]Pause[/b] playback: [I]!pause[/I]
-[b]Stop[/b] playback: [I]!stop[/I]
-"#;
-*/
+/// Render help of the shared command table
+fn help() -> String {
+    let mut help = String::from("[b]YAMBA HELP[/b]\n");
+    for (description, usage) in HELP {
+        help.push_str(&format!("\n{}: [i]{}[/i]", description, usage));
+    }
+    help
+}
 
 /// Print tracks for queue lookahead
 pub fn print_tracks(connection: &ts3plugin::Connection, tracks: Vec<String>) {
-    let mut message = String::from("Upcoming tracks:\n");
+    print_list(connection, "Upcoming tracks:\n", tracks);
+}
+
+/// Print list with header, split into multiple messages if required
+pub fn print_list(connection: &ts3plugin::Connection, header: &str, tracks: Vec<String>) {
+    let mut message = String::from(header);
     tracks.iter().for_each(|track| {
         if message.len() + track.len() + 1 >= 1024 {
             let _ = connection.send_message(message.as_str());
-            message = String::from("Upcoming tracks:\n");
+            message = String::from(header);
         }
         message.push_str(track);
         message.push_str("\n");
@@ -252,6 +223,14 @@ impl Plugin for MyTsPlugin {
         if status == ConnectStatus::Disconnected
             || error == ts3plugin::Error::FailedConnectionInitialisation
         {
+            // kicks & bans are reported before, the daemon keeps the first reason
+            report_disconnect(
+                api,
+                match error {
+                    ts3plugin::Error::FailedConnectionInitialisation => StopReason::ConnectFailed,
+                    _ => StopReason::ServerDown,
+                },
+            );
             match self.killer.send(()) {
                 Ok(_) => (),
                 Err(e) => api.log_or_print(
@@ -284,6 +263,58 @@ impl Plugin for MyTsPlugin {
         }
     }
 
+    fn server_stop(&mut self, api: &mut TsApi, _server_id: ServerId, message: String) {
+        api.log_or_print(
+            format!("Server stopped: {}", message),
+            PLUGIN_NAME_I,
+            LogLevel::Info,
+        );
+        report_disconnect(api, StopReason::ServerDown);
+    }
+
+    fn connection_server_kicked(
+        &mut self,
+        api: &mut TsApi,
+        server_id: ServerId,
+        connection_id: ConnectionId,
+        _old_channel_id: ChannelId,
+        _new_channel_id: ChannelId,
+        _visibility: Visibility,
+        invoker: Invoker,
+        message: String,
+    ) {
+        if is_own_connection(api, server_id, connection_id) {
+            api.log_or_print(
+                format!("Kicked by {}: {}", invoker.get_name(), message),
+                PLUGIN_NAME_I,
+                LogLevel::Info,
+            );
+            report_disconnect(api, StopReason::Kicked);
+        }
+    }
+
+    fn connection_server_banned(
+        &mut self,
+        api: &mut TsApi,
+        server_id: ServerId,
+        connection_id: ConnectionId,
+        _old_channel_id: ChannelId,
+        _new_channel_id: ChannelId,
+        _visibility: Visibility,
+        invoker: Invoker,
+        message: String,
+        _time: u64,
+    ) {
+        if is_own_connection(api, server_id, connection_id) {
+            api.log_or_print(
+                format!("Banned by {}: {}", invoker.get_name(), message),
+                PLUGIN_NAME_I,
+                LogLevel::Info,
+            );
+            report_disconnect(api, StopReason::Banned);
+        }
+    }
+
     fn new(api: &mut TsApi) -> Result<Box<MyTsPlugin>, InitError> {
         api.log_or_print("Initializing ", PLUGIN_NAME_I, LogLevel::Debug);
 
@@ -371,6 +402,7 @@ impl Plugin for MyTsPlugin {
     ) -> bool {
         let id: i32 = *ID.as_ref().unwrap();
         let invoker_name: String = invoker.get_name().to_string();
+        let invoker_uid: String = invoker.get_uid().to_string();
         let invoker_groups: String;
 
         if let Some(server) = api.get_server(server_id) {
@@ -388,12 +420,12 @@ impl Plugin for MyTsPlugin {
                     let mut client_lock =
                         self.client_mut.lock().expect("Can't get client rpc lock!");
                     let mut is_rpc_error: bool = false;
+                    let mut rpc_allowed: bool = true;
+                    let mut rpc_message: String = String::from("");
                     let mut rpc_error: jsonrpc_client_core::Error =
                         jsonrpc_client_core::Error::from_kind(jsonrpc_client_core::ErrorKind::Msg(
                             String::from("No error"),
                         ));
-                    let mut rpc_allowed: bool = true;
-                    let mut rpc_message: String = String::from("");
 
                     api.log_or_print(
                         format!("\"{}\" from \"{}\"", message, invoker_name),
@@ -401,169 +433,246 @@ impl Plugin for MyTsPlugin {
                         LogLevel::Info,
                     );
 
-                    if R_IGNORE.is_match(&message) {
-                        // IGNORED MESSAGES
-                    } else if R_HELP.is_match(&message) {
-                        let _ = connection.send_message(HELP);
-                    } else if R_VOL_LOCK.is_match(&message) {
-                        match client_lock
-                            .volume_lock(id, invoker_name, invoker_groups, true)
-                            .call()
-                        {
-                            Ok(_res) => {
-                                if rpc_allowed {
-                                    let _ = connection.send_message(format!("Ok"));
+                    match commands::parse(&message) {
+                        // no command, also ignores replies of other bots
+                        None => (),
+                        Some(Command::Help) => {
+                            let _ = connection.send_message(help());
+                        }
+                        Some(Command::Search(query, site)) => {
+                            match client_lock
+                                .search(id, invoker_name, invoker_uid, invoker_groups, query, site)
+                                .call()
+                            {
+                                Ok(res) => {
+                                    if res.tracklist.is_empty() {
+                                        let _ = connection.send_message("No results found.");
+                                    } else {
+                                        print_list(
+                                            connection,
+                                            "Results, !pick <n>:\n",
+                                            res.tracklist,
+                                        );
+                                    }
+                                }
+                                Err(e) => {
+                                    is_rpc_error = true;
+                                    rpc_error = e;
                                 }
-                            }
-                            Err(e) => {
-                                is_rpc_error = true;
-                                rpc_error = e;
                             }
                         }
-                    } else if R_VOL_UNLOCK.is_match(&message) {
-                        match client_lock
-                            .volume_lock(id, invoker_name, invoker_groups, false)
-                            .call()
-                        {
-                            Ok(_res) => {
-                                let _ = connection.send_message(format!("Ok"));
-                            }
-                            Err(e) => {
-                                is_rpc_error = true;
-                                rpc_error = e;
+                        Some(Command::Pick(n)) => {
+                            match client_lock
+                                .pick(id, invoker_name, invoker_uid, invoker_groups, n)
+                                .call()
+                            {
+                                Ok(res) => {
+                                    let _ = connection.send_message(res.message);
+                                }
+                                Err(e) => {
+                                    is_rpc_error = true;
+                                    rpc_error = e;
+                                }
                             }
                         }
-                    } else if let Some(caps) = R_VOL_SET.captures(&message) {
-                        if let Ok(vol) = caps[4].parse::<i32>() {
+                        Some(Command::Soundboard(clip)) => {
                             match client_lock
-                                .volume_set(id, invoker_name, invoker_groups, vol as f64 / 100.0)
+                                .soundboard(id, invoker_name, invoker_uid, invoker_groups, clip)
                                 .call()
                             {
-                                Ok(_res) => {
-                                    let _ = connection.send_message(format!("Ok"));
+                                Ok(res) => {
+                                    // silent on success
+                                    if !res.message.is_empty() {
+                                        let _ = connection.send_message(res.message);
+                                    }
                                 }
                                 Err(e) => {
                                     is_rpc_error = true;
                                     rpc_error = e;
                                 }
                             }
-                        } else {
-                            let _ = connection.send_message(format!("n not parseable"));
                         }
-                    } else if R_VOL_GET.is_match(&message) {
-                        match client_lock
-                            .volume_get(id, invoker_name, invoker_groups)
-                            .call()
-                        {
-                            Ok(res) => {
-                                let _ = connection
-                                    .send_message(format!("{}", (res.volume * 100.0) as i32));
-                            }
-                            Err(e) => {
-                                is_rpc_error = true;
-                                rpc_error = e;
+                        Some(Command::Trim(command)) => {
+                            match client_lock
+                                .trim(id, invoker_name, invoker_uid, invoker_groups, command)
+                                .call()
+                            {
+                                Ok(res) => {
+                                    let _ = connection.send_message(res.message);
+                                }
+                                Err(e) => {
+                                    is_rpc_error = true;
+                                    rpc_error = e;
+                                }
                             }
                         }
-                    } else if R_TRACK_GET.is_match(&message) {
-                        match client_lock
-                            .track_get(id, invoker_name, invoker_groups)
-                            .call()
-                        {
-                            Ok(res) => {
-                                let _ = connection.send_message(res.title);
-                            }
-                            Err(e) => {
-                                is_rpc_error = true;
-                                rpc_error = e;
+                        Some(Command::Speed(command)) => {
+                            match client_lock
+                                .speed(id, invoker_name, invoker_uid, invoker_groups, command)
+                                .call()
+                            {
+                                Ok(res) => {
+                                    let _ = connection.send_message(res.message);
+                                }
+                                Err(e) => {
+                                    is_rpc_error = true;
+                                    rpc_error = e;
+                                }
                             }
                         }
-                    } else if R_TRACK_NEXT.is_match(&message) {
-                        match client_lock
-                            .track_next(id, invoker_name, invoker_groups)
-                            .call()
-                        {
-                            Ok(_res) => {
-                                let _ = connection.send_message(format!("Ok"));
-                            }
-                            Err(e) => {
-                                is_rpc_error = true;
-                                rpc_error = e;
+                        Some(Command::Chapters) => {
+                            match client_lock
+                                .chapters(id, invoker_name, invoker_uid, invoker_groups)
+                                .call()
+                            {
+                                Ok(res) => {
+                                    if res.tracklist.is_empty() {
+                                        let _ = connection.send_message("No chapters available.");
+                                    } else {
+                                        print_list(
+                                            connection,
+                                            "Chapters, !chapter <n>:\n",
+                                            res.tracklist,
+                                        );
+                                    }
+                                }
+                                Err(e) => {
+                                    is_rpc_error = true;
+                                    rpc_error = e;
+                                }
                             }
                         }
-                    } else if R_TRACK_PREVIOUS.is_match(&message) {
-                        match client_lock
-                            .track_previous(id, invoker_name, invoker_groups)
-                            .call()
-                        {
-                            Ok(_res) => {
-                                let _ = connection.send_message(format!("Ok"));
-                            }
-                            Err(e) => {
-                                is_rpc_error = true;
-                                rpc_error = e;
+                        Some(Command::Chapter(command)) => {
+                            match client_lock
+                                .chapter(id, invoker_name, invoker_uid, invoker_groups, command)
+                                .call()
+                            {
+                                Ok(res) => {
+                                    let _ = connection.send_message(res.message);
+                                }
+                                Err(e) => {
+                                    is_rpc_error = true;
+                                    rpc_error = e;
+                                }
                             }
                         }
-                    } else if R_TRACK_RESUME.is_match(&message) {
-                        match client_lock
-                            .track_resume(id, invoker_name, invoker_groups)
-                            .call()
-                        {
-                            Ok(_res) => {
-                                let _ = connection.send_message(format!("Ok"));
-                            }
-                            Err(e) => {
-                                is_rpc_error = true;
-                                rpc_error = e;
+                        Some(Command::SoundboardList) => {
+                            match client_lock
+                                .soundboard_list(id, invoker_name, invoker_uid, invoker_groups)
+                                .call()
+                            {
+                                Ok(res) => {
+                                    if res.tracklist.is_empty() {
+                                        let _ = connection.send_message("No clips available.");
+                                    } else {
+                                        print_list(
+                                            connection,
+                                            "Clips, !sb <name>:\n",
+                                            res.tracklist,
+                                        );
+                                    }
+                                }
+                                Err(e) => {
+                                    is_rpc_error = true;
+                                    rpc_error = e;
+                                }
                             }
                         }
-                    } else if R_TRACK_PAUSE.is_match(&message) {
-                        match client_lock
-                            .track_pause(id, invoker_name, invoker_groups)
-                            .call()
-                        {
-                            Ok(_res) => {
-                                let _ = connection.send_message(format!("Ok"));
-                            }
-                            Err(e) => {
-                                is_rpc_error = true;
-                                rpc_error = e;
+                        Some(Command::VolumeLock(lock)) => {
+                            match client_lock
+                                .volume_lock(id, invoker_name, invoker_uid, invoker_groups, lock)
+                                .call()
+                            {
+                                Ok(res) => {
+                                    // message set if refused
+                                    if res.message.is_empty() {
+                                        let _ = connection.send_message(format!("Ok"));
+                                    } else {
+                                        let _ = connection.send_message(res.message);
+                                    }
+                                }
+                                Err(e) => {
+                                    is_rpc_error = true;
+                                    rpc_error = e;
+                                }
                             }
                         }
-                    } else if R_TRACK_STOP.is_match(&message) {
-                        match client_lock
-                            .track_stop(id, invoker_name, invoker_groups)
-                            .call()
-                        {
-                            Ok(_res) => {
-                                let _ = connection.send_message(format!("Ok"));
+                        Some(Command::VolumeSet(vol)) => {
+                            match client_lock
+                                .volume_set(
+                                    id,
+                                    invoker_name,
+                                    invoker_uid,
+                                    invoker_groups,
+                                    f64::from(vol) / 100.0,
+                                )
+                                .call()
+                            {
+                                Ok(res) => {
+                                    // message set if refused
+                                    if res.message.is_empty() {
+                                        let _ = connection.send_message(format!("Ok"));
+                                    } else {
+                                        let _ = connection.send_message(res.message);
+                                    }
+                                }
+                                Err(e) => {
+                                    is_rpc_error = true;
+                                    rpc_error = e;
+                                }
                             }
-                            Err(e) => {
-                                is_rpc_error = true;
-                                rpc_error = e;
+                        }
+                        Some(Command::VolumeGet) => {
+                            match client_lock
+                                .volume_get(id, invoker_name, invoker_uid, invoker_groups)
+                                .call()
+                            {
+                                Ok(res) => {
+                                    let _ = connection
+                                        .send_message(format!("{}", (res.volume * 100.0) as i32));
+                                }
+                                Err(e) => {
+                                    is_rpc_error = true;
+                                    rpc_error = e;
+                                }
                             }
                         }
-                    } else if R_PLAYLIST_GET.is_match(&message) {
-                        match client_lock
-                            .playlist_get(id, invoker_name, invoker_groups)
-                            .call()
-                        {
-                            Ok(res) => {
-                                rpc_allowed = res.allowed;
-                                rpc_message = res.message;
-                                let name = res.name;
-                                if rpc_allowed {
-                                    let _ = connection.send_message(format!("{}", name));
+                        Some(Command::Playing) => {
+                            match client_lock
+                                .track_get(id, invoker_name, invoker_uid, invoker_groups)
+                                .call()
+                            {
+                                Ok(res) => {
+                                    let _ = connection.send_message(res.title);
+                                }
+                                Err(e) => {
+                                    is_rpc_error = true;
+                                    rpc_error = e;
                                 }
                             }
-                            Err(e) => {
-                                is_rpc_error = true;
-                                rpc_error = e;
+                        }
+                        Some(Command::Next) => {
+                            match client_lock
+                                .track_next(id, invoker_name, invoker_uid, invoker_groups)
+                                .call()
+                            {
+                                Ok(res) => {
+                                    // message set if refused
+                                    if res.message.is_empty() {
+                                        let _ = connection.send_message(format!("Ok"));
+                                    } else {
+                                        let _ = connection.send_message(res.message);
+                                    }
+                                }
+                                Err(e) => {
+                                    is_rpc_error = true;
+                                    rpc_error = e;
+                                }
                             }
                         }
-                    } else if let Some(caps) = R_PLAYLIST_TRACKS_N.captures(&message) {
-                        if let Ok(n) = caps[4].parse::<i32>() {
+                        Some(Command::Tracks(n)) => {
                             match client_lock
-                                .queue_tracks(id, invoker_name, invoker_groups, n)
+                                .queue_tracks(id, invoker_name, invoker_uid, invoker_groups, n)
                                 .call()
                             {
                                 Ok(res) => {
@@ -574,128 +683,188 @@ impl Plugin for MyTsPlugin {
                                     rpc_error = e;
                                 }
                             }
-                        } else {
-                            let _ = connection.send_message(format!("n not parseable"));
                         }
-                    } else if R_PLAYLIST_TRACKS_5.is_match(&message) {
-                        match client_lock
-                            .queue_tracks(id, invoker_name, invoker_groups, 5)
-                            .call()
-                        {
-                            Ok(res) => {
-                                print_tracks(connection, res.tracklist);
-                            }
-                            Err(e) => {
-                                is_rpc_error = true;
-                                rpc_error = e;
+                        Some(Command::Queue(url)) => {
+                            let url = url.replace("[URL]", "").replace("[/URL]", "");
+                            match client_lock
+                                .queue(id, invoker_name, invoker_uid, invoker_groups, url)
+                                .call()
+                            {
+                                Ok(res) => {
+                                    // empty on success, otherwise why the url can't be played
+                                    if res.message.is_empty() {
+                                        let _ = connection.send_message(format!("Ok"));
+                                    } else {
+                                        let _ = connection.send_message(res.message);
+                                    }
+                                }
+                                Err(e) => {
+                                    is_rpc_error = true;
+                                    rpc_error = e;
+                                }
                             }
                         }
-                    } else if R_QUEUE_CLEAR.is_match(&message) {
-                        match client_lock
-                            .queue_clear(id, invoker_name, invoker_groups)
-                            .call()
-                        {
-                            Ok(_res) => {
-                                let _ = connection.send_message(format!("Ok"));
-                            }
-                            Err(e) => {
-                                is_rpc_error = true;
-                                rpc_error = e;
+                        Some(Command::Random) => {
+                            match client_lock
+                                .playback_random(id, invoker_name, invoker_uid, invoker_groups)
+                                .call()
+                            {
+                                Ok(_res) => {
+                                    let _ = connection.send_message(format!("Ok"));
+                                }
+                                Err(e) => {
+                                    is_rpc_error = true;
+                                    rpc_error = e;
+                                }
                             }
                         }
-                    } else if R_PLAYLIST_LOCK.is_match(&message) {
-                        match client_lock
-                            .queue_lock(id, invoker_name, invoker_groups, true)
-                            .call()
-                        {
-                            Ok(_res) => {
-                                let _ = connection.send_message(format!("Ok"));
-                            }
-                            Err(e) => {
-                                is_rpc_error = true;
-                                rpc_error = e;
+                        Some(Command::Previous) => {
+                            match client_lock
+                                .track_previous(id, invoker_name, invoker_uid, invoker_groups)
+                                .call()
+                            {
+                                Ok(_res) => {
+                                    let _ = connection.send_message(format!("Ok"));
+                                }
+                                Err(e) => {
+                                    is_rpc_error = true;
+                                    rpc_error = e;
+                                }
                             }
                         }
-                    } else if R_PLAYLIST_UNLOCK.is_match(&message) {
-                        match client_lock
-                            .queue_lock(id, invoker_name, invoker_groups, false)
-                            .call()
-                        {
-                            Ok(_res) => {
-                                let _ = connection.send_message(format!("Ok"));
-                            }
-                            Err(e) => {
-                                is_rpc_error = true;
-                                rpc_error = e;
+                        Some(Command::Resume) => {
+                            match client_lock
+                                .track_resume(id, invoker_name, invoker_uid, invoker_groups)
+                                .call()
+                            {
+                                Ok(_res) => {
+                                    let _ = connection.send_message(format!("Ok"));
+                                }
+                                Err(e) => {
+                                    is_rpc_error = true;
+                                    rpc_error = e;
+                                }
                             }
                         }
-                    } else if let Some(caps) = R_ENQUEUE.captures(&message) {
-                        let url = String::from(&caps[2])
-                            .replace("[URL]", "")
-                            .replace("[/URL]", "");
-                        match client_lock
-                            .queue(id, invoker_name, invoker_groups, url)
-                            .call()
-                        {
-                            Ok(_res) => {
-                                let _ = connection.send_message(format!("Ok"));
+                        Some(Command::Pause) => {
+                            match client_lock
+                                .track_pause(id, invoker_name, invoker_uid, invoker_groups)
+                                .call()
+                            {
+                                Ok(_res) => {
+                                    let _ = connection.send_message(format!("Ok"));
+                                }
+                                Err(e) => {
+                                    is_rpc_error = true;
+                                    rpc_error = e;
+                                }
                             }
-                            Err(e) => {
-                                is_rpc_error = true;
-                                rpc_error = e;
+                        }
+                        Some(Command::Stop) => {
+                            match client_lock
+                                .track_stop(id, invoker_name, invoker_uid, invoker_groups)
+                                .call()
+                            {
+                                Ok(_res) => {
+                                    let _ = connection.send_message(format!("Ok"));
+                                }
+                                Err(e) => {
+                                    is_rpc_error = true;
+                                    rpc_error = e;
+                                }
                             }
                         }
-                    } else if R_PLAYLIST_LOAD.is_match(&message) {
-                        let playlist_name = String::from(&R_VOL_SET.captures(&message).unwrap()[4]);
-                        match client_lock
-                            .playlist_load(id, invoker_name, invoker_groups, playlist_name)
-                            .call()
-                        {
-                            Ok(_res) => {
-                                let _ = connection.send_message(format!("Ok"));
+                        Some(Command::QueueLock(lock)) => {
+                            match client_lock
+                                .queue_lock(id, invoker_name, invoker_uid, invoker_groups, lock)
+                                .call()
+                            {
+                                Ok(_res) => {
+                                    let _ = connection.send_message(format!("Ok"));
+                                }
+                                Err(e) => {
+                                    is_rpc_error = true;
+                                    rpc_error = e;
+                                }
                             }
-                            Err(e) => {
-                                is_rpc_error = true;
-                                rpc_error = e;
+                        }
+                        Some(Command::QueueClear) => {
+                            match client_lock
+                                .queue_clear(id, invoker_name, invoker_uid, invoker_groups)
+                                .call()
+                            {
+                                Ok(_res) => {
+                                    let _ = connection.send_message(format!("Ok"));
+                                }
+                                Err(e) => {
+                                    is_rpc_error = true;
+                                    rpc_error = e;
+                                }
                             }
                         }
-                    } else if R_RANDOM.is_match(&message) {
-                        println!("Randomize..");
-                        match client_lock
-                            .playback_random(id, invoker_name, invoker_groups)
-                            .call()
-                        {
-                            Ok(_res) => {
-                                let _ = connection.send_message(format!("Ok"));
+                        Some(Command::PlaylistGet) => {
+                            match client_lock
+                                .playlist_get(id, invoker_name, invoker_uid, invoker_groups)
+                                .call()
+                            {
+                                Ok(res) => {
+                                    rpc_allowed = res.allowed;
+                                    rpc_message = res.message;
+                                    if rpc_allowed {
+                                        let _ = connection.send_message(res.name);
+                                    }
+                                }
+                                Err(e) => {
+                                    is_rpc_error = true;
+                                    rpc_error = e;
+                                }
                             }
-                            Err(e) => {
-                                is_rpc_error = true;
-                                rpc_error = e;
+                        }
+                        Some(Command::PlaylistLoad(playlist_name)) => {
+                            match client_lock
+                                .playlist_load(
+                                    id,
+                                    invoker_name,
+                                    invoker_uid,
+                                    invoker_groups,
+                                    playlist_name,
+                                )
+                                .call()
+                            {
+                                Ok(_res) => {
+                                    let _ = connection.send_message(format!("Ok"));
+                                }
+                                Err(e) => {
+                                    is_rpc_error = true;
+                                    rpc_error = e;
+                                }
                             }
                         }
-                    } else {
+                        Some(Command::InvalidNumber) => {
+                            let _ = connection.send_message(format!("n not parseable"));
+                        }
                         #[cfg(massif)]
-                        {
-                            if R_HALT.is_match(&message) {
-                                match client_lock.halt(id, invoker_name, invoker_groups).call() {
-                                    Ok(res) => {
-                                        let _ = connection.send_message(format!("Ok"));
-                                    }
-                                    Err(e) => {
-                                        is_rpc_error = true;
-                                        rpc_error = e;
-                                    }
+                        Some(Command::Halt) => {
+                            match client_lock
+                                .halt(id, invoker_name, invoker_uid, invoker_groups)
+                                .call()
+                            {
+                                Ok(_res) => {
+                                    let _ = connection.send_message(format!("Ok"));
+                                }
+                                Err(e) => {
+                                    is_rpc_error = true;
+                                    rpc_error = e;
                                 }
                             }
                         }
-
-                        if match target {
-                            MessageReceiver::Connection(_) => true,
-                            _ => false,
-                        } {
-                            let _ = connection.send_message(
-                                "Sorry, I didn't get that... Have you tried !help yet?",
-                            );
+                        Some(_) => {
+                            if match target {
+                                MessageReceiver::Connection(_) => true,
+                                _ => false,
+                            } {
+                                let _ = connection.send_message(UNKNOWN_REPLY);
+                            }
                         }
                     }
 
@@ -747,6 +916,44 @@ fn connected(id: i32, api: &TsApi) -> Fallible<()> {
     }
 }
 
+/// Returns true if the connection is the one of this client
+fn is_own_connection(api: &TsApi, server_id: ServerId, connection_id: ConnectionId) -> bool {
+    api.get_server(server_id)
+        .map_or(false, |s| Ok(connection_id) == s.get_own_connection_id())
+}
+
+/// Report disconnect reason, logging failures
+fn report_disconnect(api: &TsApi, reason: StopReason) {
+    if let Err(e) = disconnected(*ID.as_ref().unwrap(), reason) {
+        api.log_or_print(
+            format!("Unable to report disconnect {:?}: {}", reason, e),
+            PLUGIN_NAME_I,
+            LogLevel::Warning,
+        );
+    }
+}
+
+/// Report reason of the upcoming stop
+fn disconnected(id: i32, reason: StopReason) -> Fallible<()> {
+    match CLIENT
+        .post(&format!(
+            "http://{}/internal/disconnected",
+            *CALLBACK_INTERNAL
+        ))
+        .json(&DisconnectedRequest { id, reason })
+        .send()
+    {
+        Err(e) => Err(APIErr::RequestError(e).into()),
+        Ok(v) => {
+            if v.status() == reqwest::StatusCode::ACCEPTED {
+                Ok(())
+            } else {
+                Err(APIErr::NoSuccess(format!("Disconnect report failed: {}", v.status())).into())
+            }
+        }
+    }
+}
+
 /// run heartbeat command
 fn heartbeat(id: i32) -> Fallible<()> {
     match CLIENT
@@ -1,3 +1,5 @@
+use yamba_types::StopReason;
+
 /// Generic Request who require an instance ID
 #[derive(Serialize)]
 pub struct HeartbeatRequest {
@@ -9,6 +11,12 @@ pub struct HeartbeatResponse {
     pub success: bool,
 }
 
+#[derive(Serialize)]
+pub struct DisconnectedRequest {
+    pub id: i32,
+    pub reason: StopReason,
+}
+
 #[derive(Serialize)]
 pub struct ConnectedRequest {
     pub id: i32,
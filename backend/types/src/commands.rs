@@ -0,0 +1,237 @@
+/*
+ *  YAMBA types
+ *  Copyright (C) 2019 Aron Heinecke
+ *
+ *  This program is free software: you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation, either version 3 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::SearchSite;
+
+/// Chat command table, shared by the TS plugin & the Mumble client
+/// Clients map commands onto the manager JSON-RPC methods
+
+/// Amount of upcoming tracks shown by default
+pub const TRACKS_DEFAULT: usize = 5;
+
+/// Documented commands as (description, usage), clients render them in their markup
+pub const HELP: &[(&str, &str)] = &[
+    ("Help", "!help"),
+    ("Get volume", "!volume"),
+    ("Set volume <vol>", "!volume <vol>"),
+    ("Lock volume", "!lock volume"),
+    ("Unlock volume", "!unlock volume"),
+    ("Randomize queue", "!random"),
+    ("Get current track", "!playing"),
+    ("Next track", "!next"),
+    ("Previous track", "!previous"),
+    ("Resume playback", "!resume"),
+    ("Pause playback", "!pause"),
+    ("Stop playback", "!stop"),
+    ("Get next tracks <n>", "!tracks <n>"),
+    ("Enqueue <url>", "!queue <url>"),
+    ("Clear queue", "!clear"),
+    ("Lock queue", "!lock playlist"),
+    ("Unlock queue", "!unlock playlist"),
+    ("Get playlist", "!playlist"),
+    ("Load playlist <name>", "!pload <name>"),
+    ("Search youtube <terms>", "!search <terms>"),
+    ("Search soundcloud <terms>", "!scsearch <terms>"),
+    ("Pick search result <n>", "!pick <n>"),
+    ("Soundboard clips", "!sb"),
+    ("Play clip <name>", "!sb <name>"),
+    ("Show/set speed", "!speed [0.75-2]"),
+    ("List chapters", "!chapters"),
+    ("Jump to chapter", "!chapter next|<n>"),
+    (
+        "Trim current track",
+        "!trim start|end <m:ss>, !trim skip <m:ss>-<m:ss>, !trim clear",
+    ),
+];
+
+/// Reply for unknown commands
+pub const UNKNOWN_REPLY: &str = "Sorry, I didn't get that... Have you tried !help yet?";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Help,
+    VolumeGet,
+    /// Volume in percent
+    VolumeSet(u32),
+    /// Lock or unlock volume
+    VolumeLock(bool),
+    Playing,
+    Next,
+    Previous,
+    Resume,
+    Pause,
+    Stop,
+    Random,
+    /// Queue URL
+    Queue(String),
+    /// Lock or unlock queue
+    QueueLock(bool),
+    QueueClear,
+    /// Upcoming n tracks
+    Tracks(usize),
+    PlaylistGet,
+    /// Load playlist by name
+    PlaylistLoad(String),
+    Search(String, SearchSite),
+    /// Pick search result, starting at 1
+    Pick(usize),
+    /// Play soundboard clip
+    Soundboard(String),
+    SoundboardList,
+    /// Speed command, parsed by the manager
+    Speed(String),
+    Chapters,
+    /// Chapter command, parsed by the manager
+    Chapter(String),
+    /// Trim command, parsed by the manager
+    Trim(String),
+    /// Debug, stop the daemon
+    Halt,
+    /// Number argument not parseable
+    InvalidNumber,
+    Unknown,
+}
+
+/// Parse plain text chat message, None if it isn't a command
+pub fn parse(message: &str) -> Option<Command> {
+    let message = message.trim();
+    match message {
+        "?" => return Some(Command::Help),
+        ">>" => return Some(Command::Next),
+        "<<" => return Some(Command::Previous),
+        ">" => return Some(Command::Resume),
+        "||" => return Some(Command::Pause),
+        _ => (),
+    }
+    if !message.starts_with('!') {
+        return None;
+    }
+    let mut split = message[1..].splitn(2, char::is_whitespace);
+    let cmd = split.next().unwrap_or("").to_lowercase();
+    let arg = split.next().map(|v| v.trim()).filter(|v| !v.is_empty());
+    let number = |v: &str| v.parse::<usize>().ok();
+    Some(match (cmd.as_str(), arg) {
+        ("help", _) => Command::Help,
+        ("v", None) | ("vol", None) | ("volume", None) => Command::VolumeGet,
+        ("v", Some(v)) | ("vol", Some(v)) | ("volume", Some(v)) => match v.parse::<u32>() {
+            Ok(v) => Command::VolumeSet(v),
+            Err(_) => Command::InvalidNumber,
+        },
+        ("lock", Some(v)) if is_volume(v) => Command::VolumeLock(true),
+        ("unlock", Some(v)) if is_volume(v) => Command::VolumeLock(false),
+        ("lock", Some(v)) if is_playlist(v) => Command::QueueLock(true),
+        ("unlock", Some(v)) if is_playlist(v) => Command::QueueLock(false),
+        ("playing", _) => Command::Playing,
+        ("n", _) | ("next", _) => Command::Next,
+        ("prv", _) | ("previous", _) => Command::Previous,
+        ("r", _) | ("resume", _) => Command::Resume,
+        ("pause", _) => Command::Pause,
+        ("s", _) | ("stop", _) => Command::Stop,
+        ("random", _) => Command::Random,
+        ("c", _) | ("clear", _) => Command::QueueClear,
+        ("q", Some(v)) | ("queue", Some(v)) => Command::Queue(v.to_string()),
+        ("t", None) | ("trx", None) | ("tracks", None) => Command::Tracks(TRACKS_DEFAULT),
+        ("t", Some(v)) | ("trx", Some(v)) | ("tracks", Some(v)) => match number(v) {
+            Some(v) => Command::Tracks(v),
+            None => Command::InvalidNumber,
+        },
+        ("playlist", None) | ("plst", None) => Command::PlaylistGet,
+        ("pld", Some(v)) | ("pload", Some(v)) => Command::PlaylistLoad(v.to_string()),
+        ("search", Some(v)) => Command::Search(v.to_string(), SearchSite::Youtube),
+        ("scsearch", Some(v)) => Command::Search(v.to_string(), SearchSite::Soundcloud),
+        ("pick", Some(v)) => match number(v) {
+            Some(v) => Command::Pick(v),
+            None => Command::InvalidNumber,
+        },
+        ("speed", v) => Command::Speed(v.unwrap_or("").to_string()),
+        ("chapters", None) => Command::Chapters,
+        ("chapter", v) => Command::Chapter(v.unwrap_or("").to_string()),
+        ("trim", v) => Command::Trim(v.unwrap_or("").to_string()),
+        ("sb", None) => Command::SoundboardList,
+        ("sb", Some(v)) => Command::Soundboard(v.to_string()),
+        ("halt", None) => Command::Halt,
+        _ => Command::Unknown,
+    })
+}
+
+fn is_volume(arg: &str) -> bool {
+    arg == "v" || arg == "vol" || arg == "volume"
+}
+
+fn is_playlist(arg: &str) -> bool {
+    arg == "p" || arg == "plst" || arg == "playlist"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands() {
+        assert_eq!(None, parse("hello"));
+        assert_eq!(Some(Command::Help), parse("?"));
+        assert_eq!(Some(Command::VolumeGet), parse("!vol"));
+        assert_eq!(Some(Command::VolumeSet(20)), parse("!volume 20"));
+        assert_eq!(Some(Command::VolumeLock(true)), parse("!lock volume"));
+        assert_eq!(Some(Command::VolumeLock(false)), parse("!unlock v"));
+        assert_eq!(Some(Command::QueueLock(true)), parse("!lock playlist"));
+        assert_eq!(Some(Command::Unknown), parse("!lock"));
+        assert_eq!(Some(Command::InvalidNumber), parse("!v abc"));
+        assert_eq!(Some(Command::InvalidNumber), parse("!v 4294967346"));
+        assert_eq!(Some(Command::Next), parse(">>"));
+        assert_eq!(Some(Command::Pause), parse("||"));
+        assert_eq!(Some(Command::Stop), parse("!stop"));
+        assert_eq!(Some(Command::Tracks(TRACKS_DEFAULT)), parse("!tracks"));
+        assert_eq!(Some(Command::Tracks(10)), parse("!t 10"));
+        assert_eq!(
+            Some(Command::PlaylistLoad(String::from("party"))),
+            parse("!pload party")
+        );
+        assert_eq!(
+            Some(Command::Search(
+                String::from("foo bar"),
+                SearchSite::Soundcloud
+            )),
+            parse("!scsearch  foo bar ")
+        );
+        assert_eq!(Some(Command::Pick(3)), parse("!pick 3"));
+        assert_eq!(Some(Command::Unknown), parse("!pick"));
+        assert_eq!(Some(Command::SoundboardList), parse("!sb"));
+        assert_eq!(
+            Some(Command::Soundboard(String::from("horn"))),
+            parse("!sb horn")
+        );
+        assert_eq!(
+            Some(Command::Trim(String::from("start 0:12"))),
+            parse("!trim start 0:12")
+        );
+        assert_eq!(Some(Command::Trim(String::new())), parse("!trim"));
+        assert_eq!(
+            Some(Command::Speed(String::from("1.25"))),
+            parse("!speed 1.25")
+        );
+        assert_eq!(Some(Command::Chapters), parse("!chapters"));
+        assert_eq!(
+            Some(Command::Chapter(String::from("next"))),
+            parse("!chapter next")
+        );
+        assert_eq!(Some(Command::Chapter(String::new())), parse("!chapter"));
+        assert_eq!(Some(Command::Unknown), parse("!queue"));
+        assert_eq!(Some(Command::Unknown), parse("!nope"));
+    }
+}
@@ -21,6 +21,8 @@
 extern crate tower_web;
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "commands")]
+pub mod commands;
 #[cfg(feature = "rest")]
 pub mod models;
 #[cfg(feature = "rpc")]
@@ -34,17 +36,129 @@ extern crate actix;
 pub type ID = i32;
 
 #[allow(non_camel_case_types)]
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum ErrorCodes {
     NONE = 0,
     INVALID_INSTANCE = 401,
     INVALID_VOLUME = 402,
     INSTANCE_RUNNING = 403,
     RESOLVE_QUEUE_OVERLOAD = 404,
+    YTDL_ROLLBACK_FAILED = 405,
+    INVALID_OUTPUT = 406,
+    RECORDING_ACTIVE = 407,
+    NO_RECORDING = 408,
+    /// Soundboard clips require running playback
+    SOUNDBOARD_NOT_PLAYING = 409,
+    /// Video removed or not existing
+    RESOLVE_UNAVAILABLE = 410,
+    RESOLVE_PRIVATE = 411,
+    RESOLVE_AGE_RESTRICTED = 412,
+    RESOLVE_GEO_BLOCKED = 413,
+    RESOLVE_COPYRIGHT = 414,
+    RESOLVE_UNSUPPORTED_URL = 415,
+    RESOLVE_NETWORK = 416,
+    RESOLVE_RATE_LIMITED = 417,
+    /// Unclassified resolve failure
+    RESOLVE_UNKNOWN = 418,
+    /// Max amount of concurrent soundboard clips reached
+    SOUNDBOARD_BUSY = 419,
+    INVALID_CLIP = 420,
+    /// Playback rate out of `RATE_MIN` to `RATE_MAX`
+    INVALID_RATE = 421,
+    /// Audio effects out of range, see `AudioEffects::is_valid`
+    INVALID_EFFECTS = 422,
+}
+
+impl Default for ErrorCodes {
+    fn default() -> Self {
+        ErrorCodes::NONE
+    }
+}
+
+impl ErrorCodes {
+    /// Returns true if retrying the failed request later can succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ErrorCodes::RESOLVE_NETWORK
+            | ErrorCodes::RESOLVE_RATE_LIMITED
+            | ErrorCodes::RESOLVE_QUEUE_OVERLOAD => true,
+            _ => false,
+        }
+    }
+}
+
+/// Sites supported for search requests
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SearchSite {
+    Youtube,
+    Soundcloud,
+}
+
+impl Default for SearchSite {
+    fn default() -> Self {
+        SearchSite::Youtube
+    }
+}
+
+/// Resolver lane for resolve requests
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ResolvePriority {
+    /// Needed for playback right now, separate workers
+    Play,
+    /// Background resolves like playlist imports
+    Bulk,
+}
+
+impl Default for ResolvePriority {
+    fn default() -> Self {
+        ResolvePriority::Bulk
+    }
+}
+
+/// Reason of an instance stop
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum StopReason {
+    /// Stopped via API
+    Requested,
+    /// Kicked from the server
+    Kicked,
+    /// Banned from the server
+    Banned,
+    /// Server shut down or connection lost
+    ServerDown,
+    /// Unable to connect to the server
+    ConnectFailed,
+    /// VoIP client exited unexpectedly
+    Crashed,
+    /// VoIP client stopped sending heartbeats
+    HeartbeatTimeout,
+}
+
+impl StopReason {
+    /// Returns true if the stop wasn't caused by a user or server admin
+    pub fn is_failure(&self) -> bool {
+        match self {
+            StopReason::Requested | StopReason::Kicked | StopReason::Banned => false,
+            _ => true,
+        }
+    }
 }
 
 /// Volume it 0 to 1.0 (you can go above but that's undefined)
 pub type Volume = f64;
 
+/// Playback rate, 1.0 for normal speed
+pub type Rate = f64;
+
+/// Min supported playback rate
+pub const RATE_MIN: Rate = 0.75;
+/// Max supported playback rate
+pub const RATE_MAX: Rate = 2.0;
+
+/// Returns true if the playback rate is supported
+pub fn is_valid_rate(rate: Rate) -> bool {
+    rate >= RATE_MIN && rate <= RATE_MAX
+}
+
 /// Time unit (playback)
 pub type TimeMS = u32;
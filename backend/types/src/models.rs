@@ -24,7 +24,7 @@ use serde::{Deserialize, Serialize};
 use crate::track::Track;
 
 pub use crate::ErrorCodes;
-pub use crate::{Volume, ID};
+pub use crate::{Rate, ResolvePriority, SearchSite, StopReason, Volume, ID};
 
 /// Song identifier, char(32)
 /// Effectively u128, but not supported by json
@@ -48,6 +48,19 @@ impl From<Track> for Song {
             id: track.get_id(),
             artist: track.take_artist(),
             length: track.duration_as_u32(),
+            live: track.is_live(),
+            chapters: track
+                .take_chapters()
+                .into_iter()
+                .enumerate()
+                .map(|(i, chapter)| SongChapter {
+                    title: chapter
+                        .title
+                        .unwrap_or_else(|| format!("Chapter {}", i + 1)),
+                    start_ms: (chapter.start_time * 1000.0) as TimeMS,
+                    end_ms: (chapter.end_time * 1000.0) as TimeMS,
+                })
+                .collect(),
             name: track.title,
             source: track.webpage_url,
         }
@@ -61,17 +74,154 @@ pub struct PlaybackUrlReq {
     /// Instance
     pub id: ID,
     pub song: Song,
+    /// Trim points applied at playback time
+    #[serde(default)]
+    pub trim: Option<SongTrim>,
 }
 
-/// Volume set data
+/// Seek to position of a song, ignored if it's not playing anymore
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "tower", derive(Extract))]
+pub struct PlaybackSeekReq {
+    pub id: ID,
+    pub song: SongID,
+    pub position_ms: TimeMS,
+}
+
+/// Update trim points of a song, applied if it's currently playing
 #[derive(Debug, Deserialize, Serialize)]
 #[cfg_attr(feature = "tower", derive(Extract))]
+pub struct PlaybackTrimReq {
+    pub id: ID,
+    pub song: SongID,
+    pub trim: Option<SongTrim>,
+}
+
+/// Trim points of a song, offsets in ms
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct SongTrim {
+    /// Playback starts at this offset
+    #[serde(default)]
+    pub start_ms: TimeMS,
+    /// Playback ends at this offset
+    #[serde(default)]
+    pub end_ms: Option<TimeMS>,
+    /// Ranges skipped during playback
+    #[serde(default)]
+    pub skips: Vec<SkipRange>,
+}
+
+/// Range of a song skipped during playback, offsets in ms
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SkipRange {
+    pub start_ms: TimeMS,
+    pub end_ms: TimeMS,
+}
+
+/// Volume set data
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "tower", derive(Extract))]
 #[cfg_attr(feature = "message", derive(Message))]
 pub struct VolumeSetReq {
     pub id: ID,
     pub volume: Volume,
 }
 
+/// Playback rate set request
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "tower", derive(Extract))]
+pub struct PlaybackRateReq {
+    pub id: ID,
+    pub rate: Rate,
+    /// Keep the pitch via scaletempo, applies from the next track on  
+    /// Unchanged if not set
+    #[serde(default)]
+    pub preserve_pitch: Option<bool>,
+}
+
+/// Audio effects set request
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "tower", derive(Extract))]
+pub struct EffectsSetReq {
+    pub id: ID,
+    pub effects: AudioEffects,
+}
+
+pub type EffectsGetReq = GenericRequest;
+
+/// Amount of equalizer bands
+pub const EQUALIZER_BANDS: usize = 10;
+/// Min gain of equalizer bands in dB
+pub const EQUALIZER_GAIN_MIN: f64 = -24.0;
+/// Max gain of equalizer bands in dB
+pub const EQUALIZER_GAIN_MAX: f64 = 12.0;
+
+/// Effects chain applied to the music of an instance, clips aren't affected
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct AudioEffects {
+    /// 10 band equalizer, disabled if not set
+    #[serde(default)]
+    pub equalizer: Option<Equalizer>,
+    /// Compressor/limiter, disabled if not set
+    #[serde(default)]
+    pub compressor: Option<Compressor>,
+    /// Downmix to mono
+    #[serde(default)]
+    pub mono: bool,
+}
+
+impl AudioEffects {
+    /// Returns true if all values are in range
+    pub fn is_valid(&self) -> bool {
+        let eq_valid = match self.equalizer {
+            Some(Equalizer::Bands(ref bands)) => {
+                bands.len() == EQUALIZER_BANDS
+                    && bands
+                        .iter()
+                        .all(|v| *v >= EQUALIZER_GAIN_MIN && *v <= EQUALIZER_GAIN_MAX)
+            }
+            _ => true,
+        };
+        let compressor_valid = match self.compressor {
+            Some(ref v) => {
+                v.threshold >= 0.0 && v.threshold <= 1.0 && v.ratio >= 0.0 && v.ratio <= 1.0
+            }
+            None => true,
+        };
+        eq_valid && compressor_valid
+    }
+}
+
+/// Equalizer setting, either a preset or gains per band
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Equalizer {
+    Preset(EqualizerPreset),
+    /// Gains in dB from 29Hz to 15kHz, `EQUALIZER_GAIN_MIN` to `EQUALIZER_GAIN_MAX`
+    Bands(Vec<f64>),
+}
+
+/// Equalizer presets
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EqualizerPreset {
+    Flat,
+    BassBoost,
+    /// Cuts lows & boosts mids, clearer over voice codecs
+    Voice,
+    Treble,
+    Loudness,
+}
+
+/// Compressor/limiter above the threshold
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Compressor {
+    /// Threshold as linear amplitude, 0 to 1
+    pub threshold: f64,
+    /// Ratio applied above the threshold, 0 (limiter) to 1 (off)
+    pub ratio: f64,
+}
+
 /// Pause playback request
 #[derive(Debug, Deserialize, Serialize)]
 #[cfg_attr(feature = "tower", derive(Extract))]
@@ -92,6 +242,7 @@ pub type VolumeGetReq = GenericRequest;
 pub type StateGetReq = GenericRequest;
 pub type InstanceStopReq = GenericRequest;
 pub type HeartbeatReq = GenericRequest;
+pub type RateGetReq = GenericRequest;
 
 /// Instance started request, internal API
 #[derive(Debug, Deserialize, Serialize)]
@@ -101,6 +252,15 @@ pub struct InstanceStartedReq {
     pub pid: u32,
 }
 
+/// Instance disconnected request, internal API  
+/// Reported by the plugin before the VoIP client stops
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "tower", derive(Extract))]
+pub struct InstanceDisconnectedReq {
+    pub id: ID,
+    pub reason: StopReason,
+}
+
 /// Minimal song representation as required for playback
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Song {
@@ -111,6 +271,20 @@ pub struct Song {
     pub artist: Option<String>,
     /// Length in seconds
     pub length: Option<TimeMS>,
+    /// Live source without fixed end (stream/radio)
+    #[serde(default)]
+    pub live: bool,
+    /// Chapters of long mixes, ordered by start
+    #[serde(default)]
+    pub chapters: Vec<SongChapter>,
+}
+
+/// Chapter of a song, offsets in ms
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SongChapter {
+    pub title: String,
+    pub start_ms: TimeMS,
+    pub end_ms: TimeMS,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -135,6 +309,81 @@ pub struct VolumeResponse {
     pub msg: Option<String>,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "tower", derive(Response, Extract))]
+pub struct RateResponse {
+    pub rate: Rate,
+    pub preserve_pitch: bool,
+}
+
+/// Metrics of a worker pool
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WorkerPoolStats {
+    pub workers: usize,
+    /// Jobs waiting for a worker
+    pub queued: usize,
+    /// Jobs currently running
+    pub active: usize,
+    pub completed: usize,
+    /// Jobs rejected due to a full queue
+    pub rejected: usize,
+}
+
+/// Resolver load metrics
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "tower", derive(Response, Extract))]
+pub struct ResolveStatsResponse {
+    /// Pool resolving tracks for playback
+    pub playback_pool: WorkerPoolStats,
+    /// Resolves currently running
+    pub in_flight: usize,
+    /// Resolves which waited for an identical running resolve
+    pub coalesced: usize,
+    pub play_lane: ResolveLaneStats,
+    pub bulk_lane: ResolveLaneStats,
+}
+
+/// Metrics of a resolver lane
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ResolveLaneStats {
+    pub workers: usize,
+    /// Requests waiting for a worker
+    pub queued: usize,
+    pub processed: usize,
+    /// Requests rejected due to a full instance backlog
+    pub rejected: usize,
+    /// Average time spent waiting for a worker
+    pub avg_wait_ms: u64,
+    pub max_wait_ms: u64,
+}
+
+/// Installed youtube-dl versions & update history
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "tower", derive(Response, Extract))]
+pub struct YtdlVersionsResponse {
+    pub current: Option<String>,
+    /// Version kept for rollback
+    pub previous: Option<String>,
+    /// Configured version pin
+    pub pinned: Option<String>,
+    /// Versions which failed the self-test or got rolled back
+    pub blocked: Vec<String>,
+    /// Latest first
+    pub history: Vec<YtdlUpdateEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct YtdlUpdateEntry {
+    /// Unix Timestamp of the update
+    pub time: TimeStarted,
+    pub from: Option<String>,
+    pub to: String,
+    pub success: bool,
+    /// Update got rolled back, automatically or via API
+    pub rolled_back: bool,
+    pub msg: Option<String>,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[cfg_attr(feature = "tower", derive(Response, Extract))]
 pub struct InstanceListResponse {
@@ -146,6 +395,9 @@ pub struct InstanceListEntry {
     pub id: ID,
     /// Unix Timestamp of startup time
     pub started: TimeStarted,
+    /// Ownership token passed on start
+    #[serde(default)]
+    pub owner: Option<String>,
 }
 
 /// URL Resolver response with ticket number
@@ -161,6 +413,22 @@ pub struct ResolveTicketResponse {
 pub struct ResolveRequest {
     pub instance: ID,
     pub url: String,
+    #[serde(default)]
+    pub priority: ResolvePriority,
+}
+
+/// Request to search for tracks, results are queued under the given instance
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "tower", derive(Extract))]
+pub struct SearchRequest {
+    pub instance: ID,
+    pub query: String,
+    #[serde(default)]
+    pub site: SearchSite,
+    /// Amount of results to return
+    pub amount: u8,
+    #[serde(default)]
+    pub priority: ResolvePriority,
 }
 
 /// Response on successfully started instance
@@ -176,11 +444,29 @@ pub struct InstanceLoadReq {
     pub id: ID, //TODO:  zugriff ermöglichen, benötigt für plugin um sich zu identifizieren
     pub data: InstanceType,
     pub volume: Volume,
+    /// Ownership token of the starting manager, returned in the instance list
+    #[serde(default)]
+    pub owner: Option<String>,
+    /// Audio effects applied on start
+    #[serde(default)]
+    pub effects: AudioEffects,
+    /// Playback rate applied on start
+    #[serde(default = "default_rate")]
+    pub rate: Rate,
+    /// Pitch preservation applied on start
+    #[serde(default)]
+    pub preserve_pitch: bool,
+}
+
+fn default_rate() -> Rate {
+    1.0
 }
 
 #[derive(Debug, Serialize, Deserialize)] // workaround https://github.com/carllerche/tower-web/issues/189 using Deserialize
 pub enum InstanceType {
     TS(TSSettings),
+    Mumble(MumbleSettings),
+    Stream(StreamSettings),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -193,10 +479,214 @@ pub struct TSSettings {
     pub password: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MumbleSettings {
+    pub host: String,
+    /// Defaults to 64738
+    pub port: Option<u16>,
+    /// Username
+    pub name: String,
+    pub password: Option<String>,
+    /// Channel to join by name, stays in the root channel otherwise
+    pub channel: Option<String>,
+}
+
+/// HTTP audio stream without VoIP client
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StreamSettings {
+    #[serde(default)]
+    pub format: StreamFormat,
+    /// Bitrate in kbit/s, defaults to the daemon config
+    #[serde(default)]
+    pub bitrate: Option<u32>,
+    /// Announced stream name
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// Encoding of HTTP audio streams
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum StreamFormat {
+    OggOpus,
+    Mp3,
+}
+
+impl Default for StreamFormat {
+    fn default() -> Self {
+        StreamFormat::OggOpus
+    }
+}
+
+/// Mounted HTTP audio streams
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "tower", derive(Response, Extract))]
+pub struct StreamStatusResponse {
+    pub streams: Vec<StreamStatus>,
+}
+
+/// ID of an instance output, unique per instance
+pub type OutputID = u32;
+
+/// Additional output of an instance, fed by the same player
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum OutputType {
+    /// HTTP stream, mounted at `<instance>/<output>` on the stream server
+    Stream {
+        #[serde(default)]
+        format: StreamFormat,
+        /// Bitrate in kbit/s, defaults to the daemon config
+        #[serde(default)]
+        bitrate: Option<u32>,
+    },
+    /// File in the daemon recordings folder
+    File {
+        #[serde(default)]
+        format: RecordFormat,
+    },
+}
+
+/// Encoding of recorded files
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RecordFormat {
+    OggVorbis,
+    Flac,
+}
+
+impl Default for RecordFormat {
+    fn default() -> Self {
+        RecordFormat::OggVorbis
+    }
+}
+
+/// Start recording of an instance
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "tower", derive(Extract))]
+pub struct RecordingStartReq {
+    pub id: ID,
+    #[serde(default)]
+    pub format: RecordFormat,
+}
+
+pub type RecordingStopReq = GenericRequest;
+pub type RecordingListReq = GenericRequest;
+
+/// Download of recording or its sidecar
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "tower", derive(Extract))]
+pub struct RecordingFileReq {
+    /// File name as listed
+    pub name: String,
+}
+
+/// Recording of an instance, stored as JSON sidecar `<file>.json` next to the file
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Recording {
+    /// File name in the recordings folder
+    pub file: String,
+    pub instance: ID,
+    pub format: RecordFormat,
+    /// Start as UNIX timestamp
+    pub started: i64,
+    /// Recorded audio length, without paused time
+    pub duration_ms: u64,
+    /// Still recording
+    pub active: bool,
+    pub markers: Vec<TrackMarker>,
+}
+
+/// Start of a track in a recording
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TrackMarker {
+    /// Position in the recording
+    pub offset_ms: u64,
+    pub title: String,
+    pub artist: Option<String>,
+    pub source: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "tower", derive(Response, Extract))]
+pub struct RecordingListResponse {
+    pub recordings: Vec<Recording>,
+}
+
+/// Attach output to instance
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "tower", derive(Extract))]
+pub struct OutputAttachReq {
+    pub id: ID,
+    pub output: OutputType,
+}
+
+/// Detach output of instance
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "tower", derive(Extract))]
+pub struct OutputDetachReq {
+    pub id: ID,
+    pub output: OutputID,
+}
+
+pub type OutputListReq = GenericRequest;
+
+/// Attached output
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OutputInfo {
+    pub output: OutputID,
+    pub kind: OutputType,
+    /// Stream mount or file name
+    pub location: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "tower", derive(Response, Extract))]
+pub struct OutputListResponse {
+    pub outputs: Vec<OutputInfo>,
+}
+
+/// Play soundboard clip over the music of an instance
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "tower", derive(Extract))]
+pub struct ClipPlayReq {
+    pub id: ID,
+    /// Clip name, daemons fetch clips from `/clip/<name>` of the manager
+    pub clip: String,
+    /// Upload time of the clip, daemons cache clips by revision
+    pub revision: i64,
+    /// Clip volume, independent of the instance volume
+    pub volume: Volume,
+}
+
+/// Stops all clips of an instance
+pub type ClipStopReq = GenericRequest;
+
+/// Play jingle of the soundboard library as track  
+/// Jingles aren't marked in recordings
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "tower", derive(Extract))]
+pub struct JinglePlayReq {
+    pub id: ID,
+    /// Clip name, fetched & cached like soundboard clips
+    pub clip: String,
+    /// Upload time of the clip
+    pub revision: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StreamStatus {
+    /// Path of the stream on the stream server
+    pub mount: String,
+    pub instance: ID,
+    pub format: StreamFormat,
+    pub listeners: u32,
+    /// Max concurrent listeners since the stream started
+    pub listeners_peak: u32,
+}
+
 #[derive(Serialize, Deserialize)]
 #[cfg_attr(feature = "tower", derive(Response, Extract))]
 pub struct InstanceOverviewResponse {
     pub instances: Vec<InstanceOverview>,
+    pub resolver: ResolveStatsResponse,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -208,30 +698,76 @@ pub struct InstanceOverview {
     pub playback_info: String,
 }
 
-/// Callbacks
+/// Daemon events
 
 pub mod callback {
     use super::*;
 
-    /// Start of the path for all callbacks
-    pub const PATH_START_: &'static str = "callback";
-    /// Full path for callback
-    pub const PATH_INSTANCE: &'static str = "/callback/instance";
-    /// Full path for callback
-    pub const PATH_RESOLVE: &'static str = "/callback/resolve";
-    /// Full path for callback
-    pub const PATH_PLAYBACK: &'static str = "/callback/playback";
-    /// Full path for callback
-    pub const PATH_SONG: &'static str = "/callback/song";
-    /// Full path for callback
-    pub const PATH_VOLUME: &'static str = "/callback/volume";
-    /// Full path for callback
-    pub const PATH_POSITION: &'static str = "/callback/position";
-
-    #[derive(Debug, Serialize, Deserialize)]
+    /// Path of the daemon event stream (server sent events)
+    pub const PATH_EVENTS: &'static str = "/events";
+
+    /// Sequence number of daemon events, starting at 1
+    pub type Sequence = u64;
+
+    /// Daemon event, sent as SSE `data` with the sequence as `id`
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct Event {
+        /// 0 for connection specific events, which are not replayed
+        pub seq: Sequence,
+        pub data: EventData,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub enum EventData {
+        /// First event of each connection
+        Connected(StreamInfo),
+        /// Requested events aren't buffered anymore, consumers have to resync
+        Missed {
+            first_available: Sequence,
+        },
+        Instance(InstanceStateResponse),
+        Resolve(ResolveResponse),
+        Playback(PlaystateResponse),
+        Song(SongInfoUpdate),
+        Volume(VolumeChange),
+        Position(TrackPositionUpdate),
+        Positions(PositionBatch),
+    }
+
+    /// Event stream of one daemon run
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct StreamInfo {
+        /// Startup time of the daemon, sequences restart with a new epoch
+        pub epoch: i64,
+        /// Sequence of the next event
+        pub next_seq: Sequence,
+    }
+
+    /// Event stream query params
+    #[derive(Debug, Default, Serialize, Deserialize)]
+    pub struct EventStreamReq {
+        /// Last acknowledged sequence, replay starts after it
+        pub since: Option<Sequence>,
+        /// Epoch of the acknowledged sequence
+        pub epoch: Option<i64>,
+    }
+
+    /// Song info change, currently the stream title of live sources
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[cfg_attr(feature = "message", derive(Message))]
+    pub struct SongInfoUpdate {
+        pub id: ID,
+        /// Title as reported by the stream (ICY metadata)
+        pub stream_title: Option<String>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
     pub struct InstanceStateResponse {
         pub state: InstanceState,
         pub id: ID,
+        /// Set when stopped
+        #[serde(default)]
+        pub reason: Option<StopReason>,
     }
 
     #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -241,14 +777,43 @@ pub mod callback {
         Stopped = 0,
     }
 
-    #[derive(Debug, Serialize, Deserialize)]
+    /// Playback timing, sent on start, pause, seek & rate changes
+    /// Consumers interpolate the position, see `position_at`
+    #[derive(Debug, Clone, Serialize, Deserialize)]
     #[cfg_attr(feature = "message", derive(Message))]
     pub struct TrackPositionUpdate {
         pub position_ms: TimeMS,
         pub id: ID,
+        /// Unix time in ms at which `position_ms` was sampled, by the daemon clock  
+        /// The manager replaces it on receipt, consumers interpolate with its clock
+        #[serde(default)]
+        pub timestamp_ms: i64,
+        /// Playback rate, 1.0 for normal speed
+        #[serde(default = "default_rate")]
+        pub rate: f64,
+        /// Position doesn't advance while paused or stopped
+        #[serde(default)]
+        pub paused: bool,
+    }
+
+    impl TrackPositionUpdate {
+        /// Interpolated position at unix time in ms
+        pub fn position_at(&self, now_ms: i64) -> TimeMS {
+            if self.paused || now_ms <= self.timestamp_ms {
+                return self.position_ms;
+            }
+            let elapsed = (now_ms - self.timestamp_ms) as f64 * self.rate;
+            (f64::from(self.position_ms) + elapsed).max(0.0) as TimeMS
+        }
     }
 
-    #[derive(Debug, Serialize, Deserialize)]
+    /// Low frequency heartbeat with positions of all playing instances
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct PositionBatch {
+        pub positions: Vec<TrackPositionUpdate>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
     #[cfg_attr(feature = "message", derive(Message))]
     pub struct PlaystateResponse {
         pub state: Playstate,
@@ -266,12 +831,15 @@ pub mod callback {
     pub type VolumeChange = VolumeSetReq;
 
     /// Url resolve response for ticket
-    #[derive(Debug, Serialize, Deserialize)]
+    #[derive(Debug, Clone, Serialize, Deserialize)]
     pub struct ResolveResponse {
         /// Original URL for request
         pub source: String,
         /// Whether the call had success
         pub success: bool,
+        /// Failure class, NONE on success
+        #[serde(default)]
+        pub error: ErrorCodes,
         /// Message for aribtrary errors
         pub msg: Option<String>,
         /// Song list on success (can be empty for an empty playlist!)
@@ -1,6 +1,6 @@
 
 use serde::{Serialize,Deserialize};
-pub use crate::{Volume, ID};
+pub use crate::{SearchSite, Volume, ID};
 pub use crate::ErrorCodes;
 
 //allowed, message, success
@@ -28,14 +28,26 @@ macro_rules! add_trait {
 }
 
 add_trait! {(GetId) for ParamVolume}
+add_trait! {(GetId) for ParamVolumeLock}
 add_trait! {(GetId) for ParamQueue}
 add_trait! {(GetId) for ParamDefault}
 add_trait! {(GetId) for ParamQueueTracks}
-
+add_trait! {(GetId) for ParamSearch}
+add_trait! {(GetId) for ParamPick}
+add_trait! {(GetId) for ParamSoundboard}
+add_trait! {(GetId) for ParamTrim}
+add_trait! {(GetId) for ParamChapter}
+add_trait! {(GetId) for ParamSpeed}
+add_trait! {(GetId) for ParamQueueLock}
+add_trait! {(GetId) for ParamPlaylistLoad}
+
+/// Invoker of a chat command  
+/// UID is the TS unique ID or Mumble certificate hash, empty if unknown
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ParamDefault {
 	pub id: ID,
 	pub invoker_name: String,
+	pub invoker_uid: String,
 	pub invoker_groups: String,
 }
 
@@ -43,22 +55,119 @@ pub struct ParamDefault {
 pub struct ParamVolume {
 	pub id: ID,
 	pub invoker_name: String,
+	pub invoker_uid: String,
 	pub invoker_groups: String,
 	pub volume: Volume,
 }
 
+/// Lock or unlock the volume for unprivileged invokers
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ParamVolumeLock {
+	pub id: ID,
+	pub invoker_name: String,
+	pub invoker_uid: String,
+	pub invoker_groups: String,
+	pub lock: bool,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ParamQueue {
 	pub id: ID,
 	pub invoker_name: String,
+	pub invoker_uid: String,
 	pub invoker_groups: String,
 	pub url: String,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ParamSearch {
+	pub id: ID,
+	pub invoker_name: String,
+	pub invoker_uid: String,
+	pub invoker_groups: String,
+	pub query: String,
+	#[serde(default)]
+	pub site: SearchSite,
+}
+
+/// Pick result n (starting at 1) of the last search
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ParamPick {
+	pub id: ID,
+	pub invoker_name: String,
+	pub invoker_uid: String,
+	pub invoker_groups: String,
+	pub n: usize,
+}
+
+/// Play soundboard clip by name
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ParamSoundboard {
+	pub id: ID,
+	pub invoker_name: String,
+	pub invoker_uid: String,
+	pub invoker_groups: String,
+	pub clip: String,
+}
+
+/// Edit trim points of the current song  
+/// Command is the chat input after `!trim`, parsed by the manager
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ParamTrim {
+	pub id: ID,
+	pub invoker_name: String,
+	pub invoker_uid: String,
+	pub invoker_groups: String,
+	pub command: String,
+}
+
+/// Jump to chapter of the current song  
+/// Command is the chat input after `!chapter`, `next` or the chapter number
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ParamChapter {
+	pub id: ID,
+	pub invoker_name: String,
+	pub invoker_uid: String,
+	pub invoker_groups: String,
+	pub command: String,
+}
+
+/// Show or set playback speed  
+/// Command is the chat input after `!speed`, empty for showing
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ParamSpeed {
+	pub id: ID,
+	pub invoker_name: String,
+	pub invoker_uid: String,
+	pub invoker_groups: String,
+	pub command: String,
+}
+
+/// Lock or unlock the queue
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ParamQueueLock {
+	pub id: ID,
+	pub invoker_name: String,
+	pub invoker_uid: String,
+	pub invoker_groups: String,
+	pub lock: bool,
+}
+
+/// Load playlist by name into the queue
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ParamPlaylistLoad {
+	pub id: ID,
+	pub invoker_name: String,
+	pub invoker_uid: String,
+	pub invoker_groups: String,
+	pub playlist_name: String,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ParamQueueTracks {
 	pub id: ID,
 	pub invoker_name: String,
+	pub invoker_uid: String,
 	pub invoker_groups: String,
 	pub n: usize,
 }
@@ -33,6 +33,17 @@ pub struct Track {
     pub webpage_url: String,
     pub artist: Option<String>,
     pub uploader: Option<String>,
+    pub is_live: Option<bool>,
+    #[serde(default)]
+    pub chapters: Option<Vec<Chapter>>,
+}
+
+/// Chapter of a track, offsets in seconds
+#[derive(Debug, Deserialize)]
+pub struct Chapter {
+    pub start_time: f64,
+    pub end_time: f64,
+    pub title: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -70,6 +81,11 @@ impl Track {
         None
     }
 
+    /// Takes the chapters of this track
+    pub fn take_chapters(&mut self) -> Vec<Chapter> {
+        self.chapters.take().unwrap_or_default()
+    }
+
     /// Duration as u32
     pub fn duration_as_u32(&self) -> Option<u32> {
         if let Some(v) = self.duration {
@@ -79,6 +95,11 @@ impl Track {
         }
     }
 
+    /// Whether this track is a live source (stream/radio)
+    pub fn is_live(&self) -> bool {
+        self.is_live == Some(true)
+    }
+
     /// Returns best audio format
     pub fn best_audio_format(&self,min_audio_bitrate: i64 ) -> Option<&Format> {
         let track_audio = self.best_audio_only_format();
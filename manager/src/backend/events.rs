@@ -0,0 +1,356 @@
+/*
+ *  YAMBA manager
+ *  Copyright (C) 2019 Aron Heinecke
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *      http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ */
+
+use super::*;
+use crate::frontend;
+use crate::instance;
+
+use actix::SystemService;
+use futures::future::{join_all, loop_fn, result, Either, Loop};
+use futures::Stream;
+use hashbrown::HashSet;
+use tokio_timer::{Delay, Timeout};
+use yamba_types::models::callback::{self as cb, Event, EventData, EventStreamReq, Sequence};
+use yamba_types::models::{GenericRequest, ID};
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Consumer of the daemon event stream
+
+/// Delay before reconnecting to the event stream
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+/// Max time without data, the daemon sends keepalives every 15s
+const READ_TIMEOUT: Duration = Duration::from_secs(45);
+
+#[derive(Fail, Debug)]
+pub enum EventErr {
+    #[fail(display = "Event stream ended")]
+    StreamEnded,
+    #[fail(display = "Event stream timed out")]
+    Timeout,
+    #[fail(display = "Invalid event stream response {}", _0)]
+    InvalidStatus(reqwest::StatusCode),
+}
+
+/// Position in the event stream, replay on reconnect starts after it
+#[derive(Default, Debug)]
+struct StreamPos {
+    epoch: Option<i64>,
+    last_seq: Option<Sequence>,
+}
+
+/// Subscribe to daemon events, reconnects on failure  
+/// Events are only applied to instances placed on this daemon
+pub fn start_event_stream(backend: Backend, instances: Instances) {
+    let pos = Arc::new(Mutex::new(StreamPos::default()));
+    spawn(loop_fn((), move |_| {
+        subscribe(&backend, instances.clone(), pos.clone()).then(|result| {
+            if let Err(e) = result {
+                warn!("Event stream disconnected: {}", e);
+            }
+            Delay::new(Instant::now() + RECONNECT_DELAY)
+                .then(|_| -> Result<Loop<(), ()>, ()> { Ok(Loop::Continue(())) })
+        })
+    }));
+}
+
+/// Subscribe & handle events until the stream breaks
+fn subscribe(
+    backend: &Backend,
+    instances: Instances,
+    pos: Arc<Mutex<StreamPos>>,
+) -> impl Future<Item = (), Error = failure::Error> {
+    let query = {
+        let pos = pos.lock().expect("Can't lock stream position!");
+        EventStreamReq {
+            since: pos.last_seq,
+            epoch: pos.epoch,
+        }
+    };
+    debug!("Subscribing to event stream {:?}", query);
+    let backend = backend.clone();
+    backend
+        .client
+        .get(&format!(
+            "http://{}{}",
+            backend.events_addr,
+            cb::PATH_EVENTS
+        ))
+        .query(&query)
+        .send()
+        .from_err::<failure::Error>()
+        .and_then(|response| {
+            if response.status().is_success() {
+                Ok(response)
+            } else {
+                Err(EventErr::InvalidStatus(response.status()).into())
+            }
+        })
+        .and_then(move |response| {
+            let mut parser = SseParser::default();
+            Timeout::new(response.into_body(), READ_TIMEOUT)
+                .map_err(|e| -> failure::Error {
+                    if e.is_elapsed() {
+                        EventErr::Timeout.into()
+                    } else if e.is_timer() {
+                        e.into_timer().unwrap().into()
+                    } else {
+                        e.into_inner().unwrap().into()
+                    }
+                })
+                .for_each(move |chunk| {
+                    for event in parser.push(&chunk) {
+                        handle_event(&backend, &instances, &pos, event);
+                    }
+                    Ok(())
+                })
+        })
+        .and_then(|_| Err::<(), _>(EventErr::StreamEnded.into()))
+}
+
+/// Handle single event
+fn handle_event(
+    backend: &Backend,
+    instances: &Instances,
+    pos: &Arc<Mutex<StreamPos>>,
+    event: Event,
+) {
+    if event.seq != 0 {
+        let mut pos = pos.lock().expect("Can't lock stream position!");
+        if pos.last_seq.map_or(false, |v| event.seq <= v) {
+            trace!("Ignoring duplicate event {}", event.seq);
+            return;
+        }
+        pos.last_seq = Some(event.seq);
+    }
+    match event.data {
+        EventData::Connected(info) => {
+            let mut pos = pos.lock().expect("Can't lock stream position!");
+            if pos.epoch != Some(info.epoch) {
+                if pos.epoch.is_some() {
+                    info!("Daemon restarted, event sequence reset");
+                }
+                pos.epoch = Some(info.epoch);
+                pos.last_seq = None;
+            }
+            debug!("Event stream connected {:?}", info);
+        }
+        EventData::Missed { first_available } => {
+            warn!(
+                "Lost daemon events, replaying from {} onwards, resyncing state",
+                first_available
+            );
+            resync(backend, instances);
+        }
+        EventData::Instance(data) => {
+            debug!("Instance state change: {:?}", data);
+            let stopped = match instances.read_placed(&data.id, backend.get_id()) {
+                Some(i) => {
+                    i.cb_set_instance_state(data.state);
+                    match data.state {
+                        cb::InstanceState::Stopped => true,
+                        _ => false,
+                    }
+                }
+                None => false,
+            };
+            if stopped {
+                instances.get_pool().release(&data.id);
+                reconcile::handle_stop(instances, data.id, data.reason);
+            }
+        }
+        EventData::Volume(data) => {
+            debug!("Volume change: {:?}", data);
+            if let Some(i) = instances.read_placed(&data.id, backend.get_id()) {
+                i.cb_update_volume(data.volume);
+            }
+        }
+        EventData::Playback(data) => {
+            debug!("Playback change: {:?}", data);
+            if let Some(i) = instances.read_placed(&data.id, backend.get_id()) {
+                i.cb_set_playback_state(data.state);
+            }
+        }
+        EventData::Resolve(data) => {
+            debug!("Resolve event: {:?}", data);
+            backend
+                .tickets
+                .handle(&data.ticket.clone(), instances, data);
+        }
+        EventData::Position(data) => {
+            handle_position(backend, instances, data);
+        }
+        EventData::Positions(batch) => {
+            trace!("Position heartbeat for {} instances", batch.positions.len());
+            for data in batch.positions {
+                handle_position(backend, instances, data);
+            }
+        }
+        EventData::Song(data) => {
+            debug!("Song info update: {:?}", data);
+            if let Some(i) = instances.read_placed(&data.id, backend.get_id()) {
+                i.cb_set_stream_title(data);
+            }
+        }
+    }
+}
+
+/// Resync instances placed on the daemon, after events got lost  
+/// Instances not running anymore are handled as stopped, others get their playback state & volume updated
+fn resync(backend: &Backend, instances: &Instances) {
+    let list = match backend.get_instances() {
+        Ok(v) => v,
+        Err(e) => {
+            warn!("Can't resync daemon {}: {}", backend.get_id(), e);
+            return;
+        }
+    };
+    let backend = backend.clone();
+    let instances = instances.clone();
+    spawn(
+        list.map_err(|e| warn!("Can't list instances for resync: {}", e))
+            .and_then(move |list| {
+                let daemon = backend.get_id();
+                let running: HashSet<ID> = list.instances.iter().map(|v| v.id).collect();
+                let mut updates = Vec::new();
+                for id in instances.get_pool().placed_on(daemon) {
+                    if running.contains(&id) {
+                        updates.push(resync_instance(&backend, &instances, id));
+                        continue;
+                    }
+                    info!("Instance {} stopped while events were lost", id);
+                    if let Some(i) = instances.read_placed(&id, daemon) {
+                        i.cb_set_instance_state(cb::InstanceState::Stopped);
+                    }
+                    instances.get_pool().release(&id);
+                    reconcile::handle_stop(&instances, id, None);
+                }
+                join_all(updates).map(|_| ())
+            }),
+    );
+}
+
+/// Fetch & apply playback state & volume of instance
+fn resync_instance(
+    backend: &Backend,
+    instances: &Instances,
+    id: ID,
+) -> impl Future<Item = (), Error = ()> {
+    let request = GenericRequest { id };
+    let state = match (
+        backend.get_playback_state(&request),
+        backend.get_volume(&request),
+    ) {
+        (Ok(playback), Ok(volume)) => playback.join(volume),
+        (Err(e), _) | (_, Err(e)) => {
+            warn!("Can't resync instance {}: {}", id, e);
+            return Either::A(result(Ok(())));
+        }
+    };
+    let daemon = backend.get_id();
+    let instances = instances.clone();
+    Either::B(
+        state
+            .map(move |(playback, volume)| {
+                if let Some(i) = instances.read_placed(&id, daemon) {
+                    i.cb_set_instance_state(cb::InstanceState::Running);
+                    i.cb_set_playback_state(playback.state);
+                    if let Some(v) = volume.volume {
+                        i.cb_update_volume(v);
+                    }
+                }
+            })
+            .or_else(move |e| {
+                warn!("Can't resync instance {}: {}", id, e);
+                Ok(())
+            }),
+    )
+}
+
+/// Store playback timing & forward it to clients, which interpolate the position  
+/// Re-stamped with the manager clock, daemon & manager clocks may differ
+fn handle_position(backend: &Backend, instances: &Instances, mut data: cb::TrackPositionUpdate) {
+    if !instances
+        .get_pool()
+        .is_placed_on(&data.id, backend.get_id())
+    {
+        return;
+    }
+    data.timestamp_ms = instance::now_ms();
+    instances.set_pos(data.clone());
+    spawn(
+        frontend::WSServer::from_registry()
+            .send(data)
+            .map_err(|e| warn!("WS-Server error: {}", e)),
+    );
+}
+
+/// Parser for server sent events
+#[derive(Default)]
+struct SseParser {
+    buffer: Vec<u8>,
+}
+
+impl SseParser {
+    /// Add received data, returns all completed events
+    fn push(&mut self, data: &[u8]) -> Vec<Event> {
+        self.buffer.extend_from_slice(data);
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+            let frame: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            let frame = String::from_utf8_lossy(&frame);
+            let data: Vec<&str> = frame
+                .lines()
+                .filter(|l| l.starts_with("data:"))
+                .map(|l| l["data:".len()..].trim_start())
+                .collect();
+            // comments & keepalives
+            if data.is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&data.join("\n")) {
+                Ok(v) => events.push(v),
+                Err(e) => warn!("Invalid event {}: {}", frame, e),
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parser() {
+        let mut parser = SseParser::default();
+        let frame =
+            "id: 3\ndata: {\"seq\":3,\"data\":{\"Position\":{\"position_ms\":10,\"id\":1}}}\n\n";
+        let (a, b) = frame.as_bytes().split_at(20);
+        assert!(parser.push(b": keepalive\n\n").is_empty());
+        assert!(parser.push(a).is_empty());
+        let events = parser.push(b);
+        assert_eq!(1, events.len());
+        assert_eq!(3, events[0].seq);
+        match events[0].data {
+            EventData::Position(ref v) => assert_eq!(10, v.position_ms),
+            ref v => panic!("Unexpected event {:?}", v),
+        }
+        assert!(parser.buffer.is_empty());
+    }
+}
@@ -15,7 +15,9 @@
  *  limitations under the License.
  */
 
-pub mod callback;
+pub mod events;
+pub mod pool;
+pub mod reconcile;
 pub mod tickets;
 
 use actix::spawn;
@@ -28,13 +30,16 @@ use yamba_types::models;
 use std::fmt::Debug;
 use std::net::SocketAddr;
 
+pub use self::pool::{DaemonConfig, DaemonID, DaemonPool};
 use self::tickets::TicketHandler;
 
 use crate::instance::Instances;
 
 #[derive(Clone)]
 pub struct Backend {
+    id: DaemonID,
     addr: SocketAddr,
+    events_addr: SocketAddr,
     client: Client,
     tickets: TicketHandler,
 }
@@ -42,10 +47,10 @@ pub struct Backend {
 impl Backend {
     /// Create new backend endpoint
     pub fn new(
+        id: DaemonID,
         addr: SocketAddr,
-        instances: Instances,
         api_secret: &str,
-        callback_bind: SocketAddr,
+        events_addr: SocketAddr,
     ) -> Fallible<Backend> {
         let mut headers = header::HeaderMap::new();
         headers.insert(
@@ -57,15 +62,23 @@ impl Backend {
             header::HeaderValue::from_str(api_secret)?,
         );
         let tickets = TicketHandler::new();
-        let backend = Backend {
+        Ok(Backend {
             client: ClientBuilder::new().default_headers(headers).build()?,
+            id,
             addr,
-            tickets: tickets.clone(),
-        };
+            events_addr,
+            tickets,
+        })
+    }
 
-        callback::init_callback_server(backend.clone(), instances, callback_bind, tickets)?;
+    /// Returns ID of daemon in pool
+    pub fn get_id(&self) -> DaemonID {
+        self.id
+    }
 
-        Ok(backend)
+    /// Returns daemon API address
+    pub fn get_addr(&self) -> &SocketAddr {
+        &self.addr
     }
 
     /// Returns ticket handler
@@ -123,6 +136,23 @@ impl Backend {
         Ok(fut)
     }
 
+    /// Get state of instance
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn get_instance_state(
+        &self,
+        request: &models::StateGetReq,
+    ) -> Fallible<impl Future<Item = models::callback::InstanceStateResponse, Error = reqwest::Error>>
+    {
+        let fut = self
+            .get_request_base(
+                &format!("http://{}/instance/state", self.addr),
+                Some(request),
+                false,
+            )?
+            .and_then(|mut x| x.json::<models::callback::InstanceStateResponse>());
+        Ok(fut)
+    }
+
     /// Create instance
     #[must_use = "Future doesn't do anything untill polled!"]
     pub fn create_instance(
@@ -155,6 +185,55 @@ impl Backend {
         Ok(fut)
     }
 
+    /// Update trim points of the playing song
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn set_trim(
+        &self,
+        request: &models::PlaybackTrimReq,
+    ) -> Fallible<impl Future<Item = models::DefaultResponse, Error = reqwest::Error>> {
+        let fut = self
+            .get_request_base(
+                &format!("http://{}/playback/trim", self.addr),
+                Some(request),
+                true,
+            )?
+            .and_then(|mut x| x.json::<models::DefaultResponse>());
+        Ok(fut)
+    }
+
+    /// Seek in the playing song
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn seek(
+        &self,
+        request: &models::PlaybackSeekReq,
+    ) -> Fallible<impl Future<Item = models::DefaultResponse, Error = reqwest::Error>> {
+        let fut = self
+            .get_request_base(
+                &format!("http://{}/playback/seek", self.addr),
+                Some(request),
+                true,
+            )?
+            .and_then(|mut x| x.json::<models::DefaultResponse>());
+        Ok(fut)
+    }
+
+    /// Play jingle as track, fetched in the background
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn play_jingle(
+        &self,
+        request: &models::JinglePlayReq,
+    ) -> Fallible<impl Future<Item = (), Error = reqwest::Error>> {
+        let fut = self
+            .get_request_base(
+                &format!("http://{}/playback/jingle", self.addr),
+                Some(request),
+                true,
+            )?
+            .and_then(|x| x.error_for_status())
+            .map(|_| ());
+        Ok(fut)
+    }
+
     /// Resolve URL request
     #[must_use = "Future doesn't do anything untill polled!"]
     pub fn resolve_url(
@@ -175,6 +254,23 @@ impl Backend {
         Ok(fut)
     }
 
+    /// Search request, results are returned via ticket callback
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn resolve_search(
+        &self,
+        request: &models::SearchRequest,
+    ) -> Fallible<impl Future<Item = models::ResolveTicketResponse, Error = reqwest::Error>> {
+        trace!("Searching for {}", request.query);
+        let fut = self
+            .get_request_base(
+                &format!("http://{}/resolve/search", self.addr),
+                Some(request),
+                false,
+            )?
+            .and_then(|mut x| x.json::<models::ResolveTicketResponse>());
+        Ok(fut)
+    }
+
     /// Set Volume request
     #[must_use = "Future doesn't do anything untill polled!"]
     pub fn set_volume(
@@ -187,6 +283,189 @@ impl Backend {
         Ok(fut)
     }
 
+    /// Get volume of instance
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn get_volume(
+        &self,
+        request: &models::VolumeGetReq,
+    ) -> Fallible<impl Future<Item = models::VolumeResponse, Error = reqwest::Error>> {
+        let fut = self
+            .get_request_base(
+                &format!("http://{}/volume", self.addr),
+                Some(request),
+                false,
+            )?
+            .and_then(|mut x| x.json::<models::VolumeResponse>());
+        Ok(fut)
+    }
+
+    /// Get playback state of instance
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn get_playback_state(
+        &self,
+        request: &models::StateGetReq,
+    ) -> Fallible<impl Future<Item = models::callback::PlaystateResponse, Error = reqwest::Error>>
+    {
+        let fut = self
+            .get_request_base(
+                &format!("http://{}/playback/state", self.addr),
+                Some(request),
+                false,
+            )?
+            .and_then(|x| x.error_for_status())
+            .and_then(|mut x| x.json::<models::callback::PlaystateResponse>());
+        Ok(fut)
+    }
+
+    /// Set audio effects of instance
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn set_effects(
+        &self,
+        request: &models::EffectsSetReq,
+    ) -> Fallible<impl Future<Item = models::DefaultResponse, Error = reqwest::Error>> {
+        let fut = self
+            .get_request_base(
+                &format!("http://{}/instance/effects", self.addr),
+                Some(request),
+                true,
+            )?
+            .and_then(|x| x.error_for_status())
+            .and_then(|mut x| x.json::<models::DefaultResponse>());
+        Ok(fut)
+    }
+
+    /// Set playback rate
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn set_rate(
+        &self,
+        request: &models::PlaybackRateReq,
+    ) -> Fallible<impl Future<Item = models::DefaultResponse, Error = reqwest::Error>> {
+        let fut = self
+            .get_request_base(
+                &format!("http://{}/playback/rate", self.addr),
+                Some(request),
+                true,
+            )?
+            .and_then(|x| x.error_for_status())
+            .and_then(|mut x| x.json::<models::DefaultResponse>());
+        Ok(fut)
+    }
+
+    /// Get playback rate
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn get_rate(
+        &self,
+        request: &models::RateGetReq,
+    ) -> Fallible<impl Future<Item = models::RateResponse, Error = reqwest::Error>> {
+        let fut = self
+            .get_request_base(
+                &format!("http://{}/playback/rate", self.addr),
+                Some(request),
+                false,
+            )?
+            .and_then(|mut x| x.json::<models::RateResponse>());
+        Ok(fut)
+    }
+
+    /// Start recording of instance output
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn start_recording(
+        &self,
+        request: &models::RecordingStartReq,
+    ) -> Fallible<impl Future<Item = models::Recording, Error = reqwest::Error>> {
+        let fut = self
+            .get_request_base(
+                &format!("http://{}/recording/start", self.addr),
+                Some(request),
+                true,
+            )?
+            .and_then(|x| x.error_for_status())
+            .and_then(|mut x| x.json::<models::Recording>());
+        Ok(fut)
+    }
+
+    /// Stop recording of instance
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn stop_recording(
+        &self,
+        request: &models::RecordingStopReq,
+    ) -> Fallible<impl Future<Item = models::Recording, Error = reqwest::Error>> {
+        let fut = self
+            .get_request_base(
+                &format!("http://{}/recording/stop", self.addr),
+                Some(request),
+                true,
+            )?
+            .and_then(|x| x.error_for_status())
+            .and_then(|mut x| x.json::<models::Recording>());
+        Ok(fut)
+    }
+
+    /// Get recordings of instance
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn get_recordings(
+        &self,
+        request: &models::RecordingListReq,
+    ) -> Fallible<impl Future<Item = models::RecordingListResponse, Error = reqwest::Error>> {
+        let fut = self
+            .get_request_base(
+                &format!("http://{}/recording/list", self.addr),
+                Some(request),
+                false,
+            )?
+            .and_then(|mut x| x.json::<models::RecordingListResponse>());
+        Ok(fut)
+    }
+
+    /// Download recording or sidecar file, response body is the file
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn get_recording_file(
+        &self,
+        request: &models::RecordingFileReq,
+    ) -> Fallible<impl Future<Item = Response, Error = reqwest::Error>> {
+        let fut = self
+            .get_request_base(
+                &format!("http://{}/recording/file", self.addr),
+                Some(request),
+                false,
+            )?
+            .and_then(|x| x.error_for_status());
+        Ok(fut)
+    }
+
+    /// Play soundboard clip, accepted clips are fetched & played in the background
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn play_clip(
+        &self,
+        request: &models::ClipPlayReq,
+    ) -> Fallible<impl Future<Item = (), Error = reqwest::Error>> {
+        let fut = self
+            .get_request_base(
+                &format!("http://{}/soundboard/play", self.addr),
+                Some(request),
+                true,
+            )?
+            .and_then(|x| x.error_for_status())
+            .map(|_| ());
+        Ok(fut)
+    }
+
+    /// Stop all soundboard clips of instance
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn stop_clips(
+        &self,
+        request: &models::ClipStopReq,
+    ) -> Fallible<impl Future<Item = models::DefaultResponse, Error = reqwest::Error>> {
+        let fut = self
+            .get_request_base(
+                &format!("http://{}/soundboard/stop", self.addr),
+                Some(request),
+                true,
+            )?
+            .and_then(|mut x| x.json::<models::DefaultResponse>());
+        Ok(fut)
+    }
+
     /// Create request base
     #[must_use = "Future doesn't do anything untill polled!"]
     fn get_request_base<T>(
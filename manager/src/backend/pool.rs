@@ -0,0 +1,473 @@
+/*
+ *  YAMBA manager
+ *  Copyright (C) 2019 Aron Heinecke
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *      http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ */
+
+use super::*;
+
+use futures::future::{join_all, result, Either};
+use futures::Stream;
+use hashbrown::HashMap;
+use tokio_timer::{Interval, Timeout};
+use yamba_types::models::{StopReason, ID};
+
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::models::DaemonInfo;
+
+/// Pool of daemons, instances are placed on the daemon with the lowest load
+
+/// Default amount of instances per daemon
+pub const DEFAULT_CAPACITY: usize = 15;
+/// Interval of daemon health checks
+const HEALTH_INTERVAL: Duration = Duration::from_secs(10);
+/// Max time for a health check response
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(5);
+/// Failed health checks after which a daemon is considered dead
+const HEALTH_MAX_FAILURES: usize = 3;
+
+/// Index of a daemon in the pool
+pub type DaemonID = usize;
+
+#[derive(Fail, Debug)]
+pub enum PoolErr {
+    #[fail(display = "No daemon with free capacity available")]
+    NoCapacity,
+    #[fail(
+        display = "Invalid daemon config {}, expected API_IP:PORT,EVENTS_IP:PORT[,CAPACITY]",
+        _0
+    )]
+    InvalidConfig(String),
+    #[fail(display = "No daemon configured")]
+    NoDaemons,
+}
+
+/// Outcome of daemon health checks
+#[derive(Debug, Default)]
+pub struct HealthReport {
+    /// Instances of dead daemons, unplaced & to be restarted
+    pub orphaned: Vec<ID>,
+    /// Daemons reachable again, may still run instances re-placed meanwhile
+    pub recovered: Vec<DaemonID>,
+}
+
+/// Daemon endpoint configuration
+#[derive(Debug, PartialEq)]
+pub struct DaemonConfig {
+    pub addr: SocketAddr,
+    pub events_addr: SocketAddr,
+    pub capacity: usize,
+}
+
+impl FromStr for DaemonConfig {
+    type Err = failure::Error;
+    /// Parse `API_IP:PORT,EVENTS_IP:PORT[,CAPACITY]`
+    fn from_str(s: &str) -> Fallible<DaemonConfig> {
+        let parts: Vec<&str> = s.split(',').map(|v| v.trim()).collect();
+        if parts.len() < 2 || parts.len() > 3 {
+            return Err(PoolErr::InvalidConfig(s.to_string()).into());
+        }
+        let capacity = match parts.get(2) {
+            Some(v) => v.parse()?,
+            None => DEFAULT_CAPACITY,
+        };
+        if capacity == 0 {
+            return Err(PoolErr::InvalidConfig(s.to_string()).into());
+        }
+        Ok(DaemonConfig {
+            addr: parts[0].parse()?,
+            events_addr: parts[1].parse()?,
+            capacity,
+        })
+    }
+}
+
+struct Daemon {
+    backend: Backend,
+    capacity: usize,
+    healthy: AtomicBool,
+    failures: AtomicUsize,
+}
+
+impl Daemon {
+    fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+}
+
+struct PoolInner {
+    daemons: Vec<Daemon>,
+    /// Daemon each instance is running on
+    placement: RwLock<HashMap<ID, DaemonID>>,
+}
+
+#[derive(Clone)]
+pub struct DaemonPool {
+    inner: Arc<PoolInner>,
+}
+
+impl DaemonPool {
+    /// Create new pool, daemons are assumed to be healthy
+    pub fn new(configs: &[DaemonConfig], api_secret: &str) -> Fallible<DaemonPool> {
+        if configs.is_empty() {
+            return Err(PoolErr::NoDaemons.into());
+        }
+        let mut daemons = Vec::with_capacity(configs.len());
+        for (id, config) in configs.iter().enumerate() {
+            daemons.push(Daemon {
+                backend: Backend::new(id, config.addr, api_secret, config.events_addr)?,
+                capacity: config.capacity,
+                healthy: AtomicBool::new(true),
+                failures: AtomicUsize::new(0),
+            });
+        }
+        Ok(DaemonPool {
+            inner: Arc::new(PoolInner {
+                daemons,
+                placement: RwLock::new(HashMap::new()),
+            }),
+        })
+    }
+
+    /// Start event streams & health checks of all daemons
+    pub fn start(&self, instances: Instances) {
+        for daemon in self.inner.daemons.iter() {
+            events::start_event_stream(daemon.backend.clone(), instances.clone());
+        }
+        let pool = self.clone();
+        spawn(
+            Interval::new(Instant::now() + HEALTH_INTERVAL, HEALTH_INTERVAL)
+                .map_err(|e| error!("Health check timer failed: {}", e))
+                .for_each(move |_| {
+                    let instances = instances.clone();
+                    pool.check_health().map(move |report| {
+                        for id in report.orphaned {
+                            // gets placed on another daemon
+                            reconcile::handle_stop(&instances, id, Some(StopReason::Crashed));
+                        }
+                        if !report.recovered.is_empty() {
+                            // stale copies of re-placed instances are stopped as duplicates
+                            reconcile::reconcile_now(instances);
+                        }
+                    })
+                }),
+        );
+    }
+
+    /// Returns all backends
+    pub fn backends(&self) -> Vec<Backend> {
+        self.inner
+            .daemons
+            .iter()
+            .map(|d| d.backend.clone())
+            .collect()
+    }
+
+    /// Returns backend of daemon
+    pub fn backend(&self, daemon: DaemonID) -> Backend {
+        self.inner.daemons[daemon].backend.clone()
+    }
+
+    /// Returns daemon the instance is placed on
+    pub fn placement_of(&self, id: &ID) -> Option<DaemonID> {
+        let placement_r = self.inner.placement.read().expect("Can't lock placement!");
+        placement_r.get(id).cloned()
+    }
+
+    /// Returns backend of instance, places it on the least loaded daemon if required
+    pub fn backend_for(&self, id: &ID) -> Fallible<Backend> {
+        let mut placement_w = self.inner.placement.write().expect("Can't lock placement!");
+        if let Some(daemon) = placement_w.get(id) {
+            return Ok(self.inner.daemons[*daemon].backend.clone());
+        }
+        let daemon = self.pick(&placement_w)?;
+        debug!("Placing instance {} on daemon {}", id, daemon);
+        placement_w.insert(*id, daemon);
+        Ok(self.inner.daemons[daemon].backend.clone())
+    }
+
+    /// Pick healthy daemon with free capacity & lowest load
+    fn pick(&self, placement: &HashMap<ID, DaemonID>) -> Fallible<DaemonID> {
+        let mut load = vec![0; self.inner.daemons.len()];
+        for daemon in placement.values() {
+            load[*daemon] += 1;
+        }
+        self.inner
+            .daemons
+            .iter()
+            .enumerate()
+            .filter(|(id, d)| d.is_healthy() && load[*id] < d.capacity)
+            // compare load[a]/cap[a] with load[b]/cap[b]
+            .min_by(|(a, da), (b, db)| (load[*a] * db.capacity).cmp(&(load[*b] * da.capacity)))
+            .map(|(id, _)| id)
+            .ok_or_else(|| PoolErr::NoCapacity.into())
+    }
+
+    /// Register instance already running on daemon
+    pub fn assign(&self, id: ID, daemon: DaemonID) {
+        let mut placement_w = self.inner.placement.write().expect("Can't lock placement!");
+        placement_w.insert(id, daemon);
+    }
+
+    /// Free capacity of stopped instance
+    pub fn release(&self, id: &ID) {
+        let mut placement_w = self.inner.placement.write().expect("Can't lock placement!");
+        if placement_w.remove(id).is_some() {
+            debug!("Released placement of instance {}", id);
+        }
+    }
+
+    /// Returns all instances placed on the daemon
+    pub fn placed_on(&self, daemon: DaemonID) -> Vec<ID> {
+        let placement_r = self.inner.placement.read().expect("Can't lock placement!");
+        placement_r
+            .iter()
+            .filter(|(_, d)| **d == daemon)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// Returns whether the instance is placed on the daemon
+    /// Events of other daemons are stale and have to be ignored
+    pub fn is_placed_on(&self, id: &ID, daemon: DaemonID) -> bool {
+        let placement_r = self.inner.placement.read().expect("Can't lock placement!");
+        placement_r.get(id) == Some(&daemon)
+    }
+
+    /// Returns current state of all daemons
+    pub fn get_daemons(&self) -> Vec<DaemonInfo> {
+        let placement_r = self.inner.placement.read().expect("Can't lock placement!");
+        self.inner
+            .daemons
+            .iter()
+            .enumerate()
+            .map(|(id, d)| DaemonInfo {
+                id,
+                addr: d.backend.get_addr().to_string(),
+                capacity: d.capacity,
+                instances: placement_r.values().filter(|v| **v == id).count(),
+                healthy: d.is_healthy(),
+            })
+            .collect()
+    }
+
+    /// Check all daemons, returns instances of daemons considered dead & recovered daemons  
+    /// The orphaned instances are unplaced and have to be restarted
+    pub fn check_health(&self) -> impl Future<Item = HealthReport, Error = ()> {
+        let pool = self.clone();
+        let checks = self
+            .inner
+            .daemons
+            .iter()
+            .enumerate()
+            .map(|(id, d)| {
+                let fut = match d.backend.get_instances() {
+                    Ok(v) => v,
+                    Err(e) => {
+                        warn!("Can't create health check for daemon {}: {}", id, e);
+                        return Either::A(result(Ok((id, false))));
+                    }
+                };
+                Either::B(
+                    Timeout::new(fut, HEALTH_TIMEOUT).then(move |res| -> Result<_, ()> {
+                        if let Err(e) = res {
+                            debug!("Health check of daemon {} failed: {:?}", id, e);
+                            return Ok((id, false));
+                        }
+                        Ok((id, true))
+                    }),
+                )
+            })
+            .collect::<Vec<_>>();
+        join_all(checks).map(move |results| {
+            let mut report = HealthReport::default();
+            for (id, alive) in results {
+                pool.update_health(id, alive, &mut report);
+            }
+            report
+        })
+    }
+
+    /// Update health of daemon, reports instances to re-place when it died
+    fn update_health(&self, id: DaemonID, alive: bool, report: &mut HealthReport) {
+        let daemon = &self.inner.daemons[id];
+        if alive {
+            daemon.failures.store(0, Ordering::Relaxed);
+            if !daemon.healthy.swap(true, Ordering::Relaxed) {
+                info!("Daemon {} is reachable again", id);
+                report.recovered.push(id);
+            }
+            return;
+        }
+        let failures = daemon.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures < HEALTH_MAX_FAILURES || !daemon.healthy.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        let mut placement_w = self.inner.placement.write().expect("Can't lock placement!");
+        let orphaned: Vec<ID> = placement_w
+            .iter()
+            .filter(|(_, d)| **d == id)
+            .map(|(i, _)| *i)
+            .collect();
+        for i in orphaned.iter() {
+            placement_w.remove(i);
+        }
+        warn!(
+            "Daemon {} is dead after {} failed health checks, re-placing {} instances",
+            id,
+            failures,
+            orphaned.len()
+        );
+        report.orphaned.extend(orphaned);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix::System;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    const INSTANCE_LIST: &'static str = "{\"instances\":[]}";
+
+    /// Mock daemon answering every request with an empty instance list
+    /// Drops all connections while not alive
+    fn mock_daemon() -> (SocketAddr, Arc<AtomicBool>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let alive = Arc::new(AtomicBool::new(true));
+        let alive_c = alive.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(_) if !alive_c.load(Ordering::Relaxed) => continue,
+                    Ok(v) => v,
+                    Err(_) => continue,
+                };
+                let mut buffer = [0; 1024];
+                let _ = stream.read(&mut buffer);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    INSTANCE_LIST.len(),
+                    INSTANCE_LIST
+                );
+            }
+        });
+        (addr, alive)
+    }
+
+    fn config(addr: SocketAddr, capacity: usize) -> DaemonConfig {
+        DaemonConfig {
+            addr,
+            events_addr: addr,
+            capacity,
+        }
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let config: DaemonConfig = "127.0.0.1:1338,127.0.0.1:1336,4".parse().unwrap();
+        assert_eq!(
+            DaemonConfig {
+                addr: "127.0.0.1:1338".parse().unwrap(),
+                events_addr: "127.0.0.1:1336".parse().unwrap(),
+                capacity: 4,
+            },
+            config
+        );
+        let config: DaemonConfig = "127.0.0.1:1338,127.0.0.1:1336".parse().unwrap();
+        assert_eq!(DEFAULT_CAPACITY, config.capacity);
+        assert!("127.0.0.1:1338".parse::<DaemonConfig>().is_err());
+        assert!("127.0.0.1:1338,127.0.0.1:1336,0"
+            .parse::<DaemonConfig>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_placement() {
+        let a = "127.0.0.1:1338".parse().unwrap();
+        let b = "127.0.0.1:1339".parse().unwrap();
+        let pool = DaemonPool::new(&[config(a, 1), config(b, 2)], "secret").unwrap();
+        for id in 0..3 {
+            pool.backend_for(&id).unwrap();
+        }
+        // placement is sticky
+        assert_eq!(pool.backend_for(&0).unwrap().get_id(), 0);
+        assert!(pool.is_placed_on(&0, 0));
+        assert!(pool.is_placed_on(&1, 1));
+        assert!(pool.is_placed_on(&2, 1));
+        assert!(pool.backend_for(&3).is_err());
+
+        pool.release(&1);
+        assert_eq!(pool.backend_for(&3).unwrap().get_id(), 1);
+    }
+
+    #[test]
+    fn test_failover() {
+        let (a, _) = mock_daemon();
+        let (b, b_alive) = mock_daemon();
+        let pool = DaemonPool::new(&[config(a, 2), config(b, 2)], "secret").unwrap();
+        pool.assign(1, 1);
+        pool.assign(2, 1);
+        pool.backend_for(&3).unwrap();
+
+        let mut sys = System::new("test");
+        assert!(sys
+            .block_on(pool.check_health())
+            .unwrap()
+            .orphaned
+            .is_empty());
+        b_alive.store(false, Ordering::Relaxed);
+        for _ in 1..HEALTH_MAX_FAILURES {
+            assert!(sys
+                .block_on(pool.check_health())
+                .unwrap()
+                .orphaned
+                .is_empty());
+        }
+        let mut orphaned = sys.block_on(pool.check_health()).unwrap().orphaned;
+        orphaned.sort();
+        assert_eq!(vec![1, 2], orphaned);
+        // no more capacity on the dead daemon
+        assert!(pool.get_daemons()[0].healthy);
+        assert!(!pool.get_daemons()[1].healthy);
+        assert_eq!(pool.backend_for(&1).unwrap().get_id(), 0);
+        assert!(pool.backend_for(&2).is_err());
+        // dead daemon isn't reported twice
+        assert!(sys
+            .block_on(pool.check_health())
+            .unwrap()
+            .orphaned
+            .is_empty());
+
+        b_alive.store(true, Ordering::Relaxed);
+        let report = sys.block_on(pool.check_health()).unwrap();
+        assert!(report.orphaned.is_empty());
+        assert_eq!(vec![1], report.recovered);
+        assert!(sys
+            .block_on(pool.check_health())
+            .unwrap()
+            .recovered
+            .is_empty());
+        assert!(pool.get_daemons()[1].healthy);
+        assert_eq!(pool.backend_for(&2).unwrap().get_id(), 1);
+    }
+}
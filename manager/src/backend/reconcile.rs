@@ -0,0 +1,590 @@
+/*
+ *  YAMBA manager
+ *  Copyright (C) 2019 Aron Heinecke
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *      http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ */
+
+use super::*;
+
+use futures::future::{join_all, result, Either};
+use futures::Stream;
+use hashbrown::{HashMap, HashSet};
+use tokio_timer::{Interval, Timeout};
+use yamba_types::models::{
+    callback::{InstanceState, Playstate},
+    InstanceListEntry, InstanceStopReq, StateGetReq, StopReason, TimeStarted, ID,
+};
+
+use std::cmp::min;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Reconciliation of the desired instance state with the instances running on the daemons
+
+/// Interval of reconciliation runs
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(30);
+/// Max time for listing the instances of a daemon
+const LIST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Delay after the first failed restart, doubles per attempt
+const RESTART_BACKOFF_BASE: Duration = Duration::from_secs(5);
+/// Max delay between restarts
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(300);
+/// Restarts without becoming stable, after which an instance is considered crash looping
+const RESTART_MAX_ATTEMPTS: u32 = 5;
+/// Time an instance has to run after a restart for its backoff to reset
+const RESTART_STABLE: Duration = Duration::from_secs(300);
+
+/// Local state of an instance
+#[derive(Debug)]
+struct Desired {
+    /// Instance should be running
+    wanted: bool,
+    /// Daemon the instance is placed on
+    placed: Option<DaemonID>,
+}
+
+#[derive(Debug, PartialEq)]
+enum StopCause {
+    /// Instance isn't known or shouldn't be running
+    Unwanted,
+    /// Instance is running on multiple daemons
+    Duplicate,
+}
+
+/// Corrective action
+#[derive(Debug, PartialEq)]
+enum Action {
+    /// Track instance of this manager running on daemon
+    Adopt {
+        id: ID,
+        daemon: DaemonID,
+        started: TimeStarted,
+    },
+    /// Stop instance of this manager on daemon
+    Stop {
+        id: ID,
+        daemon: DaemonID,
+        reason: StopCause,
+    },
+    /// Start instance which should be running
+    Start { id: ID },
+}
+
+/// Result of diffing desired against actual state
+#[derive(Debug, Default)]
+struct Plan {
+    actions: Vec<Action>,
+    /// Instances running as expected
+    running: Vec<ID>,
+}
+
+/// Diff desired state against the instance lists of all reachable daemons
+/// Instances with a foreign ownership token are never touched
+fn plan(
+    desired: &HashMap<ID, Desired>,
+    listed: &HashMap<DaemonID, Vec<InstanceListEntry>>,
+    owner: &str,
+) -> Plan {
+    let mut plan = Plan::default();
+    let mut found: HashMap<ID, Vec<(DaemonID, TimeStarted)>> = HashMap::new();
+    for (daemon, entries) in listed.iter() {
+        for entry in entries.iter() {
+            if entry.owner.as_ref().map(|v| v.as_str()) != Some(owner) {
+                trace!(
+                    "Ignoring instance {} on daemon {} of owner {:?}",
+                    entry.id,
+                    daemon,
+                    entry.owner
+                );
+                continue;
+            }
+            found
+                .entry(entry.id)
+                .or_insert_with(Vec::new)
+                .push((*daemon, entry.started));
+        }
+    }
+
+    for (id, mut running) in found.into_iter() {
+        let state = match desired.get(&id) {
+            Some(v) if v.wanted => v,
+            _ => {
+                for (daemon, _) in running {
+                    plan.actions.push(Action::Stop {
+                        id,
+                        daemon,
+                        reason: StopCause::Unwanted,
+                    });
+                }
+                continue;
+            }
+        };
+        // keep the placed one, otherwise the oldest
+        running.sort_by_key(|(daemon, started)| (Some(*daemon) != state.placed, *started));
+        let (daemon, started) = running.remove(0);
+        if state.placed == Some(daemon) {
+            plan.running.push(id);
+        } else {
+            plan.actions.push(Action::Adopt {
+                id,
+                daemon,
+                started,
+            });
+        }
+        for (daemon, _) in running {
+            plan.actions.push(Action::Stop {
+                id,
+                daemon,
+                reason: StopCause::Duplicate,
+            });
+        }
+    }
+
+    let seen: HashSet<ID> = plan
+        .running
+        .iter()
+        .cloned()
+        .chain(plan.actions.iter().filter_map(|a| match a {
+            Action::Adopt { id, .. } => Some(*id),
+            _ => None,
+        }))
+        .collect();
+    for (id, state) in desired.iter() {
+        if !state.wanted || seen.contains(id) {
+            continue;
+        }
+        // daemon not reachable, handled by the pool health checks
+        if let Some(daemon) = state.placed {
+            if !listed.contains_key(&daemon) {
+                continue;
+            }
+        }
+        plan.actions.push(Action::Start { id: *id });
+    }
+    plan
+}
+
+/// Restart backoff of an instance
+#[derive(Debug)]
+struct Backoff {
+    attempts: u32,
+    next: Instant,
+    last_start: Instant,
+}
+
+/// Outcome of a restart request
+#[derive(Debug, PartialEq)]
+enum Attempt {
+    /// Restart with attempt number
+    Allowed(u32),
+    /// Backoff not yet elapsed
+    Delayed,
+    /// Max attempts reached without becoming stable
+    CrashLoop,
+}
+
+/// Delay after restart attempt
+fn backoff_delay(attempt: u32) -> Duration {
+    let factor = 1u32
+        .checked_shl(attempt.saturating_sub(1))
+        .unwrap_or(u32::max_value());
+    RESTART_BACKOFF_BASE
+        .checked_mul(factor)
+        .map_or(RESTART_BACKOFF_MAX, |v| min(v, RESTART_BACKOFF_MAX))
+}
+
+/// Restart backoff & crash loop detection of all instances
+#[derive(Clone, Default)]
+pub struct Restarts {
+    data: Arc<Mutex<HashMap<ID, Backoff>>>,
+}
+
+impl Restarts {
+    /// Register restart attempt
+    fn try_attempt(&self, id: ID, now: Instant) -> Attempt {
+        let mut data = self.data.lock().expect("Can't lock restarts!");
+        let entry = data.entry(id).or_insert(Backoff {
+            attempts: 0,
+            next: now,
+            last_start: now,
+        });
+        if entry.attempts >= RESTART_MAX_ATTEMPTS {
+            return Attempt::CrashLoop;
+        }
+        if entry.next > now {
+            return Attempt::Delayed;
+        }
+        entry.attempts += 1;
+        entry.next = now + backoff_delay(entry.attempts);
+        entry.last_start = now;
+        Attempt::Allowed(entry.attempts)
+    }
+
+    /// Instance is running, resets its backoff once it's stable
+    fn confirm_running(&self, id: &ID, now: Instant) {
+        let mut data = self.data.lock().expect("Can't lock restarts!");
+        let stable = match data.get(id) {
+            Some(v) => now >= v.last_start + RESTART_STABLE,
+            None => false,
+        };
+        if stable {
+            debug!("Instance {} is stable, resetting restart backoff", id);
+            data.remove(id);
+        }
+    }
+
+    /// Reset backoff
+    pub fn reset(&self, id: &ID) {
+        self.data.lock().expect("Can't lock restarts!").remove(id);
+    }
+}
+
+/// Restart instance which should be running  
+/// Respects backoff, restarts are disabled for crash looping instances
+pub fn restart(instances: &Instances, id: ID, cause: &str) {
+    let restarts = instances.get_restarts();
+    match restarts.try_attempt(id, Instant::now()) {
+        Attempt::Delayed => {
+            trace!("Restart of instance {} delayed by backoff", id);
+            return;
+        }
+        Attempt::CrashLoop => {
+            error!(
+                "Instance {} is crash looping, giving up after {} restarts",
+                id, RESTART_MAX_ATTEMPTS
+            );
+            restarts.reset(&id);
+            if let Some(i) = instances.read(&id) {
+                i.disable_restart();
+            }
+            return;
+        }
+        Attempt::Allowed(attempt) => {
+            info!(
+                "Restarting instance {} ({}), attempt {}",
+                id, cause, attempt
+            );
+        }
+    }
+    instances.get_pool().release(&id);
+    if let Some(i) = instances.read(&id) {
+        i.cb_set_instance_state(InstanceState::Stopped);
+        i.cb_set_playback_state(Playstate::Stopped);
+    }
+    match instances.start_instance(id) {
+        Ok(fut) => Backend::spawn_ignore(fut),
+        Err(e) => warn!("Can't start instance {}: {}", id, e),
+    }
+}
+
+/// Apply restart policy of instance stopped by the daemon
+pub fn handle_stop(instances: &Instances, id: ID, reason: Option<StopReason>) {
+    let policy = match instances.read(&id) {
+        Some(ref i) if i.should_run() => i.get_restart_policy(),
+        // stopped by the manager
+        _ => return,
+    };
+    let reason = reason.unwrap_or_else(|| {
+        debug!("No stop reason for instance {}, assuming crash", id);
+        StopReason::Crashed
+    });
+    if policy.should_restart(reason) {
+        restart(instances, id, &format!("{:?}", reason));
+    } else {
+        info!(
+            "Instance {} stopped ({:?}), not restarting with policy {:?}",
+            id, reason, policy
+        );
+        if let Some(i) = instances.read(&id) {
+            i.disable_restart();
+        }
+    }
+}
+
+struct Reconciler {
+    instances: Instances,
+}
+
+/// Start periodic reconciliation, the first run is immediate
+pub fn start_reconciliation(instances: Instances) {
+    let reconciler = Arc::new(Reconciler { instances });
+    spawn(
+        Interval::new(Instant::now(), RECONCILE_INTERVAL)
+            .map_err(|e| error!("Reconciliation timer failed: {}", e))
+            .for_each(move |_| reconciler.clone().run()),
+    );
+}
+
+/// Run reconciliation now, for example after a daemon recovered
+pub fn reconcile_now(instances: Instances) {
+    spawn(Arc::new(Reconciler { instances }).run());
+}
+
+impl Reconciler {
+    /// Run single reconciliation
+    fn run(self: Arc<Self>) -> impl Future<Item = (), Error = ()> {
+        let lists = self
+            .instances
+            .get_pool()
+            .backends()
+            .into_iter()
+            .map(|backend| {
+                let daemon = backend.get_id();
+                let fut = match backend.get_instances() {
+                    Ok(v) => v,
+                    Err(e) => {
+                        warn!("Can't list instances of daemon {}: {}", daemon, e);
+                        return Either::A(result(Ok((daemon, None))));
+                    }
+                };
+                Either::B(
+                    Timeout::new(fut, LIST_TIMEOUT).then(move |res| -> Result<_, ()> {
+                        match res {
+                            Ok(v) => Ok((daemon, Some(v.instances))),
+                            Err(e) => {
+                                debug!("Can't list instances of daemon {}: {:?}", daemon, e);
+                                Ok((daemon, None))
+                            }
+                        }
+                    }),
+                )
+            })
+            .collect::<Vec<_>>();
+        join_all(lists).map(move |results| {
+            let listed: HashMap<DaemonID, Vec<InstanceListEntry>> = results
+                .into_iter()
+                .filter_map(|(daemon, v)| v.map(|v| (daemon, v)))
+                .collect();
+            let desired = self.desired();
+            let plan = plan(&desired, &listed, self.instances.get_owner());
+            let now = Instant::now();
+            for id in plan.running.iter() {
+                self.instances.get_restarts().confirm_running(id, now);
+            }
+            for action in plan.actions {
+                self.apply(action);
+            }
+        })
+    }
+
+    /// Snapshot of local instance state
+    fn desired(&self) -> HashMap<ID, Desired> {
+        let pool = self.instances.get_pool();
+        let instances_r = (*self.instances).read().expect("Can't read instances!");
+        instances_r
+            .iter()
+            .map(|(id, inst)| {
+                (
+                    *id,
+                    Desired {
+                        wanted: inst.should_run(),
+                        placed: pool.placement_of(id),
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// Apply corrective action
+    fn apply(&self, action: Action) {
+        let pool = self.instances.get_pool();
+        match action {
+            Action::Adopt {
+                id,
+                daemon,
+                started,
+            } => {
+                info!("Adopting instance {} running on daemon {}", id, daemon);
+                self.instances.get_restarts().reset(&id);
+                pool.assign(id, daemon);
+                if let Some(i) = self.instances.read(&id) {
+                    i.update_startup_time(Some(started));
+                }
+                let instances = self.instances.clone();
+                match pool.backend(daemon).get_instance_state(&StateGetReq { id }) {
+                    Ok(fut) => Backend::spawn_ignore(fut.map(move |v| {
+                        if let Some(i) = instances.read_placed(&v.id, daemon) {
+                            i.cb_set_instance_state(v.state);
+                        }
+                    })),
+                    Err(e) => warn!("Can't request state of instance {}: {}", id, e),
+                }
+            }
+            Action::Stop { id, daemon, reason } => {
+                warn!(
+                    "Stopping instance {} on daemon {}, reason: {:?}",
+                    id, daemon, reason
+                );
+                match pool.backend(daemon).stop_instance(&InstanceStopReq { id }) {
+                    Ok(fut) => Backend::spawn_ignore(fut),
+                    Err(e) => warn!("Can't stop instance {}: {}", id, e),
+                }
+            }
+            Action::Start { id } => restart(&self.instances, id, "missing"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const OWNER: &'static str = "owner";
+
+    fn entry(id: ID, started: TimeStarted, owner: Option<&str>) -> InstanceListEntry {
+        InstanceListEntry {
+            id,
+            started,
+            owner: owner.map(|v| v.to_string()),
+        }
+    }
+
+    fn desired(wanted: bool, placed: Option<DaemonID>) -> Desired {
+        Desired { wanted, placed }
+    }
+
+    #[test]
+    fn test_plan() {
+        let mut local = HashMap::new();
+        local.insert(1, desired(true, Some(0)));
+        local.insert(2, desired(true, None));
+        local.insert(3, desired(false, None));
+        local.insert(4, desired(true, Some(0)));
+        local.insert(5, desired(true, Some(1)));
+        let mut listed = HashMap::new();
+        listed.insert(
+            0,
+            vec![
+                entry(1, 10, Some(OWNER)),
+                entry(2, 10, Some(OWNER)),
+                entry(3, 10, Some(OWNER)),
+                entry(6, 10, Some(OWNER)),
+                entry(7, 10, Some("other")),
+                entry(8, 10, None),
+            ],
+        );
+        let mut plan = plan(&local, &listed, OWNER);
+        assert_eq!(vec![1], plan.running);
+        plan.actions.sort_by_key(|a| match a {
+            Action::Adopt { id, .. } | Action::Stop { id, .. } | Action::Start { id } => *id,
+        });
+        assert_eq!(
+            vec![
+                Action::Adopt {
+                    id: 2,
+                    daemon: 0,
+                    started: 10
+                },
+                Action::Stop {
+                    id: 3,
+                    daemon: 0,
+                    reason: StopCause::Unwanted
+                },
+                // crashed
+                Action::Start { id: 4 },
+                // 5 is on an unreachable daemon
+                Action::Stop {
+                    id: 6,
+                    daemon: 0,
+                    reason: StopCause::Unwanted
+                },
+            ],
+            plan.actions
+        );
+    }
+
+    #[test]
+    fn test_plan_duplicate() {
+        let mut local = HashMap::new();
+        local.insert(1, desired(true, Some(1)));
+        local.insert(2, desired(true, None));
+        let mut listed = HashMap::new();
+        listed.insert(0, vec![entry(1, 5, Some(OWNER)), entry(2, 20, Some(OWNER))]);
+        listed.insert(
+            1,
+            vec![entry(1, 10, Some(OWNER)), entry(2, 10, Some(OWNER))],
+        );
+        let plan = plan(&local, &listed, OWNER);
+        assert_eq!(vec![1], plan.running);
+        assert_eq!(3, plan.actions.len());
+        assert!(plan.actions.contains(&Action::Stop {
+            id: 1,
+            daemon: 0,
+            reason: StopCause::Duplicate
+        }));
+        // oldest one is adopted
+        assert!(plan.actions.contains(&Action::Adopt {
+            id: 2,
+            daemon: 1,
+            started: 10
+        }));
+        assert!(plan.actions.contains(&Action::Stop {
+            id: 2,
+            daemon: 0,
+            reason: StopCause::Duplicate
+        }));
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(RESTART_BACKOFF_BASE, backoff_delay(1));
+        assert_eq!(RESTART_BACKOFF_BASE * 4, backoff_delay(3));
+        assert_eq!(RESTART_BACKOFF_MAX, backoff_delay(10));
+        assert_eq!(RESTART_BACKOFF_MAX, backoff_delay(100));
+
+        let restarts = Restarts::default();
+        let now = Instant::now();
+        assert_eq!(Attempt::Allowed(1), restarts.try_attempt(1, now));
+        assert_eq!(Attempt::Delayed, restarts.try_attempt(1, now));
+        assert_eq!(
+            Attempt::Allowed(2),
+            restarts.try_attempt(1, now + RESTART_BACKOFF_BASE)
+        );
+        assert_eq!(
+            Attempt::Delayed,
+            restarts.try_attempt(1, now + RESTART_BACKOFF_BASE * 2)
+        );
+        assert_eq!(Attempt::Allowed(1), restarts.try_attempt(2, now));
+    }
+
+    #[test]
+    fn test_restart_policy() {
+        use crate::models::RestartPolicy;
+        assert!(RestartPolicy::OnFailure.should_restart(StopReason::Crashed));
+        assert!(RestartPolicy::OnFailure.should_restart(StopReason::HeartbeatTimeout));
+        assert!(!RestartPolicy::OnFailure.should_restart(StopReason::Kicked));
+        assert!(RestartPolicy::Always.should_restart(StopReason::Kicked));
+        assert!(!RestartPolicy::Always.should_restart(StopReason::Banned));
+        assert!(!RestartPolicy::Always.should_restart(StopReason::Requested));
+        assert!(!RestartPolicy::Never.should_restart(StopReason::ServerDown));
+    }
+
+    #[test]
+    fn test_crash_loop() {
+        let restarts = Restarts::default();
+        let mut now = Instant::now();
+        for i in 1..=RESTART_MAX_ATTEMPTS {
+            assert_eq!(Attempt::Allowed(i), restarts.try_attempt(1, now));
+            // not yet stable
+            restarts.confirm_running(&1, now + RESTART_STABLE / 2);
+            now += RESTART_BACKOFF_MAX;
+        }
+        assert_eq!(Attempt::CrashLoop, restarts.try_attempt(1, now));
+
+        restarts.reset(&1);
+        assert_eq!(Attempt::Allowed(1), restarts.try_attempt(1, now));
+        restarts.confirm_running(&1, now + RESTART_STABLE);
+        assert_eq!(Attempt::Allowed(1), restarts.try_attempt(1, now));
+    }
+}
@@ -16,13 +16,14 @@
  */
 
 use failure::Fallible;
+use futures::sync::oneshot;
 use hashbrown::HashMap;
-use yamba_types::models::{Song, Ticket as TicketID, ID};
+use yamba_types::models::{callback::ResolveResponse, Song, Ticket as TicketID, ID};
 
 use std::sync::{Arc, RwLock};
 
 use crate::db::Database;
-use crate::instance::Instances;
+use crate::instance::{resolve_error_message, InstanceErr, Instances};
 use crate::models::NewPlaylistData;
 
 /// Ticket Handler that stores callback tickets.  
@@ -41,52 +42,92 @@ impl TicketHandler {
         }
     }
 
-    /// Add queue ticket
-    pub fn add_queue(&self, instance: ID, ticket: TicketID) {
+    /// Add queue ticket, retry is the amount of previous resolve attempts  
+    /// The sender receives the chat reply once resolved, empty on success
+    pub fn add_queue(
+        &self,
+        instance: ID,
+        ticket: TicketID,
+        retry: u8,
+        sender: Option<oneshot::Sender<String>>,
+    ) {
         let mut data_w = self.data.write().expect("Can't lock tickets!");
-        let handler = QueueTicket::new(instance);
+        let handler = QueueTicket::new(instance, retry, sender);
         data_w.insert(ticket, Box::new(handler));
     }
 
-    /// Handle ticket
-    pub fn handle(
+    /// Add search ticket, results are stored as choices for the invoker
+    pub fn add_search(
         &self,
-        ticket: &TicketID,
-        instances: &Instances,
-        songs: Vec<Song>,
-        source: String,
+        instance: ID,
+        invoker: String,
+        ticket: TicketID,
+        sender: oneshot::Sender<Fallible<Vec<Song>>>,
     ) {
+        let mut data_w = self.data.write().expect("Can't lock tickets!");
+        let handler = SearchTicket::new(instance, invoker, sender);
+        data_w.insert(ticket, Box::new(handler));
+    }
+
+    /// Handle ticket
+    pub fn handle(&self, ticket: &TicketID, instances: &Instances, response: ResolveResponse) {
         let mut data_w = self.data.write().expect("Can't lock tickets!");
         debug!("Handling {}", ticket);
         match data_w.remove(ticket) {
             Some(v) => {
-                if let Err(e) = v.handle(instances, songs, source) {
+                if let Err(e) = v.handle(instances, response) {
                     warn!("Error on handling ticket: {}", e);
                 }
             }
-            None => warn!("Ticket unknown: {} {:?}!", ticket, songs),
+            None => warn!("Ticket unknown: {} {:?}!", ticket, response),
         }
     }
 }
 
 /// Ticket with action desciption
 pub trait Ticket {
-    fn handle(&self, instances: &Instances, songs: Vec<Song>, souce: String) -> Fallible<()>;
+    fn handle(self: Box<Self>, instances: &Instances, response: ResolveResponse) -> Fallible<()>;
+}
+
+/// Send chat reply of a queue request, if anyone is waiting for it
+pub fn reply_queue(sender: Option<oneshot::Sender<String>>, reply: String) {
+    if let Some(sender) = sender {
+        if sender.send(reply).is_err() {
+            debug!("Queue reply receiver gone, ignoring.");
+        }
+    }
 }
 
 /// Queue ticket type, inserts into queue
 pub struct QueueTicket {
     instance: ID,
+    retry: u8,
+    sender: Option<oneshot::Sender<String>>,
 }
 
 impl QueueTicket {
-    pub fn new(instance: ID) -> QueueTicket {
-        QueueTicket { instance }
+    pub fn new(instance: ID, retry: u8, sender: Option<oneshot::Sender<String>>) -> QueueTicket {
+        QueueTicket {
+            instance,
+            retry,
+            sender,
+        }
     }
 }
 
 impl Ticket for QueueTicket {
-    fn handle(&self, instances: &Instances, songs: Vec<Song>, source: String) -> Fallible<()> {
+    fn handle(self: Box<Self>, instances: &Instances, response: ResolveResponse) -> Fallible<()> {
+        let ticket = *self;
+        if !response.success {
+            let reply = match instances.read(&ticket.instance) {
+                Some(inst) => inst.resolve_failed(instances, response, ticket.retry),
+                None => resolve_error_message(response.error).to_string(),
+            };
+            reply_queue(ticket.sender, reply);
+            return Ok(());
+        }
+        let songs = response.songs;
+        let source = response.source;
         let song_url = match songs.len() == 1 {
             true => Some(source.as_str()),
             false => None,
@@ -100,11 +141,52 @@ impl Ticket for QueueTicket {
                 .get_db()
                 .upsert_playlist(&pl_data, Some(source.as_str()))?;
         }
-        instances.read(&self.instance).map(|inst| {
+        instances.read(&ticket.instance).map(|inst| {
             inst.add_to_queue(songs);
             inst.check_playback();
         });
+        reply_queue(ticket.sender, String::new());
 
         Ok(())
     }
 }
+
+/// Search ticket type, stores results as choices & returns them
+pub struct SearchTicket {
+    instance: ID,
+    invoker: String,
+    sender: oneshot::Sender<Fallible<Vec<Song>>>,
+}
+
+impl SearchTicket {
+    pub fn new(
+        instance: ID,
+        invoker: String,
+        sender: oneshot::Sender<Fallible<Vec<Song>>>,
+    ) -> SearchTicket {
+        SearchTicket {
+            instance,
+            invoker,
+            sender,
+        }
+    }
+}
+
+impl Ticket for SearchTicket {
+    fn handle(self: Box<Self>, instances: &Instances, response: ResolveResponse) -> Fallible<()> {
+        let ticket = *self;
+        let result = match response.success {
+            true => {
+                if let Some(inst) = instances.read(&ticket.instance) {
+                    inst.set_search_choices(ticket.invoker, response.songs.clone());
+                }
+                Ok(response.songs)
+            }
+            false => Err(InstanceErr::ResolveFailed(resolve_error_message(response.error)).into()),
+        };
+        if ticket.sender.send(result).is_err() {
+            debug!("Search result receiver gone, ignoring.");
+        }
+        Ok(())
+    }
+}
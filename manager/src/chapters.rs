@@ -0,0 +1,137 @@
+/*
+ *  YAMBA manager
+ *  Copyright (C) 2019 Aron Heinecke
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *      http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ */
+
+use failure::Fallible;
+use yamba_types::models::{SongChapter, TimeMS};
+
+use crate::trim::format_time;
+
+/// Chapters of long mixes, as reported by youtube-dl
+
+#[derive(Fail, Debug)]
+pub enum ChapterErr {
+    #[fail(display = "Usage: !chapters, !chapter next, !chapter <n>")]
+    InvalidCommand,
+    #[fail(display = "No chapter {}, see !chapters", _0)]
+    InvalidChapter(usize),
+    #[fail(display = "Already at the last chapter!")]
+    LastChapter,
+    #[fail(display = "Current song has no chapters!")]
+    NoChapters,
+}
+
+/// Chat chapter command
+#[derive(Debug, PartialEq)]
+pub enum ChapterCommand {
+    Next,
+    /// Chapter number, starting at 1
+    Goto(usize),
+}
+
+/// Parse command after `!chapter`
+pub fn parse_command(input: &str) -> Fallible<ChapterCommand> {
+    let input = input.trim();
+    if input.eq_ignore_ascii_case("next") {
+        return Ok(ChapterCommand::Next);
+    }
+    match input.parse::<usize>() {
+        Ok(v) if v > 0 => Ok(ChapterCommand::Goto(v)),
+        _ => Err(ChapterErr::InvalidCommand.into()),
+    }
+}
+
+/// Returns index of the chapter at position
+pub fn current(chapters: &[SongChapter], position: TimeMS) -> Option<usize> {
+    chapters.iter().rposition(|v| v.start_ms <= position)
+}
+
+/// Returns index of the chapter to jump to
+pub fn target(
+    chapters: &[SongChapter],
+    position: Option<TimeMS>,
+    cmd: ChapterCommand,
+) -> Fallible<usize> {
+    if chapters.is_empty() {
+        return Err(ChapterErr::NoChapters.into());
+    }
+    match cmd {
+        ChapterCommand::Next => {
+            let next = position
+                .and_then(|v| current(chapters, v))
+                .map_or(0, |v| v + 1);
+            match next < chapters.len() {
+                true => Ok(next),
+                false => Err(ChapterErr::LastChapter.into()),
+            }
+        }
+        ChapterCommand::Goto(n) if n <= chapters.len() => Ok(n - 1),
+        ChapterCommand::Goto(n) => Err(ChapterErr::InvalidChapter(n).into()),
+    }
+}
+
+/// Format chapter by index for chat
+pub fn format_chapter(chapters: &[SongChapter], index: usize) -> String {
+    let chapter = &chapters[index];
+    format!(
+        "{}. {} [{}]",
+        index + 1,
+        chapter.title,
+        format_time(chapter.start_ms)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapters() -> Vec<SongChapter> {
+        vec![
+            SongChapter {
+                title: String::from("Intro"),
+                start_ms: 0,
+                end_ms: 60_000,
+            },
+            SongChapter {
+                title: String::from("Main"),
+                start_ms: 60_000,
+                end_ms: 120_000,
+            },
+        ]
+    }
+
+    #[test]
+    fn command() {
+        assert_eq!(ChapterCommand::Next, parse_command(" Next").unwrap());
+        assert_eq!(ChapterCommand::Goto(5), parse_command("5").unwrap());
+        assert!(parse_command("0").is_err());
+        assert!(parse_command("").is_err());
+    }
+
+    #[test]
+    fn jump() {
+        let chapters = chapters();
+        assert_eq!(Some(0), current(&chapters, 59_999));
+        assert_eq!(Some(1), current(&chapters, 60_000));
+        assert_eq!(1, target(&chapters, Some(0), ChapterCommand::Next).unwrap());
+        assert_eq!(0, target(&chapters, None, ChapterCommand::Next).unwrap());
+        assert!(target(&chapters, Some(60_000), ChapterCommand::Next).is_err());
+        assert_eq!(1, target(&chapters, None, ChapterCommand::Goto(2)).unwrap());
+        assert!(target(&chapters, None, ChapterCommand::Goto(3)).is_err());
+        assert!(target(&[], None, ChapterCommand::Goto(1)).is_err());
+        assert_eq!("2. Main [1:00]", format_chapter(&chapters, 1));
+    }
+}
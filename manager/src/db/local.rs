@@ -19,10 +19,12 @@ use sled::{self, *};
 
 use std::sync::Arc;
 
+use super::migrate;
 use super::Database;
 use crate::models::*;
 use bincode::{deserialize, serialize};
-use yamba_types::models::{Song, SongID, TimeStarted, ID};
+use rand::{thread_rng, Rng};
+use yamba_types::models::{Song, SongID, SongTrim, TimeStarted, ID};
 
 /// Instance data storage
 const TREE_INSTANCES: &'static str = "instances";
@@ -37,10 +39,23 @@ const TREE_SONG_URL: &'static str = "songs_url";
 const TREE_PLAYLISTS: &'static str = "playlists";
 /// Match URL <-> Playlist
 const TREE_PLAYLIST_URL: &'static str = "playlists_url";
+/// Soundboard clips by name
+const TREE_CLIPS: &'static str = "clips";
+/// Trim points by song
+const TREE_SONG_TRIMS: &'static str = "song_trims";
+/// Jingle rules by instance
+const TREE_JINGLES: &'static str = "jingles";
+/// Volume rules by instance
+const TREE_VOLUME_RULES: &'static str = "volume_rules";
+/// Chat trim rules by instance
+const TREE_TRIM_RULES: &'static str = "trim_rules";
 
 const KEY_VERSION: &'static str = "DB_VERSION";
-const DB_VERSION: &'static str = "0.0.1";
+const DB_VERSION: &'static str = "0.0.8";
 const KEY_INSTANCE_ID: &'static str = "INSTANCE_ID";
+const KEY_OWNER_TOKEN: &'static str = "OWNER_TOKEN";
+/// Random bytes of generated ownership tokens
+const OWNER_TOKEN_BYTES: usize = 16;
 const INSTANCE_ID_ZERO: ID = 0;
 
 #[derive(Fail, Debug)]
@@ -113,6 +128,11 @@ impl Database for DB {
         tree.set(serialize(&id).unwrap(), serialize(&instance).unwrap())?;
         Ok(instance)
     }
+    fn update_instance(&self, instance: &Instance) -> Fallible<()> {
+        let tree = self.open_tree(TREE_INSTANCES)?;
+        tree.set(serialize(&instance.id)?, serialize(instance)?)?;
+        Ok(())
+    }
     fn get_instance_startup(&self, instance: &ID) -> Fallible<Option<TimeStarted>> {
         Ok(self
             .open_tree(TREE_STARTUP_TIMES)?
@@ -144,7 +164,10 @@ impl Database for DB {
     }
     fn get_song(&self, song: SongID) -> Fallible<Option<Song>> {
         let tree = self.open_tree(TREE_SONGS)?;
-        Ok(tree.get(song)?.map(|v| deserialize::<Song>(&v).unwrap()))
+        match tree.get(song)? {
+            Some(v) => Ok(Some(deserialize::<Song>(&v)?)),
+            None => Ok(None),
+        }
     }
     fn get_song_by_url(&self, url: &str) -> Fallible<Option<Song>> {
         let tree_url = self.open_tree(TREE_SONG_URL)?;
@@ -186,6 +209,100 @@ impl Database for DB {
         }
         Ok(None)
     }
+    fn get_clips(&self) -> Fallible<Vec<Clip>> {
+        let tree = self.open_tree(TREE_CLIPS)?;
+        tree.iter()
+            .map(|r| -> Fallible<Clip> {
+                let (_, v) = r?;
+                Ok(deserialize::<Clip>(&v)?)
+            })
+            .collect()
+    }
+    fn get_clip(&self, name: &str) -> Fallible<Option<Clip>> {
+        let tree = self.open_tree(TREE_CLIPS)?;
+        match tree.get(name)? {
+            Some(v) => Ok(Some(deserialize::<Clip>(&v)?)),
+            None => Ok(None),
+        }
+    }
+    fn upsert_clip(&self, clip: &Clip) -> Fallible<()> {
+        let tree = self.open_tree(TREE_CLIPS)?;
+        tree.set(clip.name.as_str(), serialize(clip)?)?;
+        Ok(())
+    }
+    fn delete_clip(&self, name: &str) -> Fallible<bool> {
+        let tree = self.open_tree(TREE_CLIPS)?;
+        Ok(tree.del(name)?.is_some())
+    }
+    fn get_song_trim(&self, song: &SongID) -> Fallible<Option<SongTrim>> {
+        let tree = self.open_tree(TREE_SONG_TRIMS)?;
+        match tree.get(song.as_str())? {
+            Some(v) => Ok(Some(deserialize::<SongTrim>(&v)?)),
+            None => Ok(None),
+        }
+    }
+    fn set_song_trim(&self, song: &SongID, trim: Option<&SongTrim>) -> Fallible<()> {
+        let tree = self.open_tree(TREE_SONG_TRIMS)?;
+        match trim {
+            Some(v) => {
+                tree.set(song.as_str(), serialize(v)?)?;
+            }
+            None => {
+                tree.del(song.as_str())?;
+            }
+        }
+        Ok(())
+    }
+    fn get_jingle_rules(&self, instance: &ID) -> Fallible<Option<JingleRules>> {
+        let tree = self.open_tree(TREE_JINGLES)?;
+        match tree.get(serialize(instance)?)? {
+            Some(v) => Ok(Some(deserialize::<JingleRules>(&v)?)),
+            None => Ok(None),
+        }
+    }
+    fn set_jingle_rules(&self, instance: &ID, rules: &JingleRules) -> Fallible<()> {
+        let tree = self.open_tree(TREE_JINGLES)?;
+        tree.set(serialize(instance)?, serialize(rules)?)?;
+        Ok(())
+    }
+    fn get_trim_rules(&self, instance: &ID) -> Fallible<Option<TrimRules>> {
+        let tree = self.open_tree(TREE_TRIM_RULES)?;
+        match tree.get(serialize(instance)?)? {
+            Some(v) => Ok(Some(deserialize::<TrimRules>(&v)?)),
+            None => Ok(None),
+        }
+    }
+    fn set_trim_rules(&self, instance: &ID, rules: &TrimRules) -> Fallible<()> {
+        let tree = self.open_tree(TREE_TRIM_RULES)?;
+        tree.set(serialize(instance)?, serialize(rules)?)?;
+        Ok(())
+    }
+    fn get_volume_rules(&self, instance: &ID) -> Fallible<Option<VolumeRules>> {
+        let tree = self.open_tree(TREE_VOLUME_RULES)?;
+        match tree.get(serialize(instance)?)? {
+            Some(v) => Ok(Some(deserialize::<VolumeRules>(&v)?)),
+            None => Ok(None),
+        }
+    }
+    fn set_volume_rules(&self, instance: &ID, rules: &VolumeRules) -> Fallible<()> {
+        let tree = self.open_tree(TREE_VOLUME_RULES)?;
+        tree.set(serialize(instance)?, serialize(rules)?)?;
+        Ok(())
+    }
+    fn get_owner_token(&self) -> Fallible<String> {
+        let tree = self.open_tree(TREE_META)?;
+        if let Some(v) = tree.get(KEY_OWNER_TOKEN)? {
+            return Ok(deserialize::<String>(&v)?);
+        }
+        let mut rng = thread_rng();
+        let token: String = (0..OWNER_TOKEN_BYTES)
+            .map(|_| format!("{:02x}", rng.gen::<u8>()))
+            .collect();
+        info!("Generated ownership token {}", token);
+        tree.set(KEY_OWNER_TOKEN, serialize(&token)?)?;
+        self.db.flush()?;
+        Ok(token)
+    }
 }
 
 type WTree = Arc<Tree>;
@@ -205,11 +322,7 @@ impl DB {
             match deserialize::<String>(&v) {
                 Ok(v) => {
                     if v != DB_VERSION {
-                        return Err(LocalDBErr::InvalidVersion(format!(
-                            "Invalid DB version found {} != {}!",
-                            v, DB_VERSION
-                        ))
-                        .into());
+                        self.upgrade(&v)?;
                     }
                 }
                 Err(e) => {
@@ -226,6 +339,48 @@ impl DB {
         Ok(())
     }
 
+    /// Upgrade DB of an older version, re-encodes trees changed since
+    fn upgrade(&self, version: &str) -> Fallible<()> {
+        let current = migrate::parse_version(DB_VERSION).expect("Invalid DB_VERSION!");
+        let from = match migrate::parse_version(version) {
+            Some(v) if v >= migrate::OLDEST_VERSION && v < current => v,
+            _ => {
+                return Err(LocalDBErr::InvalidVersion(format!(
+                    "Can't upgrade DB version {} to {}!",
+                    version, DB_VERSION
+                ))
+                .into());
+            }
+        };
+        info!("Upgrading DB from {} to {}", version, DB_VERSION);
+        // live flag & chapters of songs
+        if from < 6 {
+            self.reencode(TREE_SONGS, |v| migrate::song(from, v))?;
+            self.reencode(TREE_PLAYLISTS, |v| migrate::playlist(from, v))?;
+        }
+        // restart policy, VoIP type, stream format, effects & rate of instances
+        if from < 8 {
+            self.reencode(TREE_INSTANCES, |v| migrate::instance(from, v))?;
+        }
+        self.open_tree(TREE_META)?
+            .set(KEY_VERSION, serialize(DB_VERSION)?)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// Re-encode all values of a tree
+    fn reencode<F>(&self, tree: &'static str, convert: F) -> Fallible<()>
+    where
+        F: Fn(&[u8]) -> Fallible<Vec<u8>>,
+    {
+        let tree = self.open_tree(tree)?;
+        let entries = tree.iter().collect::<std::result::Result<Vec<_>, _>>()?;
+        for (key, value) in entries {
+            tree.set(key, convert(&value)?)?;
+        }
+        Ok(())
+    }
+
     fn gen_instance_id(&self) -> Fallible<ID> {
         self.gen_id(KEY_INSTANCE_ID, INSTANCE_ID_ZERO)
     }
@@ -0,0 +1,398 @@
+/*
+ *  YAMBA manager
+ *  Copyright (C) 2019 Aron Heinecke
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *      http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ */
+
+use bincode::{deserialize, serialize};
+use failure::Fallible;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use yamba_types::models::{AudioEffects, Song, SongID, StreamFormat, TimeMS};
+use yamba_types::{Volume, ID};
+
+use crate::models::{Instance, NewPlaylistData, PlaylistID, RestartPolicy, VoipType};
+
+/// Upgrades of the local DB from older versions  
+/// Bincode isn't self describing, values are decoded with the layout of their version & re-encoded
+
+/// Oldest version with an upgrade path
+pub const OLDEST_VERSION: u32 = 1;
+
+#[derive(Fail, Debug)]
+pub enum MigrateErr {
+    #[fail(display = "No upgrade path from DB version 0.0.{}", _0)]
+    Unsupported(u32),
+}
+
+/// Returns n of version `0.0.n`
+pub fn parse_version(version: &str) -> Option<u32> {
+    let mut parts = version.split('.');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some("0"), Some("0"), Some(n), None) => n.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Song layout of 0.0.1, before live streams
+#[derive(Deserialize)]
+struct SongV1 {
+    id: SongID,
+    name: String,
+    source: String,
+    artist: Option<String>,
+    length: Option<TimeMS>,
+}
+
+/// Song layout of 0.0.2 to 0.0.5, without chapters  
+/// Bincode encodes nested structs inline, so older layouts are embedded
+#[derive(Deserialize)]
+struct SongV2 {
+    v1: SongV1,
+    live: bool,
+}
+
+impl From<SongV1> for SongV2 {
+    fn from(song: SongV1) -> SongV2 {
+        SongV2 {
+            v1: song,
+            live: false,
+        }
+    }
+}
+
+impl From<SongV1> for Song {
+    fn from(song: SongV1) -> Song {
+        SongV2::from(song).into()
+    }
+}
+
+impl From<SongV2> for Song {
+    fn from(song: SongV2) -> Song {
+        Song {
+            id: song.v1.id,
+            name: song.v1.name,
+            source: song.v1.source,
+            artist: song.v1.artist,
+            length: song.v1.length,
+            live: song.live,
+            chapters: Vec::new(),
+        }
+    }
+}
+
+/// Playlist layout, generic over the song layout
+#[derive(Deserialize)]
+struct PlaylistLayout<T> {
+    id: PlaylistID,
+    name: String,
+    data: Vec<T>,
+}
+
+/// Instance layout of 0.0.1 & 0.0.2
+#[derive(Deserialize)]
+struct InstanceV1 {
+    id: ID,
+    host: String,
+    port: Option<u16>,
+    identity: Option<String>,
+    cid: Option<i32>,
+    name: String,
+    password: Option<String>,
+    autostart: bool,
+    volume: Volume,
+    nick: String,
+}
+
+/// Instance layout of 0.0.3, with restart policy
+#[derive(Deserialize)]
+struct InstanceV3 {
+    v1: InstanceV1,
+    restart_policy: RestartPolicy,
+}
+
+/// Instance layout of 0.0.4, with VoIP type
+#[derive(Deserialize)]
+struct InstanceV4 {
+    v3: InstanceV3,
+    voip: VoipType,
+    channel: Option<String>,
+}
+
+/// Instance layout of 0.0.5 & 0.0.6, without effects
+#[derive(Deserialize)]
+struct InstanceV5 {
+    v4: InstanceV4,
+    stream_format: StreamFormat,
+}
+
+/// Instance layout of 0.0.7, without playback rate
+#[derive(Deserialize)]
+struct InstanceV7 {
+    v5: InstanceV5,
+    effects: AudioEffects,
+}
+
+impl From<InstanceV1> for InstanceV3 {
+    fn from(instance: InstanceV1) -> InstanceV3 {
+        InstanceV3 {
+            v1: instance,
+            restart_policy: RestartPolicy::default(),
+        }
+    }
+}
+
+impl From<InstanceV3> for InstanceV4 {
+    fn from(instance: InstanceV3) -> InstanceV4 {
+        InstanceV4 {
+            v3: instance,
+            voip: VoipType::default(),
+            channel: None,
+        }
+    }
+}
+
+impl From<InstanceV4> for InstanceV5 {
+    fn from(instance: InstanceV4) -> InstanceV5 {
+        InstanceV5 {
+            v4: instance,
+            stream_format: StreamFormat::default(),
+        }
+    }
+}
+
+impl From<InstanceV5> for InstanceV7 {
+    fn from(instance: InstanceV5) -> InstanceV7 {
+        InstanceV7 {
+            v5: instance,
+            effects: AudioEffects::default(),
+        }
+    }
+}
+
+impl From<InstanceV7> for Instance {
+    fn from(instance: InstanceV7) -> Instance {
+        let v5 = instance.v5;
+        let v4 = v5.v4;
+        let v1 = v4.v3.v1;
+        Instance {
+            id: v1.id,
+            host: v1.host,
+            port: v1.port,
+            identity: v1.identity,
+            cid: v1.cid,
+            name: v1.name,
+            password: v1.password,
+            autostart: v1.autostart,
+            volume: v1.volume,
+            nick: v1.nick,
+            restart_policy: v4.v3.restart_policy,
+            voip: v4.voip,
+            channel: v4.channel,
+            stream_format: v5.stream_format,
+            effects: instance.effects,
+            rate: 1.0,
+            preserve_pitch: false,
+        }
+    }
+}
+
+/// Decode song stored with version
+fn decode_song(version: u32, data: &[u8]) -> Fallible<Song> {
+    Ok(match version {
+        1 => deserialize::<SongV1>(data)?.into(),
+        2..=5 => deserialize::<SongV2>(data)?.into(),
+        _ => return Err(MigrateErr::Unsupported(version).into()),
+    })
+}
+
+/// Decode instance stored with version
+fn decode_instance(version: u32, data: &[u8]) -> Fallible<Instance> {
+    let instance: InstanceV7 = match version {
+        1..=2 => InstanceV5::from(InstanceV4::from(InstanceV3::from(
+            deserialize::<InstanceV1>(data)?,
+        )))
+        .into(),
+        3 => InstanceV5::from(InstanceV4::from(deserialize::<InstanceV3>(data)?)).into(),
+        4 => InstanceV5::from(deserialize::<InstanceV4>(data)?).into(),
+        5..=6 => deserialize::<InstanceV5>(data)?.into(),
+        7 => deserialize::<InstanceV7>(data)?,
+        _ => return Err(MigrateErr::Unsupported(version).into()),
+    };
+    Ok(instance.into())
+}
+
+/// Re-encode song stored with version into the current layout
+pub fn song(version: u32, data: &[u8]) -> Fallible<Vec<u8>> {
+    Ok(serialize(&decode_song(version, data)?)?)
+}
+
+/// Re-encode playlist stored with version into the current layout
+pub fn playlist(version: u32, data: &[u8]) -> Fallible<Vec<u8>> {
+    match version {
+        1 => reencode_playlist::<SongV1>(data),
+        2..=5 => reencode_playlist::<SongV2>(data),
+        _ => Err(MigrateErr::Unsupported(version).into()),
+    }
+}
+
+/// Re-encode instance stored with version into the current layout
+pub fn instance(version: u32, data: &[u8]) -> Fallible<Vec<u8>> {
+    Ok(serialize(&decode_instance(version, data)?)?)
+}
+
+/// Re-encode playlist with songs of layout T
+fn reencode_playlist<T>(data: &[u8]) -> Fallible<Vec<u8>>
+where
+    T: DeserializeOwned + Into<Song>,
+{
+    let playlist = deserialize::<PlaylistLayout<T>>(data)?;
+    let songs: Vec<Song> = playlist.data.into_iter().map(|v| v.into()).collect();
+    Ok(serialize(&NewPlaylistData {
+        id: playlist.id,
+        name: playlist.name,
+        data: &songs,
+    })?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    #[derive(Serialize)]
+    struct OldSong<'a> {
+        id: &'a str,
+        name: &'a str,
+        source: &'a str,
+        artist: Option<&'a str>,
+        length: Option<TimeMS>,
+        live: bool,
+    }
+
+    #[test]
+    fn version() {
+        assert_eq!(Some(5), parse_version("0.0.5"));
+        assert_eq!(None, parse_version("0.1.5"));
+        assert_eq!(None, parse_version("0.0.x"));
+    }
+
+    #[test]
+    fn songs() {
+        let old = serialize(&OldSong {
+            id: "abc",
+            name: "name",
+            source: "http://example.com",
+            artist: None,
+            length: Some(1000),
+            live: true,
+        })
+        .unwrap();
+        let song = deserialize::<Song>(&song(5, &old).unwrap()).unwrap();
+        assert_eq!("abc", song.id);
+        assert_eq!(Some(1000), song.length);
+        assert!(song.live);
+        assert!(song.chapters.is_empty());
+        assert!(self::song(0, &old).is_err());
+
+        let old = serialize(&(
+            "abc",
+            "name",
+            "http://example.com",
+            None::<String>,
+            None::<TimeMS>,
+        ))
+        .unwrap();
+        let song = deserialize::<Song>(&self::song(1, &old).unwrap()).unwrap();
+        assert_eq!("http://example.com", song.source);
+        assert!(!song.live);
+    }
+
+    #[test]
+    fn instances() {
+        // bincode encodes structs like tuples
+        let old = serialize(&(
+            1 as ID,
+            "localhost",
+            Some(9987u16),
+            None::<String>,
+            None::<i32>,
+            "name",
+            None::<String>,
+            true,
+            0.5 as Volume,
+            "nick",
+            RestartPolicy::default(),
+            VoipType::default(),
+            None::<String>,
+            StreamFormat::default(),
+        ))
+        .unwrap();
+        let instance = deserialize::<Instance>(&instance(5, &old).unwrap()).unwrap();
+        assert_eq!(1, instance.id);
+        assert_eq!(Some(9987), instance.port);
+        assert_eq!("nick", instance.nick);
+        assert_eq!(Default::default(), instance.effects);
+        assert_eq!(1.0, instance.rate);
+        assert!(self::instance(6, &old).is_ok());
+        assert!(self::instance(0, &old).is_err());
+
+        let effects = AudioEffects {
+            mono: true,
+            ..Default::default()
+        };
+        let old = serialize(&(
+            3 as ID,
+            "localhost",
+            None::<u16>,
+            None::<String>,
+            None::<i32>,
+            "name",
+            None::<String>,
+            true,
+            0.5 as Volume,
+            "nick",
+            RestartPolicy::default(),
+            VoipType::default(),
+            None::<String>,
+            StreamFormat::default(),
+            &effects,
+        ))
+        .unwrap();
+        let instance = deserialize::<Instance>(&self::instance(7, &old).unwrap()).unwrap();
+        assert_eq!(3, instance.id);
+        assert_eq!(effects, instance.effects);
+        assert_eq!(1.0, instance.rate);
+        assert!(!instance.preserve_pitch);
+
+        let old = serialize(&(
+            2 as ID,
+            "localhost",
+            None::<u16>,
+            None::<String>,
+            Some(3i32),
+            "name",
+            None::<String>,
+            false,
+            0.5 as Volume,
+            "nick",
+        ))
+        .unwrap();
+        let instance = deserialize::<Instance>(&self::instance(1, &old).unwrap()).unwrap();
+        assert_eq!(2, instance.id);
+        assert_eq!(Some(3), instance.cid);
+        assert_eq!(VoipType::default(), instance.voip);
+        assert_eq!(StreamFormat::default(), instance.stream_format);
+    }
+}
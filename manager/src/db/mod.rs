@@ -15,12 +15,14 @@
  *  limitations under the License.
  */
 use crate::models::*;
-use yamba_types::models::{Song, SongID, TimeStarted, ID};
+use yamba_types::models::{Song, SongID, SongTrim, TimeStarted, ID};
 
 use failure::Fallible;
 
 #[cfg(feature = "local")]
 mod local;
+#[cfg(feature = "local")]
+mod migrate;
 #[cfg(any(feature = "maria", feature = "postgres"))]
 mod remote;
 
@@ -56,6 +58,8 @@ pub trait Database: Send + Sync + Clone {
     fn get_instances(&self, is_autostart: bool) -> Fallible<Vec<Instance>>;
     /// Create a new instance
     fn create_instance(&self, instance: NewInstance) -> Fallible<Instance>;
+    /// Update stored instance
+    fn update_instance(&self, instance: &Instance) -> Fallible<()>;
     /// Get startup time for instance
     fn get_instance_startup(&self, instance: &ID) -> Fallible<Option<TimeStarted>>;
     /// Set startup time for instance
@@ -70,6 +74,32 @@ pub trait Database: Send + Sync + Clone {
     fn upsert_playlist(&self, playlist: &NewPlaylistData, url: Option<&str>) -> Fallible<()>;
     /// Get Playlist by URL
     fn get_playlist_by_url(&self, url: &str) -> Fallible<Option<PlaylistData>>;
+    /// Get all soundboard clips
+    fn get_clips(&self) -> Fallible<Vec<Clip>>;
+    /// Get soundboard clip by name
+    fn get_clip(&self, name: &str) -> Fallible<Option<Clip>>;
+    /// Insert or replace soundboard clip
+    fn upsert_clip(&self, clip: &Clip) -> Fallible<()>;
+    /// Delete soundboard clip, returns false if not existing
+    fn delete_clip(&self, name: &str) -> Fallible<bool>;
+    /// Get trim points of song
+    fn get_song_trim(&self, song: &SongID) -> Fallible<Option<SongTrim>>;
+    /// Set trim points of song, None removes them
+    fn set_song_trim(&self, song: &SongID, trim: Option<&SongTrim>) -> Fallible<()>;
+    /// Get jingle rules of instance
+    fn get_jingle_rules(&self, instance: &ID) -> Fallible<Option<JingleRules>>;
+    /// Set jingle rules of instance
+    fn set_jingle_rules(&self, instance: &ID, rules: &JingleRules) -> Fallible<()>;
+    /// Get trim rules of instance
+    fn get_trim_rules(&self, instance: &ID) -> Fallible<Option<TrimRules>>;
+    /// Set trim rules of instance
+    fn set_trim_rules(&self, instance: &ID, rules: &TrimRules) -> Fallible<()>;
+    /// Get volume rules of instance
+    fn get_volume_rules(&self, instance: &ID) -> Fallible<Option<VolumeRules>>;
+    /// Set volume rules of instance
+    fn set_volume_rules(&self, instance: &ID, rules: &VolumeRules) -> Fallible<()>;
+    /// Get ownership token of this manager, generated on first call
+    fn get_owner_token(&self) -> Fallible<String>;
     // /// Get user by UID
     // fn get_user(&self, uid: UID) -> Fallible<User>;
     // /// Create user
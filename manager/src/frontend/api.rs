@@ -15,12 +15,21 @@
  *  limitations under the License.
  */
 
+use crate::db::Database;
+use crate::instance::InstanceErr;
 use crate::models::{self, *};
-use actix_web::{Error, HttpResponse, Json, State};
+use crate::soundboard::{SoundboardErr, CLIP_MAX_SIZE};
+use crate::speed::SpeedErr;
+use crate::trim::TrimErr;
+use crate::volume::VolumeErr;
+use actix_web::{
+    error::ErrorBadGateway, Error, HttpMessage, HttpRequest, HttpResponse, Json, Query, State,
+};
+use bytes::Bytes;
 use failure::Fallible;
 use futures::{
     future::{err, result, Either},
-    Future,
+    Future, Stream,
 };
 use reqwest::StatusCode;
 
@@ -34,6 +43,13 @@ pub fn handle_instances_get(state: State<FrState>) -> Fallible<HttpResponse> {
     }))
 }
 
+/// Returns load & health of all daemons
+pub fn handle_daemons_get(state: State<FrState>) -> Fallible<HttpResponse> {
+    Ok(HttpResponse::Ok().json(models::Daemons {
+        daemons: state.instances.get_pool().get_daemons(),
+    }))
+}
+
 /// Returns current track information
 pub fn handle_track_get(
     (state, params): (State<FrState>, Json<GenericRequest>),
@@ -56,7 +72,7 @@ pub fn handle_volume_get(
     if let Some(i) = state.instances.read(&params.instance) {
         let vol = VolumeFull {
             current: i.get_volume()?,
-            max: 1.0, // TODO: add support for volume limit
+            max: i.get_volume_rules().max,
         };
         Ok(HttpResponse::Ok().json(vol))
     } else {
@@ -64,6 +80,56 @@ pub fn handle_volume_get(
     }
 }
 
+/// Set volume, limited to the maximum, ignores the volume lock
+pub fn handle_volume_set(
+    (state, params): (State<FrState>, Json<VolumeUpdate>),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let fut = match state.instances.read(&params.instance) {
+        Some(i) => i.set_volume(params.volume),
+        None => return Either::B(result(Ok(invalid_instance()))),
+    };
+    match fut {
+        Ok(v) => Either::A(v.then(|res| {
+            result(Ok(match res {
+                Err(e) => HttpResponse::InternalServerError()
+                    .content_type("text/plain")
+                    .body(format!("Error setting volume {:?}", e)),
+                Ok(_) => HttpResponse::Ok().json(true),
+            }))
+        })),
+        Err(e) => Either::B(result(Ok(request_failed(e)))),
+    }
+}
+
+/// Returns volume rules of instance
+pub fn handle_volume_rules_get(
+    (state, params): (State<FrState>, Json<GenericRequest>),
+) -> HttpResponse {
+    match state.instances.read(&params.instance) {
+        Some(i) => HttpResponse::Ok().json(i.get_volume_rules()),
+        None => invalid_instance(),
+    }
+}
+
+/// Set volume rules of instance, 400 for invalid limits
+pub fn handle_volume_rules_set(
+    (state, params): (State<FrState>, Json<VolumeRulesUpdate>),
+) -> HttpResponse {
+    let params = params.into_inner();
+    match state.instances.read(&params.instance) {
+        Some(i) => match i.set_volume_rules(params.rules) {
+            Ok(_) => HttpResponse::Ok().json(true),
+            Err(e) => match e.downcast_ref::<VolumeErr>() {
+                Some(_) => HttpResponse::BadRequest()
+                    .content_type("text/plain")
+                    .body(e.to_string()),
+                None => request_failed(e),
+            },
+        },
+        None => invalid_instance(),
+    }
+}
+
 /// Returns playback state
 pub fn handle_playback_get(
     (state, params): (State<FrState>, Json<GenericRequest>),
@@ -72,6 +138,7 @@ pub fn handle_playback_get(
         let playback = Playback {
             playing: i.is_playing(),
             position: i.get_pos().unwrap_or(0),
+            rate: i.get_rate_cached(),
         };
         Ok(HttpResponse::Ok().json(playback))
     } else {
@@ -79,14 +146,83 @@ pub fn handle_playback_get(
     }
 }
 
+/// Returns audio effects of instance
+pub fn handle_effects_get((state, params): (State<FrState>, Json<GenericRequest>)) -> HttpResponse {
+    match state.instances.read(&params.instance) {
+        Some(i) => HttpResponse::Ok().json(i.get_effects()),
+        None => invalid_instance(),
+    }
+}
+
+/// Set audio effects of instance, persisted & reapplied on start
+pub fn handle_effects_set((state, params): (State<FrState>, Json<EffectsUpdate>)) -> HttpResponse {
+    let params = params.into_inner();
+    match state.instances.read(&params.instance) {
+        Some(i) => match i.set_effects(params.effects) {
+            Ok(_) => HttpResponse::Ok().json(true),
+            Err(e) => match e.downcast_ref::<InstanceErr>() {
+                Some(InstanceErr::InvalidEffects) => HttpResponse::BadRequest()
+                    .content_type("text/plain")
+                    .body(e.to_string()),
+                _ => request_failed(e),
+            },
+        },
+        None => invalid_instance(),
+    }
+}
+
+/// Returns playback rate & pitch preservation
+pub fn handle_rate_get(
+    (state, params): (State<FrState>, Json<GenericRequest>),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let fut = match state.instances.read(&params.instance) {
+        Some(i) => i.get_rate(),
+        None => return Either::B(result(Ok(invalid_instance()))),
+    };
+    match fut {
+        Ok(v) => Either::A(v.then(|res| {
+            result(Ok(match res {
+                Err(e) => HttpResponse::InternalServerError()
+                    .content_type("text/plain")
+                    .body(format!("Error reading rate {:?}", e)),
+                Ok(rate) => HttpResponse::Ok().json(rate),
+            }))
+        })),
+        Err(e) => Either::B(result(Ok(request_failed(e)))),
+    }
+}
+
+/// Set playback rate, 400 for unsupported rates
+pub fn handle_rate_set(
+    (state, params): (State<FrState>, Json<RateUpdate>),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    if !yamba_types::is_valid_rate(params.rate) {
+        return Either::B(result(Ok(HttpResponse::BadRequest()
+            .content_type("text/plain")
+            .body(SpeedErr::InvalidRate(params.rate.to_string()).to_string()))));
+    }
+    let fut = match state.instances.read(&params.instance) {
+        Some(i) => i.set_rate(params.rate, params.preserve_pitch),
+        None => return Either::B(result(Ok(invalid_instance()))),
+    };
+    match fut {
+        Ok(v) => Either::A(v.then(|res| {
+            result(Ok(match res {
+                Err(e) => HttpResponse::InternalServerError()
+                    .content_type("text/plain")
+                    .body(format!("Error setting rate {:?}", e)),
+                Ok(_) => HttpResponse::Ok().json(true),
+            }))
+        })),
+        Err(e) => Either::B(result(Ok(request_failed(e)))),
+    }
+}
+
 /// Returns instance ID on success
 pub fn handle_instances_create(
     (state, params): (State<FrState>, Json<NewInstance>),
 ) -> impl Future<Item = HttpResponse, Error = Error> {
-    match state
-        .instances
-        .create_instance(params.into_inner(), state.backend.clone())
-    {
+    match state.instances.create_instance(params.into_inner()) {
         Ok(id) => Either::A(match state.instances.start_instance(id) {
             Ok(v) => Either::A(v.then(|res| {
                 result(Ok(match res {
@@ -111,3 +247,294 @@ pub fn handle_instances_create(
         }
     }
 }
+
+/// Starts recording, returns the recording
+pub fn handle_recording_start(
+    (state, params): (State<FrState>, Json<RecordingStart>),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let fut = match state.instances.read(&params.instance) {
+        Some(i) => i.start_recording(params.format),
+        None => return Either::B(result(Ok(invalid_instance()))),
+    };
+    match fut {
+        Ok(v) => Either::A(v.then(|res| {
+            result(Ok(match res {
+                Err(e) => match e.status() {
+                    Some(StatusCode::CONFLICT) => HttpResponse::Conflict()
+                        .content_type("text/plain")
+                        .body("Instance is already recording!"),
+                    _ => HttpResponse::InternalServerError()
+                        .content_type("text/plain")
+                        .body(format!("Error starting recording {:?}", e)),
+                },
+                Ok(recording) => HttpResponse::Ok().json(recording),
+            }))
+        })),
+        Err(e) => Either::B(result(Ok(request_failed(e)))),
+    }
+}
+
+/// Stops recording, returns the finished recording
+pub fn handle_recording_stop(
+    (state, params): (State<FrState>, Json<GenericRequest>),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let fut = match state.instances.read(&params.instance) {
+        Some(i) => i.stop_recording(),
+        None => return Either::B(result(Ok(invalid_instance()))),
+    };
+    match fut {
+        Ok(v) => Either::A(v.then(|res| {
+            result(Ok(match res {
+                Err(e) => match e.status() {
+                    Some(StatusCode::NOT_FOUND) => HttpResponse::NotFound()
+                        .content_type("text/plain")
+                        .body("Instance is not recording!"),
+                    _ => HttpResponse::InternalServerError()
+                        .content_type("text/plain")
+                        .body(format!("Error stopping recording {:?}", e)),
+                },
+                Ok(recording) => HttpResponse::Ok().json(recording),
+            }))
+        })),
+        Err(e) => Either::B(result(Ok(request_failed(e)))),
+    }
+}
+
+/// Returns recordings of instance
+pub fn handle_recordings_get(
+    (state, params): (State<FrState>, Json<GenericRequest>),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let fut = match state.instances.read(&params.instance) {
+        Some(i) => i.get_recordings(),
+        None => return Either::B(result(Ok(invalid_instance()))),
+    };
+    match fut {
+        Ok(v) => Either::A(v.then(|res| {
+            result(Ok(match res {
+                Err(e) => HttpResponse::InternalServerError()
+                    .content_type("text/plain")
+                    .body(format!("Error listing recordings {:?}", e)),
+                Ok(list) => HttpResponse::Ok().json(list.recordings),
+            }))
+        })),
+        Err(e) => Either::B(result(Ok(request_failed(e)))),
+    }
+}
+
+/// Streams recording or sidecar file from the daemon
+pub fn handle_recording_file(
+    (state, params): (State<FrState>, Query<RecordingDownload>),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let params = params.into_inner();
+    let fut = match state.instances.read(&params.instance) {
+        Some(i) => i.get_recording_file(params.name.clone()),
+        None => return Either::B(result(Ok(invalid_instance()))),
+    };
+    match fut {
+        Ok(v) => Either::A(v.then(move |res| {
+            result(Ok(match res {
+                Err(e) => match e.status() {
+                    Some(StatusCode::NOT_FOUND) => HttpResponse::NotFound()
+                        .content_type("text/plain")
+                        .body("Invalid recording!"),
+                    _ => HttpResponse::BadGateway()
+                        .content_type("text/plain")
+                        .body(format!("Error downloading recording {:?}", e)),
+                },
+                Ok(resp) => HttpResponse::Ok()
+                    .content_type("application/octet-stream")
+                    .header(
+                        "Content-Disposition",
+                        format!("attachment; filename=\"{}\"", params.name),
+                    )
+                    .streaming(
+                        resp.into_body()
+                            .map(|chunk| Bytes::from(&chunk[..]))
+                            .map_err(ErrorBadGateway),
+                    ),
+            }))
+        })),
+        Err(e) => Either::B(result(Ok(request_failed(e)))),
+    }
+}
+
+/// Returns trim points of song
+pub fn handle_trim_get((state, params): (State<FrState>, Json<SongTrimRequest>)) -> HttpResponse {
+    match state.instances.get_db().get_song_trim(&params.song) {
+        Ok(trim) => HttpResponse::Ok().json(trim),
+        Err(e) => request_failed(e),
+    }
+}
+
+/// Set trim points of song, applied to instances playing it
+pub fn handle_trim_set((state, params): (State<FrState>, Json<SongTrimUpdate>)) -> HttpResponse {
+    let params = params.into_inner();
+    match state.instances.set_song_trim(&params.song, params.trim) {
+        Ok(_) => HttpResponse::Ok().json(true),
+        Err(e) => match e.downcast_ref::<TrimErr>() {
+            Some(_) => HttpResponse::BadRequest()
+                .content_type("text/plain")
+                .body(e.to_string()),
+            None => request_failed(e),
+        },
+    }
+}
+
+/// Returns trim rules of instance
+pub fn handle_trim_rules_get(
+    (state, params): (State<FrState>, Json<GenericRequest>),
+) -> HttpResponse {
+    match state.instances.read(&params.instance) {
+        Some(i) => HttpResponse::Ok().json(i.get_trim_rules()),
+        None => invalid_instance(),
+    }
+}
+
+/// Set trim rules of instance
+pub fn handle_trim_rules_set(
+    (state, params): (State<FrState>, Json<TrimRulesUpdate>),
+) -> HttpResponse {
+    let params = params.into_inner();
+    match state.instances.read(&params.instance) {
+        Some(i) => match i.set_trim_rules(params.rules) {
+            Ok(_) => HttpResponse::Ok().json(true),
+            Err(e) => request_failed(e),
+        },
+        None => invalid_instance(),
+    }
+}
+
+/// Returns jingle rules of instance
+pub fn handle_jingles_get((state, params): (State<FrState>, Json<GenericRequest>)) -> HttpResponse {
+    match state.instances.read(&params.instance) {
+        Some(i) => HttpResponse::Ok().json(i.get_jingle_rules()),
+        None => invalid_instance(),
+    }
+}
+
+/// Set jingle rules of instance
+pub fn handle_jingles_set(
+    (state, params): (State<FrState>, Json<JingleRulesUpdate>),
+) -> HttpResponse {
+    let params = params.into_inner();
+    match state.instances.read(&params.instance) {
+        Some(i) => match i.set_jingle_rules(params.rules) {
+            Ok(_) => HttpResponse::Ok().json(true),
+            Err(e) => request_failed(e),
+        },
+        None => invalid_instance(),
+    }
+}
+
+/// Returns all clips of the soundboard
+pub fn handle_clips_get(state: State<FrState>) -> HttpResponse {
+    match state.instances.get_library().get_clips() {
+        Ok(clips) => HttpResponse::Ok().json(clips),
+        Err(e) => request_failed(e),
+    }
+}
+
+/// Upload clip audio as raw body, replaces existing audio of the clip
+pub fn handle_clip_upload(
+    (req, params): (HttpRequest<FrState>, Query<ClipName>),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let library = req.state().instances.get_library().clone();
+    req.body().limit(CLIP_MAX_SIZE).from_err().map(move |data| {
+        match library.upload(&params.name, &data) {
+            Ok(clip) => HttpResponse::Ok().json(clip),
+            Err(e) => soundboard_failed(e),
+        }
+    })
+}
+
+/// Update clip settings
+pub fn handle_clip_update((state, params): (State<FrState>, Json<ClipSettings>)) -> HttpResponse {
+    match state.instances.get_library().update(params.into_inner()) {
+        Ok(clip) => HttpResponse::Ok().json(clip),
+        Err(e) => soundboard_failed(e),
+    }
+}
+
+/// Delete clip
+pub fn handle_clip_delete((state, params): (State<FrState>, Json<ClipName>)) -> HttpResponse {
+    match state.instances.get_library().delete(&params.name) {
+        Ok(_) => HttpResponse::Ok().json(true),
+        Err(e) => soundboard_failed(e),
+    }
+}
+
+/// Play clip on instance, not restricted by the clip permissions
+pub fn handle_clip_play(
+    (state, params): (State<FrState>, Json<ClipTrigger>),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let fut = match state.instances.read(&params.instance) {
+        Some(i) => state
+            .instances
+            .get_library()
+            .get_clip(&params.clip)
+            .and_then(|clip| i.play_clip(&clip)),
+        None => return Either::B(result(Ok(invalid_instance()))),
+    };
+    match fut {
+        Ok(v) => Either::A(v.then(|res| {
+            result(Ok(match res {
+                Err(e) => match e.status() {
+                    Some(StatusCode::CONFLICT) => HttpResponse::Conflict()
+                        .content_type("text/plain")
+                        .body("Clips can only be played over music!"),
+                    Some(StatusCode::TOO_MANY_REQUESTS) => HttpResponse::TooManyRequests()
+                        .content_type("text/plain")
+                        .body("Too many clips playing!"),
+                    _ => HttpResponse::InternalServerError()
+                        .content_type("text/plain")
+                        .body(format!("Error playing clip {:?}", e)),
+                },
+                Ok(_) => HttpResponse::Accepted().finish(),
+            }))
+        })),
+        Err(e) => Either::B(result(Ok(soundboard_failed(e)))),
+    }
+}
+
+/// Stop all clips of instance
+pub fn handle_clips_stop(
+    (state, params): (State<FrState>, Json<GenericRequest>),
+) -> impl Future<Item = HttpResponse, Error = Error> {
+    let fut = match state.instances.read(&params.instance) {
+        Some(i) => i.stop_clips(),
+        None => return Either::B(result(Ok(invalid_instance()))),
+    };
+    match fut {
+        Ok(v) => Either::A(v.then(|res| {
+            result(Ok(match res {
+                Err(e) => HttpResponse::InternalServerError()
+                    .content_type("text/plain")
+                    .body(format!("Error stopping clips {:?}", e)),
+                Ok(_) => HttpResponse::Ok().json(true),
+            }))
+        })),
+        Err(e) => Either::B(result(Ok(request_failed(e)))),
+    }
+}
+
+fn invalid_instance() -> HttpResponse {
+    HttpResponse::BadRequest().json("Invalid instance!")
+}
+
+fn request_failed(e: failure::Error) -> HttpResponse {
+    HttpResponse::InternalServerError()
+        .content_type("text/plain")
+        .body(format!("Error on sending request: {}", e))
+}
+
+fn soundboard_failed(e: failure::Error) -> HttpResponse {
+    match e.downcast_ref::<SoundboardErr>() {
+        Some(SoundboardErr::Cooldown(_, _)) => HttpResponse::TooManyRequests()
+            .content_type("text/plain")
+            .body(e.to_string()),
+        Some(_) => HttpResponse::BadRequest()
+            .content_type("text/plain")
+            .body(e.to_string()),
+        None => request_failed(e),
+    }
+}
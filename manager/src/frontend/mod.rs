@@ -15,13 +15,12 @@
  *  limitations under the License.
  */
 
-use crate::backend::Backend;
 use crate::instance::Instances;
 use actix_web::{fs, http, middleware, server, App};
 use failure::Fallible;
 use std::net::SocketAddr;
 
-pub use ws::{InstanceCreated, WSServer};
+pub use ws::{InstanceCreated, ResolveFailed, WSServer};
 
 mod api;
 mod ws;
@@ -29,7 +28,6 @@ mod ws;
 #[derive(Clone)]
 pub struct FrState {
     pub instances: Instances,
-    pub backend: Backend,
 }
 
 #[derive(Fail, Debug)]
@@ -39,14 +37,9 @@ pub enum ServerErr {
 }
 
 /// Init frontend server + websockets
-pub fn init_frontend_server(
-    instances: Instances,
-    backend: Backend,
-    bind_addr: SocketAddr,
-) -> Fallible<()> {
+pub fn init_frontend_server(instances: Instances, bind_addr: SocketAddr) -> Fallible<()> {
     let state = FrState {
         instances: instances.clone(),
-        backend: backend.clone(),
     };
     server::new(move || {
         App::with_state(state.clone())
@@ -56,7 +49,24 @@ pub fn init_frontend_server(
                     .with_async(api::handle_instances_create)
             })
             .resource("/api/playback/volume", |r| {
-                r.method(http::Method::GET).with(api::handle_volume_get)
+                r.method(http::Method::GET).with(api::handle_volume_get);
+                r.method(http::Method::POST)
+                    .with_async(api::handle_volume_set)
+            })
+            .resource("/api/volume/rules", |r| {
+                r.method(http::Method::GET)
+                    .with(api::handle_volume_rules_get);
+                r.method(http::Method::POST)
+                    .with(api::handle_volume_rules_set)
+            })
+            .resource("/api/instances/effects", |r| {
+                r.method(http::Method::GET).with(api::handle_effects_get);
+                r.method(http::Method::POST).with(api::handle_effects_set)
+            })
+            .resource("/api/playback/rate", |r| {
+                r.method(http::Method::GET).with_async(api::handle_rate_get);
+                r.method(http::Method::POST)
+                    .with_async(api::handle_rate_set)
             })
             .resource("/api/playback/state", |r| {
                 r.method(http::Method::GET).with(api::handle_playback_get)
@@ -64,9 +74,62 @@ pub fn init_frontend_server(
             .resource("/api/playback/track", |r| {
                 r.method(http::Method::GET).with(api::handle_track_get)
             })
+            .resource("/api/recording/start", |r| {
+                r.method(http::Method::POST)
+                    .with_async(api::handle_recording_start)
+            })
+            .resource("/api/recording/stop", |r| {
+                r.method(http::Method::POST)
+                    .with_async(api::handle_recording_stop)
+            })
+            .resource("/api/recordings", |r| {
+                r.method(http::Method::GET)
+                    .with_async(api::handle_recordings_get)
+            })
+            .resource("/api/recordings/file", |r| {
+                r.method(http::Method::GET)
+                    .with_async(api::handle_recording_file)
+            })
+            .resource("/api/trim", |r| {
+                r.method(http::Method::GET).with(api::handle_trim_get);
+                r.method(http::Method::POST).with(api::handle_trim_set)
+            })
+            .resource("/api/trim/rules", |r| {
+                r.method(http::Method::GET).with(api::handle_trim_rules_get);
+                r.method(http::Method::POST)
+                    .with(api::handle_trim_rules_set)
+            })
+            .resource("/api/jingles", |r| {
+                r.method(http::Method::GET).with(api::handle_jingles_get);
+                r.method(http::Method::POST).with(api::handle_jingles_set)
+            })
+            .resource("/api/soundboard/clips", |r| {
+                r.method(http::Method::GET).with(api::handle_clips_get)
+            })
+            .resource("/api/soundboard/upload", |r| {
+                r.method(http::Method::POST)
+                    .with_async(api::handle_clip_upload)
+            })
+            .resource("/api/soundboard/clip", |r| {
+                r.method(http::Method::POST).with(api::handle_clip_update)
+            })
+            .resource("/api/soundboard/delete", |r| {
+                r.method(http::Method::POST).with(api::handle_clip_delete)
+            })
+            .resource("/api/soundboard/play", |r| {
+                r.method(http::Method::POST)
+                    .with_async(api::handle_clip_play)
+            })
+            .resource("/api/soundboard/stop", |r| {
+                r.method(http::Method::POST)
+                    .with_async(api::handle_clips_stop)
+            })
             .resource("/api/instances", |r| {
                 r.method(http::Method::GET).with(api::handle_instances_get)
             })
+            .resource("/api/daemons", |r| {
+                r.method(http::Method::GET).with(api::handle_daemons_get)
+            })
             .resource("/ws", |r| r.route().f(ws::ws_route))
             .handler("/static", fs::StaticFiles::new("./static").unwrap())
             .handler(
@@ -15,7 +15,7 @@
  *  limitations under the License.
  */
 
-pub use server::{InstanceCreated, WSServer};
+pub use server::{InstanceCreated, ResolveFailed, WSServer};
 
 mod server;
 
@@ -50,6 +50,8 @@ pub enum Message {
     InstancePlayback(models::callback::PlaystateResponse),
     InstanceCreated(ID),
     PositionUpdate(models::callback::TrackPositionUpdate),
+    StreamTitle(models::callback::SongInfoUpdate),
+    ResolveFailed(server::ResolveFailed),
 }
 
 #[derive(Serialize)]
@@ -19,6 +19,7 @@ use actix::prelude::*;
 use actix::registry::SystemService;
 use failure::Fallible;
 use rand::{self, rngs::ThreadRng, Rng};
+use serde::Serialize;
 use yamba_types::models::{self, ID};
 
 use std::collections::{HashMap, HashSet};
@@ -146,6 +147,25 @@ impl Handler<InstanceCreated> for WSServer {
     }
 }
 
+/// Internal: Send failed resolve of queued url
+#[derive(Message, Serialize)]
+pub struct ResolveFailed {
+    pub id: ID,
+    pub source: String,
+    pub error: models::ErrorCodes,
+    /// User friendly message
+    pub message: String,
+}
+
+impl Handler<ResolveFailed> for WSServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: ResolveFailed, _: &mut Context<Self>) {
+        debug!("Resolve failed on {}: {:?}", msg.id, msg.error);
+        warn_log!(self.send_message(&msg.id.clone(), &Message::ResolveFailed(msg), 0));
+    }
+}
+
 /// Internal: Send instance volume change
 impl Handler<models::VolumeSetReq> for WSServer {
     type Result = ();
@@ -166,6 +186,15 @@ impl Handler<models::callback::TrackPositionUpdate> for WSServer {
     }
 }
 
+/// Internal, Send stream title update of live sources
+impl Handler<models::callback::SongInfoUpdate> for WSServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: models::callback::SongInfoUpdate, _: &mut Context<Self>) {
+        warn_log!(self.send_message(&msg.id.clone(), &Message::StreamTitle(msg), 0));
+    }
+}
+
 /// Internal, Send instance playback state
 impl Handler<models::callback::PlaystateResponse> for WSServer {
     type Result = ();
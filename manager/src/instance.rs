@@ -19,37 +19,99 @@ use actix::{registry::SystemService, spawn};
 use chashmap::CHashMap;
 use failure::Fallible;
 use futures::future::{result, Either, Future};
+use futures::sync::oneshot;
 use hashbrown::HashMap;
 use owning_ref::OwningRef;
+use tokio_timer::{Delay, Timeout};
 use yamba_types::models::{
-    callback::{InstanceState, Playstate, PlaystateResponse},
+    callback::{InstanceState, Playstate, PlaystateResponse, SongInfoUpdate, TrackPositionUpdate},
     *,
 };
 
 use std::ops::Deref;
 use std::sync::{
-    atomic::{AtomicUsize, Ordering},
+    atomic::{AtomicBool, AtomicUsize, Ordering},
     Arc, RwLock, RwLockReadGuard, Weak,
 };
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use crate::backend::Backend;
+use crate::backend::{reconcile::Restarts, tickets, Backend, DaemonID, DaemonPool};
+use crate::chapters;
 use crate::db::{Database, DB};
 use crate::frontend;
-use crate::models;
+use crate::jingles::{self, JingleErr, JingleState};
+use crate::models::{self, RestartPolicy};
 use crate::playlist::{ItemReturn, Playlist};
+use crate::soundboard::{self, Library, SoundboardErr};
+use crate::speed;
+use crate::trim::{self, TrimCommand, TrimErr};
+use crate::volume;
+
+/// Amount of results returned per search
+const SEARCH_RESULT_AMOUNT: u8 = 5;
+/// Max time to wait for search results
+const SEARCH_TIMEOUT: Duration = Duration::from_secs(30);
+/// Time after which search choices of an invoker expire
+const SEARCH_CHOICE_TIMEOUT: Duration = Duration::from_secs(180);
+/// Max time to wait for the resolve outcome of a queued url
+const QUEUE_REPLY_TIMEOUT: Duration = Duration::from_secs(30);
+/// Chat reply for queued urls still resolving after the timeout
+const QUEUE_PENDING_REPLY: &str = "Still resolving, tracks get queued when done.";
+/// Max retries for queued urls failing with retryable errors
+const QUEUE_RESOLVE_RETRIES: u8 = 2;
+/// Delay before the first retry, doubles per retry
+const QUEUE_RESOLVE_RETRY_DELAY: Duration = Duration::from_secs(10);
 
 #[derive(Fail, Debug)]
 pub enum InstanceErr {
     #[fail(display = "No instance with id {} found when expected!", _0)]
     NoInstanceFound(ID),
+    #[fail(display = "No search results to pick from, search first.")]
+    NoSearchChoices,
+    #[fail(display = "Invalid choice {}!", _0)]
+    InvalidSearchChoice(usize),
+    #[fail(display = "Search timed out!")]
+    SearchTimeout,
+    #[fail(display = "{}", _0)]
+    ResolveFailed(&'static str),
+    #[fail(display = "Audio effects out of range!")]
+    InvalidEffects,
+}
+
+/// Current unix time in ms, for position interpolation
+pub fn now_ms() -> i64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(v) => v.as_secs() as i64 * 1000 + i64::from(v.subsec_millis()),
+        Err(_) => 0,
+    }
+}
+
+/// User friendly message for resolve error codes
+pub fn resolve_error_message(code: ErrorCodes) -> &'static str {
+    match code {
+        ErrorCodes::RESOLVE_UNAVAILABLE => "This video is unavailable or got removed.",
+        ErrorCodes::RESOLVE_PRIVATE => "This video is private.",
+        ErrorCodes::RESOLVE_AGE_RESTRICTED => "This video is age-restricted.",
+        ErrorCodes::RESOLVE_GEO_BLOCKED => "This video is not available in our country.",
+        ErrorCodes::RESOLVE_COPYRIGHT => "This video got blocked due to copyright claims.",
+        ErrorCodes::RESOLVE_UNSUPPORTED_URL => "This URL is not supported.",
+        ErrorCodes::RESOLVE_NETWORK => "Network error, please try again later.",
+        ErrorCodes::RESOLVE_RATE_LIMITED => "Rate limited by the site, please try again later.",
+        ErrorCodes::RESOLVE_QUEUE_OVERLOAD => "Too many requests queued, please try again later.",
+        _ => "Unable to resolve URL.",
+    }
 }
 
 //pub type Instances = Arc<RwLock<HashMap<ID, Instance>>>;
 #[derive(Clone)]
 pub struct Instances {
     ins: Arc<RwLock<HashMap<ID, Instance>>>,
-    pos_cache: Arc<CHashMap<ID, TimeMS>>,
+    pos_cache: Arc<CHashMap<ID, TrackPositionUpdate>>,
+    pool: DaemonPool,
+    restarts: Restarts,
+    owner: String,
     db: DB,
+    library: Library,
 }
 
 impl Deref for Instances {
@@ -74,6 +136,16 @@ impl Instances {
             .ok()
     }
 
+    /// Get read-ref to instance, only if it's placed on the daemon
+    pub fn read_placed<'a>(&'a self, id: &ID, daemon: DaemonID) -> Option<InstanceRef<'a>> {
+        if self.pool.is_placed_on(id, daemon) {
+            self.read(id)
+        } else {
+            trace!("Instance {} isn't placed on daemon {}", id, daemon);
+            None
+        }
+    }
+
     /// Read instance but return error when none is found
     pub fn read_err<'a>(&'a self, id: &ID) -> Fallible<InstanceRef<'a>> {
         self.read(id)
@@ -81,11 +153,11 @@ impl Instances {
     }
 
     /// Create new instance
-    pub fn create_instance(&self, new: models::NewInstance, backend: Backend) -> Fallible<ID> {
+    pub fn create_instance(&self, new: models::NewInstance) -> Fallible<ID> {
         let model = self.db.create_instance(new)?;
         let mut instances_w = self.ins.write().expect("Can't lock instance!");
         let id = model.id.clone();
-        instances_w.insert(id, Instance::new(backend, self.db.clone(), self, model));
+        instances_w.insert(id, Instance::new(self.db.clone(), self, model));
         Ok(id)
     }
 
@@ -102,6 +174,25 @@ impl Instances {
             .collect()
     }
 
+    /// Store trim points of song & send them to running instances
+    pub fn set_song_trim(&self, song: &SongID, trim: Option<SongTrim>) -> Fallible<()> {
+        if let Some(ref v) = trim {
+            trim::validate(v)?;
+        }
+        self.db.set_song_trim(song, trim.as_ref())?;
+        let instances_r = self.ins.read().expect("Can't read instance!");
+        for inst in instances_r.values().filter(|v| v.is_running()) {
+            if let Err(e) = inst.push_trim(song, trim.clone()) {
+                warn!(
+                    "Can't send trim points to instance {}: {}",
+                    inst.get_id(),
+                    e
+                );
+            }
+        }
+        Ok(())
+    }
+
     /// Start created/loaded instance  
     /// Updates startup time & writes back to DB
     pub fn start_instance(
@@ -118,12 +209,17 @@ impl Instances {
         }))
     }
 
-    /// New Instances-Instance
-    pub fn new(db: DB) -> Instances {
+    /// New Instances-Instance  
+    /// Instances are started with the ownership token of this manager
+    pub fn new(db: DB, pool: DaemonPool, owner: String, library: Library) -> Instances {
         Instances {
             ins: Arc::new(RwLock::new(HashMap::new())),
             pos_cache: Arc::new(CHashMap::new()),
+            pool,
+            restarts: Restarts::default(),
+            owner,
             db,
+            library,
         }
     }
 
@@ -132,31 +228,45 @@ impl Instances {
         &self.db
     }
 
-    /// Load instances from DB
-    pub fn load_instances(&self, backend: Backend) -> Fallible<()> {
+    /// Get soundboard clip library
+    pub fn get_library(&self) -> &Library {
+        &self.library
+    }
+
+    /// Get daemon pool
+    pub fn get_pool(&self) -> &DaemonPool {
+        &self.pool
+    }
+
+    /// Get restart backoff of instances
+    pub fn get_restarts(&self) -> &Restarts {
+        &self.restarts
+    }
+
+    /// Get ownership token of this manager
+    pub fn get_owner(&self) -> &str {
+        &self.owner
+    }
+
+    /// Load instances from DB  
+    /// Instances are started by the reconciliation
+    pub fn load_instances(&self) -> Fallible<()> {
         let mut instances_w = self.ins.write().expect("Can't lock instance!");
         for instance in self.db.get_instances(false)? {
             let id = instance.id.clone();
-            let start = instance.autostart;
-            instances_w.insert(
-                id,
-                Instance::new(backend.clone(), self.db.clone(), self, instance),
-            );
-            if start {
-                instances_w.get_mut(&id).unwrap().start_ignore()?;
-            }
+            instances_w.insert(id, Instance::new(self.db.clone(), self, instance));
         }
         Ok(())
     }
 
-    /// Returns playback position
+    /// Returns interpolated playback position
     #[allow(unused)]
     pub fn get_pos(&self, id: &ID) -> Option<TimeMS> {
-        self.pos_cache.get(id).map(|v| v.clone())
+        self.pos_cache.get(id).map(|v| v.position_at(now_ms()))
     }
-    /// Set (new) position for instance
-    pub fn set_pos(&self, id: ID, pos: TimeMS) {
-        self.pos_cache.insert(id, pos);
+    /// Set (new) playback timing for instance
+    pub fn set_pos(&self, pos: TrackPositionUpdate) {
+        self.pos_cache.insert(pos.id, pos);
     }
 }
 
@@ -169,11 +279,30 @@ pub struct Instance {
     volume: RwLock<Volume>,
     state: AtomicUsize,
     playstate: AtomicUsize,
-    backend: Backend,
-    model: InstanceLoadReq,
-    position: Weak<CHashMap<ID, TimeMS>>,
+    /// Whether the instance should be running
+    wanted: AtomicBool,
+    restart_policy: RestartPolicy,
+    pool: DaemonPool,
+    model: RwLock<InstanceLoadReq>,
+    position: Weak<CHashMap<ID, TrackPositionUpdate>>,
     db: DB,
     start_time: RwLock<Option<TimeStarted>>,
+    /// Search results by invoker, see `search_invoker`
+    search_choices: RwLock<HashMap<String, SearchChoices>>,
+    stream_title: RwLock<Option<String>>,
+    /// Last play of soundboard clips by name
+    clip_plays: Arc<RwLock<HashMap<String, Instant>>>,
+    library: Library,
+    jingle_rules: RwLock<models::JingleRules>,
+    jingles: RwLock<JingleState>,
+    volume_rules: RwLock<models::VolumeRules>,
+    trim_rules: RwLock<models::TrimRules>,
+}
+
+/// Search results of an invoker, waiting to be picked
+struct SearchChoices {
+    songs: Vec<Song>,
+    created: Instant,
 }
 
 impl Drop for Instance {
@@ -184,16 +313,13 @@ impl Drop for Instance {
             }
             Err(e) => warn!("Can't auto-kill instance: {}", e),
         }
-        let _ = self.db.set_instance_startup(
-            &self.get_id(),
-            &self.start_time.read().expect("Can't read start_time!"),
-        );
+        self.pool.release(&self.id);
     }
 }
 
 #[allow(unused)]
 impl Instance {
-    fn new(backend: Backend, db: DB, instances: &Instances, model: models::Instance) -> Instance {
+    fn new(db: DB, instances: &Instances, model: models::Instance) -> Instance {
         let id = model.id.clone();
         spawn(
             frontend::WSServer::from_registry()
@@ -201,10 +327,42 @@ impl Instance {
                 .map_err(|e| warn!("WS-Server error: {}", e)),
         );
 
-        let (load_req, name) = model.into_InstanceLoadReq();
+        // running flag of last manager run
+        let was_running = match db.get_instance_startup(&id) {
+            Ok(v) => v.is_some(),
+            Err(e) => {
+                warn!("Can't read startup time of instance {}: {}", id, e);
+                false
+            }
+        };
+        let jingle_rules = match db.get_jingle_rules(&id) {
+            Ok(v) => v.unwrap_or_default(),
+            Err(e) => {
+                warn!("Can't read jingle rules of instance {}: {}", id, e);
+                models::JingleRules::default()
+            }
+        };
+        let volume_rules = match db.get_volume_rules(&id) {
+            Ok(v) => v.unwrap_or_default(),
+            Err(e) => {
+                warn!("Can't read volume rules of instance {}: {}", id, e);
+                models::VolumeRules::default()
+            }
+        };
+        let trim_rules = match db.get_trim_rules(&id) {
+            Ok(v) => v.unwrap_or_default(),
+            Err(e) => {
+                warn!("Can't read trim rules of instance {}: {}", id, e);
+                models::TrimRules::default()
+            }
+        };
+        let wanted = model.autostart || was_running;
+        let restart_policy = model.restart_policy;
+        let (mut load_req, name) = model.into_InstanceLoadReq();
+        load_req.owner = Some(instances.owner.clone());
 
         Instance {
-            model: load_req,
+            model: RwLock::new(load_req),
             name: name,
             id,
             db,
@@ -212,9 +370,19 @@ impl Instance {
             playlist: SPlaylist::new(),
             volume: RwLock::new(0.05),
             state: AtomicUsize::new(InstanceState::Stopped as usize),
-            backend,
+            pool: instances.pool.clone(),
+            wanted: AtomicBool::new(wanted),
+            restart_policy,
             playstate: AtomicUsize::new(Playstate::Stopped as usize),
             position: Arc::downgrade(&instances.pos_cache),
+            search_choices: RwLock::new(HashMap::new()),
+            stream_title: RwLock::new(None),
+            clip_plays: Arc::new(RwLock::new(HashMap::new())),
+            library: instances.library.clone(),
+            jingle_rules: RwLock::new(jingle_rules),
+            jingles: RwLock::new(JingleState::default()),
+            volume_rules: RwLock::new(volume_rules),
+            trim_rules: RwLock::new(trim_rules),
         }
     }
 
@@ -255,7 +423,10 @@ impl Instance {
             Some(v) => format!("{} / ", Self::format_time(Some(v / 1000))),
             None => String::new(),
         };
-        let length = Self::format_time(song.length);
+        let length = match song.live {
+            true => String::from("LIVE"),
+            false => Self::format_time(song.length),
+        };
         format!("{} {} {}{}", song.name, artist, pos, length)
     }
 
@@ -270,14 +441,22 @@ impl Instance {
             .collect()
     }
 
-    /// Get playback position for current title in instance
+    /// Get interpolated playback position for current title in instance
     pub fn get_pos(&self) -> Option<TimeMS> {
         match self.position.upgrade() {
-            Some(v) => v.get(&self.id).map(|v| v.clone()),
+            Some(v) => v.get(&self.id).map(|v| v.position_at(now_ms())),
             None => None,
         }
     }
 
+    /// Returns playback rate of the last position update
+    pub fn get_rate_cached(&self) -> Rate {
+        match self.position.upgrade() {
+            Some(v) => v.get(&self.id).map_or(1.0, |v| v.rate),
+            None => 1.0,
+        }
+    }
+
     /// Returns formated playback info
     pub fn get_formated_title(&self) -> Fallible<String> {
         debug!(
@@ -285,23 +464,70 @@ impl Instance {
             self.playlist.size(),
             self.playlist.amount_upcoming()
         );
+        let jingle = self
+            .jingles
+            .read()
+            .expect("Can't lock jingles!")
+            .get_playing()
+            .map(|v| format!("Jingle {}", v));
+        if let Some(v) = jingle {
+            return Ok(v);
+        }
         match self.playstate.load(Ordering::Relaxed) {
-            x if x == (Playstate::Playing as usize) => Ok(self
-                .playlist
-                .get_current()
-                .map_or(String::from("No current song! This is an error."), |v| {
-                    Self::format_track(&v, self.get_pos())
-                })),
+            x if x == (Playstate::Playing as usize) => Ok(self.playlist.get_current().map_or(
+                String::from("No current song! This is an error."),
+                |v| {
+                    let pos = self.get_pos();
+                    let mut title = Self::format_track(&v, pos);
+                    let rate = self.get_rate_cached();
+                    if rate != 1.0 {
+                        title = format!("{} @ {}", title, speed::format_rate(rate));
+                        // length in seconds, overflows as ms for bogus lengths
+                        let length = v.length.and_then(|l| l.checked_mul(1000));
+                        if let (Some(p), Some(length), false) = (pos, length, v.live) {
+                            let left = speed::remaining(length, p, rate);
+                            title =
+                                format!("{}, {} left", title, Self::format_time(Some(left / 1000)));
+                        }
+                    }
+                    if let Some(i) = pos.and_then(|p| chapters::current(&v.chapters, p)) {
+                        title = format!("{} | {}", title, v.chapters[i].title);
+                    }
+                    match *self.stream_title.read().expect("Can't read stream_title!") {
+                        Some(ref s) => format!("{} | {}", title, s),
+                        None => title,
+                    }
+                },
+            )),
             _ => Ok(String::from("--:--")),
         }
     }
 
-    /// Update startup time
+    /// Update startup time & write it back to DB, used as running flag
     pub fn update_startup_time(&self, time: Option<TimeStarted>) {
         let mut val = self.start_time.write().expect("Can't lock startup_time!");
+        if let Err(e) = self.db.set_instance_startup(&self.id, &time) {
+            warn!("Can't store startup time of instance {}: {}", self.id, e);
+        }
         *val = time;
     }
 
+    /// Returns whether the instance should be running
+    pub fn should_run(&self) -> bool {
+        self.wanted.load(Ordering::Relaxed)
+    }
+
+    /// Returns restart policy
+    pub fn get_restart_policy(&self) -> RestartPolicy {
+        self.restart_policy
+    }
+
+    /// Instance stopped for good, don't restart it
+    pub fn disable_restart(&self) {
+        self.wanted.store(false, Ordering::Relaxed);
+        self.update_startup_time(None);
+    }
+
     /// Start instance, ignore outcome spawn on runtime
     pub fn start_with_rt(&mut self) -> Fallible<()> {
         spawn(self.start()?.map_err(|e| error!("{:?}", e)).map(|_| ()));
@@ -312,10 +538,17 @@ impl Instance {
         self.state.load(Ordering::Relaxed) != InstanceState::Stopped as usize
     }
 
-    /// Return start future, has to be called by Instances to store state changes
+    /// Return start future, has to be called by Instances to store state changes  
+    /// The start volume is limited to the maximum of the volume rules
     #[must_use = "Future doesn't do anything untill polled!"]
     fn start(&self) -> Fallible<impl Future<Item = InstanceLoadResponse, Error = reqwest::Error>> {
-        Ok(self.backend.create_instance(&self.model)?)
+        self.wanted.store(true, Ordering::Relaxed);
+        let mut model = self.model.write().expect("Can't lock model!");
+        model.volume = volume::limit(
+            &self.volume_rules.read().expect("Can't lock volume rules!"),
+            model.volume,
+        );
+        Ok(self.backend()?.create_instance(&*model)?)
     }
 
     /// Update volume, intendet for callbacks
@@ -332,18 +565,268 @@ impl Instance {
         );
     }
 
-    /// Return volume set future
+    /// Return volume set future, volume is limited to the maximum
     #[must_use = "Future doesn't do anything untill polled!"]
     pub fn set_volume(
         &self,
         v: Volume,
     ) -> Fallible<impl Future<Item = DefaultResponse, Error = reqwest::Error>> {
-        Ok(self.backend.set_volume(&VolumeSetReq {
+        let v = volume::limit(
+            &self.volume_rules.read().expect("Can't lock volume rules!"),
+            v,
+        );
+        Ok(self.backend()?.set_volume(&VolumeSetReq {
             id: self.get_id(),
             volume: v,
         })?)
     }
 
+    /// Return volume set future of chat invoker, fails if locked for the invoker
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn set_volume_by(
+        &self,
+        v: Volume,
+        invoker_groups: &str,
+    ) -> Fallible<impl Future<Item = DefaultResponse, Error = reqwest::Error>> {
+        volume::check_change(
+            &self.volume_rules.read().expect("Can't lock volume rules!"),
+            invoker_groups,
+        )?;
+        self.set_volume(v)
+    }
+
+    /// Lock or unlock volume by chat invoker & write it back to DB
+    pub fn lock_volume(&self, lock: bool, invoker_groups: &str) -> Fallible<()> {
+        let mut rules = self.get_volume_rules();
+        volume::check_lock(&rules, invoker_groups)?;
+        rules.locked = lock;
+        self.db.set_volume_rules(&self.id, &rules)?;
+        *self.volume_rules.write().expect("Can't lock volume rules!") = rules;
+        Ok(())
+    }
+
+    /// Returns volume rules
+    pub fn get_volume_rules(&self) -> models::VolumeRules {
+        self.volume_rules
+            .read()
+            .expect("Can't lock volume rules!")
+            .clone()
+    }
+
+    /// Set volume rules & write them back to DB  
+    /// Lowers the current volume to a new maximum
+    pub fn set_volume_rules(&self, rules: models::VolumeRules) -> Fallible<()> {
+        volume::check_rules(&rules)?;
+        self.db.set_volume_rules(&self.id, &rules)?;
+        let max = rules.max;
+        *self.volume_rules.write().expect("Can't lock volume rules!") = rules;
+        if !self.is_running() || self.get_volume()? <= max {
+            return Ok(());
+        }
+        let fut = self.set_volume(max)?;
+        let id = self.get_id();
+        Backend::spawn_on_default({
+            fut.then(move |v| {
+                if let Err(e) = v {
+                    warn!("Error on volume limit update, instance {}! {}", id, e);
+                }
+                Ok(())
+            })
+        })?;
+        Ok(())
+    }
+
+    /// Return playback rate set future, pitch preservation is kept if not set  
+    /// Stores the rate, reapplied on every start
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn set_rate(
+        &self,
+        rate: Rate,
+        preserve_pitch: Option<bool>,
+    ) -> Fallible<impl Future<Item = DefaultResponse, Error = reqwest::Error>> {
+        let mut stored = self.db.get_instance(self.id)?;
+        stored.rate = rate;
+        stored.preserve_pitch = preserve_pitch.unwrap_or(stored.preserve_pitch);
+        self.db.update_instance(&stored)?;
+        {
+            let mut model = self.model.write().expect("Can't lock model!");
+            model.rate = rate;
+            model.preserve_pitch = stored.preserve_pitch;
+        }
+        Ok(self.backend()?.set_rate(&PlaybackRateReq {
+            id: self.get_id(),
+            rate,
+            preserve_pitch,
+        })?)
+    }
+
+    /// Return playback rate future
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn get_rate(&self) -> Fallible<impl Future<Item = RateResponse, Error = reqwest::Error>> {
+        Ok(self
+            .backend()?
+            .get_rate(&RateGetReq { id: self.get_id() })?)
+    }
+
+    /// Show or set playback rate by chat command, returns reply future
+    pub fn speed_command(
+        &self,
+        command: &str,
+    ) -> Fallible<impl Future<Item = String, Error = reqwest::Error>> {
+        if command.trim().is_empty() {
+            return Ok(Either::A(self.get_rate()?.map(|v| {
+                let pitch = match v.preserve_pitch {
+                    true => ", pitch preserved",
+                    false => "",
+                };
+                format!("Speed {}{}", speed::format_rate(v.rate), pitch)
+            })));
+        }
+        let rate = speed::parse_rate(command)?;
+        Ok(Either::B(self.set_rate(rate, None)?.map(move |_| {
+            format!("Speed set to {}", speed::format_rate(rate))
+        })))
+    }
+
+    /// Returns audio effects of the instance
+    pub fn get_effects(&self) -> AudioEffects {
+        self.model
+            .read()
+            .expect("Can't lock model!")
+            .effects
+            .clone()
+    }
+
+    /// Store audio effects, applied directly if running & on every start
+    pub fn set_effects(&self, effects: AudioEffects) -> Fallible<()> {
+        if !effects.is_valid() {
+            return Err(InstanceErr::InvalidEffects.into());
+        }
+        let mut stored = self.db.get_instance(self.id)?;
+        stored.effects = effects.clone();
+        self.db.update_instance(&stored)?;
+        self.model.write().expect("Can't lock model!").effects = effects.clone();
+        if !self.is_running() {
+            return Ok(());
+        }
+        let fut = self.backend()?.set_effects(&EffectsSetReq {
+            id: self.get_id(),
+            effects,
+        })?;
+        let id = self.get_id();
+        Backend::spawn_on_default({
+            fut.then(move |v| {
+                if let Err(e) = v {
+                    warn!("Error on effects update, instance {}! {}", id, e);
+                }
+                Ok(())
+            })
+        })?;
+        Ok(())
+    }
+
+    /// Start recording the instance output
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn start_recording(
+        &self,
+        format: RecordFormat,
+    ) -> Fallible<impl Future<Item = Recording, Error = reqwest::Error>> {
+        Ok(self.backend()?.start_recording(&RecordingStartReq {
+            id: self.get_id(),
+            format,
+        })?)
+    }
+
+    /// Stop recording, returns finished recording
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn stop_recording(
+        &self,
+    ) -> Fallible<impl Future<Item = Recording, Error = reqwest::Error>> {
+        Ok(self
+            .backend()?
+            .stop_recording(&RecordingStopReq { id: self.get_id() })?)
+    }
+
+    /// List recordings of instance, including a running one
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn get_recordings(
+        &self,
+    ) -> Fallible<impl Future<Item = RecordingListResponse, Error = reqwest::Error>> {
+        Ok(self
+            .backend()?
+            .get_recordings(&RecordingListReq { id: self.get_id() })?)
+    }
+
+    /// Download recording file or sidecar by file name
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn get_recording_file(
+        &self,
+        name: String,
+    ) -> Fallible<impl Future<Item = reqwest::r#async::Response, Error = reqwest::Error>> {
+        Ok(self
+            .backend()?
+            .get_recording_file(&RecordingFileReq { name })?)
+    }
+
+    /// Play soundboard clip over the music, fails while the clip is on cooldown  
+    /// The cooldown starts on trigger, it's reverted if the daemon doesn't accept the clip
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn play_clip(
+        &self,
+        clip: &models::Clip,
+    ) -> Fallible<impl Future<Item = (), Error = reqwest::Error>> {
+        let backend = self.backend()?;
+        let now = Instant::now();
+        let last = {
+            let mut plays = self.clip_plays.write().expect("Can't lock clip plays!");
+            let last = plays.get(&clip.name).cloned();
+            if let Some(left) = soundboard::cooldown_left(last, clip.cooldown_secs, now) {
+                let secs = left.as_secs() + if left.subsec_nanos() > 0 { 1 } else { 0 };
+                return Err(SoundboardErr::Cooldown(clip.name.clone(), secs).into());
+            }
+            plays.insert(clip.name.clone(), now);
+            last
+        };
+        let plays = self.clip_plays.clone();
+        let name = clip.name.clone();
+        let revert = move || {
+            let mut plays = plays.write().expect("Can't lock clip plays!");
+            // unless triggered again meanwhile
+            if plays.get(&name) == Some(&now) {
+                match last {
+                    Some(v) => plays.insert(name, v),
+                    None => plays.remove(&name),
+                };
+            }
+        };
+        let fut = match backend.play_clip(&ClipPlayReq {
+            id: self.get_id(),
+            clip: clip.name.clone(),
+            revision: clip.revision,
+            volume: clip.volume,
+        }) {
+            Ok(v) => v,
+            Err(e) => {
+                revert();
+                return Err(e);
+            }
+        };
+        Ok(fut.map_err(move |e| {
+            revert();
+            e
+        }))
+    }
+
+    /// Stop all clips
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn stop_clips(
+        &self,
+    ) -> Fallible<impl Future<Item = DefaultResponse, Error = reqwest::Error>> {
+        Ok(self
+            .backend()?
+            .stop_clips(&ClipStopReq { id: self.get_id() })?)
+    }
+
     /// Return current volume
     pub fn get_volume(&self) -> Fallible<Volume> {
         let mut vol_r = self.volume.read().expect("Can't lock volume!");
@@ -353,9 +836,10 @@ impl Instance {
     /// Return stop future
     #[must_use = "Future doesn't do anything untill polled!"]
     pub fn stop(&self) -> Fallible<impl Future<Item = DefaultResponse, Error = reqwest::Error>> {
+        self.wanted.store(false, Ordering::Relaxed);
         self.update_startup_time(None);
         Ok(self
-            .backend
+            .backend()?
             .stop_instance(&InstanceStopReq { id: self.get_id() })?)
     }
 
@@ -366,13 +850,45 @@ impl Instance {
 
     /// Returns queue future.
     /// Resolves URL by cache or calling daemon.
+    /// Resolves to the chat reply, empty on success or a message why the URL can't be played.
     #[must_use = "Future doesn't do anything untill polled!"]
-    pub fn queue(&self, url: String) -> Fallible<impl Future<Item = (), Error = reqwest::Error>> {
+    pub fn queue(
+        &self,
+        url: String,
+    ) -> Fallible<impl Future<Item = String, Error = failure::Error>> {
+        let (sender, receiver) = oneshot::channel();
+        let fut = self
+            .queue_retry(url, 0, Some(sender))?
+            .from_err::<failure::Error>()
+            .and_then(|_| receiver.from_err::<failure::Error>());
+
+        Ok(
+            Timeout::new(fut, QUEUE_REPLY_TIMEOUT).then(|res| match res {
+                Ok(v) => Ok(v),
+                Err(ref e) if e.is_elapsed() => Ok(String::from(QUEUE_PENDING_REPLY)),
+                Err(e) => Err(if e.is_timer() {
+                    e.into_timer().unwrap().into()
+                } else {
+                    e.into_inner().unwrap()
+                }),
+            }),
+        )
+    }
+
+    /// Queue with amount of previous failed resolve attempts  
+    /// The sender receives the chat reply, see `TicketHandler::add_queue`
+    fn queue_retry(
+        &self,
+        url: String,
+        retry: u8,
+        sender: Option<oneshot::Sender<String>>,
+    ) -> Fallible<impl Future<Item = (), Error = reqwest::Error>> {
         if let Some(pl) = self.db.get_playlist_by_url(&url)? {
             trace!("Found playlist cache hit for url.");
             self.add_to_queue(pl.data);
             // check playback as no ticket handler will be executed
             self.check_playback();
+            tickets::reply_queue(sender, String::new());
             return Ok(Either::A(result(Ok(()))));
         }
         if let Some(song) = self.db.get_song_by_url(&url)? {
@@ -380,24 +896,189 @@ impl Instance {
             self.add_to_queue(vec![song]);
             // check playback as no ticket handler will be executed
             self.check_playback();
+            tickets::reply_queue(sender, String::new());
             return Ok(Either::A(result(Ok(()))));
         }
 
-        let fut = self.backend.resolve_url(&ResolveRequest {
+        let backend = self.backend()?;
+        let fut = backend.resolve_url(&ResolveRequest {
             instance: self.get_id(),
             url,
+            priority: self.queue_priority(retry),
         })?;
 
-        let tickets = self.backend.get_tickets().clone();
+        let tickets = backend.get_tickets().clone();
         let id = self.get_id();
         let fut = fut.map(move |v| {
-            tickets.add_queue(id, v.ticket.clone());
+            tickets.add_queue(id, v.ticket.clone(), retry, sender);
             ()
         });
 
         Ok(Either::B(fut))
     }
 
+    /// Resolver lane for queued urls  
+    /// Only urls needed to start playback skip background resolves
+    fn queue_priority(&self, retry: u8) -> ResolvePriority {
+        if retry == 0 && !self.is_playing() && self.playlist.amount_upcoming() == 0 {
+            ResolvePriority::Play
+        } else {
+            ResolvePriority::Bulk
+        }
+    }
+
+    /// Handle failed resolve of queued url, returns chat reply  
+    /// Retries with increasing delay for retryable errors, notifies clients otherwise
+    pub fn resolve_failed(
+        &self,
+        instances: &Instances,
+        response: callback::ResolveResponse,
+        retry: u8,
+    ) -> String {
+        if response.error.is_retryable() && retry < QUEUE_RESOLVE_RETRIES {
+            let delay = QUEUE_RESOLVE_RETRY_DELAY * 2u32.pow(u32::from(retry));
+            debug!(
+                "Retrying resolve of {} in {}s, {:?}",
+                response.source,
+                delay.as_secs(),
+                response.error
+            );
+            let instances = instances.clone();
+            let id = self.get_id();
+            let source = response.source;
+            spawn(
+                Delay::new(Instant::now() + delay)
+                    .map_err(|e| warn!("Timer error: {}", e))
+                    .and_then(move |_| {
+                        let inst = match instances.read(&id) {
+                            Some(v) => v,
+                            None => return Either::A(result(Ok(()))),
+                        };
+                        match inst.queue_retry(source, retry + 1, None) {
+                            Ok(fut) => Either::B(
+                                fut.map_err(|e| warn!("Unable to retry queue url: {}", e)),
+                            ),
+                            Err(e) => {
+                                warn!("Unable to retry queue url: {}", e);
+                                Either::A(result(Ok(())))
+                            }
+                        }
+                    }),
+            );
+            return format!("Unable to resolve URL, retrying in {}s.", delay.as_secs());
+        }
+        warn!(
+            "Unable to resolve {}: {:?} {:?}",
+            response.source, response.error, response.msg
+        );
+        let message = resolve_error_message(response.error).to_string();
+        spawn(
+            frontend::WSServer::from_registry()
+                .send(frontend::ResolveFailed {
+                    id: self.get_id(),
+                    source: response.source,
+                    error: response.error,
+                    message: message.clone(),
+                })
+                .map_err(|e| warn!("WS-Server error: {}", e)),
+        );
+        message
+    }
+
+    /// Returns search future.
+    /// Resolves to the found songs, which are also stored as choices for the invoker.
+    #[must_use = "Future doesn't do anything untill polled!"]
+    pub fn search(
+        &self,
+        query: String,
+        site: SearchSite,
+        invoker: String,
+    ) -> Fallible<impl Future<Item = Vec<Song>, Error = failure::Error>> {
+        let backend = self.backend()?;
+        let fut = backend.resolve_search(&SearchRequest {
+            instance: self.get_id(),
+            query,
+            site,
+            amount: SEARCH_RESULT_AMOUNT,
+            priority: ResolvePriority::Play,
+        })?;
+
+        let tickets = backend.get_tickets().clone();
+        let id = self.get_id();
+        let fut = fut.from_err::<failure::Error>().and_then(move |v| {
+            let (sender, receiver) = oneshot::channel();
+            tickets.add_search(id, invoker, v.ticket, sender);
+            receiver.from_err::<failure::Error>().and_then(|v| v)
+        });
+
+        Ok(Timeout::new(fut, SEARCH_TIMEOUT).map_err(|e| {
+            if e.is_elapsed() {
+                InstanceErr::SearchTimeout.into()
+            } else if e.is_timer() {
+                e.into_timer().unwrap().into()
+            } else {
+                e.into_inner().unwrap()
+            }
+        }))
+    }
+
+    /// Key of an invoker for search choices, survives renames  
+    /// Falls back to the name for invokers without UID, Mumble users without certificate
+    pub fn search_invoker(invoker_uid: &str, invoker_name: &str) -> String {
+        match invoker_uid.is_empty() {
+            true => format!("name:{}", invoker_name),
+            false => format!("uid:{}", invoker_uid),
+        }
+    }
+
+    /// Format search results for display, numbered from 1
+    pub fn format_search_results(songs: &[Song]) -> Vec<String> {
+        songs
+            .iter()
+            .enumerate()
+            .map(|(i, v)| format!("{}: {}", i + 1, Self::format_track(v, None)))
+            .collect()
+    }
+
+    /// Store search results as choices for invoker, replacing older ones
+    pub fn set_search_choices(&self, invoker: String, songs: Vec<Song>) {
+        let mut choices_w = self
+            .search_choices
+            .write()
+            .expect("Can't lock search choices!");
+        choices_w.retain(|_, v| v.created.elapsed() < SEARCH_CHOICE_TIMEOUT);
+        choices_w.insert(
+            invoker,
+            SearchChoices {
+                songs,
+                created: Instant::now(),
+            },
+        );
+    }
+
+    /// Queue choice n (starting at 1) of the last search by invoker
+    pub fn pick_search_choice(&self, invoker: &str, n: usize) -> Fallible<Song> {
+        let song = {
+            let choices_r = self
+                .search_choices
+                .read()
+                .expect("Can't lock search choices!");
+            match choices_r.get(invoker) {
+                Some(v) if v.created.elapsed() < SEARCH_CHOICE_TIMEOUT => {
+                    match n.checked_sub(1).and_then(|i| v.songs.get(i)) {
+                        Some(s) => s.clone(),
+                        None => return Err(InstanceErr::InvalidSearchChoice(n).into()),
+                    }
+                }
+                _ => return Err(InstanceErr::NoSearchChoices.into()),
+            }
+        };
+        self.db.upsert_song(&song, &Some(song.source.as_str()))?;
+        self.add_to_queue(vec![song.clone()]);
+        self.check_playback();
+        Ok(song)
+    }
+
     /// Start instance, ignore outcome
     pub fn start_ignore(&mut self) -> Fallible<()> {
         trace!("Startin instance {}", self.id);
@@ -428,6 +1109,16 @@ impl Instance {
         );
     }
 
+    /// Set stream title of live source, intended for backend callbacks
+    pub fn cb_set_stream_title(&self, info: SongInfoUpdate) {
+        *self.stream_title.write().expect("Can't lock stream_title!") = info.stream_title.clone();
+        spawn(
+            frontend::WSServer::from_registry()
+                .send(info)
+                .map_err(|e| warn!("WS-Server error: {}", e)),
+        );
+    }
+
     /// Handle end of current song
     fn song_end(&self) {
         if let Err(e) = self.play_next_int() {
@@ -455,18 +1146,114 @@ impl Instance {
         }
     }
 
-    /// Play next track, supposed to add permission checks
-    pub fn play_next(&self) -> Fallible<()> {
+    /// Play next track, jingles can only be skipped by the skip groups
+    pub fn play_next(&self, invoker_groups: &str) -> Fallible<()> {
+        let jingle_playing = self
+            .jingles
+            .read()
+            .expect("Can't lock jingles!")
+            .get_playing()
+            .is_some();
+        if jingle_playing {
+            let rules = self.jingle_rules.read().expect("Can't lock jingle rules!");
+            if !jingles::may_skip(&rules, invoker_groups) {
+                return Err(JingleErr::NotSkippable.into());
+            }
+        }
         self.play_next_int()
     }
 
-    /// Play next track
+    /// Returns jingle rules
+    pub fn get_jingle_rules(&self) -> models::JingleRules {
+        self.jingle_rules
+            .read()
+            .expect("Can't lock jingle rules!")
+            .clone()
+    }
+
+    /// Returns trim rules
+    pub fn get_trim_rules(&self) -> models::TrimRules {
+        self.trim_rules
+            .read()
+            .expect("Can't lock trim rules!")
+            .clone()
+    }
+
+    /// Set trim rules & write them back to DB
+    pub fn set_trim_rules(&self, rules: models::TrimRules) -> Fallible<()> {
+        self.db.set_trim_rules(&self.id, &rules)?;
+        *self.trim_rules.write().expect("Can't lock trim rules!") = rules;
+        Ok(())
+    }
+
+    /// Set jingle rules & write them back to DB
+    pub fn set_jingle_rules(&self, rules: models::JingleRules) -> Fallible<()> {
+        self.db.set_jingle_rules(&self.id, &rules)?;
+        *self.jingle_rules.write().expect("Can't lock jingle rules!") = rules;
+        Ok(())
+    }
+
+    /// Play jingle if due, returns true if started
+    fn play_jingle_due(&self) -> Fallible<bool> {
+        let now = Instant::now();
+        let clip = {
+            let rules = self.jingle_rules.read().expect("Can't lock jingle rules!");
+            let due = self
+                .jingles
+                .read()
+                .expect("Can't lock jingles!")
+                .is_due(&rules, now);
+            // only between tracks
+            if !due || !self.playlist.has_next() {
+                return Ok(false);
+            }
+            match jingles::pick(&rules, &self.library) {
+                Some(v) => v,
+                None => return Ok(false),
+            }
+        };
+        let fut = self.backend()?.play_jingle(&JinglePlayReq {
+            id: self.get_id(),
+            clip: clip.name.clone(),
+            revision: clip.revision,
+        })?;
+        *self.stream_title.write().expect("Can't lock stream_title!") = None;
+        self.jingles
+            .write()
+            .expect("Can't lock jingles!")
+            .jingle_started(clip.name, now);
+
+        let id = self.get_id();
+        Backend::spawn_on_default({
+            fut.then(move |v| {
+                if let Err(e) = v {
+                    warn!("Error on jingle playback start, instance {}! {}", id, e);
+                }
+                Ok(())
+            })
+        })?;
+        Ok(true)
+    }
+
+    /// Play next track, plays a jingle before if due  
     /// Note: Currently only queue
     fn play_next_int(&self) -> Fallible<()> {
-        if let Some(v) = self.playlist.get_next(false) {
-            let fut = self.backend.play_url(&PlaybackUrlReq {
+        if self.play_jingle_due()? {
+            return Ok(());
+        }
+        let next = self.playlist.get_next(false);
+        let mut jingles = self.jingles.write().expect("Can't lock jingles!");
+        match next {
+            Some(_) => jingles.track_started(Instant::now()),
+            None => jingles.stopped(),
+        }
+        drop(jingles);
+        if let Some(v) = next {
+            *self.stream_title.write().expect("Can't lock stream_title!") = None;
+            let fut = self.backend()?.play_url(&PlaybackUrlReq {
                 id: self.get_id(),
                 song: v.clone(),
+                trim: self.get_song_trim(&v),
             })?;
 
             let id = self.get_id();
@@ -483,10 +1270,136 @@ impl Instance {
         Ok(())
     }
 
+    /// Returns trim points of song, live sources aren't trimmed
+    fn get_song_trim(&self, song: &Song) -> Option<SongTrim> {
+        if song.live {
+            return None;
+        }
+        match self.db.get_song_trim(&song.id) {
+            Ok(v) => v,
+            Err(e) => {
+                warn!("Can't read trim points of {}: {}", song.id, e);
+                None
+            }
+        }
+    }
+
+    /// Edit trim points of the current song by chat command, returns reply  
+    /// Only the edit groups of the trim rules are allowed to do so
+    pub fn trim_current(&self, command: &str, invoker_groups: &str) -> Fallible<String> {
+        if !trim::may_edit(
+            &self.trim_rules.read().expect("Can't lock trim rules!"),
+            invoker_groups,
+        ) {
+            return Err(TrimErr::NotPermitted.into());
+        }
+        let cmd = trim::parse_command(command)?;
+        let song = match self.get_current_track() {
+            Some(v) => v,
+            None => return Err(TrimErr::NoSong.into()),
+        };
+        if song.live {
+            return Err(TrimErr::LiveSource.into());
+        }
+        let current = self.db.get_song_trim(&song.id)?;
+        if cmd == TrimCommand::Show {
+            return Ok(format!(
+                "{}: {}",
+                song.name,
+                trim::describe(current.as_ref())
+            ));
+        }
+        let updated = trim::apply(current, cmd)?;
+        self.db.set_song_trim(&song.id, updated.as_ref())?;
+        self.push_trim(&song.id, updated.clone())?;
+        Ok(format!(
+            "{}: {}",
+            song.name,
+            trim::describe(updated.as_ref())
+        ))
+    }
+
+    /// Returns current track of the playlist, None while a jingle is playing
+    fn get_current_track(&self) -> Option<Song> {
+        let jingle_playing = self
+            .jingles
+            .read()
+            .expect("Can't lock jingles!")
+            .get_playing()
+            .is_some();
+        match self.playlist.get_current() {
+            Some(ref v) if !jingle_playing => Some(Song::clone(v)),
+            _ => None,
+        }
+    }
+
+    /// Returns chapters of the current track formated
+    pub fn get_chapters(&self) -> Vec<String> {
+        let song = match self.get_current_track() {
+            Some(v) => v,
+            None => return Vec::new(),
+        };
+        (0..song.chapters.len())
+            .map(|i| chapters::format_chapter(&song.chapters, i))
+            .collect()
+    }
+
+    /// Jump to chapter of the current track by chat command, returns reply
+    pub fn chapter_current(&self, command: &str) -> Fallible<String> {
+        let cmd = chapters::parse_command(command)?;
+        let song = match self.get_current_track() {
+            Some(v) => v,
+            None => return Err(chapters::ChapterErr::NoChapters.into()),
+        };
+        let index = chapters::target(&song.chapters, self.get_pos(), cmd)?;
+        let fut = self.backend()?.seek(&PlaybackSeekReq {
+            id: self.get_id(),
+            song: song.id.clone(),
+            position_ms: song.chapters[index].start_ms,
+        })?;
+        let id = self.get_id();
+        Backend::spawn_on_default({
+            fut.then(move |v| {
+                if let Err(e) = v {
+                    warn!("Error on chapter seek, instance {}! {}", id, e);
+                }
+                Ok(())
+            })
+        })?;
+        Ok(format!(
+            "Chapter {}",
+            chapters::format_chapter(&song.chapters, index)
+        ))
+    }
+
+    /// Send trim points to the daemon, applied if the song is playing
+    pub fn push_trim(&self, song: &SongID, trim: Option<SongTrim>) -> Fallible<()> {
+        let fut = self.backend()?.set_trim(&PlaybackTrimReq {
+            id: self.get_id(),
+            song: song.clone(),
+            trim,
+        })?;
+        let id = self.get_id();
+        Backend::spawn_on_default({
+            fut.then(move |v| {
+                if let Err(e) = v {
+                    warn!("Error on trim update, instance {}! {}", id, e);
+                }
+                Ok(())
+            })
+        })?;
+        Ok(())
+    }
+
     pub fn get_id(&self) -> ID {
         self.id
     }
 
+    /// Returns backend of the daemon this instance is placed on
+    fn backend(&self) -> Fallible<Backend> {
+        self.pool.backend_for(&self.id)
+    }
+
     pub fn get_playlist(&self) -> &SPlaylist {
         &self.playlist
     }
@@ -0,0 +1,158 @@
+/*
+ *  YAMBA manager
+ *  Copyright (C) 2019 Aron Heinecke
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *      http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ */
+
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+
+use std::time::{Duration, Instant};
+
+use crate::models::{Clip, JingleRules};
+use crate::security;
+use crate::soundboard::Library;
+
+/// Jingles & station IDs played between tracks  
+/// Jingles don't enter the playlist, so they're not part of its history
+
+#[derive(Fail, Debug)]
+pub enum JingleErr {
+    #[fail(display = "Jingles can't be skipped!")]
+    NotSkippable,
+}
+
+/// Jingle progress of an instance
+#[derive(Debug, Default)]
+pub struct JingleState {
+    /// Tracks played since the last jingle
+    tracks: u32,
+    /// Last jingle, start of the first track before
+    last: Option<Instant>,
+    /// Currently playing jingle
+    playing: Option<String>,
+}
+
+impl JingleState {
+    /// Whether a jingle is due before the next track  
+    /// Never before the first track or twice in a row
+    pub fn is_due(&self, rules: &JingleRules, now: Instant) -> bool {
+        if self.tracks == 0 {
+            return false;
+        }
+        let by_tracks = rules.every_tracks > 0 && self.tracks >= rules.every_tracks;
+        let by_time = rules.every_minutes > 0
+            && self.last.map_or(false, |last| {
+                now.duration_since(last) >= Duration::from_secs(u64::from(rules.every_minutes) * 60)
+            });
+        by_tracks || by_time
+    }
+
+    /// Track of the playlist started
+    pub fn track_started(&mut self, now: Instant) {
+        self.tracks += 1;
+        self.playing = None;
+        if self.last.is_none() {
+            self.last = Some(now);
+        }
+    }
+
+    /// Jingle started
+    pub fn jingle_started(&mut self, name: String, now: Instant) {
+        self.tracks = 0;
+        self.last = Some(now);
+        self.playing = Some(name);
+    }
+
+    /// Playback ended without next track
+    pub fn stopped(&mut self) {
+        self.playing = None;
+    }
+
+    /// Returns currently playing jingle
+    pub fn get_playing(&self) -> Option<&str> {
+        self.playing.as_ref().map(|v| v.as_str())
+    }
+}
+
+/// Whether the invoker may skip jingles
+pub fn may_skip(rules: &JingleRules, invoker_groups: &str) -> bool {
+    security::in_groups(invoker_groups, &rules.skip_groups)
+}
+
+/// Pick random jingle of the pool, deleted clips are ignored
+pub fn pick(rules: &JingleRules, library: &Library) -> Option<Clip> {
+    let mut pool: Vec<&String> = rules.pool.iter().collect();
+    pool.shuffle(&mut thread_rng());
+    pool.into_iter()
+        .filter_map(|name| match library.get_clip(name) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                debug!("Ignoring jingle {}: {}", name, e);
+                None
+            }
+        })
+        .next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(every_tracks: u32, every_minutes: u32) -> JingleRules {
+        JingleRules {
+            every_tracks,
+            every_minutes,
+            pool: vec![String::from("id")],
+            skip_groups: vec![String::from("6")],
+        }
+    }
+
+    #[test]
+    fn due_by_tracks() {
+        let now = Instant::now();
+        let rules = rules(2, 0);
+        let mut state = JingleState::default();
+        assert!(!state.is_due(&rules, now));
+        state.track_started(now);
+        assert!(!state.is_due(&rules, now));
+        state.track_started(now);
+        assert!(state.is_due(&rules, now));
+        state.jingle_started(String::from("id"), now);
+        assert_eq!(Some("id"), state.get_playing());
+        assert!(!state.is_due(&rules, now));
+        state.track_started(now);
+        assert_eq!(None, state.get_playing());
+    }
+
+    #[test]
+    fn due_by_time() {
+        let now = Instant::now();
+        let rules = rules(0, 10);
+        let mut state = JingleState::default();
+        state.track_started(now);
+        assert!(!state.is_due(&rules, now + Duration::from_secs(599)));
+        assert!(state.is_due(&rules, now + Duration::from_secs(600)));
+        // disabled
+        assert!(!state.is_due(&JingleRules::default(), now + Duration::from_secs(600)));
+    }
+
+    #[test]
+    fn skip() {
+        let rules = rules(2, 0);
+        assert!(may_skip(&rules, "8,6"));
+        assert!(!may_skip(&rules, "8"));
+        assert!(!may_skip(&rules, ""));
+    }
+}
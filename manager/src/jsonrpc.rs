@@ -16,7 +16,9 @@
  */
 
 use actix_web::{
-	error::ErrorInternalServerError, middleware, Error as WebError, HttpRequest, HttpResponse, Json,
+	error::{ErrorInternalServerError, ErrorNotFound},
+	fs::NamedFile,
+	middleware, Error as WebError, HttpRequest, HttpResponse, Json,
 };
 use failure::Fallible;
 use futures::{
@@ -29,6 +31,7 @@ use jsonrpc_core::{types::Request, *};
 use owning_ref::OwningRef;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use reqwest::StatusCode;
 use serde_json;
 use yamba_types::rpc::*;
 
@@ -36,7 +39,10 @@ use std::net::{IpAddr, SocketAddr};
 use std::sync::{Arc, RwLockReadGuard};
 
 use crate::instance::{Instance, Instances};
+use crate::jingles::JingleErr;
 use crate::security::SecurityModule;
+use crate::soundboard::{self, Library};
+use crate::volume::VolumeErr;
 
 /// Parse input and call fn on success
 fn parse_input<T, F, D>(data: Params, foo: F) -> impl Future<Item = Value, Error = Error>
@@ -131,10 +137,19 @@ fn jsonrpc_websocket_bridge(
 		})
 }
 
+/// Serve clip audio to daemons
+fn serve_clip(library: &Library, req: &HttpRequest<JsonrpcState>) -> actix_web::Result<NamedFile> {
+	let name = req.match_info().get("name").unwrap_or("");
+	match library.get_path(name) {
+		Some(path) => Ok(NamedFile::open(path)?),
+		None => Err(ErrorNotFound("Unknown clip")),
+	}
+}
+
 /// Create jsonrpc server for handling chat cmds
 pub fn create_server(
 	bind_addr: &SocketAddr,
-	allowed_host: IpAddr,
+	allowed_hosts: Vec<IpAddr>,
 	instances: Instances,
 ) -> Fallible<()> {
 	let mut io = IoHandler::new();
@@ -142,8 +157,13 @@ pub fn create_server(
 	let inst_c = instances.clone();
 	io.add_method("volume_set", move |data: Params| {
 		parse_input_instance(inst_c.clone(), data, |v: ParamVolume, inst| {
-			match inst.set_volume(v.volume) {
-				Err(e) => Either::A(send_internal_server_error(e)),
+			match inst.set_volume_by(v.volume, &v.invoker_groups) {
+				Err(e) => match e.downcast_ref::<VolumeErr>() {
+					Some(_) => Either::A(Either::A(send_ok_custom(DefaultResponse {
+						message: e.to_string(),
+					}))),
+					None => Either::A(Either::B(send_internal_server_error(e))),
+				},
 				Ok(val) => Either::B(
 					val.map_err(|e| {
 						warn!("Unable to set volume: {}", e);
@@ -159,6 +179,20 @@ pub fn create_server(
 		})
 	});
 	let inst_c = instances.clone();
+	io.add_method("volume_lock", move |data: Params| {
+		parse_input_instance(inst_c.clone(), data, |v: ParamVolumeLock, inst| {
+			match inst.lock_volume(v.lock, &v.invoker_groups) {
+				Err(e) => match e.downcast_ref::<VolumeErr>() {
+					Some(_) => Either::B(send_ok_custom(DefaultResponse {
+						message: e.to_string(),
+					})),
+					None => Either::A(send_internal_server_error(e)),
+				},
+				Ok(_) => Either::B(send_ok_custom(response_ignore())),
+			}
+		})
+	});
+	let inst_c = instances.clone();
 	io.add_method("queue", move |data: Params| {
 		parse_input_instance(inst_c.clone(), data, |v: ParamQueue, inst| {
 			match inst.queue(v.url) {
@@ -172,17 +206,22 @@ pub fn create_server(
 							code: error::ErrorCode::InternalError,
 						}
 					})
-					.map(|_v| serde_json::to_value(response_ignore()).unwrap()),
+					.map(|message| serde_json::to_value(DefaultResponse { message }).unwrap()),
 				),
 			}
 		})
 	});
 	let inst_c = instances.clone();
 	io.add_method("track_next", move |data: Params| {
-		parse_input_instance(inst_c.clone(), data, |_v: ParamDefault, inst| {
-			match inst.play_next() {
-				Err(e) => Either::A(send_internal_server_error(e)),
-				Ok(_) => Either::B(send_ok()),
+		parse_input_instance(inst_c.clone(), data, |v: ParamDefault, inst| {
+			match inst.play_next(&v.invoker_groups) {
+				Err(e) => match e.downcast_ref::<JingleErr>() {
+					Some(_) => Either::B(send_ok_custom(DefaultResponse {
+						message: e.to_string(),
+					})),
+					None => Either::A(send_internal_server_error(e)),
+				},
+				Ok(_) => Either::B(send_ok_custom(response_ignore())),
 			}
 		})
 	});
@@ -223,18 +262,172 @@ pub fn create_server(
 		})
 	});
 
+	let inst_c = instances.clone();
+	io.add_method("search", move |data: Params| {
+		parse_input_instance(inst_c.clone(), data, |v: ParamSearch, inst| {
+			let invoker = Instance::search_invoker(&v.invoker_uid, &v.invoker_name);
+			match inst.search(v.query, v.site, invoker) {
+				Err(e) => Either::A(send_internal_server_error(e)),
+				Ok(val) => Either::B(
+					val.map_err(|e| {
+						warn!("Unable to search: {}", e);
+						Error {
+							data: None,
+							message: e.to_string(),
+							code: error::ErrorCode::InternalError,
+						}
+					})
+					.map(|songs| {
+						serde_json::to_value(TitleListResponse {
+							tracklist: Instance::format_search_results(&songs),
+						})
+						.unwrap()
+					}),
+				),
+			}
+		})
+	});
+	let inst_c = instances.clone();
+	io.add_method("pick", move |data: Params| {
+		parse_input_instance(inst_c.clone(), data, |v: ParamPick, inst| {
+			let invoker = Instance::search_invoker(&v.invoker_uid, &v.invoker_name);
+			let message = match inst.pick_search_choice(&invoker, v.n) {
+				Ok(song) => format!("Queued {}", song.name),
+				Err(e) => e.to_string(),
+			};
+			send_ok_custom(DefaultResponse { message })
+		})
+	});
+	let inst_c = instances.clone();
+	io.add_method("trim", move |data: Params| {
+		parse_input_instance(inst_c.clone(), data, |v: ParamTrim, inst| {
+			let message = match inst.trim_current(&v.command, &v.invoker_groups) {
+				Ok(v) => v,
+				Err(e) => e.to_string(),
+			};
+			send_ok_custom(DefaultResponse { message })
+		})
+	});
+	let inst_c = instances.clone();
+	io.add_method("speed", move |data: Params| {
+		parse_input_instance(inst_c.clone(), data, |v: ParamSpeed, inst| {
+			match inst.speed_command(&v.command) {
+				Err(e) => Either::A(send_ok_custom(DefaultResponse {
+					message: e.to_string(),
+				})),
+				Ok(val) => Either::B(val.then(|res| {
+					let message = match res {
+						Ok(v) => v,
+						Err(e) => {
+							warn!("Unable to change speed: {}", e);
+							format!("Unable to change speed: {}", e)
+						}
+					};
+					result::<Value, Error>(Ok(
+						serde_json::to_value(DefaultResponse { message }).unwrap()
+					))
+				})),
+			}
+		})
+	});
+	let inst_c = instances.clone();
+	io.add_method("chapters", move |data: Params| {
+		parse_input_instance(inst_c.clone(), data, |_v: ParamDefault, inst| {
+			send_ok_custom(TitleListResponse {
+				tracklist: inst.get_chapters(),
+			})
+		})
+	});
+	let inst_c = instances.clone();
+	io.add_method("chapter", move |data: Params| {
+		parse_input_instance(inst_c.clone(), data, |v: ParamChapter, inst| {
+			let message = match inst.chapter_current(&v.command) {
+				Ok(v) => v,
+				Err(e) => e.to_string(),
+			};
+			send_ok_custom(DefaultResponse { message })
+		})
+	});
+	let inst_c = instances.clone();
+	io.add_method("soundboard", move |data: Params| {
+		let library = inst_c.get_library().clone();
+		parse_input_instance(inst_c.clone(), data, move |v: ParamSoundboard, inst| {
+			let clip = match library.get_clip(&v.clip).and_then(|clip| {
+				soundboard::check_allowed(&clip, &v.invoker_uid, &v.invoker_groups)?;
+				Ok(clip)
+			}) {
+				Ok(v) => v,
+				Err(e) => {
+					return Either::A(send_ok_custom(DefaultResponse {
+						message: e.to_string(),
+					}))
+				}
+			};
+			match inst.play_clip(&clip) {
+				Err(e) => Either::A(send_ok_custom(DefaultResponse {
+					message: e.to_string(),
+				})),
+				Ok(val) => Either::B(val.then(|res| {
+					// no reply on success, clips are heard
+					let message = match res {
+						Ok(_) => String::new(),
+						Err(e) => match e.status() {
+							Some(StatusCode::CONFLICT) => {
+								String::from("Clips can only be played over music!")
+							}
+							Some(StatusCode::TOO_MANY_REQUESTS) => {
+								String::from("Too many clips playing, try again later!")
+							}
+							_ => {
+								warn!("Unable to play clip: {}", e);
+								format!("Unable to play clip: {}", e)
+							}
+						},
+					};
+					result::<Value, Error>(Ok(
+						serde_json::to_value(DefaultResponse { message }).unwrap()
+					))
+				})),
+			}
+		})
+	});
+	let inst_c = instances.clone();
+	io.add_method("soundboard_list", move |data: Params| {
+		let library = inst_c.get_library().clone();
+		parse_input_instance(inst_c.clone(), data, move |v: ParamDefault, _inst| {
+			match library.get_clips() {
+				Err(e) => Either::A(send_internal_server_error(e)),
+				Ok(clips) => Either::B(send_ok_custom(TitleListResponse {
+					tracklist: clips
+						.into_iter()
+						.filter(|clip| {
+							soundboard::check_allowed(clip, &v.invoker_uid, &v.invoker_groups)
+								.is_ok()
+						})
+						.map(|clip| clip.name)
+						.collect(),
+				})),
+			}
+		})
+	});
+
 	let state: JsonrpcState = Arc::new(io);
+	let library = instances.get_library().clone();
 
 	actix_web::server::new(move || {
 		let json_only = actix_web::pred::Header("Content-Type", "application/json");
 		actix_web::App::with_state(state.clone())
 			.middleware(middleware::Logger::new("manager::api::jsonrpc"))
-			.middleware(SecurityModule::new(allowed_host))
+			.middleware(SecurityModule::new(allowed_hosts.clone()))
 			.resource("/", |r| {
 				r.post()
 					.filter(json_only)
 					.with_async(jsonrpc_websocket_bridge)
 			})
+			.resource("/clip/{name}", {
+				let library = library.clone();
+				move |r| r.get().f(move |req| serve_clip(&library, req))
+			})
 			.boxed()
 	})
 	.bind(bind_addr)
@@ -29,19 +29,26 @@ use env_logger::{self, Env};
 use futures::future::Future;
 use futures::stream::Stream;
 use tokio_signal;
-use yamba_types::models::GenericRequest;
 
-use crate::db::Database;
 use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use crate::db::Database;
 
 mod backend;
+mod chapters;
 mod db;
 mod frontend;
 mod instance;
+mod jingles;
 mod jsonrpc;
 mod models;
 mod playlist;
 mod security;
+mod soundboard;
+mod speed;
+mod trim;
+mod volume;
 
 #[cfg(any(feature = "maria", feature = "postgres"))]
 const DB_DEFAULT_PATH: &'static str = "127.0.0.1:3306";
@@ -64,18 +71,19 @@ fn main() -> Fallible<()> {
             Arg::with_name("daemon")
                 .short("d")
                 .long("daemon")
-                .value_name("IP:PORT")
-                .help("Set daemon Address to use")
-                .default_value("127.0.0.1:1338")
+                .value_name("API_IP:PORT,EVENTS_IP:PORT[,CAPACITY]")
+                .help("Add daemon to use, can be specified multiple times")
+                .default_value("127.0.0.1:1338,127.0.0.1:1336,15")
+                .multiple(true)
+                .number_of_values(1)
                 .takes_value(true),
         )
         .arg(
-            Arg::with_name("callback")
-                .short("c")
-                .long("callback")
-                .value_name("IP:PORT")
-                .help("Set daemon callback bind to use")
-                .default_value("127.0.0.1:1336")
+            Arg::with_name("owner")
+                .short("o")
+                .long("owner")
+                .value_name("Token")
+                .help("Ownership token sent to daemons, running instances with it get adopted. Defaults to a random token, generated on first start")
                 .takes_value(true),
         )
         .arg(
@@ -136,68 +144,57 @@ fn main() -> Fallible<()> {
                 .takes_value(true)
                 .default_value(DB_DEFAULT_PATH),
         )
+        .arg(
+            Arg::with_name("clips")
+                .long("clips")
+                .value_name("DIR")
+                .help("Folder of uploaded soundboard clips")
+                .default_value("clips")
+                .takes_value(true),
+        )
         .get_matches();
 
-    let addr_daemon: SocketAddr = matches.value_of("daemon").unwrap().parse()?;
+    let daemons = matches
+        .values_of("daemon")
+        .unwrap()
+        .map(|v| v.parse())
+        .collect::<Fallible<Vec<backend::DaemonConfig>>>()?;
     let addr_frontend: SocketAddr = matches.value_of("frontend").unwrap().parse()?;
     let addr_jsonrpc: SocketAddr = matches.value_of("jsonrpc").unwrap().parse()?;
-    let addr_callback_bind: SocketAddr = matches.value_of("callback").unwrap().parse()?;
     let api_secret = matches.value_of("api_secret").unwrap();
     let db_path = matches.value_of("db").unwrap();
+    let clips_dir = matches.value_of("clips").unwrap();
 
     let mut sys = System::new("manager");
 
     let db = db::DB::create(db_path.to_owned())?;
 
-    let instances = instance::Instances::new(db.clone());
+    let owner = match matches.value_of("owner") {
+        Some(v) => v.to_string(),
+        None => db.get_owner_token()?,
+    };
+
+    let pool = backend::DaemonPool::new(&daemons, api_secret)?;
+
+    let library = soundboard::Library::new(db.clone(), PathBuf::from(clips_dir))?;
 
-    let my_backend = backend::Backend::new(
-        addr_daemon,
-        instances.clone(),
-        api_secret,
-        addr_callback_bind,
-    )?;
+    let instances = instance::Instances::new(db, pool.clone(), owner, library);
 
-    let _server = jsonrpc::create_server(&addr_jsonrpc, addr_daemon.ip(), instances.clone())?;
+    instances.load_instances()?;
 
-    match create_instance_cmd(&my_backend, &instances, &matches) {
+    pool.start(instances.clone());
+
+    let daemon_ips = daemons.iter().map(|v| v.addr.ip()).collect();
+    let _server = jsonrpc::create_server(&addr_jsonrpc, daemon_ips, instances.clone())?;
+
+    match create_instance_cmd(&instances, &matches) {
         Err(e) => error!("Error during test-cmd handling: {}", e),
         Ok(_) => (),
     }
 
-    frontend::init_frontend_server(instances.clone(), my_backend.clone(), addr_frontend)?;
-
-    let backend_c = my_backend.clone();
-    backend::Backend::spawn_ignore(
-        my_backend
-            .get_instances()?
-            .and_then(move |i| {
-                i.instances.into_iter().for_each(|v| {
-                    let time_db = db.get_instance_startup(&v.id);
-                    let foreign = match time_db {
-                        Ok(Some(time)) => time == v.started,
-                        _ => false,
-                    };
-                    if foreign {
-                        warn!("Found foreign instance running: {}", v.id);
-                        backend::Backend::spawn_ignore(
-                            backend_c
-                                .stop_instance(&GenericRequest { id: v.id })
-                                .unwrap(),
-                        );
-                    } else {
-                        info!("Found own instance running! {}", v.id);
-                    }
-                });
-                Ok(())
-            })
-            .and_then(move |_| {
-                if let Err(e) = instances.load_instances(my_backend.clone()) {
-                    warn!("Error loading instances! {}", e);
-                }
-                Ok(())
-            }),
-    );
+    frontend::init_frontend_server(instances.clone(), addr_frontend)?;
+
+    backend::reconcile::start_reconciliation(instances);
 
     let ctrl_c = tokio_signal::ctrl_c().flatten_stream().into_future();
 
@@ -213,11 +210,7 @@ fn main() -> Fallible<()> {
 }
 
 /// Create cmd ts instance if applicable
-fn create_instance_cmd(
-    backend: &backend::Backend,
-    instances: &instance::Instances,
-    args: &ArgMatches,
-) -> Fallible<()> {
+fn create_instance_cmd(instances: &instance::Instances, args: &ArgMatches) -> Fallible<()> {
     if let Some(addr) = args.value_of("ts") {
         let _pw = args.value_of("pw");
         let cid: Option<i32> = match args
@@ -238,9 +231,13 @@ fn create_instance_cmd(
             name: String::from("test_instance"),
             password: None,
             nick: String::from("TestYambaInstance"),
+            restart_policy: Default::default(),
+            voip: Default::default(),
+            channel: None,
+            stream_format: Default::default(),
         };
 
-        instances.create_instance(model, backend.clone())?;
+        instances.create_instance(model)?;
 
         let mut inst_w = instances.write().expect("Can't lock instances!");
         let inst = inst_w.get_mut(&1).expect("Invalid identifier ?");
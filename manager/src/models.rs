@@ -27,8 +27,11 @@ use crate::db::DB;
 #[cfg(feature = "local")]
 use failure::Fallible;
 use serde::{Deserialize, Serialize};
-use yamba_types::models::{InstanceLoadReq, InstanceType, Song, TSSettings};
-use yamba_types::{TimeMS, Volume, ID};
+use yamba_types::models::{
+    AudioEffects, InstanceLoadReq, InstanceType, MumbleSettings, RecordFormat, Song, SongID,
+    SongTrim, StopReason, StreamFormat, StreamSettings, TSSettings,
+};
+use yamba_types::{Rate, TimeMS, Volume, ID};
 
 pub type PlaylistID = u64;
 
@@ -51,6 +54,65 @@ pub struct NewInstance {
     pub autostart: bool,
     /// VoIP identity nick
     pub nick: String,
+    /// Restart behaviour when stopped by the daemon
+    #[serde(default)]
+    pub restart_policy: RestartPolicy,
+    /// VoIP system to connect to
+    #[serde(default)]
+    pub voip: VoipType,
+    /// Channel to join by name, Mumble only
+    #[serde(default)]
+    pub channel: Option<String>,
+    /// Encoding of HTTP streams
+    #[serde(default)]
+    pub stream_format: StreamFormat,
+}
+
+/// VoIP system of an instance
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum VoipType {
+    Teamspeak,
+    Mumble,
+    /// HTTP audio stream served by the daemon
+    Stream,
+}
+
+impl Default for VoipType {
+    fn default() -> Self {
+        VoipType::Teamspeak
+    }
+}
+
+/// Restart policy of an instance, applied when the daemon stops it  
+/// Instances stopped on request or banned are never restarted
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RestartPolicy {
+    /// Never restart
+    Never,
+    /// Restart after crashes, timeouts & connection failures
+    OnFailure,
+    /// Also restart after being kicked
+    Always,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy::OnFailure
+    }
+}
+
+impl RestartPolicy {
+    /// Returns true if the instance should be restarted after this stop reason
+    pub fn should_restart(&self, reason: StopReason) -> bool {
+        match reason {
+            StopReason::Requested | StopReason::Banned => false,
+            _ => match self {
+                RestartPolicy::Never => false,
+                RestartPolicy::OnFailure => reason.is_failure(),
+                RestartPolicy::Always => true,
+            },
+        }
+    }
 }
 
 /// PlaylistData for DB retrieval
@@ -101,6 +163,31 @@ pub struct Instance {
     pub volume: Volume,
     /// VoIP identity nick
     pub nick: String,
+    /// Restart behaviour when stopped by the daemon
+    #[serde(default)]
+    pub restart_policy: RestartPolicy,
+    /// VoIP system to connect to
+    #[serde(default)]
+    pub voip: VoipType,
+    /// Channel to join by name, Mumble only
+    #[serde(default)]
+    pub channel: Option<String>,
+    /// Encoding of HTTP streams
+    #[serde(default)]
+    pub stream_format: StreamFormat,
+    /// Audio effects, reapplied on start
+    #[serde(default)]
+    pub effects: AudioEffects,
+    /// Playback rate, reapplied on start
+    #[serde(default = "default_rate")]
+    pub rate: Rate,
+    /// Pitch preservation on rate changes, reapplied on start
+    #[serde(default)]
+    pub preserve_pitch: bool,
+}
+
+fn default_rate() -> Rate {
+    1.0
 }
 
 impl Instance {
@@ -117,6 +204,13 @@ impl Instance {
             autostart: new.autostart,
             volume: 0.05,
             nick: new.nick,
+            restart_policy: new.restart_policy,
+            voip: new.voip,
+            channel: new.channel,
+            stream_format: new.stream_format,
+            effects: AudioEffects::default(),
+            rate: default_rate(),
+            preserve_pitch: false,
         }
     }
     /// Turn Model into InstanceLoadReq
@@ -127,14 +221,32 @@ impl Instance {
             InstanceLoadReq {
                 id: self.id,
                 volume: self.volume,
-                data: InstanceType::TS(TSSettings {
-                    host: self.host,
-                    port: self.port,
-                    identity: self.identity,
-                    cid: self.cid,
-                    name: self.nick,
-                    password: self.password,
-                }),
+                owner: None,
+                effects: self.effects,
+                rate: self.rate,
+                preserve_pitch: self.preserve_pitch,
+                data: match self.voip {
+                    VoipType::Teamspeak => InstanceType::TS(TSSettings {
+                        host: self.host,
+                        port: self.port,
+                        identity: self.identity,
+                        cid: self.cid,
+                        name: self.nick,
+                        password: self.password,
+                    }),
+                    VoipType::Mumble => InstanceType::Mumble(MumbleSettings {
+                        host: self.host,
+                        port: self.port,
+                        name: self.nick,
+                        password: self.password,
+                        channel: self.channel,
+                    }),
+                    VoipType::Stream => InstanceType::Stream(StreamSettings {
+                        format: self.stream_format,
+                        bitrate: None,
+                        name: Some(self.name.clone()),
+                    }),
+                },
             },
             self.name,
         )
@@ -146,6 +258,148 @@ pub struct GenericRequest {
     pub instance: ID,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RecordingStart {
+    pub instance: ID,
+    #[serde(default)]
+    pub format: RecordFormat,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RecordingDownload {
+    pub instance: ID,
+    /// File name of recording or sidecar
+    pub name: String,
+}
+
+/// Soundboard clip of the library, audio is stored as file
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Clip {
+    pub name: String,
+    /// Upload time, daemons cache clips by revision
+    pub revision: i64,
+    pub volume: Volume,
+    /// Min time between plays per instance
+    pub cooldown_secs: u32,
+    /// Unique IDs of users allowed to play the clip by chat, everyone if empty & no groups set  
+    /// TS UIDs or Mumble certificate hashes
+    pub allowed_users: Vec<String>,
+    /// Server groups allowed to play the clip by chat
+    pub allowed_groups: Vec<String>,
+}
+
+/// Changeable settings of a clip
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ClipSettings {
+    pub name: String,
+    pub volume: Volume,
+    pub cooldown_secs: u32,
+    #[serde(default)]
+    pub allowed_users: Vec<String>,
+    #[serde(default)]
+    pub allowed_groups: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ClipName {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ClipTrigger {
+    pub instance: ID,
+    pub clip: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SongTrimRequest {
+    pub song: SongID,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SongTrimUpdate {
+    pub song: SongID,
+    /// None removes all trim points
+    pub trim: Option<SongTrim>,
+}
+
+/// Chat trim rules of an instance
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct TrimRules {
+    /// Server groups allowed to edit trim points by chat
+    #[serde(default)]
+    pub edit_groups: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TrimRulesUpdate {
+    pub instance: ID,
+    pub rules: TrimRules,
+}
+
+/// Interstitial rules of an instance, jingles are soundboard clips
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct JingleRules {
+    /// Play a jingle every n tracks, 0 disables
+    #[serde(default)]
+    pub every_tracks: u32,
+    /// Play a jingle every m minutes, 0 disables
+    #[serde(default)]
+    pub every_minutes: u32,
+    /// Clip names of the jingle pool
+    #[serde(default)]
+    pub pool: Vec<String>,
+    /// Server groups allowed to skip jingles
+    #[serde(default)]
+    pub skip_groups: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct JingleRulesUpdate {
+    pub instance: ID,
+    pub rules: JingleRules,
+}
+
+/// Volume rules of an instance
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct VolumeRules {
+    /// Maximum volume, 0 to 1
+    #[serde(default = "default_max_volume")]
+    pub max: Volume,
+    /// Volume can't be changed by chat, except by the lock groups
+    #[serde(default)]
+    pub locked: bool,
+    /// Server groups allowed to lock the volume & change it while locked
+    #[serde(default)]
+    pub lock_groups: Vec<String>,
+}
+
+impl Default for VolumeRules {
+    fn default() -> VolumeRules {
+        VolumeRules {
+            max: default_max_volume(),
+            locked: false,
+            lock_groups: Vec::new(),
+        }
+    }
+}
+
+fn default_max_volume() -> Volume {
+    1.0
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct VolumeRulesUpdate {
+    pub instance: ID,
+    pub rules: VolumeRules,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct VolumeUpdate {
+    pub instance: ID,
+    pub volume: Volume,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct UseInstance {
     pub id: ID,
@@ -165,11 +419,46 @@ pub struct InstanceMin {
     pub name: String,
 }
 
+/// Load & health of all daemons
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Daemons {
+    pub daemons: Vec<DaemonInfo>,
+}
+
+/// Load & health of a daemon in the pool
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DaemonInfo {
+    pub id: usize,
+    pub addr: String,
+    pub capacity: usize,
+    pub instances: usize,
+    pub healthy: bool,
+}
+
 /// Send to client on initial connect
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Playback {
     pub playing: bool,
     pub position: TimeMS,
+    /// Playback rate, position advances by it
+    pub rate: Rate,
+}
+
+/// Audio effects change of an instance
+#[derive(Debug, Deserialize, Serialize)]
+pub struct EffectsUpdate {
+    pub instance: ID,
+    pub effects: AudioEffects,
+}
+
+/// Playback rate change
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RateUpdate {
+    pub instance: ID,
+    pub rate: Rate,
+    /// Pitch preservation, unchanged if not set
+    #[serde(default)]
+    pub preserve_pitch: Option<bool>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -181,6 +181,15 @@ where
         self.get_item(*self.get_pos())
     }
 
+    /// Returns whether get_next without repeat returns a track
+    pub fn has_next(&self) -> bool {
+        let lst = self.list.read().expect("Can't lock list!");
+        match *self.get_pos_exact() {
+            Some(v) => v.wrapping_add(1) < lst.len(),
+            None => !lst.is_empty(),
+        }
+    }
+
     /// Get next track, updating current position
     pub fn get_next(&self, repeat: bool) -> ItemReturn<T> {
         let lst = self.list.read().expect("Can't lock list!");
@@ -254,6 +263,18 @@ mod tests {
         assert!(playlist.get_next(false).is_none());
     }
 
+    #[test]
+    fn has_next() {
+        let playlist = Playlist::new();
+        assert!(!playlist.has_next());
+        playlist.push(vec![0, 1]);
+        assert!(playlist.has_next());
+        assert_eq!(0, **playlist.get_next(false).unwrap());
+        assert!(playlist.has_next());
+        assert_eq!(1, **playlist.get_next(false).unwrap());
+        assert!(!playlist.has_next());
+    }
+
     #[test]
     fn shuffle() {
         let playlist = Playlist::new();
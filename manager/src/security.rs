@@ -22,16 +22,24 @@ use actix_web::{
 };
 use std::net::{IpAddr, SocketAddr};
 
-/// Actix security module to allow only a specific IP
+/// Whether any of the invoker groups is contained in `groups`  
+/// Invoker groups are the comma separated server groups of a chat invoker.
+/// Mumble invokers don't send groups, so they never match.
+pub fn in_groups(invoker_groups: &str, groups: &[String]) -> bool {
+    invoker_groups
+        .split(',')
+        .map(|v| v.trim())
+        .any(|group| groups.iter().any(|v| v == group))
+}
+
+/// Actix security module to allow only specific IPs
 pub struct SecurityModule {
-    ip: String,
+    ips: Vec<IpAddr>,
 }
 
 impl SecurityModule {
-    pub fn new(addr: IpAddr) -> SecurityModule {
-        SecurityModule {
-            ip: addr.to_string(),
-        }
+    pub fn new(ips: Vec<IpAddr>) -> SecurityModule {
+        SecurityModule { ips }
     }
 }
 
@@ -40,7 +48,7 @@ impl<S> Middleware<S> for SecurityModule {
         if let Some(remote) = req.connection_info().remote() {
             if remote
                 .parse::<SocketAddr>()
-                .map(|v| v.ip().to_string() == self.ip)
+                .map(|v| self.ips.contains(&v.ip()))
                 .unwrap_or_else(|e| {
                     warn!("Can't parse remote IP! {}", e);
                     false
@@ -48,9 +56,24 @@ impl<S> Middleware<S> for SecurityModule {
             {
                 return Ok(Started::Done);
             } else {
-                debug!("Remote: {} Own: {}", remote, self.ip);
+                debug!("Remote: {} Allowed: {:?}", remote, self.ips);
             }
         }
         Ok(Started::Response(HttpResponse::Unauthorized().finish()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups() {
+        let groups = vec![String::from("6"), String::from("9")];
+        assert!(in_groups("8, 6", &groups));
+        assert!(in_groups("9", &groups));
+        assert!(!in_groups("8", &groups));
+        assert!(!in_groups("", &groups));
+        assert!(!in_groups("6", &[]));
+    }
+}
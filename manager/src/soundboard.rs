@@ -0,0 +1,229 @@
+/*
+ *  YAMBA manager
+ *  Copyright (C) 2019 Aron Heinecke
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *      http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ */
+
+use failure::Fallible;
+
+use std::fs::{self, DirBuilder};
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::db::{Database, DB};
+use crate::models::{Clip, ClipSettings};
+use crate::security;
+
+/// Soundboard clip library, audio files are stored in a folder, settings in the DB
+
+/// Max size of uploaded clips
+pub const CLIP_MAX_SIZE: usize = 5 * 1024 * 1024;
+/// Max length of clip names, daemons validate the same
+const NAME_MAX_LENGTH: usize = 64;
+const DEFAULT_VOLUME: f64 = 1.0;
+const DEFAULT_COOLDOWN_SECS: u32 = 10;
+
+#[derive(Fail, Debug)]
+pub enum SoundboardErr {
+    #[fail(display = "Invalid clip name {}, allowed are a-z, 0-9, - and _", _0)]
+    InvalidName(String),
+    #[fail(display = "No clip {} found!", _0)]
+    UnknownClip(String),
+    #[fail(display = "You're not allowed to play {}!", _0)]
+    NotAllowed(String),
+    #[fail(display = "{} is on cooldown for {}s!", _0, _1)]
+    Cooldown(String, u64),
+}
+
+/// Clip names are used in URLs & file names
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= NAME_MAX_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Check whether the chat invoker may play the clip  
+/// Users are matched by their unique ID, groups are the comma separated server groups of the invoker
+pub fn check_allowed(clip: &Clip, invoker_uid: &str, invoker_groups: &str) -> Fallible<()> {
+    if is_allowed(clip, invoker_uid, invoker_groups) {
+        Ok(())
+    } else {
+        Err(SoundboardErr::NotAllowed(clip.name.clone()).into())
+    }
+}
+
+fn is_allowed(clip: &Clip, invoker_uid: &str, invoker_groups: &str) -> bool {
+    if clip.allowed_users.is_empty() && clip.allowed_groups.is_empty() {
+        return true;
+    }
+    // no UID for unidentified invokers
+    (!invoker_uid.is_empty() && clip.allowed_users.iter().any(|v| v == invoker_uid))
+        || security::in_groups(invoker_groups, &clip.allowed_groups)
+}
+
+/// Remaining cooldown of a clip last played at `last`
+pub fn cooldown_left(last: Option<Instant>, cooldown_secs: u32, now: Instant) -> Option<Duration> {
+    let elapsed = now.duration_since(last?);
+    Duration::from_secs(u64::from(cooldown_secs))
+        .checked_sub(elapsed)
+        .filter(|v| *v > Duration::from_secs(0))
+}
+
+/// Clip library
+#[derive(Clone)]
+pub struct Library {
+    db: DB,
+    dir: PathBuf,
+}
+
+impl Library {
+    /// Create library storing clips in dir
+    pub fn new(db: DB, dir: PathBuf) -> Fallible<Library> {
+        DirBuilder::new().recursive(true).create(&dir)?;
+        Ok(Library { db, dir })
+    }
+
+    /// Returns all clips, sorted by name
+    pub fn get_clips(&self) -> Fallible<Vec<Clip>> {
+        let mut clips = self.db.get_clips()?;
+        clips.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(clips)
+    }
+
+    pub fn get_clip(&self, name: &str) -> Fallible<Clip> {
+        match self.db.get_clip(name)? {
+            Some(v) => Ok(v),
+            None => Err(SoundboardErr::UnknownClip(name.to_string()).into()),
+        }
+    }
+
+    /// Store uploaded clip audio, replaced clips keep their settings
+    pub fn upload(&self, name: &str, data: &[u8]) -> Fallible<Clip> {
+        if !is_valid_name(name) {
+            return Err(SoundboardErr::InvalidName(name.to_string()).into());
+        }
+        fs::write(self.dir.join(name), data)?;
+        let revision = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(v) => v.as_secs() as i64 * 1000 + i64::from(v.subsec_millis()),
+            Err(_) => 0,
+        };
+        let clip = match self.db.get_clip(name)? {
+            Some(v) => Clip { revision, ..v },
+            None => Clip {
+                name: name.to_string(),
+                revision,
+                volume: DEFAULT_VOLUME,
+                cooldown_secs: DEFAULT_COOLDOWN_SECS,
+                allowed_users: Vec::new(),
+                allowed_groups: Vec::new(),
+            },
+        };
+        self.db.upsert_clip(&clip)?;
+        info!("Stored clip {}, {} bytes", name, data.len());
+        Ok(clip)
+    }
+
+    /// Update settings of existing clip
+    pub fn update(&self, settings: ClipSettings) -> Fallible<Clip> {
+        let clip = self.get_clip(&settings.name)?;
+        let clip = Clip {
+            volume: settings.volume,
+            cooldown_secs: settings.cooldown_secs,
+            allowed_users: settings.allowed_users,
+            allowed_groups: settings.allowed_groups,
+            ..clip
+        };
+        self.db.upsert_clip(&clip)?;
+        Ok(clip)
+    }
+
+    /// Delete clip & its audio
+    pub fn delete(&self, name: &str) -> Fallible<()> {
+        if !is_valid_name(name) || !self.db.delete_clip(name)? {
+            return Err(SoundboardErr::UnknownClip(name.to_string()).into());
+        }
+        if let Err(e) = fs::remove_file(self.dir.join(name)) {
+            warn!("Can't remove audio of clip {}: {}", name, e);
+        }
+        Ok(())
+    }
+
+    /// Path of clip audio, None for unknown clips
+    pub fn get_path(&self, name: &str) -> Option<PathBuf> {
+        if !is_valid_name(name) {
+            return None;
+        }
+        let path = self.dir.join(name);
+        match path.is_file() {
+            true => Some(path),
+            false => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clip(users: &[&str], groups: &[&str]) -> Clip {
+        Clip {
+            name: String::from("horn"),
+            revision: 1,
+            volume: 1.0,
+            cooldown_secs: 10,
+            allowed_users: users.iter().map(|v| v.to_string()).collect(),
+            allowed_groups: groups.iter().map(|v| v.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn valid_name() {
+        assert!(is_valid_name("air_horn-2"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("../horn"));
+        assert!(!is_valid_name("air horn"));
+    }
+
+    #[test]
+    fn allowed() {
+        assert!(is_allowed(&clip(&[], &[]), "", ""));
+        let restricted = clip(&["aW5ZdPR1dA+Ux8hhNBEbSkBwQ7k="], &["6"]);
+        assert!(is_allowed(&restricted, "aW5ZdPR1dA+Ux8hhNBEbSkBwQ7k=", ""));
+        assert!(is_allowed(&restricted, "bob", "8,6"));
+        assert!(!is_allowed(&restricted, "bob", "8,16"));
+        assert!(!is_allowed(&restricted, "bob", ""));
+        // unidentified invokers
+        assert!(!is_allowed(&clip(&[""], &[]), "", ""));
+    }
+
+    #[test]
+    fn cooldown() {
+        let now = Instant::now();
+        assert_eq!(None, cooldown_left(None, 10, now));
+        assert_eq!(
+            Some(Duration::from_secs(6)),
+            cooldown_left(Some(now), 10, now + Duration::from_secs(4))
+        );
+        assert_eq!(
+            None,
+            cooldown_left(Some(now), 10, now + Duration::from_secs(11))
+        );
+        assert_eq!(
+            None,
+            cooldown_left(Some(now), 10, now + Duration::from_secs(10))
+        );
+    }
+}
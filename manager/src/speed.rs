@@ -0,0 +1,73 @@
+/*
+ *  YAMBA manager
+ *  Copyright (C) 2019 Aron Heinecke
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *      http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ */
+
+use failure::Fallible;
+use yamba_types::models::{Rate, TimeMS};
+use yamba_types::{is_valid_rate, RATE_MAX, RATE_MIN};
+
+/// Playback speed of instances, positions are in media time
+
+#[derive(Fail, Debug)]
+pub enum SpeedErr {
+    #[fail(display = "Invalid speed {}, use {} to {}!", _0, RATE_MIN, RATE_MAX)]
+    InvalidRate(String),
+}
+
+/// Parse rate as `1.25` or `1.25x`
+pub fn parse_rate(input: &str) -> Fallible<Rate> {
+    let input = input.trim();
+    let value = input.trim_end_matches(|c| c == 'x' || c == 'X');
+    match value.parse::<Rate>() {
+        Ok(v) if is_valid_rate(v) => Ok(v),
+        _ => Err(SpeedErr::InvalidRate(input.to_string()).into()),
+    }
+}
+
+/// Format rate as `1.25x`
+pub fn format_rate(rate: Rate) -> String {
+    format!("{}x", (rate * 100.0).round() / 100.0)
+}
+
+/// Remaining real time in ms at rate
+pub fn remaining(length: TimeMS, position: TimeMS, rate: Rate) -> TimeMS {
+    let left = length.saturating_sub(position);
+    (f64::from(left) / rate) as TimeMS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate() {
+        assert_eq!(1.25, parse_rate("1.25").unwrap());
+        assert_eq!(2.0, parse_rate(" 2x").unwrap());
+        assert_eq!(0.75, parse_rate("0.75X").unwrap());
+        assert!(parse_rate("0.5").is_err());
+        assert!(parse_rate("3").is_err());
+        assert!(parse_rate("fast").is_err());
+        assert_eq!("1.25x", format_rate(1.25));
+        assert_eq!("1x", format_rate(1.0));
+    }
+
+    #[test]
+    fn remaining_time() {
+        assert_eq!(60_000, remaining(120_000, 0, 2.0));
+        assert_eq!(80_000, remaining(120_000, 20_000, 1.25));
+        assert_eq!(0, remaining(120_000, 130_000, 1.0));
+    }
+}
@@ -0,0 +1,238 @@
+/*
+ *  YAMBA manager
+ *  Copyright (C) 2019 Aron Heinecke
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *      http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ */
+
+use failure::Fallible;
+use yamba_types::models::{SkipRange, SongTrim, TimeMS};
+
+use crate::models::TrimRules;
+use crate::security;
+
+/// Per song trim points & skip ranges, applied by the daemon at playback time
+
+#[derive(Fail, Debug)]
+pub enum TrimErr {
+    #[fail(display = "Invalid time {}, use m:ss or seconds!", _0)]
+    InvalidTime(String),
+    #[fail(
+        display = "Usage: !trim start <time>, !trim end <time>, !trim skip <from>-<to>, !trim clear"
+    )]
+    InvalidCommand,
+    #[fail(display = "Start has to be before the end!")]
+    InvalidRange,
+    #[fail(display = "Live sources can't be trimmed!")]
+    LiveSource,
+    #[fail(display = "No current song to trim!")]
+    NoSong,
+    #[fail(display = "Not allowed to trim songs!")]
+    NotPermitted,
+}
+
+/// Whether the invoker may edit trim points by chat
+pub fn may_edit(rules: &TrimRules, invoker_groups: &str) -> bool {
+    security::in_groups(invoker_groups, &rules.edit_groups)
+}
+
+/// Chat trim command
+#[derive(Debug, PartialEq)]
+pub enum TrimCommand {
+    Show,
+    Start(TimeMS),
+    End(TimeMS),
+    Skip(TimeMS, TimeMS),
+    Clear,
+}
+
+/// Parse time as `[h:]m:ss[.ms]` or seconds
+pub fn parse_time(input: &str) -> Fallible<TimeMS> {
+    let invalid = || TrimErr::InvalidTime(input.to_string());
+    let (rest, millis) = match input.find('.') {
+        Some(i) => {
+            let fraction = &input[i + 1..];
+            if fraction.is_empty() || fraction.len() > 3 {
+                return Err(invalid().into());
+            }
+            let value: u32 = fraction.parse().map_err(|_| invalid())?;
+            (&input[..i], value * 10u32.pow(3 - fraction.len() as u32))
+        }
+        None => (input, 0),
+    };
+    let parts: Vec<&str> = rest.split(':').collect();
+    if parts.len() > 3 {
+        return Err(invalid().into());
+    }
+    let mut seconds: u32 = 0;
+    for (i, part) in parts.iter().enumerate() {
+        let value: u32 = part.parse().map_err(|_| invalid())?;
+        // minutes & seconds of clock notation
+        if i > 0 && value >= 60 {
+            return Err(invalid().into());
+        }
+        seconds = seconds
+            .checked_mul(60)
+            .and_then(|v| v.checked_add(value))
+            .ok_or_else(invalid)?;
+    }
+    Ok(seconds
+        .checked_mul(1000)
+        .and_then(|v| v.checked_add(millis))
+        .ok_or_else(invalid)?)
+}
+
+/// Format time as `[h:]mm:ss`
+pub fn format_time(time: TimeMS) -> String {
+    let seconds = time / 1000;
+    match seconds / 3600 {
+        0 => format!("{}:{:02}", seconds / 60, seconds % 60),
+        h => format!("{}:{:02}:{:02}", h, (seconds / 60) % 60, seconds % 60),
+    }
+}
+
+/// Parse command after `!trim`
+pub fn parse_command(input: &str) -> Fallible<TrimCommand> {
+    let mut split = input.split_whitespace();
+    let cmd = split.next().map(|v| v.to_lowercase());
+    let arg = split.next();
+    if split.next().is_some() {
+        return Err(TrimErr::InvalidCommand.into());
+    }
+    Ok(match (cmd.as_ref().map(|v| v.as_str()), arg) {
+        (None, _) => TrimCommand::Show,
+        (Some("clear"), None) => TrimCommand::Clear,
+        (Some("start"), Some(v)) => TrimCommand::Start(parse_time(v)?),
+        (Some("end"), Some(v)) => TrimCommand::End(parse_time(v)?),
+        (Some("skip"), Some(v)) => {
+            let mut range = v.splitn(2, '-');
+            let start = parse_time(range.next().unwrap_or(""))?;
+            let end = parse_time(range.next().ok_or(TrimErr::InvalidCommand)?)?;
+            TrimCommand::Skip(start, end)
+        }
+        _ => return Err(TrimErr::InvalidCommand.into()),
+    })
+}
+
+/// Validate trim points
+pub fn validate(trim: &SongTrim) -> Fallible<()> {
+    if let Some(end) = trim.end_ms {
+        if end <= trim.start_ms {
+            return Err(TrimErr::InvalidRange.into());
+        }
+    }
+    if trim.skips.iter().any(|v| v.end_ms <= v.start_ms) {
+        return Err(TrimErr::InvalidRange.into());
+    }
+    Ok(())
+}
+
+/// Apply command, returns None if nothing is trimmed anymore
+pub fn apply(trim: Option<SongTrim>, cmd: TrimCommand) -> Fallible<Option<SongTrim>> {
+    let mut trim = trim.unwrap_or_default();
+    match cmd {
+        TrimCommand::Show => (),
+        TrimCommand::Clear => return Ok(None),
+        TrimCommand::Start(v) => trim.start_ms = v,
+        TrimCommand::End(v) => trim.end_ms = Some(v),
+        TrimCommand::Skip(start_ms, end_ms) => {
+            trim.skips.push(SkipRange { start_ms, end_ms });
+            trim.skips.sort_by_key(|v| v.start_ms);
+        }
+    }
+    validate(&trim)?;
+    match trim == SongTrim::default() {
+        true => Ok(None),
+        false => Ok(Some(trim)),
+    }
+}
+
+/// Describe trim points for chat
+pub fn describe(trim: Option<&SongTrim>) -> String {
+    let trim = match trim {
+        Some(v) => v,
+        None => return String::from("No trim points set."),
+    };
+    let mut parts = vec![format!("Start {}", format_time(trim.start_ms))];
+    if let Some(end) = trim.end_ms {
+        parts.push(format!("End {}", format_time(end)));
+    }
+    for skip in trim.skips.iter() {
+        parts.push(format!(
+            "Skip {}-{}",
+            format_time(skip.start_ms),
+            format_time(skip.end_ms)
+        ));
+    }
+    parts.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time() {
+        assert_eq!(12_000, parse_time("0:12").unwrap());
+        assert_eq!(12_000, parse_time("12").unwrap());
+        assert_eq!(12_500, parse_time("0:12.5").unwrap());
+        assert_eq!(3_723_000, parse_time("1:02:03").unwrap());
+        assert!(parse_time("0:60").is_err());
+        assert!(parse_time("1:2:3:4").is_err());
+        assert!(parse_time("abc").is_err());
+        assert!(parse_time("").is_err());
+        assert_eq!("0:12", format_time(12_500));
+        assert_eq!("1:02:03", format_time(3_723_000));
+    }
+
+    #[test]
+    fn command() {
+        assert_eq!(TrimCommand::Show, parse_command("").unwrap());
+        assert_eq!(
+            TrimCommand::Start(12_000),
+            parse_command("start 0:12").unwrap()
+        );
+        assert_eq!(
+            TrimCommand::Skip(60_000, 90_000),
+            parse_command("skip 1:00-1:30").unwrap()
+        );
+        assert_eq!(TrimCommand::Clear, parse_command(" CLEAR ").unwrap());
+        assert!(parse_command("skip 1:00").is_err());
+        assert!(parse_command("start").is_err());
+        assert!(parse_command("start 1 2").is_err());
+    }
+
+    #[test]
+    fn apply_commands() {
+        let trim = apply(None, TrimCommand::Start(12_000)).unwrap();
+        let trim = apply(trim, TrimCommand::Skip(90_000, 95_000)).unwrap();
+        let trim = apply(trim, TrimCommand::Skip(60_000, 70_000))
+            .unwrap()
+            .unwrap();
+        assert_eq!(60_000, trim.skips[0].start_ms);
+        assert!(apply(Some(trim.clone()), TrimCommand::End(10_000)).is_err());
+        assert!(apply(Some(trim.clone()), TrimCommand::Skip(5000, 5000)).is_err());
+        assert_eq!(None, apply(Some(trim), TrimCommand::Clear).unwrap());
+        assert_eq!(None, apply(None, TrimCommand::Start(0)).unwrap());
+    }
+
+    #[test]
+    fn edit_groups() {
+        let rules = TrimRules {
+            edit_groups: vec![String::from("6")],
+        };
+        assert!(may_edit(&rules, "8, 6"));
+        assert!(!may_edit(&rules, "8"));
+        assert!(!may_edit(&TrimRules::default(), ""));
+    }
+}
@@ -0,0 +1,106 @@
+/*
+ *  YAMBA manager
+ *  Copyright (C) 2019 Aron Heinecke
+ *
+ *  Licensed under the Apache License, Version 2.0 (the "License");
+ *  you may not use this file except in compliance with the License.
+ *  You may obtain a copy of the License at
+ *
+ *      http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *  Unless required by applicable law or agreed to in writing, software
+ *  distributed under the License is distributed on an "AS IS" BASIS,
+ *  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *  See the License for the specific language governing permissions and
+ *  limitations under the License.
+ */
+
+use failure::Fallible;
+use yamba_types::Volume;
+
+use crate::models::VolumeRules;
+use crate::security;
+
+/// Volume limit & lock of instances
+
+#[derive(Fail, Debug)]
+pub enum VolumeErr {
+    #[fail(display = "Volume is locked!")]
+    Locked,
+    #[fail(display = "Not allowed to lock the volume!")]
+    NotPrivileged,
+    #[fail(display = "Invalid volume limit {}, use 0 to 1!", _0)]
+    InvalidLimit(Volume),
+}
+
+/// Whether the invoker may lock the volume & change it while locked
+pub fn is_privileged(rules: &VolumeRules, invoker_groups: &str) -> bool {
+    security::in_groups(invoker_groups, &rules.lock_groups)
+}
+
+/// Check whether the invoker may change the volume
+pub fn check_change(rules: &VolumeRules, invoker_groups: &str) -> Fallible<()> {
+    if rules.locked && !is_privileged(rules, invoker_groups) {
+        return Err(VolumeErr::Locked.into());
+    }
+    Ok(())
+}
+
+/// Check whether the invoker may lock or unlock the volume
+pub fn check_lock(rules: &VolumeRules, invoker_groups: &str) -> Fallible<()> {
+    match is_privileged(rules, invoker_groups) {
+        true => Ok(()),
+        false => Err(VolumeErr::NotPrivileged.into()),
+    }
+}
+
+/// Check volume limit of rules
+pub fn check_rules(rules: &VolumeRules) -> Fallible<()> {
+    match rules.max >= 0.0 && rules.max <= 1.0 {
+        true => Ok(()),
+        false => Err(VolumeErr::InvalidLimit(rules.max).into()),
+    }
+}
+
+/// Returns volume within 0 and the limit
+pub fn limit(rules: &VolumeRules, volume: Volume) -> Volume {
+    volume.max(0.0).min(rules.max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(locked: bool) -> VolumeRules {
+        VolumeRules {
+            max: 0.5,
+            locked,
+            lock_groups: vec![String::from("6")],
+        }
+    }
+
+    #[test]
+    fn lock() {
+        let rules = rules(true);
+        assert!(check_change(&rules, "8, 6").is_ok());
+        assert!(check_change(&rules, "8").is_err());
+        assert!(check_change(&rules, "").is_err());
+        assert!(check_change(&self::rules(false), "").is_ok());
+        assert!(check_lock(&rules, "6").is_ok());
+        assert!(check_lock(&rules, "").is_err());
+        // nobody can lock without groups
+        assert!(check_lock(&VolumeRules::default(), "").is_err());
+    }
+
+    #[test]
+    fn limits() {
+        let rules = rules(false);
+        assert_eq!(0.5, limit(&rules, 0.8));
+        assert_eq!(0.3, limit(&rules, 0.3));
+        assert_eq!(0.0, limit(&rules, -1.0));
+        assert!(check_rules(&rules).is_ok());
+        let mut invalid = rules.clone();
+        invalid.max = 1.5;
+        assert!(check_rules(&invalid).is_err());
+    }
+}